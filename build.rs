@@ -0,0 +1,99 @@
+use std::{
+    env,
+    fs,
+    path::{Path, PathBuf},
+};
+
+// The shaders in `shaders/` are embedded in the crate as
+// SPIR-V, as a fallback for when they can't be loaded from
+// disk at runtime. The SPIR-V is compiled from the sources:
+//  - with `glslc`, from the Vulkan SDK (in `VULKAN_SDK` or on
+//    the PATH), by default;
+//  - with the `shaderc` crate, with the opt-in `shaderc`
+//    feature, which builds the compiler from source if needed
+//    (which takes CMake and Python) and so works without the
+//    Vulkan SDK.
+// If that fails, the `.spv` files next to the sources are used
+// instead, when there are some, with a warning: they may be
+// out of date, and their modification time can't tell (it is
+// that of the checkout, not of the compilation). The result
+// goes to OUT_DIR, where `src/shaders.rs` includes it from.
+
+const SHADERS: &[&str] = &["shader.vert", "shader.frag"];
+
+fn main() {
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    println!("cargo:rerun-if-env-changed=VULKAN_SDK");
+
+    for name in SHADERS {
+        let source = Path::new("shaders").join(name);
+        let prebuilt = Path::new("shaders").join(format!("{}.spv", name));
+        let output = out_dir.join(format!("{}.spv", name));
+        println!("cargo:rerun-if-changed={}", source.display());
+        println!("cargo:rerun-if-changed={}", prebuilt.display());
+
+        let Err(error) = compile(&source, &output) else {
+            continue;
+        };
+
+        if prebuilt.exists() {
+            println!(
+                "cargo:warning=Can't compile {} to SPIR-V ({}), using {} instead.",
+                source.display(),
+                error,
+                prebuilt.display(),
+            );
+            fs::copy(&prebuilt, &output).unwrap();
+            continue;
+        }
+
+        panic!(
+            "\n\nCan't compile {} to SPIR-V: {}\n\
+             Either install the Vulkan SDK (for glslc), build with the `shaderc` feature, \
+             or compile it to {} yourself.\n\n",
+            source.display(),
+            error,
+            prebuilt.display(),
+        );
+    }
+}
+
+#[cfg(feature = "shaderc")]
+fn compile(source: &Path, output: &Path) -> Result<(), String> {
+    let kind = match source.extension().and_then(|e| e.to_str()) {
+        Some("vert") => shaderc::ShaderKind::Vertex,
+        Some("frag") => shaderc::ShaderKind::Fragment,
+        _ => return Err("unknown shader stage".to_string()),
+    };
+
+    let text = fs::read_to_string(source).map_err(|e| e.to_string())?;
+    let compiler = shaderc::Compiler::new().ok_or("failed to initialize shaderc")?;
+    let artifact = compiler
+        .compile_into_spirv(&text, kind, &source.display().to_string(), "main", None)
+        .map_err(|e| e.to_string())?;
+
+    fs::write(output, artifact.as_binary_u8()).map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "shaderc"))]
+fn compile(source: &Path, output: &Path) -> Result<(), String> {
+    use std::process::Command;
+
+    let glslc = match env::var_os("VULKAN_SDK") {
+        Some(sdk) => PathBuf::from(sdk).join("bin").join("glslc"),
+        None => PathBuf::from("glslc"),
+    };
+
+    let status = Command::new(&glslc)
+        .arg(source)
+        .arg("-o")
+        .arg(output)
+        .status()
+        .map_err(|e| format!("failed to run {}: {}", glslc.display(), e))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("{} failed ({})", glslc.display(), status))
+    }
+}
@@ -0,0 +1,150 @@
+mod common;
+
+use std::collections::VecDeque;
+
+use vulkanalia::prelude::v1_0::*;
+
+use log::info;
+use common::Headless;
+use caliban::prelude::{
+    Allocation,
+    Allocator,
+    Lifetime,
+    MemoryUse,
+    ResourceCategory,
+    ResourceType,
+};
+
+/// Number of simulated frames for each run.
+const FRAME_COUNT: usize = 10_000;
+/// Number of frames between two reports.
+const REPORT_INTERVAL: usize = 1000;
+/// Frames in flight: per-frame allocations are freed when
+/// their frame comes around again.
+const FRAMES_IN_FLIGHT: usize = 2;
+/// Per-frame allocations made each frame.
+const PER_FRAME_ALLOCATIONS: usize = 16;
+/// Frames a staging buffer lives for before being freed.
+const TRANSIENT_FRAMES: usize = 3;
+/// Maximum number of persistent allocations alive at once.
+const MAX_PERSISTENT: usize = 200;
+
+/// Small xorshift generator, so that both runs see the exact
+/// same sequence of allocations.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Random number in `min..max`.
+    fn range(&mut self, min: u64, max: u64) -> u64 {
+        min + self.next() % (max - min)
+    }
+
+    /// True with a probability of 1 in `n`.
+    fn one_in(&mut self, n: u64) -> bool {
+        self.next() % n == 0
+    }
+}
+
+fn main() {
+    common::init_logging();
+
+    let vulkan = Headless::new(b"allocator-soak\0");
+    let (instance, device) = (&vulkan.instance, &vulkan.device);
+    let physical_device = vulkan.physical_device;
+
+    // Every allocation is a host-visible buffer, so that all of
+    // them compete for the same memory type and could share
+    // blocks; only the sizes differ, and the memory type bits
+    // are taken from a real buffer.
+    let buffer_info = vk::BufferCreateInfo::builder()
+        .size(1024)
+        .usage(vk::BufferUsageFlags::UNIFORM_BUFFER | vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::VERTEX_BUFFER)
+        .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+    let base_requirements = unsafe {
+        let buffer = device.create_buffer(&buffer_info, None).unwrap();
+        let requirements = device.get_buffer_memory_requirements(buffer);
+        device.destroy_buffer(buffer, None);
+        requirements
+    };
+
+    // The first run gives every allocation the persistent hint,
+    // as if there were no lifetime classes: all allocations
+    // share the same blocks. The second gives the real hints.
+    for segregated in [false, true] {
+        let mut allocator = Allocator::new(instance, physical_device, vulkan.limits);
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+
+        let allocate = |allocator: &mut Allocator, size: u64, lifetime: Lifetime, category: ResourceCategory| {
+            let requirements = vk::MemoryRequirements { size, ..base_requirements };
+            let lifetime = if segregated { lifetime } else { Lifetime::Persistent };
+            allocator.allocate(device, requirements, MemoryUse::CpuToGpu, ResourceType::Linear, lifetime, category)
+                .unwrap()
+        };
+
+        let mut per_frame: Vec<Vec<Allocation>> = (0..FRAMES_IN_FLIGHT).map(|_| Vec::new()).collect();
+        let mut transient: VecDeque<(usize, Allocation)> = VecDeque::new();
+        let mut persistent: Vec<Allocation> = Vec::new();
+
+        info!("Lifetime classes {}:", if segregated { "on" } else { "off" });
+
+        for frame in 0..FRAME_COUNT {
+            // Per-frame data: the allocations of the frame that
+            // last used this slot are done with.
+            let slot = &mut per_frame[frame % FRAMES_IN_FLIGHT];
+            slot.drain(..).for_each(|a| allocator.free(a));
+            for _ in 0..PER_FRAME_ALLOCATIONS {
+                let size = rng.range(4 * 1024, 64 * 1024);
+                slot.push(allocate(&mut allocator, size, Lifetime::PerFrame, ResourceCategory::Uniform));
+            }
+
+            // Staging buffers of uploads, freed once the copies
+            // are done, a few frames later.
+            while transient.front().is_some_and(|&(freed_at, _)| freed_at <= frame) {
+                allocator.free(transient.pop_front().unwrap().1);
+            }
+            if rng.one_in(4) {
+                let size = rng.range(256 * 1024, 4 * 1024 * 1024);
+                let allocation = allocate(&mut allocator, size, Lifetime::Transient, ResourceCategory::Staging);
+                transient.push_back((frame + TRANSIENT_FRAMES, allocation));
+            }
+
+            // Scene content streamed in, and now and then out.
+            if rng.one_in(20) && persistent.len() < MAX_PERSISTENT {
+                let size = rng.range(16 * 1024, 1024 * 1024);
+                persistent.push(allocate(&mut allocator, size, Lifetime::Persistent, ResourceCategory::Mesh));
+            }
+            if rng.one_in(50) && !persistent.is_empty() {
+                let index = rng.range(0, persistent.len() as u64) as usize;
+                allocator.free(persistent.swap_remove(index));
+            }
+
+            if (frame + 1) % REPORT_INTERVAL == 0 {
+                let stats = allocator.stats();
+                let blocks = stats.lifetimes.iter().map(|(_, s)| s.blocks).sum::<usize>();
+                let classes = stats.lifetimes
+                    .iter()
+                    .filter(|(_, s)| s.blocks > 0)
+                    .map(|(lifetime, s)| format!("{:?} {} ({:.1}%)", lifetime, s.blocks, s.occupancy() * 100.0))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                info!("  Frame {:>6}: {} blocks [{}]", frame + 1, blocks, classes);
+            }
+        }
+
+        allocator.log_report();
+        allocator.destroy(device);
+    }
+
+    unsafe {
+        vulkan.destroy();
+    }
+}
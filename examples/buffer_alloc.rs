@@ -1,75 +1,74 @@
-use vulkanalia::{
-    prelude::v1_0::*,
-    vk::DeviceV1_3,
-    window as vk_window,
-    loader::{LibloadingLoader, LIBRARY},
-    Version,
-    vk::ExtDebugUtilsExtension,
-    vk::KhrSurfaceExtension,
-    vk::KhrSwapchainExtension,
-};
+mod common;
+
+use vulkanalia::prelude::v1_0::*;
 
 use log::info;
-use caliban::{
-    core::queues::get_graphics_family_index,
-    renderer::VALIDATION_LAYER,
+use common::Headless;
+use caliban::prelude::{
+    Allocator,
+    Lifetime,
+    MemoryUse,
+    ResourceCategory,
+    ResourceType,
 };
 
-fn main() {
-    std::env::set_var("RUST_LOG", "info");
-    pretty_env_logger::init();
-    
-    // Vulkan entry point
-    let entry = unsafe {
-        let loader = LibloadingLoader::new(LIBRARY).unwrap();
-        Entry::new(loader).unwrap()
-    };
+/// Vertices of a triangle: a position and a color each.
+const VERTICES: [[f32; 5]; 3] = [
+    [0.0, -0.5, 1.0, 0.0, 0.0],
+    [0.5, 0.5, 0.0, 1.0, 0.0],
+    [-0.5, 0.5, 0.0, 0.0, 1.0],
+];
 
-    // Application info and validation layers
-    let application_info = vk::ApplicationInfo::builder()
-        .application_name(b"buffer-alloc\0")
-        .application_version(vk::make_version(1, 0, 0))
-        .api_version(vk::make_version(1, 3, 0));
-
-    let layers = [VALIDATION_LAYER.as_ptr()];
+/// Allocates a host-visible vertex buffer, fills it through the
+/// persistent mapping, and prints the allocator's memory map.
+fn main() {
+    common::init_logging();
 
-    let info = vk::InstanceCreateInfo::builder()
-        .application_info(&application_info)
-        .enabled_layer_names(&layers);
+    let vulkan = Headless::new(b"buffer-alloc\0");
+    let (instance, device) = (&vulkan.instance, &vulkan.device);
+    let mut allocator = Allocator::new(instance, vulkan.physical_device, vulkan.limits);
 
-    // Vulkan instance
-    let instance = unsafe { entry.create_instance(&info, None).unwrap() };
+    // The buffer is created first, to know its memory
+    // requirements, then bound to the memory allocated for it.
+    let size = std::mem::size_of_val(&VERTICES) as u64;
+    let buffer_info = vk::BufferCreateInfo::builder()
+        .size(size)
+        .usage(vk::BufferUsageFlags::VERTEX_BUFFER)
+        .sharing_mode(vk::SharingMode::EXCLUSIVE);
 
-    // Physical device
-    let (physical_device, graphics_queue) = unsafe {
-        instance
-            .enumerate_physical_devices()
-            .unwrap()
-            .iter()
-            .find_map(|&physical_device| {
-                if let Ok(queue_index) = get_graphics_family_index(&instance, physical_device) {
-                    Some((physical_device, queue_index))
-                } else {
-                    None
-                }
-            })
-            .unwrap()
-    };
+    let buffer = unsafe { device.create_buffer(&buffer_info, None).unwrap() };
+    let requirements = unsafe { device.get_buffer_memory_requirements(buffer) };
 
-    // Logical device
-    let priorities = &[1.0];
-    let graphics_queues = &[
-        vk::DeviceQueueCreateInfo::builder()
-            .queue_family_index(graphics_queue)
-            .queue_priorities(priorities)
-    ];
+    let allocation = allocator.allocate(
+        device,
+        requirements,
+        MemoryUse::CpuToGpu,
+        ResourceType::Linear,
+        Lifetime::Persistent,
+        ResourceCategory::Mesh,
+    ).unwrap();
+    allocator.set_debug_name(&allocation, "triangle vertices");
+    unsafe { device.bind_buffer_memory(buffer, allocation.memory, allocation.offset).unwrap() };
 
-    let create_info = vk::DeviceCreateInfo::builder()
-        .queue_create_infos(graphics_queues)
-        .enabled_layer_names(&layers);
+    // Nothing uses the buffer yet, so it can be written right
+    // away; the write only has to be flushed for non-coherent
+    // memory.
+    unsafe { allocation.write(0, &VERTICES) };
+    allocator.flush(device, &allocation, 0, size).unwrap();
 
-    let device = unsafe { instance.create_device(physical_device, &create_info, None).unwrap() };
-    info!("Created device.");
+    // Read the data back through the mapping, to check it.
+    let mapped = allocation.mapped.unwrap().as_ptr().cast::<[[f32; 5]; 3]>();
+    assert_eq!(unsafe { mapped.read_unaligned() }, VERTICES);
+    info!(
+        "Wrote {} bytes at offset {} (coherent: {}).",
+        size,
+        allocation.offset,
+        allocation.coherent,
+    );
+    info!("Memory map:\n{}", allocator.dump_layout());
 
-    todo!("allocate a buffer");
-}
\ No newline at end of file
+    unsafe { device.destroy_buffer(buffer, None) };
+    allocator.free(allocation);
+    allocator.destroy(device);
+    unsafe { vulkan.destroy() };
+}
@@ -0,0 +1,40 @@
+use caliban::prelude::*;
+use anyhow::Result;
+
+/// Clears the window to a fixed color, or, when run with
+/// `flash`, to a blue that flashes over 120 frames.
+struct ClearColor {
+    flash: bool,
+}
+
+impl AppHooks for ClearColor {
+    fn init(&mut self, renderer: &mut Renderer, _assets: &Assets) -> Result<()> {
+        if self.flash {
+            renderer.set_animated_clear(|frame| {
+                let flash = (frame as f32 / 120.0).sin().abs();
+                [0.0, 0.0, flash, 1.0]
+            });
+        } else {
+            renderer.set_clear_color([0.8, 0.4, 0.1, 1.0]);
+        }
+
+        Ok(())
+    }
+}
+
+fn main() -> Result<()> {
+    // Log at the info level, unless RUST_LOG says otherwise.
+    if std::env::var_os("RUST_LOG").is_none() {
+        std::env::set_var("RUST_LOG", "info");
+    }
+    pretty_env_logger::init();
+
+    let flash = std::env::args().nth(1).is_some_and(|arg| arg == "flash");
+    // The animation only moves if frames keep coming.
+    let config = AppConfig {
+        continuous_redraw: flash,
+        ..Default::default()
+    };
+
+    run(config, ClearColor { flash })
+}
@@ -0,0 +1,109 @@
+// Setup shared by the examples that exercise the allocator on
+// their own, without a window or a renderer. Each example only
+// uses part of it.
+#![allow(dead_code)]
+
+use vulkanalia::{
+    prelude::v1_0::*,
+    loader::{LibloadingLoader, LIBRARY},
+};
+
+use log::info;
+use caliban::prelude::{get_graphics_family_index, DeviceLimits, VALIDATION_LAYER};
+
+/// Log at the info level, unless RUST_LOG says otherwise.
+pub fn init_logging() {
+    if std::env::var_os("RUST_LOG").is_none() {
+        std::env::set_var("RUST_LOG", "info");
+    }
+    pretty_env_logger::init();
+}
+
+/// Vulkan instance and device, without a surface.
+pub struct Headless {
+    /// Vulkan entry point, which keeps the loader library
+    /// loaded for as long as the instance lives.
+    pub entry: Entry,
+    pub instance: Instance,
+    pub physical_device: vk::PhysicalDevice,
+    /// Queue family of the device's only queue, a graphics one.
+    pub graphics_queue_family: u32,
+    /// Limits of the physical device, for the allocator.
+    pub limits: DeviceLimits,
+    pub device: Device,
+}
+
+impl Headless {
+    /// Create an instance with the validation layer, and a
+    /// device with a single queue on the first physical device
+    /// that has a graphics queue family. `application_name`
+    /// must be null-terminated.
+    pub fn new(application_name: &[u8]) -> Self {
+        // Vulkan entry point
+        let entry = unsafe {
+            let loader = LibloadingLoader::new(LIBRARY).unwrap();
+            Entry::new(loader).unwrap()
+        };
+
+        // Application info and validation layers
+        let application_info = vk::ApplicationInfo::builder()
+            .application_name(application_name)
+            .application_version(vk::make_version(1, 0, 0))
+            .api_version(vk::make_version(1, 3, 0));
+
+        let layers = [VALIDATION_LAYER.as_ptr()];
+
+        let info = vk::InstanceCreateInfo::builder()
+            .application_info(&application_info)
+            .enabled_layer_names(&layers);
+
+        // Vulkan instance
+        let instance = unsafe { entry.create_instance(&info, None).unwrap() };
+
+        // Physical device
+        let (physical_device, graphics_queue_family) = unsafe {
+            instance
+                .enumerate_physical_devices()
+                .unwrap()
+                .iter()
+                .find_map(|&physical_device| {
+                    get_graphics_family_index(&instance, physical_device)
+                        .ok()
+                        .map(|queue_index| (physical_device, queue_index))
+                })
+                .unwrap()
+        };
+
+        // Logical device
+        let priorities = &[1.0];
+        let graphics_queues = &[
+            vk::DeviceQueueCreateInfo::builder()
+                .queue_family_index(graphics_queue_family)
+                .queue_priorities(priorities)
+        ];
+
+        let create_info = vk::DeviceCreateInfo::builder()
+            .queue_create_infos(graphics_queues)
+            .enabled_layer_names(&layers);
+
+        let device = unsafe { instance.create_device(physical_device, &create_info, None).unwrap() };
+        info!("Created device.");
+        let limits = DeviceLimits::new(&instance, physical_device);
+
+        Self {
+            entry,
+            instance,
+            physical_device,
+            graphics_queue_family,
+            limits,
+            device,
+        }
+    }
+
+    /// Destroy the device, then the instance. Everything
+    /// created from the device must be gone.
+    pub unsafe fn destroy(self) {
+        self.device.destroy_device(None);
+        self.instance.destroy_instance(None);
+    }
+}
@@ -0,0 +1,71 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    process::ExitCode,
+    time::Duration,
+};
+
+use caliban::prelude::*;
+use log::{error, info};
+
+/// Frame whose update panics.
+const PANIC_FRAME: u64 = 10;
+
+/// Panics in the update hook after a few frames, with the
+/// validation layer on, and checks that the renderer was torn
+/// down cleanly on the way out: no validation error, and no
+/// Vulkan object left alive (the renderer logs those as it is
+/// destroyed). The exit code is non-zero if anything was
+/// reported, so that this can run in CI on a machine with the
+/// Vulkan SDK installed.
+struct PanicInUpdate {
+    frames: u64,
+}
+
+impl AppHooks for PanicInUpdate {
+    fn update(&mut self, _context: &mut Context, _dt: Duration) {
+        self.frames += 1;
+        if self.frames == PANIC_FRAME {
+            panic!("Deliberate panic in the update hook, at frame {}.", self.frames);
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    // Log at the info level, unless RUST_LOG says otherwise.
+    if std::env::var_os("RUST_LOG").is_none() {
+        std::env::set_var("RUST_LOG", "info");
+    }
+    pretty_env_logger::init();
+
+    let mut config = AppConfig {
+        continuous_redraw: true,
+        ..Default::default()
+    };
+    config.renderer.validation = true;
+    config.renderer.validation_required = true;
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| run(config, PanicInUpdate { frames: 0 })));
+    match result {
+        Err(_) => info!("The panic unwound out of the application, as expected."),
+        Ok(Ok(())) => {
+            error!("The application exited without panicking.");
+            return ExitCode::FAILURE;
+        }
+        Ok(Err(error)) => {
+            error!("The application failed: {:#}", error);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let errors = take_validation_errors();
+    if !errors.is_empty() {
+        for message in &errors {
+            error!("{}", message);
+        }
+        error!("{} validation errors while shutting down.", errors.len());
+        return ExitCode::FAILURE;
+    }
+
+    info!("Shut down cleanly.");
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,112 @@
+use std::path::PathBuf;
+
+use caliban::prelude::*;
+use anyhow::Result;
+use log::info;
+use winit::{
+    application::ApplicationHandler,
+    dpi::LogicalSize,
+    event::WindowEvent,
+    event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
+    window::{Window, WindowId},
+};
+
+/// Number of frames rendered; the screenshot is taken from the
+/// last one.
+const FRAME_COUNT: u64 = 3;
+
+/// Renders a few frames with an animated clear color, saves a
+/// screenshot of the last one, and exits: the kind of run an
+/// automated visual test does, comparing the PNG against a
+/// reference. The path of the PNG is the first argument, or
+/// `screenshot.png`.
+struct Screenshot {
+    path: PathBuf,
+    window: Option<Window>,
+    renderer: Option<Renderer>,
+    frames: u64,
+}
+
+impl Screenshot {
+    unsafe fn render(&mut self, event_loop: &ActiveEventLoop) -> Result<()> {
+        let Some(renderer) = &mut self.renderer else {
+            return Ok(());
+        };
+
+        // The screenshot is requested before rendering the
+        // frame it is taken from, and written by `render`.
+        self.frames += 1;
+        if self.frames == FRAME_COUNT {
+            renderer.capture_screenshot(&self.path)?;
+        }
+
+        match renderer.render(FrameTime::default()) {
+            Ok(()) => {}
+            Err(RenderError::OutOfDate) => renderer.recreate_swapchain(self.window.as_ref().unwrap())?,
+            Err(error) => return Err(error.into()),
+        }
+
+        if self.frames >= FRAME_COUNT {
+            info!("Rendered {} frames.", self.frames);
+            renderer.wait_idle();
+            renderer.destroy();
+            self.renderer = None;
+            event_loop.exit();
+        }
+
+        Ok(())
+    }
+}
+
+impl ApplicationHandler for Screenshot {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        if self.window.is_some() {
+            return;
+        }
+
+        let attributes = Window::default_attributes()
+            .with_title("caliban screenshot")
+            .with_inner_size(LogicalSize::new(640, 480));
+        let window = event_loop.create_window(attributes).unwrap();
+
+        let mut renderer = unsafe { Renderer::create(&window, RendererConfig::default()).unwrap() };
+        renderer.set_animated_clear(|frame| [frame as f32 / FRAME_COUNT as f32, 0.3, 0.6, 1.0]);
+
+        self.renderer = Some(renderer);
+        self.window = Some(window);
+    }
+
+    fn window_event(&mut self, event_loop: &ActiveEventLoop, _id: WindowId, event: WindowEvent) {
+        if let WindowEvent::RedrawRequested = event {
+            unsafe { self.render(event_loop).unwrap() };
+        }
+    }
+
+    fn about_to_wait(&mut self, _event_loop: &ActiveEventLoop) {
+        if let Some(window) = &self.window {
+            window.request_redraw();
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    // Log at the info level, unless RUST_LOG says otherwise.
+    if std::env::var_os("RUST_LOG").is_none() {
+        std::env::set_var("RUST_LOG", "info");
+    }
+    pretty_env_logger::init();
+
+    let path = std::env::args().nth(1).unwrap_or_else(|| "screenshot.png".to_string());
+    let mut app = Screenshot {
+        path: PathBuf::from(path),
+        window: None,
+        renderer: None,
+        frames: 0,
+    };
+
+    let event_loop = EventLoop::new()?;
+    event_loop.set_control_flow(ControlFlow::Poll);
+    event_loop.run_app(&mut app)?;
+
+    Ok(())
+}
@@ -0,0 +1,84 @@
+mod common;
+
+use std::time::Instant;
+
+use vulkanalia::prelude::v1_0::*;
+
+use log::info;
+use common::Headless;
+use caliban::prelude::{
+    Allocator,
+    Lifetime,
+    MemoryUse,
+    ResourceCategory,
+    ResourceType,
+};
+
+/// Number of tiny allocations made for each method.
+const ALLOCATION_COUNT: usize = 100_000;
+/// Size of each allocation, typical of a per-object uniform
+/// block.
+const ALLOCATION_SIZE: u64 = 48;
+
+fn main() {
+    common::init_logging();
+
+    let vulkan = Headless::new(b"small-alloc-bench\0");
+    let (instance, device) = (&vulkan.instance, &vulkan.device);
+    let physical_device = vulkan.physical_device;
+
+    // The memory requirements of a tiny uniform buffer, which
+    // every allocation below reuses.
+    let buffer_info = vk::BufferCreateInfo::builder()
+        .size(ALLOCATION_SIZE)
+        .usage(vk::BufferUsageFlags::UNIFORM_BUFFER)
+        .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+    let requirements = unsafe {
+        let buffer = device.create_buffer(&buffer_info, None).unwrap();
+        let requirements = device.get_buffer_memory_requirements(buffer);
+        device.destroy_buffer(buffer, None);
+        requirements
+    };
+
+    for pooling in [false, true] {
+        let mut allocator = Allocator::new(instance, physical_device, vulkan.limits);
+        allocator.set_small_allocation_pooling(pooling);
+
+        let start = Instant::now();
+        let allocations = (0..ALLOCATION_COUNT)
+            .map(|_| allocator.allocate(
+                device,
+                requirements,
+                MemoryUse::CpuToGpu,
+                ResourceType::Linear,
+                Lifetime::Persistent,
+                ResourceCategory::Uniform,
+            ).unwrap())
+            .collect::<Vec<_>>();
+        let allocate = start.elapsed();
+
+        let blocks = allocator.layout();
+        let chunks = blocks.iter().map(|b| b.chunks.len()).sum::<usize>();
+
+        let start = Instant::now();
+        allocations.into_iter().for_each(|a| allocator.free(a));
+        let free = start.elapsed();
+
+        info!(
+            "Pooling {}: {} allocations in {:?}, freed in {:?}, {} blocks, {} chunks.",
+            if pooling { "on" } else { "off" },
+            ALLOCATION_COUNT,
+            allocate,
+            free,
+            blocks.len(),
+            chunks,
+        );
+
+        allocator.destroy(device);
+    }
+
+    unsafe {
+        vulkan.destroy();
+    }
+}
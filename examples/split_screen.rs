@@ -0,0 +1,78 @@
+use caliban::prelude::*;
+use anyhow::Result;
+use glam::{vec3, Mat4};
+use vulkanalia::prelude::v1_0::*;
+
+/// Colors the views are cleared to, in order.
+const VIEW_COLORS: [[f32; 4]; 4] = [
+    [0.8, 0.2, 0.2, 1.0],
+    [0.2, 0.6, 0.2, 1.0],
+    [0.2, 0.3, 0.8, 1.0],
+    [0.8, 0.7, 0.2, 1.0],
+];
+
+/// Splits the window into views, two by default or as many as
+/// the first argument says (up to 4), and clears each one to
+/// its own color; the device time of each view is logged every
+/// second. A real application would draw its scene in the view
+/// pass instead, with the constants of the view.
+struct SplitScreen {
+    count: usize,
+    frames: u64,
+}
+
+impl AppHooks for SplitScreen {
+    fn update(&mut self, context: &mut Context, _dt: std::time::Duration) {
+        // The views follow the size of the window.
+        let views = split_screen(context.renderer.extent(), self.count)
+            .into_iter()
+            .enumerate()
+            .map(|(i, rect)| {
+                let eye = vec3(i as f32 * 2.0, 1.0, 5.0);
+                View::new(rect, Mat4::look_at_rh(eye, vec3(0.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0)))
+            })
+            .collect();
+        context.renderer.set_views(views).unwrap();
+
+        self.frames += 1;
+        if self.frames % 60 == 0 {
+            log::info!("View timings: {:?}", context.renderer.view_timings());
+        }
+    }
+
+    fn render(&mut self, frame: &mut FrameContext) {
+        frame.view_pass("clear view", |command_buffer, device, index, view| {
+            let [r, g, b, a] = VIEW_COLORS[index % VIEW_COLORS.len()];
+            let clear = vk::ClearAttachment::builder()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .color_attachment(0)
+                .clear_value(vk::ClearValue { color: vk::ClearColorValue { float32: [r, g, b, a] } });
+            let rect = vk::ClearRect::builder()
+                .rect(view.rect)
+                .base_array_layer(0)
+                .layer_count(1);
+
+            unsafe { device.cmd_clear_attachments(command_buffer, &[clear], &[rect]) };
+        });
+    }
+}
+
+fn main() -> Result<()> {
+    // Log at the info level, unless RUST_LOG says otherwise.
+    if std::env::var_os("RUST_LOG").is_none() {
+        std::env::set_var("RUST_LOG", "info");
+    }
+    pretty_env_logger::init();
+
+    let count = std::env::args()
+        .nth(1)
+        .and_then(|arg| arg.parse().ok())
+        .unwrap_or(2usize)
+        .clamp(1, VIEW_COLORS.len());
+    let config = AppConfig {
+        continuous_redraw: true,
+        ..Default::default()
+    };
+
+    run(config, SplitScreen { count, frames: 0 })
+}
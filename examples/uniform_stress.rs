@@ -0,0 +1,132 @@
+mod common;
+
+use std::time::Instant;
+
+use vulkanalia::prelude::v1_0::*;
+
+use log::info;
+use common::Headless;
+use caliban::prelude::{
+    Allocator,
+    DynamicBinding,
+    Lifetime,
+    MemoryUse,
+    ResourceCategory,
+};
+
+/// Number of per-object uniform blocks updated each frame.
+const OBJECT_COUNT: usize = 1000;
+/// Number of simulated frames for each method.
+const FRAME_COUNT: usize = 1000;
+
+/// Per-object uniform block: a model matrix and a color.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ObjectUniform {
+    model: glam::Mat4,
+    color: glam::Vec4,
+}
+
+fn main() {
+    common::init_logging();
+
+    let vulkan = Headless::new(b"uniform-stress\0");
+    let (instance, device) = (&vulkan.instance, &vulkan.device);
+    let physical_device = vulkan.physical_device;
+
+    // Uniform buffer holding every object's block, each one at
+    // a valid dynamic offset.
+    let mut allocator = Allocator::new(instance, physical_device, vulkan.limits);
+    let stride = vulkan.limits.dynamic_stride(
+        std::mem::size_of::<ObjectUniform>() as u64,
+        DynamicBinding::Uniform,
+    );
+    let size = stride * OBJECT_COUNT as u64;
+
+    let buffer_info = vk::BufferCreateInfo::builder()
+        .size(size)
+        .usage(vk::BufferUsageFlags::UNIFORM_BUFFER)
+        .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+    let buffer = unsafe { device.create_buffer(&buffer_info, None).unwrap() };
+    let requirements = unsafe { device.get_buffer_memory_requirements(buffer) };
+
+    let objects = (0..OBJECT_COUNT)
+        .map(|i| ObjectUniform {
+            model: glam::Mat4::from_translation(glam::vec3(i as f32, 0.0, 0.0)),
+            color: glam::Vec4::ONE,
+        })
+        .collect::<Vec<_>>();
+
+    // Persistent mapping: write through the retained pointer,
+    // and flush the whole range once per frame if the memory
+    // is not coherent.
+    let allocation = allocator.allocate_dynamic(
+        device,
+        requirements,
+        MemoryUse::CpuToGpu,
+        DynamicBinding::Uniform,
+        Lifetime::Persistent,
+        ResourceCategory::Uniform,
+    ).unwrap();
+    info!("Uniform memory coherent: {}", allocation.coherent);
+
+    let start = Instant::now();
+    for _ in 0..FRAME_COUNT {
+        for (i, object) in objects.iter().enumerate() {
+            unsafe { allocation.write(i as u64 * stride, std::slice::from_ref(object)) };
+        }
+        allocator.flush(device, &allocation, 0, size).unwrap();
+    }
+    let persistent = start.elapsed();
+
+    // Map/unmap on every update, on a separate memory object
+    // (memory that is already mapped can't be mapped again).
+    let memory_properties = unsafe { instance.get_physical_device_memory_properties(physical_device) };
+    let memory_type = memory_properties.memory_types
+        .iter()
+        .enumerate()
+        .position(|(index, memory)| {
+            requirements.memory_type_bits & (1 << index) != 0
+                && memory.property_flags.contains(
+                    vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT
+                )
+        })
+        .unwrap();
+
+    let memory_info = vk::MemoryAllocateInfo::builder()
+        .allocation_size(size)
+        .memory_type_index(memory_type as u32);
+    let memory = unsafe { device.allocate_memory(&memory_info, None).unwrap() };
+
+    let start = Instant::now();
+    for _ in 0..FRAME_COUNT {
+        for (i, object) in objects.iter().enumerate() {
+            unsafe {
+                let ptr = device
+                    .map_memory(memory, i as u64 * stride, stride, vk::MemoryMapFlags::empty())
+                    .unwrap();
+                std::ptr::copy_nonoverlapping(object, ptr.cast(), 1);
+                device.unmap_memory(memory);
+            }
+        }
+    }
+    let map_unmap = start.elapsed();
+
+    info!(
+        "{} objects, {} frames: persistent mapping {:?}/frame, map/unmap {:?}/frame.",
+        OBJECT_COUNT,
+        FRAME_COUNT,
+        persistent / FRAME_COUNT as u32,
+        map_unmap / FRAME_COUNT as u32,
+    );
+
+    allocator.free(allocation);
+    allocator.destroy(device);
+
+    unsafe {
+        device.free_memory(memory, None);
+        device.destroy_buffer(buffer, None);
+        vulkan.destroy();
+    }
+}
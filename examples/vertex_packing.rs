@@ -0,0 +1,29 @@
+use log::info;
+use caliban::prelude::{shapes, VertexPacking};
+
+fn main() {
+    // Log at the info level, unless RUST_LOG says otherwise.
+    if std::env::var_os("RUST_LOG").is_none() {
+        std::env::set_var("RUST_LOG", "info");
+    }
+    pretty_env_logger::init();
+
+    // A dense sphere, standing in for a large static mesh.
+    let (vertices, indices) = shapes::uv_sphere(1.0, 512, 1024);
+    info!("{} vertices, {} indices.", vertices.len(), indices.len());
+
+    // The vertex buffer size in each format; the vertices are
+    // read once per draw (modulo the post-transform cache), so
+    // this is also the vertex fetch bandwidth of a draw.
+    let full = VertexPacking::Full.pack(&vertices).len();
+    let packed = VertexPacking::Packed.pack(&vertices).len();
+
+    info!(
+        "Full: {} bytes ({} B/vertex), packed: {} bytes ({} B/vertex), {:.1}% of the full size.",
+        full,
+        VertexPacking::Full.stride(),
+        packed,
+        VertexPacking::Packed.stride(),
+        100.0 * packed as f64 / full as f64,
+    );
+}
@@ -1,39 +1,572 @@
-use crate::renderer::Renderer;
-use winit::window::Window;
+mod mode;
+mod startup;
+
+pub use mode::WindowMode;
+pub use startup::{draw_progress_bar, LoadingProgress, StartupTasks, STARTUP_FRAME_BUDGET};
+
+use std::{
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    assets::Assets,
+    config::RendererConfig,
+    core::frame::FrameToken,
+    renderer::{is_retryable, is_surface_lost, Renderer, SwapchainError},
+    stats::{FrameTime, MAX_FRAME_DT},
+    throttle::PresentationThrottle,
+    view::View,
+};
+use winit::{
+    event::WindowEvent,
+    event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
+    window::Window,
+};
+use vulkanalia::prelude::v1_0::*;
 use anyhow::Result;
+use log::*;
+
+/// Number of consecutive frame timeouts tolerated before giving
+/// up on the renderer.
+pub const MAX_TIMEOUT_RETRIES: u32 = 3;
+
+/// Number of consecutive swapchain recreation failures
+/// tolerated before giving up.
+pub const MAX_RECREATE_RETRIES: u32 = 6;
+
+/// Delay before the first retry of a failed swapchain
+/// recreation; it doubles with each failure.
+pub const RECREATE_BACKOFF: Duration = Duration::from_millis(10);
+
+/// Configuration of the application window.
+#[derive(Clone, Debug)]
+pub struct AppConfig {
+    /// Title of the window.
+    pub title: String,
+    /// Logical size of the window, in pixels. By default, the
+    /// window covers 60% of the primary monitor.
+    pub size: Option<(u32, u32)>,
+    /// File where the window position and size are saved on
+    /// exit, and restored from at the next start. Nothing is
+    /// saved by default.
+    pub geometry_file: Option<PathBuf>,
+    /// How the window is shown at startup; F11 then toggles
+    /// between windowed and borderless fullscreen.
+    pub window_mode: WindowMode,
+    /// Whether to render continuously, as fast as presentation
+    /// allows, instead of only when the window needs to be
+    /// redrawn. Applications with animations want this, while
+    /// tools that only change on input keep the default
+    /// event-driven mode, which doesn't use the CPU and GPU
+    /// while idle.
+    pub continuous_redraw: bool,
+    /// Whether to show the frame rate in the window title,
+    /// updated every second.
+    pub stats_in_title: bool,
+    /// Configuration of the renderer.
+    pub renderer: RendererConfig,
+    /// Mount points assets are looked up in, completed with
+    /// `CALIBAN_ASSET_PATH`.
+    pub assets: Assets,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            title: "caliban".to_string(),
+            size: None,
+            geometry_file: None,
+            window_mode: WindowMode::Windowed,
+            continuous_redraw: false,
+            stats_in_title: false,
+            renderer: RendererConfig::default(),
+            assets: Assets::default(),
+        }
+    }
+}
+
+/// State available to the application when updating.
+pub struct Context<'a> {
+    pub renderer: &'a mut Renderer,
+    pub window: &'a Window,
+    pub assets: &'a Assets,
+    /// Frame in flight being prepared.
+    pub frame: FrameToken,
+    /// Time step and application time of the frame.
+    pub time: FrameTime,
+}
+
+/// State available to the application when rendering a frame.
+pub struct FrameContext<'a> {
+    pub renderer: &'a mut Renderer,
+    /// Frame in flight being rendered.
+    pub frame: FrameToken,
+    /// Time step and application time of the frame.
+    pub time: FrameTime,
+}
+
+impl FrameContext<'_> {
+    /// Record commands of the application in this frame: `pass`
+    /// is called while the frame's command buffer is recorded,
+    /// with that buffer and the device. Passes run in the order
+    /// they were added, after the renderer's own commands, and
+    /// are wrapped in a debug label named `name` when the
+    /// validation layer is on.
+    ///
+    /// On entry, the command buffer is inside the main
+    /// rendering scope (dynamic rendering), with the swapchain
+    /// image as its only color attachment, in
+    /// COLOR_ATTACHMENT_OPTIMAL layout, and no depth
+    /// attachment. No pipeline, descriptor set, vertex or index
+    /// buffer is bound, and the dynamic state (viewport,
+    /// scissor, etc) is undefined: the pass sets everything it
+    /// uses. It must not end the rendering scope nor record
+    /// commands that are invalid inside one (barriers on other
+    /// resources, copies), and the next pass can't rely on
+    /// anything it binds or sets. Synchronizing resources the
+    /// pass reads or writes with the rest of the frame is up to
+    /// the application.
+    pub fn custom_pass(
+        &mut self,
+        name: impl Into<String>,
+        pass: impl FnOnce(vk::CommandBuffer, &Device) + 'static,
+    ) {
+        self.renderer.add_custom_pass(name, pass);
+    }
+
+    /// Record commands of the application in each view of this
+    /// frame (see [`Renderer::set_views`]): `pass` is called
+    /// once per view, with the index of the view and the view
+    /// itself, to draw the scene as seen from its camera. View
+    /// passes run after the renderer's own commands and before
+    /// the custom passes, which draw over the whole image
+    /// (an interface spanning the views, for example).
+    ///
+    /// On entry, the state is the same as for
+    /// [`FrameContext::custom_pass`], except that the viewport
+    /// and scissor are set to the view's rectangle (with the
+    /// viewport flipped like everywhere else). The constants of
+    /// the view (see [`View::constants`](crate::view::View::constants))
+    /// are meant to be stored for every view in a uniform
+    /// buffer, and bound at the offset given by
+    /// [`view_constants_offset`](crate::view::view_constants_offset)
+    /// for the view's index. There is no depth attachment, so
+    /// nothing to clear between views.
+    pub fn view_pass(
+        &mut self,
+        name: impl Into<String>,
+        pass: impl Fn(vk::CommandBuffer, &Device, usize, &View) + 'static,
+    ) {
+        self.renderer.add_view_pass(name, pass);
+    }
+}
+
+/// Callbacks through which an application plugs into the main
+/// loop. Every callback has a default that does nothing, so
+/// only the relevant ones need to be implemented.
+pub trait AppHooks {
+    /// Called once, right after the renderer is created. This
+    /// blocks the window, so only what is needed for the first
+    /// frame should be created here, the rest going into
+    /// [`AppHooks::startup`].
+    fn init(&mut self, _renderer: &mut Renderer, _assets: &Assets) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called once after [`AppHooks::init`], to add the tasks
+    /// run while the loading screen is shown.
+    fn startup(&mut self, _tasks: &mut StartupTasks) {}
+
+    /// Called every frame while the startup tasks run, instead
+    /// of `update` and `render`, to draw the loading screen. By
+    /// default, this draws a progress bar.
+    fn loading_progress(&mut self, frame: &mut FrameContext, progress: &LoadingProgress) {
+        draw_progress_bar(frame.renderer, progress.fraction());
+    }
+
+    /// Called every frame before rendering, with the time
+    /// elapsed since the previous update (the same as
+    /// `context.time.dt`).
+    fn update(&mut self, _context: &mut Context, _dt: Duration) {}
+
+    /// Called every frame, right before the renderer records
+    /// and submits it.
+    fn render(&mut self, _frame: &mut FrameContext) {}
+
+    /// Called for every window event, before the application
+    /// handles it.
+    fn event(&mut self, _event: &WindowEvent) {}
+}
+
+/// The unit type is the application that does nothing more
+/// than what the renderer does on its own.
+impl AppHooks for () {}
 
 /// Main application struct, which holds the renderer and the
 /// window.
 pub struct App {
     pub renderer: Option<Renderer>,
     pub window: Option<Window>,
-    pub minimised: bool,
+    /// Configuration the window is created with.
+    pub config: AppConfig,
+    /// User callbacks.
+    pub hooks: Box<dyn AppHooks>,
+    /// Asset resolver, used by every loader.
+    pub assets: Assets,
+    /// Tasks left to run before the application starts
+    /// updating.
+    pub startup: StartupTasks,
+    /// Whether the window can currently be presented to, and
+    /// at which rate.
+    pub presentation: PresentationThrottle,
     pub resized: bool,
+    /// Number of consecutive frames that timed out.
+    pub timeouts: u32,
+    /// Errors of the consecutive failed swapchain recreations.
+    pub recreate_errors: Vec<String>,
+    /// Time of the next swapchain recreation attempt, after a
+    /// failure; frames are skipped until then.
+    pub recreate_retry_at: Option<Instant>,
+    /// Time of the last update.
+    pub last_update: Option<Instant>,
+    /// Time of the current frame.
+    pub time: FrameTime,
+    /// Time the window title was last updated with the frame
+    /// statistics.
+    pub last_title_update: Option<Instant>,
+    /// How the window is currently shown.
+    pub window_mode: WindowMode,
+    /// Mode requested while the window was minimised, applied
+    /// once it is restored.
+    pub pending_window_mode: Option<WindowMode>,
+    /// Error that stopped the application, returned by
+    /// [`run`].
+    pub error: Option<anyhow::Error>,
 }
 
 impl App {
-    pub fn new() -> Self {
+    /// Switch to continuous rendering (see
+    /// [`AppConfig::continuous_redraw`]).
+    pub fn with_continuous_redraw(mut self, continuous: bool) -> Self {
+        self.config.continuous_redraw = continuous;
+        self
+    }
+
+    pub fn new(config: AppConfig, hooks: impl AppHooks + 'static) -> Self {
         App {
+            assets: config.assets.clone().with_env_overrides(),
+            startup: StartupTasks::default(),
             renderer: None,
             window: None,
-            minimised: false,
+            config,
+            hooks: Box::new(hooks),
+            presentation: PresentationThrottle::new(),
             resized: false,
+            timeouts: 0,
+            recreate_errors: Vec::new(),
+            recreate_retry_at: None,
+            last_update: None,
+            time: FrameTime::default(),
+            last_title_update: None,
+            window_mode: WindowMode::Windowed,
+            pending_window_mode: None,
+            error: None,
         }
     }
 
     /// Initialize the application with the given window handle
     /// and a new Vulkan renderer.
     pub fn init(&mut self, window: Window) -> Result<()> {
-        let renderer = unsafe { Renderer::create(&window)? };
+        let mut renderer = unsafe { Renderer::create(&window, self.config.renderer.clone())? };
+        self.hooks.init(&mut renderer, &self.assets)?;
+        self.hooks.startup(&mut self.startup);
+        if !self.startup.is_done() {
+            info!("Running {} startup tasks.", self.startup.progress().total);
+        }
+
         self.renderer = Some(renderer);
         self.window = Some(window);
 
         Ok(())
     }
 
+    /// Run the user update and render callbacks for a new
+    /// frame, or the next startup tasks and the loading screen
+    /// if they are not done. Fails if a startup task failed.
+    pub fn update(&mut self) -> Result<()> {
+        let (Some(renderer), Some(window)) = (&mut self.renderer, &self.window) else {
+            return Ok(());
+        };
+
+        // The startup tasks run a few at a time, between
+        // frames of the loading screen, so that the window keeps
+        // handling events and being redrawn in the meantime.
+        if !self.startup.is_done() {
+            self.startup.run(renderer, &self.assets, STARTUP_FRAME_BUDGET)?;
+
+            let frame = renderer.frame_token();
+            let time = self.time;
+            let progress = self.startup.progress();
+            self.hooks.loading_progress(&mut FrameContext { renderer, frame, time }, &progress);
+
+            if self.startup.is_done() {
+                info!("Startup tasks done.");
+            }
+
+            // The time spent loading is not part of the
+            // application time step.
+            self.last_update = None;
+            return Ok(());
+        }
+
+        // The time step is clamped, so that a long pause makes
+        // the application time stall rather than jump.
+        let now = Instant::now();
+        let dt = self.last_update.map_or(Duration::ZERO, |last| (now - last).min(MAX_FRAME_DT));
+        self.last_update = Some(now);
+        self.time = FrameTime { dt, elapsed: self.time.elapsed + dt };
+
+        let frame = renderer.frame_token();
+        let assets = &self.assets;
+        let time = self.time;
+        self.hooks.update(&mut Context { renderer: &mut *renderer, window, assets, frame, time }, dt);
+        self.hooks.render(&mut FrameContext { renderer, frame, time });
+
+        Ok(())
+    }
+
+    /// Show the frame statistics in the window title, if
+    /// enabled, at most once per second.
+    pub fn update_title(&mut self) {
+        if !self.config.stats_in_title {
+            return;
+        }
+
+        let (Some(renderer), Some(window)) = (&self.renderer, &self.window) else {
+            return;
+        };
+
+        let now = Instant::now();
+        if self.last_title_update.is_some_and(|last| now - last < Duration::from_secs(1)) {
+            return;
+        }
+
+        let stats = renderer.stats();
+        window.set_title(&format!(
+            "{} - {:.0} fps ({:.2} ms)",
+            self.config.title, stats.fps, stats.frame_time_ms,
+        ));
+        self.last_title_update = Some(now);
+    }
+
+    /// Switch the window to `mode`. The swapchain is rebuilt
+    /// for the new size of the surface on the next frame, and
+    /// the viewport follows from its extent. Some platforms
+    /// refuse to change a minimised window, or restore it on the
+    /// spot; the change is then deferred until it is restored.
+    pub fn set_window_mode(&mut self, mode: WindowMode) {
+        let Some(window) = &self.window else {
+            return;
+        };
+
+        if window.is_minimized() == Some(true) {
+            debug!("Window minimised, switching to {:?} once restored.", mode);
+            self.pending_window_mode = Some(mode);
+            return;
+        }
+
+        info!("Switching the window to {:?}.", mode);
+        window.set_fullscreen(mode::fullscreen(window, mode));
+        self.window_mode = mode;
+        self.pending_window_mode = None;
+
+        // Not every platform sends a resize event when going
+        // fullscreen at the same size (a maximised borderless
+        // window, for example), so the swapchain is recreated
+        // either way.
+        self.resized = true;
+        window.request_redraw();
+    }
+
+    /// Keep the window mode in sync with the window, which the
+    /// user or the system may have taken out of fullscreen on
+    /// its own, and apply a mode requested while minimised.
+    pub fn sync_window_mode(&mut self) {
+        let Some(window) = &self.window else {
+            return;
+        };
+
+        if window.fullscreen().is_none() && self.window_mode != WindowMode::Windowed {
+            self.window_mode = WindowMode::Windowed;
+        }
+
+        if window.is_minimized() != Some(true) {
+            if let Some(mode) = self.pending_window_mode {
+                self.set_window_mode(mode);
+            }
+        }
+    }
+
+    /// Recreate the swapchain for the current window size. The
+    /// renderer is the one source of truth for whether the
+    /// window can be presented to, so the minimised state
+    /// follows from it.
+    ///
+    /// If recreation fails with a retryable error, it is
+    /// retried with an exponential backoff, `resized` staying
+    /// set (and frames being skipped) in the meantime; an error
+    /// is returned once the failure is fatal or the retries are
+    /// exhausted.
+    pub fn recreate_swapchain(&mut self) -> Result<(), SwapchainError> {
+        let now = Instant::now();
+        if self.recreate_retry_at.is_some_and(|retry_at| now < retry_at) {
+            return Ok(());
+        }
+
+        let (Some(renderer), Some(window)) = (&mut self.renderer, &self.window) else {
+            self.resized = false;
+            return Ok(());
+        };
+
+        match unsafe { renderer.recreate_swapchain(window) } {
+            Ok(()) => {
+                self.presentation.set_minimised(!renderer.can_present());
+                self.recreate_errors.clear();
+                self.recreate_retry_at = None;
+                self.resized = false;
+
+                Ok(())
+            },
+            // Docking or undocking may lose the surface right
+            // when the window is resized; there is no point in
+            // retrying with it then.
+            Err(error) if is_surface_lost(&error) => self.recreate_surface(),
+            Err(error) => {
+                self.recreate_errors.push(format!("{:#}", error));
+
+                let failures = self.recreate_errors.len() as u32;
+                if !is_retryable(&error) {
+                    return Err(SwapchainError::Fatal(error));
+                }
+
+                if failures > MAX_RECREATE_RETRIES {
+                    return Err(SwapchainError::RetriesExhausted {
+                        attempts: failures,
+                        errors: std::mem::take(&mut self.recreate_errors),
+                    });
+                }
+
+                // Retrying right away would just spin on the
+                // driver, so each failure doubles the delay
+                // before the next attempt.
+                let backoff = RECREATE_BACKOFF * 2u32.pow(failures - 1);
+                warn!(
+                    "Failed to recreate the swapchain (attempt {}), retrying in {:?}: {:#}",
+                    failures, backoff, error,
+                );
+                self.recreate_retry_at = Some(now + backoff);
+
+                Ok(())
+            },
+        }
+    }
+
+    /// Recreate the surface of the window, and the swapchain
+    /// with it, after the renderer reported it lost. A failure
+    /// is fatal: the window can't be presented to anymore.
+    pub fn recreate_surface(&mut self) -> Result<(), SwapchainError> {
+        let (Some(renderer), Some(window)) = (&mut self.renderer, &self.window) else {
+            return Ok(());
+        };
+
+        unsafe { renderer.recreate_surface(window) }.map_err(SwapchainError::Fatal)?;
+        self.presentation.set_minimised(!renderer.can_present());
+        self.recreate_errors.clear();
+        self.recreate_retry_at = None;
+        self.resized = false;
+
+        Ok(())
+    }
+
     pub fn destroy(&mut self) {
         if let Some(mut renderer) = self.renderer.take() {
             unsafe { renderer.destroy() };
         }
     }
-}
\ No newline at end of file
+
+    /// Stop the application on an error it can't carry on
+    /// after (a failed startup task, for example): the error is
+    /// logged, the renderer destroyed, and the event loop
+    /// exited, after which [`run`] returns the error.
+    pub fn fail(&mut self, event_loop: &ActiveEventLoop, error: anyhow::Error) {
+        error!("{:#}", error);
+        self.destroy();
+        self.error = Some(error);
+        event_loop.exit();
+    }
+
+    /// Run `f`, which calls into the application (its hooks, or
+    /// the passes it added to the frame), and if it panics,
+    /// destroy the renderer before carrying on unwinding.
+    ///
+    /// A panic can leave the frame half-prepared, but nothing
+    /// has been submitted for it yet, and the frames in flight
+    /// are complete once the device is idle; so the renderer
+    /// can be destroyed as usual, while the application state
+    /// is left alone: the panic is resumed right after, and the
+    /// state is dropped with the application.
+    pub fn guard<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        match panic::catch_unwind(AssertUnwindSafe(|| f(self))) {
+            Ok(value) => value,
+            Err(payload) => {
+                error!("The application panicked, destroying the renderer.");
+                self.destroy();
+                panic::resume_unwind(payload)
+            }
+        }
+    }
+}
+
+// Applications that stop with an error, or that a panic unwinds
+// out of (through `run`, or their own event loop), still tear
+// the renderer down before the window and the hooks are
+// dropped.
+impl Drop for App {
+    fn drop(&mut self) {
+        self.destroy();
+    }
+}
+
+/// Run an application: this creates the event loop and the
+/// window, drives the user hooks, and tears everything down in
+/// order when the window is closed. Errors the application
+/// can't carry on after (a failed startup task, for example)
+/// stop it, and are returned.
+pub fn run(config: AppConfig, hooks: impl AppHooks + 'static) -> Result<()> {
+    // In continuous mode, the event loop polls for events and
+    // carries on rendering instead of sleeping until the next
+    // one.
+    let event_loop = EventLoop::new()?;
+    event_loop.set_control_flow(match config.continuous_redraw {
+        true => ControlFlow::Poll,
+        false => ControlFlow::Wait,
+    });
+
+    let mut app = App::new(config, hooks);
+    event_loop.run_app(&mut app)?;
+
+    // The renderer is normally destroyed when the window is
+    // closed, but the event loop can also stop for other
+    // reasons; destroying is a no-op if it was already done.
+    if let Some(renderer) = &app.renderer {
+        renderer.wait_idle();
+    }
+    app.destroy();
+
+    match app.error.take() {
+        Some(error) => Err(error),
+        None => Ok(()),
+    }
+}
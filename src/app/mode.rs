@@ -0,0 +1,73 @@
+use winit::{
+    monitor::{MonitorHandle, VideoModeHandle},
+    window::{Fullscreen, Window},
+};
+use log::*;
+
+/// How the window is shown.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WindowMode {
+    /// A regular window, with decorations.
+    #[default]
+    Windowed,
+    /// A borderless window covering the monitor it is on, at
+    /// the monitor's current resolution. Switching to and from
+    /// it is instant, and other windows can still be shown on
+    /// top of it.
+    Borderless,
+    /// Exclusive fullscreen, with the monitor switched to a
+    /// video mode of its own. It may present with less latency,
+    /// but switching changes the display mode, which takes a
+    /// moment, and isn't supported everywhere (Wayland, in
+    /// particular, where borderless is used instead).
+    Exclusive,
+}
+
+impl WindowMode {
+    /// The mode F11 switches to from this one.
+    pub fn toggled(self) -> Self {
+        match self {
+            WindowMode::Windowed => WindowMode::Borderless,
+            WindowMode::Borderless | WindowMode::Exclusive => WindowMode::Windowed,
+        }
+    }
+}
+
+/// Fullscreen setting of the window for a mode, on the monitor
+/// the window is currently on.
+pub(crate) fn fullscreen(window: &Window, mode: WindowMode) -> Option<Fullscreen> {
+    let monitor = window.current_monitor();
+
+    match mode {
+        WindowMode::Windowed => None,
+        WindowMode::Borderless => Some(Fullscreen::Borderless(monitor)),
+        WindowMode::Exclusive => {
+            match monitor.as_ref().and_then(best_video_mode) {
+                Some(video_mode) => Some(Fullscreen::Exclusive(video_mode)),
+                None => {
+                    warn!("No video mode to switch to, using borderless fullscreen instead.");
+                    Some(Fullscreen::Borderless(monitor))
+                },
+            }
+        },
+    }
+}
+
+/// Video mode to use for exclusive fullscreen: the monitor's
+/// current resolution, with its current refresh rate if it has
+/// such a mode, or the highest one otherwise. Picking another
+/// resolution would only make the monitor rescale the image;
+/// and with monitors of different refresh rates, the one the
+/// window is on is kept, rather than that of the primary
+/// monitor.
+fn best_video_mode(monitor: &MonitorHandle) -> Option<VideoModeHandle> {
+    let size = monitor.size();
+    let refresh_rate = monitor.refresh_rate_millihertz();
+
+    monitor
+        .video_modes()
+        .filter(|mode| mode.size() == size)
+        .max_by_key(|mode| {
+            (Some(mode.refresh_rate_millihertz()) == refresh_rate, mode.refresh_rate_millihertz(), mode.bit_depth())
+        })
+}
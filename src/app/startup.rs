@@ -0,0 +1,132 @@
+use std::time::{Duration, Instant};
+
+use crate::{assets::Assets, renderer::Renderer};
+use vulkanalia::prelude::v1_0::*;
+use anyhow::{Context as _, Result};
+use log::*;
+
+/// Time spent running startup tasks in each frame of the
+/// loading screen. At least one task runs per frame, however
+/// long it takes.
+pub const STARTUP_FRAME_BUDGET: Duration = Duration::from_millis(8);
+
+type Task = Box<dyn FnOnce(&mut Renderer, &Assets) -> Result<()>>;
+
+/// Work done after the window is shown (loading assets,
+/// warming up pipelines, etc), a few tasks per frame, so that
+/// the window stays responsive while it runs.
+#[derive(Default)]
+pub struct StartupTasks {
+    /// Pending tasks, with their name and priority.
+    tasks: Vec<(i32, String, Task)>,
+    /// Number of tasks run so far.
+    done: usize,
+    /// Name of the last task run.
+    last: Option<String>,
+}
+
+impl StartupTasks {
+    /// Add a task. Tasks with a higher priority run first, and
+    /// tasks of the same priority run in the order they were
+    /// added. The name is shown in the logs and in the
+    /// loading progress.
+    pub fn add(
+        &mut self,
+        name: impl Into<String>,
+        priority: i32,
+        task: impl FnOnce(&mut Renderer, &Assets) -> Result<()> + 'static,
+    ) {
+        // The list is kept sorted by increasing priority, so
+        // that the next task is popped from the end; a new task
+        // goes before the tasks of the same priority, which
+        // were added earlier and run first.
+        let index = self.tasks.partition_point(|(p, _, _)| *p < priority);
+        self.tasks.insert(index, (priority, name.into(), Box::new(task)));
+    }
+
+    /// Whether every task has run.
+    pub fn is_done(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    pub fn progress(&self) -> LoadingProgress {
+        LoadingProgress {
+            done: self.done,
+            total: self.done + self.tasks.len(),
+            last: self.last.clone(),
+        }
+    }
+
+    /// Run tasks until `budget` is spent or none is left. A
+    /// failing task stops the loading, since whatever comes
+    /// after it may depend on it.
+    #[profiling::function]
+    pub(crate) fn run(&mut self, renderer: &mut Renderer, assets: &Assets, budget: Duration) -> Result<()> {
+        let start = Instant::now();
+
+        while let Some((_, name, task)) = self.tasks.pop() {
+            let task_start = Instant::now();
+            task(renderer, assets).with_context(|| format!("Startup task '{}' failed", name))?;
+            debug!("Startup task '{}' done in {:?}.", name, task_start.elapsed());
+
+            self.done += 1;
+            self.last = Some(name);
+
+            if start.elapsed() >= budget {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Progress of the startup tasks, for the loading screen.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LoadingProgress {
+    /// Number of tasks run.
+    pub done: usize,
+    /// Number of tasks, run or not.
+    pub total: usize,
+    /// Name of the last task run.
+    pub last: Option<String>,
+}
+
+impl LoadingProgress {
+    /// Fraction of the tasks run, between 0 and 1.
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 {
+            1.0
+        } else {
+            self.done as f32 / self.total as f32
+        }
+    }
+}
+
+/// Draw the default loading screen: a progress bar across the
+/// middle of the image, on top of the cleared background.
+pub fn draw_progress_bar(renderer: &mut Renderer, fraction: f32) {
+    let extent = renderer.extent();
+    if extent.width == 0 || extent.height == 0 {
+        return;
+    }
+
+    // The bar is at least 4 pixels high, unless the image is
+    // smaller than that.
+    let width = extent.width * 3 / 5;
+    let height = (extent.height / 40).max(4).min(extent.height);
+    let x = ((extent.width - width) / 2) as i32;
+    let y = ((extent.height - height) / 2) as i32;
+
+    let rect = |width: u32| vk::Rect2D {
+        offset: vk::Offset2D { x, y },
+        extent: vk::Extent2D { width, height },
+    };
+
+    renderer.clear_region(rect(width), [0.1, 0.1, 0.1, 1.0]);
+
+    let filled = (width as f32 * fraction.clamp(0.0, 1.0)) as u32;
+    if filled > 0 {
+        renderer.clear_region(rect(filled), [0.8, 0.8, 0.8, 1.0]);
+    }
+}
@@ -0,0 +1,182 @@
+use std::{
+    io,
+    path::{Component, Path, PathBuf},
+};
+
+use thiserror::Error;
+
+use crate::texture::{load_dds, DdsError, DdsTexture};
+
+/// Errors returned when resolving or loading an asset.
+#[derive(Error, Debug)]
+pub enum AssetError {
+    #[error("Invalid asset path {}: {reason}", path.display())]
+    InvalidPath { path: PathBuf, reason: &'static str },
+    #[error("Asset {} not found in any mount point ({mounts}).", path.display())]
+    NotFound { path: PathBuf, mounts: String },
+    #[error("Failed to read asset {} from {}: {source}", path.display(), mount.display())]
+    Io { path: PathBuf, mount: PathBuf, source: io::Error },
+    #[error("Failed to load DDS asset {} from {}: {source}", path.display(), mount.display())]
+    Dds { path: PathBuf, mount: PathBuf, source: DdsError },
+}
+
+/// Resolver of asset paths. Assets are always named by a path
+/// relative to a mount point, and looked up in every mount
+/// point in order, the first one containing the file winning.
+/// This makes loading independent from the working directory,
+/// and lets a mod or development directory shadow the shipped
+/// assets by being mounted first.
+#[derive(Clone, Debug)]
+pub struct Assets {
+    mounts: Vec<PathBuf>,
+}
+
+impl Assets {
+    pub fn new(mounts: impl IntoIterator<Item = impl Into<PathBuf>>) -> Self {
+        Self {
+            mounts: mounts.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Add the mount points listed in `CALIBAN_ASSET_PATH`
+    /// (separated like `PATH` on the platform), before the
+    /// existing ones.
+    pub fn with_env_overrides(mut self) -> Self {
+        if let Some(value) = std::env::var_os("CALIBAN_ASSET_PATH") {
+            let mut mounts = std::env::split_paths(&value)
+                .filter(|path| !path.as_os_str().is_empty())
+                .collect::<Vec<_>>();
+            mounts.append(&mut self.mounts);
+            self.mounts = mounts;
+        }
+
+        self
+    }
+
+    /// Mount points, in lookup order.
+    pub fn mounts(&self) -> &[PathBuf] {
+        &self.mounts
+    }
+
+    /// Add a mount point, looked up after the existing ones.
+    pub fn mount(&mut self, path: impl Into<PathBuf>) {
+        self.mounts.push(path.into());
+    }
+
+    /// Absolute path of an asset, along with the mount point it
+    /// was found in. Absolute paths, and paths going up out of
+    /// the mount points with `..`, are rejected.
+    pub fn locate(&self, relative: impl AsRef<Path>) -> Result<(PathBuf, &Path), AssetError> {
+        let relative = relative.as_ref();
+        check_relative(relative).map_err(|reason| AssetError::InvalidPath {
+            path: relative.to_path_buf(),
+            reason,
+        })?;
+
+        for mount in &self.mounts {
+            let path = mount.join(relative);
+            if path.is_file() {
+                // Canonicalizing gives a stable path to watch
+                // or to compare; if it fails, the joined path
+                // is still valid for reading.
+                let path = path.canonicalize().unwrap_or(path);
+                return Ok((path, mount));
+            }
+        }
+
+        Err(AssetError::NotFound {
+            path: relative.to_path_buf(),
+            mounts: self.mounts
+                .iter()
+                .map(|mount| mount.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+        })
+    }
+
+    /// Absolute path of an asset. See [`Assets::locate`].
+    pub fn resolve(&self, relative: impl AsRef<Path>) -> Result<PathBuf, AssetError> {
+        self.locate(relative).map(|(path, _)| path)
+    }
+
+    /// Read the whole content of an asset.
+    pub fn read(&self, relative: impl AsRef<Path>) -> Result<Vec<u8>, AssetError> {
+        self.read_from(relative).map(|(bytes, _)| bytes)
+    }
+
+    /// Read the whole content of an asset, along with the mount
+    /// point it was found in, for loaders to report in their
+    /// errors.
+    pub fn read_from(&self, relative: impl AsRef<Path>) -> Result<(Vec<u8>, &Path), AssetError> {
+        let relative = relative.as_ref();
+        let (path, mount) = self.locate(relative)?;
+
+        let bytes = std::fs::read(&path).map_err(|source| AssetError::Io {
+            path: relative.to_path_buf(),
+            mount: mount.to_path_buf(),
+            source,
+        })?;
+        Ok((bytes, mount))
+    }
+
+    /// Load a DDS texture. See [`load_dds`].
+    pub fn load_dds(&self, relative: impl AsRef<Path>) -> Result<DdsTexture, AssetError> {
+        load_dds(self, relative)
+    }
+}
+
+impl Default for Assets {
+    /// Look up assets in the working directory, then next to
+    /// the executable.
+    fn default() -> Self {
+        let mut mounts = vec![PathBuf::from(".")];
+        if let Some(dir) = std::env::current_exe().ok().and_then(|exe| exe.parent().map(Path::to_path_buf)) {
+            mounts.push(dir);
+        }
+
+        Self { mounts }
+    }
+}
+
+/// Check that an asset path stays inside the mount point it is
+/// joined to: joining an absolute path replaces the mount, and
+/// enough `..` components walk out of it.
+fn check_relative(path: &Path) -> Result<(), &'static str> {
+    let mut depth = 0usize;
+    for component in path.components() {
+        match component {
+            Component::Prefix(_) | Component::RootDir => return Err("absolute paths are not allowed"),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                depth = depth.checked_sub(1).ok_or("the path goes up out of the mount point")?;
+            }
+            Component::Normal(_) => depth += 1,
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_paths_are_accepted() {
+        assert!(check_relative(Path::new("textures/grass.dds")).is_ok());
+        assert!(check_relative(Path::new("./textures/../models/viking_room.obj")).is_ok());
+    }
+
+    #[test]
+    fn escaping_paths_are_rejected() {
+        assert!(check_relative(Path::new("/etc/passwd")).is_err());
+        assert!(check_relative(Path::new("../secret.dds")).is_err());
+        assert!(check_relative(Path::new("textures/../../secret.dds")).is_err());
+
+        let assets = Assets::new(["."]);
+        assert!(matches!(
+            assets.locate("../Cargo.toml"),
+            Err(AssetError::InvalidPath { .. })
+        ));
+    }
+}
@@ -0,0 +1,33 @@
+use std::process::ExitCode;
+
+use caliban::{run, selftest::run_self_test, AppConfig};
+use log::*;
+
+fn main() -> ExitCode {
+    // Log at the info level, unless RUST_LOG says otherwise.
+    if std::env::var_os("RUST_LOG").is_none() {
+        std::env::set_var("RUST_LOG", "info");
+    }
+    pretty_env_logger::init();
+
+    // The self-test reports on the standard output, and tells
+    // whether it passed through the exit code, for scripts.
+    if std::env::args().any(|arg| arg == "--self-test") {
+        return if run_self_test() { ExitCode::SUCCESS } else { ExitCode::FAILURE };
+    }
+
+    // The window is only redrawn when needed, unless asked to
+    // render continuously.
+    let config = AppConfig {
+        continuous_redraw: std::env::args().any(|arg| arg == "--continuous"),
+        ..Default::default()
+    };
+
+    match run(config, ()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            error!("{:#}", error);
+            ExitCode::FAILURE
+        },
+    }
+}
@@ -0,0 +1,214 @@
+use std::{env, path::PathBuf, time::Duration};
+
+use crate::{
+    core::{devices::DeviceRequirements, image::TextureFiltering, swapchain::{PresentPreference, SURFACE_FORMAT_SRGB}},
+    stats::LatencyMode,
+    renderer::{DEFAULT_FRAME_TIMEOUT, FRAMES_IN_FLIGHT_RANGE, MAX_FRAMES_IN_FLIGHT, VALIDATION_ENABLED},
+};
+
+use vulkanalia::prelude::v1_0::*;
+use log::*;
+
+/// Runtime configuration of the renderer, consumed by
+/// `Renderer::create`. The defaults match what used to be
+/// compile-time constants, and can be overridden from the
+/// environment with [`RendererConfig::with_env_overrides`].
+#[derive(Clone, Debug)]
+pub struct RendererConfig {
+    /// Whether to enable the Khronos validation layer. Defaults
+    /// to on in debug builds and off in release builds
+    /// (`CALIBAN_VALIDATION`).
+    pub validation: bool,
+    /// Whether the renderer should fail to start if validation
+    /// is enabled but the validation layer isn't installed,
+    /// instead of carrying on without it. This is set when
+    /// validation is explicitly turned on from the environment.
+    pub validation_required: bool,
+    /// Severities of the validation messages that are reported.
+    pub validation_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    /// Number of frames that can be worked on at the same time
+    /// by the CPU and the GPU, from 1 to 3; 1 for the lowest
+    /// latency, 3 to keep the GPU busy on slow CPUs
+    /// (`CALIBAN_FRAMES_IN_FLIGHT`).
+    pub frames_in_flight: usize,
+    /// Preferred presentation mode, resolved against the modes
+    /// the surface supports (`CALIBAN_PRESENT_MODE`).
+    pub present_mode: PresentPreference,
+    /// Preferred physical device: the first suitable device
+    /// whose name contains this string, case-insensitively, is
+    /// picked over the others (`CALIBAN_GPU`).
+    pub preferred_device: Option<String>,
+    /// Whether to start on a software implementation of Vulkan
+    /// (llvmpipe, SwiftShader) when no hardware device is
+    /// available. A software device named by
+    /// `preferred_device` is always allowed
+    /// (`CALIBAN_ALLOW_SOFTWARE`).
+    pub allow_software_adapter: bool,
+    /// Surface formats of the swapchain, by order of
+    /// preference; the first format the surface reports is
+    /// used if none of them is supported. Defaults to 8-bit
+    /// sRGB only. Formats in other color spaces than
+    /// SRGB_NONLINEAR need `VK_EXT_swapchain_colorspace`, which
+    /// is enabled when available.
+    pub surface_formats: Vec<vk::SurfaceFormatKHR>,
+    /// Number of swapchain images to request, clamped to what
+    /// the surface supports: the minimum for the lowest
+    /// latency, or one more for triple buffering. Defaults to
+    /// one more than the minimum (`CALIBAN_IMAGE_COUNT`). The
+    /// driver may create more images than requested; see
+    /// `Renderer::swapchain_image_count`.
+    pub desired_image_count: Option<u32>,
+    /// Whether projections are jittered by a different sub-pixel
+    /// offset each frame, for temporal techniques (see
+    /// [`Renderer::jitter`](crate::renderer::Renderer::jitter)).
+    pub temporal_jitter: bool,
+    /// Optional device features.
+    pub requirements: DeviceRequirements,
+    /// Anisotropic filtering level of the samplers created by
+    /// the renderer; see [`Renderer::set_texture_filtering`](crate::renderer::Renderer::set_texture_filtering).
+    pub texture_filtering: TextureFiltering,
+    /// Time to wait for the previous frame or a swapchain image
+    /// before giving up on the frame.
+    pub frame_timeout: Duration,
+    /// Whether to wait for the previous frame to be actually
+    /// presented before starting the CPU work of the next one,
+    /// which reduces the number of queued frames and thus input
+    /// latency. This requires `VK_KHR_present_wait`, and is
+    /// ignored without it (`CALIBAN_LOW_LATENCY`).
+    pub low_latency: bool,
+    /// Where the frame loop waits for the frame in flight,
+    /// relative to the application update
+    /// (`CALIBAN_LATENCY_MODE`: `submit_then_wait` or
+    /// `wait_before_input`).
+    pub latency_mode: LatencyMode,
+    /// Number of operations kept in the operation log, dumped
+    /// on validation errors and fatal render errors; 0, the
+    /// default, disables it (`CALIBAN_OP_LOG`).
+    pub op_log_capacity: usize,
+    /// File the operation log is dumped to.
+    pub op_log_path: PathBuf,
+    /// Maximum frame rate, which the renderer keeps to by
+    /// waiting after each frame; `None`, the default, doesn't
+    /// limit it. This is mostly useful without vsync, which
+    /// limits the frame rate by itself (`CALIBAN_TARGET_FPS`:
+    /// a number of frames per second, or `off`).
+    pub target_fps: Option<u32>,
+}
+
+impl Default for RendererConfig {
+    fn default() -> Self {
+        Self {
+            validation: VALIDATION_ENABLED,
+            validation_required: false,
+            validation_severity: vk::DebugUtilsMessageSeverityFlagsEXT::all(),
+            frames_in_flight: MAX_FRAMES_IN_FLIGHT,
+            present_mode: PresentPreference::default(),
+            preferred_device: None,
+            allow_software_adapter: true,
+            surface_formats: vec![SURFACE_FORMAT_SRGB],
+            desired_image_count: None,
+            temporal_jitter: false,
+            requirements: DeviceRequirements::default(),
+            texture_filtering: TextureFiltering::default(),
+            frame_timeout: DEFAULT_FRAME_TIMEOUT,
+            low_latency: false,
+            latency_mode: LatencyMode::default(),
+            op_log_capacity: 0,
+            op_log_path: PathBuf::from("caliban-oplog.txt"),
+            target_fps: None,
+        }
+    }
+}
+
+impl RendererConfig {
+    /// Apply the overrides found in the environment on top of
+    /// this configuration. Invalid values are ignored with a
+    /// warning.
+    pub fn with_env_overrides(mut self) -> Self {
+        if let Some(value) = env_var("CALIBAN_VALIDATION") {
+            match value.to_lowercase().as_str() {
+                "1" | "on" | "true" => {
+                    self.validation = true;
+                    self.validation_required = true;
+                }
+                "0" | "off" | "false" => self.validation = false,
+                _ => warn!("Ignoring invalid CALIBAN_VALIDATION value: {}", value),
+            }
+        }
+
+        if let Some(value) = env_var("CALIBAN_FRAMES_IN_FLIGHT") {
+            match value.parse() {
+                Ok(count) if FRAMES_IN_FLIGHT_RANGE.contains(&count) => self.frames_in_flight = count,
+                _ => warn!("Ignoring invalid CALIBAN_FRAMES_IN_FLIGHT value: {}", value),
+            }
+        }
+
+        if let Some(value) = env_var("CALIBAN_IMAGE_COUNT") {
+            match value.parse() {
+                Ok(count) => self.desired_image_count = Some(count),
+                Err(_) => warn!("Ignoring invalid CALIBAN_IMAGE_COUNT value: {}", value),
+            }
+        }
+
+        if let Some(value) = env_var("CALIBAN_PRESENT_MODE") {
+            match value.to_lowercase().as_str() {
+                "vsync" | "fifo" => self.present_mode = PresentPreference::Vsync,
+                "relaxed" | "fifo_relaxed" => self.present_mode = PresentPreference::Relaxed,
+                "low_latency" | "mailbox" => self.present_mode = PresentPreference::LowLatency,
+                "immediate" => self.present_mode = PresentPreference::Immediate,
+                _ => warn!("Ignoring invalid CALIBAN_PRESENT_MODE value: {}", value),
+            }
+        }
+
+        if let Some(value) = env_var("CALIBAN_GPU") {
+            self.preferred_device = Some(value);
+        }
+
+        if let Some(value) = env_var("CALIBAN_ALLOW_SOFTWARE") {
+            match value.to_lowercase().as_str() {
+                "1" | "on" | "true" => self.allow_software_adapter = true,
+                "0" | "off" | "false" => self.allow_software_adapter = false,
+                _ => warn!("Ignoring invalid CALIBAN_ALLOW_SOFTWARE value: {}", value),
+            }
+        }
+
+        if let Some(value) = env_var("CALIBAN_LOW_LATENCY") {
+            match value.to_lowercase().as_str() {
+                "1" | "on" | "true" => self.low_latency = true,
+                "0" | "off" | "false" => self.low_latency = false,
+                _ => warn!("Ignoring invalid CALIBAN_LOW_LATENCY value: {}", value),
+            }
+        }
+
+        if let Some(value) = env_var("CALIBAN_LATENCY_MODE") {
+            match value.to_lowercase().as_str() {
+                "submit_then_wait" => self.latency_mode = LatencyMode::SubmitThenWait,
+                "wait_before_input" => self.latency_mode = LatencyMode::WaitBeforeInput,
+                _ => warn!("Ignoring invalid CALIBAN_LATENCY_MODE value: {}", value),
+            }
+        }
+
+        if let Some(value) = env_var("CALIBAN_OP_LOG") {
+            match value.parse() {
+                Ok(capacity) => self.op_log_capacity = capacity,
+                Err(_) => warn!("Ignoring invalid CALIBAN_OP_LOG value: {}", value),
+            }
+        }
+
+        if let Some(value) = env_var("CALIBAN_TARGET_FPS") {
+            match value.to_lowercase().as_str() {
+                "0" | "off" => self.target_fps = None,
+                other => match other.parse() {
+                    Ok(fps) => self.target_fps = Some(fps),
+                    Err(_) => warn!("Ignoring invalid CALIBAN_TARGET_FPS value: {}", value),
+                },
+            }
+        }
+
+        self
+    }
+}
+
+fn env_var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|v| !v.is_empty())
+}
@@ -5,4 +5,11 @@ pub mod image;
 pub mod commands;
 pub mod frame;
 pub mod sync;
-pub mod allocator;
\ No newline at end of file
+pub mod allocator;
+pub mod viewport;
+pub mod rendering;
+pub mod queries;
+pub mod ownership;
+pub mod tracker;
+pub mod upload;
+pub mod screenshot;
@@ -1,8 +1,17 @@
 mod memory;
+mod slab;
 mod tlsf;
 
-use vulkanalia::prelude::v1_0::*;
-use memory::{MemoryUse, ResourceType, MemoryRegion};
+use std::{collections::HashMap, ptr::NonNull};
+
+use vulkanalia::{prelude::v1_0::*, vk::InstanceV1_1, VkResult};
+use thiserror::Error;
+use log::*;
+use memory::{AllocationSource, MemoryRegion};
+pub use memory::{BlockLayout, ChunkLayout, ChunkState, Lifetime, MemoryUse, ResourceType};
+use super::devices::{DeviceLimits, DynamicBinding};
+
+pub(crate) use memory::{align_down, align_up};
 
 /// A memory allocation object, that holds the information
 /// necessary to bind a resource to Vulkan memory.
@@ -11,8 +20,184 @@ pub struct Allocation {
     pub memory: vk::DeviceMemory,
     /// The offset of the allocation within the memory object.
     pub offset: u64,
+    /// The size of the allocation in bytes.
+    pub size: u64,
+    /// Host pointer to the start of the allocation, for
+    /// `HOST_VISIBLE` memory. It stays valid for as long as the
+    /// allocation lives, so there is no need to map and unmap
+    /// the memory around each update.
+    pub mapped: Option<NonNull<u8>>,
+    /// Whether the memory is `HOST_COHERENT`. If it isn't, host
+    /// writes only become visible to the device after a call
+    /// to [`Allocator::flush`].
+    pub coherent: bool,
+    /// Where the allocation was made from, to free it.
+    source: AllocationSource,
+}
+
+impl Allocation {
+    /// Copy `data` into the allocation, `offset` bytes from its
+    /// start, through the persistent mapping. The written range
+    /// still has to be flushed if the memory is not coherent.
+    ///
+    /// # Safety
+    ///
+    /// The device must not be reading the range concurrently
+    /// (for example, it belongs to a frame whose fence has been
+    /// waited on).
+    ///
+    /// # Panics
+    ///
+    /// If the allocation is not host-visible, or if the data
+    /// doesn't fit in it.
+    pub unsafe fn write<T: Copy>(&self, offset: u64, data: &[T]) {
+        let ptr = self.mapped.expect("Writing to memory that is not host-visible.");
+        let len = std::mem::size_of_val(data);
+        assert!(offset + len as u64 <= self.size, "Write out of the allocation's bounds.");
+
+        std::ptr::copy_nonoverlapping(
+            data.as_ptr().cast::<u8>(),
+            ptr.as_ptr().add(offset as usize),
+            len,
+        );
+    }
+}
+
+/// Errors returned when allocating memory.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllocationError {
+    /// Vulkan forbids zero-sized buffers and memory objects, so
+    /// a zero-sized request is a bug in the caller (an empty
+    /// mesh, for example), reported before reaching the driver.
+    #[error("Zero-sized allocation requested.")]
+    ZeroSize,
+    #[error("No memory type satisfies the requirements (type bits {0:#b}).")]
+    NoSuitableMemoryType(u32),
+    /// The driver refused to allocate a new memory block,
+    /// usually because the heap is full
+    /// (`ERROR_OUT_OF_DEVICE_MEMORY`, or
+    /// `ERROR_OUT_OF_HOST_MEMORY` for host-visible heaps).
+    #[error("Failed to allocate a memory block: {0:?}.")]
+    OutOfDeviceMemory(vk::ErrorCode),
+    /// A new host-visible memory block couldn't be mapped.
+    #[error("Failed to map a memory block: {0:?}.")]
+    MapFailed(vk::ErrorCode),
+    /// The request doesn't fit in a memory block, even an empty
+    /// one.
+    #[error("Allocation of {size} bytes larger than a memory block ({block_size} bytes).")]
+    TooLarge { size: u64, block_size: u64 },
+}
+
+/// What an allocation is used for, to break down memory usage
+/// by subsystem in [`Allocator::stats`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ResourceCategory {
+    /// Sampled images and their mip chains.
+    Texture,
+    /// Vertex and index buffers.
+    Mesh,
+    /// Images rendered to (color, depth, intermediate targets).
+    RenderTarget,
+    /// Staging buffers for uploads and readbacks.
+    Staging,
+    /// Uniform and storage buffers updated every frame.
+    Uniform,
+    /// Anything else.
+    Other,
+}
+
+impl ResourceCategory {
+    pub const ALL: [ResourceCategory; 6] = [
+        ResourceCategory::Texture,
+        ResourceCategory::Mesh,
+        ResourceCategory::RenderTarget,
+        ResourceCategory::Staging,
+        ResourceCategory::Uniform,
+        ResourceCategory::Other,
+    ];
+}
+
+/// Number and total size of live allocations.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct UsageStats {
+    pub count: usize,
+    pub bytes: u64,
+}
+
+/// A live allocation, as listed in [`AllocatorStats`].
+#[derive(Clone, Debug)]
+pub struct AllocationInfo {
+    pub category: ResourceCategory,
+    pub lifetime: Lifetime,
+    pub size: u64,
+    /// Name given with [`Allocator::set_debug_name`], if any.
+    pub name: Option<String>,
+}
+
+/// Occupancy of the blocks of a lifetime class.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LifetimeStats {
+    /// Number of blocks holding allocations of the class.
+    pub blocks: usize,
+    /// Size of these blocks, including free space.
+    pub reserved: u64,
+    /// Number of bytes allocated from them.
+    pub allocated: u64,
+}
+
+impl LifetimeStats {
+    /// Fraction of the reserved memory that is allocated,
+    /// between 0 and 1.
+    pub fn occupancy(&self) -> f32 {
+        if self.reserved == 0 {
+            0.0
+        } else {
+            self.allocated as f32 / self.reserved as f32
+        }
+    }
+}
+
+/// Breakdown of the live allocations of an [`Allocator`].
+#[derive(Clone, Debug, Default)]
+pub struct AllocatorStats {
+    /// Usage of each category, in the order of
+    /// [`ResourceCategory::ALL`].
+    pub categories: Vec<(ResourceCategory, UsageStats)>,
+    /// Usage over all categories.
+    pub total: UsageStats,
+    /// Memory reserved from the device, in blocks, including
+    /// free space.
+    pub reserved: u64,
+    /// Occupancy of the blocks of each lifetime class, in the
+    /// order of [`Lifetime::ALL`].
+    pub lifetimes: Vec<(Lifetime, LifetimeStats)>,
+    /// Largest allocations, largest first.
+    pub largest: Vec<AllocationInfo>,
+}
+
+impl AllocatorStats {
+    /// Usage of the given category.
+    pub fn category(&self, category: ResourceCategory) -> UsageStats {
+        self.categories
+            .iter()
+            .find(|(c, _)| *c == category)
+            .map(|&(_, usage)| usage)
+            .unwrap_or_default()
+    }
+
+    /// Occupancy of the blocks of the given lifetime class.
+    pub fn lifetime(&self, lifetime: Lifetime) -> LifetimeStats {
+        self.lifetimes
+            .iter()
+            .find(|(l, _)| *l == lifetime)
+            .map(|&(_, stats)| stats)
+            .unwrap_or_default()
+    }
 }
 
+/// Number of allocations listed in [`AllocatorStats::largest`].
+const LARGEST_ALLOCATIONS: usize = 10;
+
 /// Memory allocator that manages Vulkan memory and provides
 /// functions to allocate and free resources from it.
 pub struct Allocator {
@@ -20,12 +205,54 @@ pub struct Allocator {
     /// memory region corresponds to a single Vulkan memory
     /// type.
     regions: Vec<MemoryRegion>,
+    /// Limits of the device, used to align sub-allocations
+    /// that are bound with dynamic offsets and to round
+    /// flushes. They are the ones the caller queried when it
+    /// picked the device (`RenderData::limits` for the
+    /// renderer), not queried again here.
+    limits: DeviceLimits,
+    /// Live allocations, by memory object and offset, for the
+    /// usage statistics.
+    live: HashMap<(vk::DeviceMemory, u64), AllocationInfo>,
+}
+
+/// Memory available to the application in each heap of the
+/// device: the heap's budget when the device supports
+/// `VK_EXT_memory_budget` (which accounts for what the system
+/// and other processes use), or its size otherwise.
+fn heap_budgets(
+    instance: &Instance,
+    physical_device: vk::PhysicalDevice,
+    properties: &vk::PhysicalDeviceMemoryProperties,
+) -> Vec<u64> {
+    let sizes = properties.memory_heaps[..properties.memory_heap_count as usize]
+        .iter()
+        .map(|heap| heap.size);
+
+    let supported = unsafe { instance.enumerate_device_extension_properties(physical_device, None) }
+        .map(|extensions| extensions.iter().any(|e| e.extension_name == vk::EXT_MEMORY_BUDGET_EXTENSION.name))
+        .unwrap_or(false);
+    if !supported {
+        return sizes.collect();
+    }
+
+    let mut budget = vk::PhysicalDeviceMemoryBudgetPropertiesEXT::builder();
+    let mut properties2 = vk::PhysicalDeviceMemoryProperties2::builder()
+        .push_next(&mut budget);
+    unsafe { instance.get_physical_device_memory_properties2(physical_device, &mut properties2) };
+
+    // A budget of 0 means the driver doesn't know.
+    sizes
+        .zip(budget.heap_budget)
+        .map(|(size, budget)| if budget == 0 { size } else { size.min(budget) })
+        .collect()
 }
 
 impl Allocator {
     pub fn new(
         instance: &Instance,
         physical_device: vk::PhysicalDevice,
+        limits: DeviceLimits,
     ) -> Self {
         // Get the memory properties of the device.
         let memory_properties = unsafe {
@@ -34,54 +261,331 @@ impl Allocator {
 
         // Then, create a memory region for each memory type
         // supported by the device. The region registers the
-        // property flags and the index of the memory type.
+        // property flags and the index of the memory type, and
+        // sizes its blocks after the memory available in the
+        // type's heap.
+        let available = heap_budgets(instance, physical_device, &memory_properties);
         let regions = memory_properties.memory_types
             .iter()
             .enumerate()
             .map(|(index, memory)| {
-                MemoryRegion::new(index, memory.property_flags)
+                let available = available.get(memory.heap_index as usize).copied().unwrap_or(0);
+                MemoryRegion::new(index, memory.property_flags, available)
             })
             .collect();
 
         Self {
             regions,
+            limits,
+            live: HashMap::new(),
         }
     }
 
+    #[profiling::function]
     pub fn allocate(
         &mut self, 
         device: &Device,
         requirements: vk::MemoryRequirements, 
         location: MemoryUse,
         resource_type: ResourceType,
-    ) -> Allocation {
+        lifetime: Lifetime,
+        category: ResourceCategory,
+    ) -> Result<Allocation, AllocationError> {
+        if requirements.size == 0 {
+            return Err(AllocationError::ZeroSize);
+        }
+
         // Request memory properties based on the desired use:
         // for a gpu-only memory, we only need to set the
         // DEVICE_LOCAL flag, while for data transfered between
         // the host to the device, we want to set the
         // DEVICE_LOCAL and HOST_VISIBLE flags.
-        let requested_properties = match location {
-            MemoryUse::GpuOnly => vk::MemoryPropertyFlags::DEVICE_LOCAL,
-            MemoryUse::CpuToGpu => vk::MemoryPropertyFlags::DEVICE_LOCAL | vk::MemoryPropertyFlags::HOST_VISIBLE,
+        // Not every device has host-visible device-local memory
+        // (or enough of it), so CPU-to-GPU resources fall back
+        // on plain host-visible memory, which may also not be
+        // coherent: the allocation records that, and updates
+        // have to be flushed accordingly.
+        let requested_properties: &[vk::MemoryPropertyFlags] = match location {
+            MemoryUse::GpuOnly => &[vk::MemoryPropertyFlags::DEVICE_LOCAL],
+            MemoryUse::CpuToGpu => &[
+                vk::MemoryPropertyFlags::DEVICE_LOCAL | vk::MemoryPropertyFlags::HOST_VISIBLE,
+                vk::MemoryPropertyFlags::HOST_VISIBLE,
+            ],
         };
 
-        // Find the memory type that satisfies the requirements
-        // and properties, and select the region corresponding
-        // to this memory type.
-        let memory_type = self.find_memory_type(requirements, requested_properties);
+        // Find the first memory type that satisfies the
+        // requirements and properties, and select the region
+        // corresponding to this memory type.
+        let memory_type = requested_properties
+            .iter()
+            .find_map(|&properties| self.find_memory_type(requirements, properties))
+            .ok_or(AllocationError::NoSuitableMemoryType(requirements.memory_type_bits))?;
         let region = &mut self.regions[memory_type];
 
         // Then, allocate a memory block from the region and
-        // return the allocation.
-        region.allocate(
+        // return the allocation, after recording it for the
+        // statistics.
+        let allocation = region.allocate(
             device,
             requirements.size,
             requirements.alignment,
             resource_type,
-        )
+            lifetime,
+        )?;
+
+        self.live.insert(
+            (allocation.memory, allocation.offset),
+            AllocationInfo { category, lifetime, size: allocation.size, name: None },
+        );
+
+        Ok(allocation)
+    }
+
+    /// Allocate memory for a buffer range that will be bound
+    /// with a dynamic offset (uniform ring, per-object uniform
+    /// blocks, etc).
+    pub fn allocate_dynamic(
+        &mut self,
+        device: &Device,
+        requirements: vk::MemoryRequirements,
+        location: MemoryUse,
+        binding: DynamicBinding,
+        lifetime: Lifetime,
+        category: ResourceCategory,
+    ) -> Result<Allocation, AllocationError> {
+        // On top of the buffer's own requirements, the offset
+        // handed out must respect the device's minimum
+        // dynamic offset alignment for this kind of
+        // descriptor; both are powers of two, so the largest
+        // one satisfies the other. The regions align every
+        // offset they hand out to the requested alignment, in
+        // release builds as well, whether the allocation comes
+        // from a slab (whose slots are at least as large as
+        // the alignment) or from a chunk.
+        let alignment = requirements.alignment.max(self.limits.dynamic_offset_alignment(binding));
+        let requirements = vk::MemoryRequirements {
+            alignment,
+            ..requirements
+        };
+
+        self.allocate(device, requirements, location, ResourceType::Linear, lifetime, category)
+    }
+
+    /// Make host writes to `size` bytes of `allocation`,
+    /// starting `offset` bytes in, visible to the device. This
+    /// is a no-op for coherent memory; otherwise the range is
+    /// widened to multiples of `non_coherent_atom_size`, as
+    /// required by `vkFlushMappedMemoryRanges`.
+    pub fn flush(
+        &self,
+        device: &Device,
+        allocation: &Allocation,
+        offset: u64,
+        size: u64,
+    ) -> VkResult<()> {
+        if allocation.coherent || size == 0 {
+            return Ok(());
+        }
+
+        // Blocks have a power-of-two size, larger than any
+        // atom size, so rounding the end of the range up never
+        // goes past the end of the memory object.
+        let atom = self.limits.non_coherent_atom_size;
+        let start = align_down(allocation.offset + offset, atom);
+        let end = align_up(allocation.offset + offset + size, atom);
+
+        let range = vk::MappedMemoryRange::builder()
+            .memory(allocation.memory)
+            .offset(start)
+            .size(end - start);
+
+        unsafe { device.flush_mapped_memory_ranges(&[range]) }
+    }
+
+    /// Give an allocation back to the allocator. The resource
+    /// bound to it must have been destroyed, and the device
+    /// must be done with it.
+    pub fn free(&mut self, allocation: Allocation) {
+        self.live.remove(&(allocation.memory, allocation.offset));
+        self.regions[allocation.source.memory_type()].free(allocation.source);
+    }
+
+    /// Free all the device memory held by the allocator. Every
+    /// allocation made from it becomes invalid.
+    pub fn destroy(&mut self, device: &Device) {
+        self.regions
+            .iter_mut()
+            .for_each(|region| region.destroy(device));
+        self.live.clear();
+    }
+
+    /// Enable or disable the pooling of small allocations
+    /// (up to 4 KiB) into slabs; it is enabled by default.
+    /// Allocations already made are not affected.
+    pub fn set_small_allocation_pooling(&mut self, enabled: bool) {
+        self.regions
+            .iter_mut()
+            .for_each(|region| region.small_pooling = enabled);
+    }
+
+    /// Give a debug name to an allocation, which shows up in
+    /// [`Allocator::dump_layout`].
+    pub fn set_debug_name(&mut self, allocation: &Allocation, name: &str) {
+        if let Some(info) = self.live.get_mut(&(allocation.memory, allocation.offset)) {
+            info.name = Some(name.to_string());
+        }
+
+        for region in &mut self.regions {
+            if region.set_name(allocation.memory, allocation.offset, name) {
+                return;
+            }
+        }
+    }
+
+    /// Layout of every memory block currently allocated, with
+    /// their chunks in address order. This also checks the
+    /// consistency of the chunk lists, and panics if they are
+    /// broken.
+    pub fn layout(&self) -> Vec<BlockLayout> {
+        self.regions
+            .iter()
+            .flat_map(|region| region.layout())
+            .collect()
+    }
+
+    /// Human-readable memory map of the allocator: for each
+    /// block, an occupancy bar ('#' for linear resources, '%'
+    /// for non-linear ones, '.' for free space) followed by the
+    /// list of its chunks.
+    pub fn dump_layout(&self) -> String {
+        use std::fmt::Write;
+
+        const BAR_WIDTH: u64 = 64;
+        let mut out = String::new();
+
+        for (index, block) in self.layout().iter().enumerate() {
+            let used = block.allocated as f64 / block.size as f64 * 100.0;
+            let _ = writeln!(
+                out,
+                "Block {} (memory type {}, {:?}, {:?}, {:?}): {} bytes, {:.1}% used",
+                index, block.memory_type, block.properties, block.resource_type, block.lifetime, block.size, used,
+            );
+
+            // Each character of the bar covers 1/64th of the
+            // block, and shows as allocated if any allocated
+            // chunk overlaps it.
+            let mut bar = vec!['.'; BAR_WIDTH as usize];
+            for chunk in block.chunks.iter().filter(|c| c.state != ChunkState::Free) {
+                let start = chunk.offset * BAR_WIDTH / block.size;
+                let end = ((chunk.offset + chunk.size) * BAR_WIDTH).div_ceil(block.size);
+                let symbol = if chunk.state == ChunkState::Linear { '#' } else { '%' };
+                bar[start as usize..end.min(BAR_WIDTH) as usize].fill(symbol);
+            }
+            let _ = writeln!(out, "  [{}]", bar.into_iter().collect::<String>());
+
+            for chunk in &block.chunks {
+                let state = match chunk.state {
+                    ChunkState::Free => "free",
+                    ChunkState::Linear => "linear",
+                    ChunkState::NonLinear => "non-linear",
+                };
+                let _ = writeln!(
+                    out,
+                    "  {:#010x} {:>12} {:<10} {}",
+                    chunk.offset,
+                    chunk.size,
+                    state,
+                    chunk.name.as_deref().unwrap_or(""),
+                );
+            }
+        }
+
+        out
+    }
+
+    /// Live allocations broken down by category, with the
+    /// largest ones, and the occupancy of the blocks of each
+    /// lifetime class.
+    pub fn stats(&self) -> AllocatorStats {
+        let layout = self.layout();
+        let mut stats = AllocatorStats {
+            categories: ResourceCategory::ALL
+                .iter()
+                .map(|&category| (category, UsageStats::default()))
+                .collect(),
+            reserved: layout.iter().map(|block| block.size).sum(),
+            lifetimes: Lifetime::ALL
+                .iter()
+                .map(|&lifetime| (lifetime, LifetimeStats::default()))
+                .collect(),
+            ..Default::default()
+        };
+
+        for block in &layout {
+            let (_, occupancy) = stats.lifetimes
+                .iter_mut()
+                .find(|(lifetime, _)| *lifetime == block.lifetime)
+                .unwrap();
+            occupancy.blocks += 1;
+            occupancy.reserved += block.size;
+            occupancy.allocated += block.allocated;
+        }
+
+        for info in self.live.values() {
+            let (_, usage) = stats.categories
+                .iter_mut()
+                .find(|(category, _)| *category == info.category)
+                .unwrap();
+            usage.count += 1;
+            usage.bytes += info.size;
+            stats.total.count += 1;
+            stats.total.bytes += info.size;
+        }
+
+        let mut largest = self.live.values().collect::<Vec<_>>();
+        largest.sort_by(|a, b| b.size.cmp(&a.size));
+        stats.largest = largest
+            .into_iter()
+            .take(LARGEST_ALLOCATIONS)
+            .cloned()
+            .collect();
+
+        stats
+    }
+
+    /// Log the breakdown of [`Allocator::stats`].
+    pub fn log_report(&self) {
+        let stats = self.stats();
+        info!(
+            "GPU memory: {} allocations, {} bytes used, {} bytes reserved.",
+            stats.total.count, stats.total.bytes, stats.reserved,
+        );
+
+        for (category, usage) in stats.categories.iter().filter(|(_, usage)| usage.count > 0) {
+            info!("  {:?}: {} allocations, {} bytes", category, usage.count, usage.bytes);
+        }
+
+        for (lifetime, occupancy) in stats.lifetimes.iter().filter(|(_, occupancy)| occupancy.blocks > 0) {
+            info!(
+                "  {:?} blocks: {}, {:.1}% occupied",
+                lifetime,
+                occupancy.blocks,
+                occupancy.occupancy() * 100.0,
+            );
+        }
+
+        info!("Largest allocations:");
+        for allocation in &stats.largest {
+            info!(
+                "  {:>12} bytes, {:?}, {}",
+                allocation.size,
+                allocation.category,
+                allocation.name.as_deref().unwrap_or("(unnamed)"),
+            );
+        }
     }
 
-    fn find_memory_type(&self, requirements: vk::MemoryRequirements, properties: vk::MemoryPropertyFlags) -> usize {
+    fn find_memory_type(&self, requirements: vk::MemoryRequirements, properties: vk::MemoryPropertyFlags) -> Option<usize> {
         // Find a memory type that is suitable for the buffer
         // with the given requirements and properties. Each
         // memory region corresponds to a memory type index, so
@@ -103,6 +607,5 @@ impl Allocator {
                     && memory_properties.contains(properties)
             })
             .map(|region| region.memory_type)
-            .expect("Failed to find suitable memory type.")
     }
 }
\ No newline at end of file
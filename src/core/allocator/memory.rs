@@ -1,10 +1,16 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    ptr::NonNull,
+};
 use vulkanalia::prelude::v1_0::*;
 
-use super::Allocation;
+use super::{Allocation, AllocationError};
+use super::slab::{small_bin, Slab, SLAB_PAGE_SIZE, SMALL_BINS};
 use super::tlsf::Tlsf;
+use crate::core::tracker::*;
 
 /// How a memory resource will be used.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum MemoryUse {
     /// Resource that is only used by the GPU. Corresponds to
     /// the `DEVICE_LOCAL` flag.
@@ -14,7 +20,30 @@ pub enum MemoryUse {
     CpuToGpu,
 }
 
+/// How long an allocation is expected to live. Blocks only
+/// hold allocations of a single lifetime class, so that
+/// short-lived allocations, freed and reallocated constantly,
+/// don't leave holes between long-lived ones.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Lifetime {
+    /// Resources that live as long as the scene or longer
+    /// (textures, meshes, render targets).
+    #[default]
+    Persistent,
+    /// Resources freed after a few frames (staging buffers of
+    /// uploads and readbacks).
+    Transient,
+    /// Resources reallocated every frame (per-frame arenas,
+    /// uniform rings).
+    PerFrame,
+}
+
+impl Lifetime {
+    pub const ALL: [Lifetime; 3] = [Lifetime::Persistent, Lifetime::Transient, Lifetime::PerFrame];
+}
+
 /// Type of the resource to be allocated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum ResourceType {
     /// The resource is bound to a linear memory block (a
     /// buffer, for example).
@@ -24,9 +53,58 @@ pub enum ResourceType {
     NonLinear,
 }
 
+/// What a chunk of a memory block currently holds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChunkState {
+    /// The chunk is available for allocation.
+    Free,
+    /// The chunk holds a linear resource.
+    Linear,
+    /// The chunk holds a non-linear resource.
+    NonLinear,
+}
+
+impl From<ResourceType> for ChunkState {
+    fn from(resource_type: ResourceType) -> Self {
+        match resource_type {
+            ResourceType::Linear => ChunkState::Linear,
+            ResourceType::NonLinear => ChunkState::NonLinear,
+        }
+    }
+}
+
+/// Where an allocation was made, so that it can be freed by
+/// the right mechanism.
+#[derive(Clone, Copy, Debug)]
+pub enum AllocationSource {
+    /// A chunk of a block, managed by the TLSF structures.
+    Chunk {
+        memory_type: usize,
+        resource_type: ResourceType,
+        lifetime: Lifetime,
+        block: usize,
+        chunk: ChunkId,
+    },
+    /// A slot of a small-allocation slab.
+    Slab {
+        memory_type: usize,
+        slab: usize,
+        slot: u32,
+    },
+}
+
+impl AllocationSource {
+    pub fn memory_type(&self) -> usize {
+        match *self {
+            AllocationSource::Chunk { memory_type, .. } => memory_type,
+            AllocationSource::Slab { memory_type, .. } => memory_type,
+        }
+    }
+}
+
 /// Portion of memory that is sub-allocated (managed) within a
 /// block.
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub struct MemoryChunk {
     /// Size of the chunk in bytes.
     pub size: u64,
@@ -36,6 +114,40 @@ pub struct MemoryChunk {
     pub prev: Option<ChunkId>,
     /// Index of the next chunk in the block.
     pub next: Option<ChunkId>,
+    /// Whether the chunk is free or allocated, and to what.
+    pub state: ChunkState,
+    /// Name given to the allocation for debugging, if any.
+    pub name: Option<String>,
+}
+
+/// Snapshot of a chunk, as reported by
+/// [`Allocator::layout`](super::Allocator::layout).
+#[derive(Clone, Debug)]
+pub struct ChunkLayout {
+    pub offset: u64,
+    pub size: u64,
+    pub state: ChunkState,
+    pub name: Option<String>,
+}
+
+/// Snapshot of a memory block and its chunks, in address
+/// order.
+#[derive(Clone, Debug)]
+pub struct BlockLayout {
+    /// Index of the memory type the block was allocated from.
+    pub memory_type: usize,
+    /// Properties of that memory type.
+    pub properties: vk::MemoryPropertyFlags,
+    /// Kind of resources the block holds.
+    pub resource_type: ResourceType,
+    /// Lifetime class of the allocations the block holds.
+    pub lifetime: Lifetime,
+    /// Size of the block in bytes.
+    pub size: u64,
+    /// Number of bytes allocated from the block.
+    pub allocated: u64,
+    /// Chunks of the block, free and allocated.
+    pub chunks: Vec<ChunkLayout>,
 }
 
 /// Unique identifier of a chunk within a memory block. This is
@@ -55,148 +167,545 @@ pub struct MemoryBlock {
     chunks: HashMap<ChunkId, MemoryChunk>,
     /// Number of bytes currently allocated from the block.
     allocated: u64,
+    /// Host pointer to the start of the block, if its memory
+    /// type is `HOST_VISIBLE`. The block stays mapped for its
+    /// whole lifetime.
+    mapped: Option<NonNull<u8>>,
 }
 
-/// All blocks are allocated with a size of 256 MiB.
+/// Largest size of a memory block, 256 MiB, which is also the
+/// largest chunk the TLSF structure can hold.
 const MEM_BLOCK_SIZE: u64 = 256 * 1024 * 1024;
 
+/// Smallest size of a memory block, 1 MiB.
+const MIN_BLOCK_SIZE: u64 = 1024 * 1024;
+
+/// Number of blocks a heap should hold at least. Every pool of
+/// a region (each kind of resources and lifetime class) has
+/// blocks of its own, so blocks as large as a small heap would
+/// run it out of memory as soon as a second pool is used: a
+/// 256 MiB host-visible heap (the PCIe BAR, typically) can't
+/// even hold two 256 MiB blocks.
+const BLOCKS_PER_HEAP: u64 = 16;
+
+/// Largest power of two that is at most `value` (0 for 0).
+fn floor_power_of_two(value: u64) -> u64 {
+    match value {
+        0 => 0,
+        _ => 1 << value.ilog2(),
+    }
+}
+
 impl MemoryBlock {
+    #[profiling::function]
     pub fn new(
         device: &Device,
         size: u64,
         memory_type: usize,
-    ) -> Self {
+        properties: vk::MemoryPropertyFlags,
+    ) -> Result<Self, AllocationError> {
         // Memory info: the block is allocated from the device
         // with a specific size and memory type.
         let memory_info = vk::MemoryAllocateInfo::builder()
             .allocation_size(size)
             .memory_type_index(memory_type as u32);
 
-        // Allocate memory on the device.
+        // Allocate memory on the device. Running out of memory
+        // is an error the application can recover from (by
+        // freeing resources, or lowering the quality of its
+        // textures), so it is reported rather than fatal.
         let memory = unsafe {
             device.allocate_memory(&memory_info, None)
-                .expect("Failed to allocate memory.")
+                .map_err(AllocationError::OutOfDeviceMemory)?
         };
+        track_created(memory);
 
+        // Host-visible blocks are mapped once and for all, right
+        // after being allocated. Mapping is not free, and a
+        // resource updated every frame (uniform buffers, for
+        // example) would otherwise pay for a map/unmap pair
+        // each time; sub-allocations simply get a pointer into
+        // the block's mapping instead. Vulkan allows memory to
+        // stay mapped while the GPU uses it, as long as writes
+        // are made visible (see `Allocator::flush`).
+        let mapped = if properties.contains(vk::MemoryPropertyFlags::HOST_VISIBLE) {
+            let ptr = unsafe {
+                device.map_memory(memory, 0, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty())
+            };
+            match ptr {
+                Ok(ptr) => NonNull::new(ptr.cast::<u8>()),
+                Err(error) => {
+                    track_destroyed(memory);
+                    unsafe { device.free_memory(memory, None) };
+                    return Err(AllocationError::MapFailed(error));
+                }
+            }
+        } else {
+            None
+        };
+
+        Ok(Self::with_memory(memory, size, mapped))
+    }
+
+    /// Empty block of `size` bytes, backed by `memory`.
+    fn with_memory(memory: vk::DeviceMemory, size: u64, mapped: Option<NonNull<u8>>) -> Self {
         // At first the block is empty, so it contains a single
         // chunk at offset 0 that spans the entire size of the
-        // block (minus one byte to avoid going out of range of
-        // the TLSF structure).
+        // block.
         let chunk = MemoryChunk {
-            size: size-1,
+            size,
             offset: 0,
             prev: None,
             next: None,
+            state: ChunkState::Free,
+            name: None,
         };
-        let chunks = HashMap::from([(0, chunk)]);
 
         Self {
             memory,
             size,
-            chunks,
+            chunks: HashMap::from([(0, chunk)]),
             allocated: 0,
+            mapped,
+        }
+    }
+
+    /// Mark the first `size` bytes of the free chunk at
+    /// `offset` as allocated; whatever is left of the chunk
+    /// becomes a new free chunk right after it.
+    pub fn claim(&mut self, offset: ChunkId, size: u64, state: ChunkState) {
+        let chunk = self.chunks.get_mut(&offset).expect("No chunk at this offset.");
+        debug_assert_eq!(chunk.state, ChunkState::Free, "Chunk is already allocated.");
+
+        let remainder = chunk.size - size;
+        let next = chunk.next;
+        chunk.size = size;
+        chunk.state = state;
+        self.allocated += size;
+
+        if remainder > 0 {
+            // The new free chunk is linked in between the
+            // claimed one and its former successor.
+            let free_offset = offset + size;
+            self.chunks.get_mut(&offset).unwrap().next = Some(free_offset);
+            if let Some(next) = next {
+                self.chunks.get_mut(&next).unwrap().prev = Some(free_offset);
+            }
+
+            self.chunks.insert(free_offset, MemoryChunk {
+                size: remainder,
+                offset: free_offset,
+                prev: Some(offset),
+                next,
+                state: ChunkState::Free,
+                name: None,
+            });
+        }
+    }
+
+    /// Mark the chunk at `offset` as free again, and merge it
+    /// with its free neighbours, so that the block doesn't end
+    /// up fragmented into many small free chunks. The
+    /// neighbours are taken out of `tlsf`, and the resulting
+    /// chunk is inserted into it.
+    pub fn release(&mut self, offset: ChunkId, tlsf: &mut Tlsf, block: usize) {
+        let chunk = self.chunks.get_mut(&offset).expect("No chunk at this offset.");
+        debug_assert_ne!(chunk.state, ChunkState::Free, "Chunk freed twice.");
+
+        chunk.state = ChunkState::Free;
+        chunk.name = None;
+        self.allocated -= chunk.size;
+        let (prev, next) = (chunk.prev, chunk.next);
+
+        // Free neighbours are listed in the TLSF structure with
+        // their own size, so they are taken out of it before
+        // being merged: the next chunk into this one...
+        if let Some(next) = next.filter(|n| self.chunks[n].state == ChunkState::Free) {
+            tlsf.remove_chunk(self.chunks[&next].size, next, block);
+            self.absorb_next(offset, next);
         }
+
+        // ...and this one into the previous chunk.
+        let offset = match prev.filter(|p| self.chunks[p].state == ChunkState::Free) {
+            Some(prev) => {
+                tlsf.remove_chunk(self.chunks[&prev].size, prev, block);
+                self.absorb_next(prev, offset);
+                prev
+            }
+            None => offset,
+        };
+
+        tlsf.insert_chunk(self.chunks[&offset].size, offset, block);
+    }
+
+    fn absorb_next(&mut self, offset: ChunkId, next: ChunkId) {
+        let absorbed = self.chunks.remove(&next).unwrap();
+        let chunk = self.chunks.get_mut(&offset).unwrap();
+
+        chunk.size += absorbed.size;
+        chunk.next = absorbed.next;
+        if let Some(after) = absorbed.next {
+            self.chunks.get_mut(&after).unwrap().prev = Some(offset);
+        }
+    }
+
+    /// Give a debug name to the allocated chunk containing
+    /// `offset`.
+    pub fn set_name(&mut self, offset: u64, name: String) {
+        if let Some(chunk) = self.chunks
+            .values_mut()
+            .find(|c| c.state != ChunkState::Free && (c.offset..c.offset + c.size).contains(&offset))
+        {
+            chunk.name = Some(name);
+        }
+    }
+
+    /// Chunks of the block in address order, reconstructed from
+    /// the prev/next links. This doubles as a consistency check
+    /// of the chunk list: every chunk must start where the
+    /// previous one ends, link back to it, and be reachable
+    /// from the first chunk.
+    pub fn layout(&self) -> Vec<ChunkLayout> {
+        let mut chunks = Vec::with_capacity(self.chunks.len());
+        let mut current = Some(0);
+        let mut prev = None;
+        let mut end = 0;
+
+        while let Some(offset) = current {
+            let chunk = self.chunks
+                .get(&offset)
+                .unwrap_or_else(|| panic!("Dangling link to chunk {:#x}.", offset));
+            assert_eq!(chunk.prev, prev, "Broken back link at chunk {:#x}.", offset);
+            assert_eq!(chunk.offset, end, "Gap or overlap before chunk {:#x}.", offset);
+
+            chunks.push(ChunkLayout {
+                offset: chunk.offset,
+                size: chunk.size,
+                state: chunk.state,
+                name: chunk.name.clone(),
+            });
+
+            end = chunk.offset + chunk.size;
+            prev = Some(offset);
+            current = chunk.next;
+        }
+
+        assert_eq!(chunks.len(), self.chunks.len(), "Unreachable chunks in block.");
+        chunks
     }
+}
+
+/// Blocks holding a single kind of resources of a single
+/// lifetime class, with the TLSF structure managing their free
+/// chunks.
+struct BlockPool {
+    blocks: Vec<MemoryBlock>,
+    free: Tlsf,
+}
 
-    pub fn get_chunk(&self, offset: u64) -> MemoryChunk {
-        self.chunks[&offset]
+impl BlockPool {
+    fn new() -> Self {
+        Self { blocks: Vec::new(), free: Tlsf::new() }
     }
 }
 
 /// Memory pool blocks are allocated from. Each region
 /// corresponds to a single Vulkan memory type.
 pub struct MemoryRegion {
-    /// List of memory blocks for linear resources.
-    blocks_linear: Vec<MemoryBlock>,
-    /// List of memory blocks for non-linear resources.
-    blocks_non_linear: Vec<MemoryBlock>,
-    /// TLSF structure to manage free chunks in linear blocks.
-    free_linear: Tlsf,
-    /// TLSF structure to manage free chunks in non-linear
-    /// blocks.
-    free_non_linear: Tlsf,
+    /// Pools of blocks, by kind of resources and lifetime
+    /// class.
+    pools: HashMap<(ResourceType, Lifetime), BlockPool>,
     /// Index of the memory type of the region.
     pub memory_type: usize,
     /// Properties of the memory type of the region.
     pub properties: vk::MemoryPropertyFlags,
+    /// Slabs serving small linear allocations.
+    slabs: Vec<Slab>,
+    /// Whether small linear allocations go to slabs.
+    pub small_pooling: bool,
+    /// Size of the blocks of the region, and largest size a
+    /// block can have for requests that don't fit in one, both
+    /// following from the heap of the memory type.
+    block_size: u64,
+    max_block_size: u64,
 }
 
 impl MemoryRegion {
+    /// Region of the memory type `memory_type`, whose heap has
+    /// `available` bytes for the application (its size, or its
+    /// budget when the device reports one).
     pub fn new(
         memory_type: usize,
         properties: vk::MemoryPropertyFlags,
+        available: u64,
     ) -> Self {
+        // Blocks are powers of two, so that flushed ranges
+        // rounded to the atom size never go past their end;
+        // they are sized so that the heap holds a fair number
+        // of them, within bounds, and never larger than the
+        // heap itself.
+        let max_block_size = floor_power_of_two(available).min(MEM_BLOCK_SIZE);
+        let block_size = floor_power_of_two(available / BLOCKS_PER_HEAP)
+            .clamp(MIN_BLOCK_SIZE, MEM_BLOCK_SIZE)
+            .min(max_block_size);
+
         Self {
-            blocks_linear: Vec::new(),
-            blocks_non_linear: Vec::new(),
-            free_linear: Tlsf::new(),
-            free_non_linear: Tlsf::new(),
+            pools: HashMap::new(),
             properties,
             memory_type,
+            slabs: Vec::new(),
+            small_pooling: true,
+            block_size,
+            max_block_size,
         }
     }
 
+    #[profiling::function]
     pub fn allocate(
         &mut self,
         device: &Device,
         size: u64,
         alignment: u64,
         resource_type: ResourceType,
-    ) -> Allocation {
+        lifetime: Lifetime,
+    ) -> Result<Allocation, AllocationError> {
+        // Tiny linear resources (tens of bytes, typically) would
+        // each take a TLSF chunk, with bookkeeping larger than
+        // the payload, and fragment the blocks; they are
+        // instead served from slabs of fixed-size slots.
+        // Non-linear resources (images) are never that small.
+        match (resource_type, small_bin(size, alignment)) {
+            (ResourceType::Linear, Some(bin)) if self.small_pooling => {
+                self.allocate_small(device, size, bin, lifetime)
+            }
+            _ => self.allocate_chunk(device, size, alignment, resource_type, lifetime),
+        }
+    }
+
+    fn allocate_small(
+        &mut self,
+        device: &Device,
+        size: u64,
+        bin_size: u64,
+        lifetime: Lifetime,
+    ) -> Result<Allocation, AllocationError> {
+        // Find a slab of the right bin and lifetime with a free
+        // slot, or carve a new one out of a block of that
+        // lifetime. Pages are aligned to the largest bin, so
+        // that all slots are aligned to their own size.
+        let slab = match self.slabs
+            .iter()
+            .position(|s| s.bin_size == bin_size && s.lifetime == lifetime && !s.is_full())
+        {
+            Some(slab) => slab,
+            None => {
+                let largest_bin = SMALL_BINS[SMALL_BINS.len() - 1];
+                let page = self.allocate_chunk(device, SLAB_PAGE_SIZE, largest_bin, ResourceType::Linear, lifetime)?;
+                self.set_name(page.memory, page.offset, &format!("slab ({} B slots)", bin_size));
+                self.slabs.push(Slab::new(bin_size, lifetime, page));
+                self.slabs.len() - 1
+            }
+        };
+
+        let slot = self.slabs[slab].take().expect("Slab has no free slot.");
+        let slab_ref = &self.slabs[slab];
+        let offset = slab_ref.slot_offset(slot);
+        let mapped = slab_ref.page.mapped
+            .map(|ptr| unsafe { NonNull::new_unchecked(ptr.as_ptr().add((offset - slab_ref.page.offset) as usize)) });
+
+        Ok(Allocation {
+            memory: slab_ref.page.memory,
+            offset,
+            size,
+            mapped,
+            coherent: slab_ref.page.coherent,
+            source: AllocationSource::Slab {
+                memory_type: self.memory_type,
+                slab,
+                slot,
+            },
+        })
+    }
+
+    fn allocate_chunk(
+        &mut self,
+        device: &Device,
+        size: u64,
+        alignment: u64,
+        resource_type: ResourceType,
+        lifetime: Lifetime,
+    ) -> Result<Allocation, AllocationError> {
         // Linear and non-linear resources are managed
         // independently, in order to avoid having to deal with
-        // granularity.
-        let (tlsf, blocks) = match resource_type {
-            ResourceType::Linear => (&mut self.free_linear, &mut self.blocks_linear),
-            ResourceType::NonLinear => (&mut self.free_non_linear, &mut self.blocks_non_linear),
-        };
+        // granularity. Each lifetime class also gets blocks of
+        // its own: per-frame and transient allocations come and
+        // go constantly, and mixed with persistent ones, every
+        // long-lived resource left in a block pins it, so that
+        // the space freed around it is too fragmented to be
+        // reused, and new blocks keep being allocated. Apart,
+        // short-lived allocations recycle the same few blocks.
+        let BlockPool { free: tlsf, blocks } = self.pools
+            .entry((resource_type, lifetime))
+            .or_insert_with(BlockPool::new);
+
+        let padded_size = padded_size(size, alignment);
 
         // Request a free chunk to allocate from.
-        let (block, offset) = match tlsf.get_free_chunk(size) {
-            Some(chunk) => {
-                // If a free chunk was found, return its block
-                // and its offset.
-                (chunk.block, chunk.offset)
-            }
+        let chunk = match tlsf.get_free_chunk(padded_size) {
+            // If a free chunk was found, use it directly.
+            Some(chunk) => chunk,
             None => {
                 // Else, there is no free space available, so
                 // we first need to create a new memory block.
+                // A request too large for the region's blocks
+                // gets a larger one: twice its size, since the
+                // TLSF bins round sizes up, and the rest of the
+                // block serves later allocations. It can't be
+                // larger than the heap allows, though.
+                let block_size = padded_size
+                    .saturating_mul(2)
+                    .checked_next_power_of_two()
+                    .unwrap_or(u64::MAX)
+                    .max(self.block_size);
+                if block_size > self.max_block_size {
+                    return Err(AllocationError::TooLarge { size, block_size: self.max_block_size });
+                }
+
                 blocks.push(MemoryBlock::new(
                     device,
-                    MEM_BLOCK_SIZE,
+                    block_size,
                     self.memory_type,
-                ));
+                    self.properties,
+                )?);
 
                 // The block is the last of the list; it is of
                 // course empty, so it contains a single free
-                // chunk at offset 0.
+                // chunk at offset 0, which we register and
+                // immediately allocate from.
                 let block = blocks.len()-1;
-                let offset = 0;
-
                 tlsf.insert_chunk(
-                    MEM_BLOCK_SIZE-1,
-                    offset,
+                    block_size,
+                    0,
                     block,
                 );
 
-                (block, offset)
+                tlsf.get_free_chunk(padded_size)
+                    .ok_or(AllocationError::TooLarge { size, block_size })?
             }
         };
 
+        // The block keeps track of the chunk, for debugging and
+        // (eventually) freeing.
+        let block = chunk.block;
+        blocks[block].claim(chunk.offset, chunk.size, resource_type.into());
+
         // The offset must be aligned to the value given by the
         // memory requirements.
-        let offset = align_up(offset, alignment);
+        let offset = align_up(chunk.offset, alignment);
         
         // The chunk is now in place, so we can return the
-        // offset and the memory handle of the block.
-        Allocation {
+        // offset and the memory handle of the block, along with
+        // a pointer to the allocation if the block is mapped.
+        let mapped = blocks[block].mapped
+            .map(|ptr| unsafe { NonNull::new_unchecked(ptr.as_ptr().add(offset as usize)) });
+
+        Ok(Allocation {
             memory: blocks[block].memory,
             offset,
+            size,
+            mapped,
+            coherent: self.properties.contains(vk::MemoryPropertyFlags::HOST_COHERENT),
+            source: AllocationSource::Chunk {
+                memory_type: self.memory_type,
+                resource_type,
+                lifetime,
+                block,
+                chunk: chunk.offset,
+            },
+        })
+    }
+
+    /// Free an allocation made from this region. Slab slots go
+    /// back to their slab (the page itself is kept for later
+    /// small allocations), and chunks back to the TLSF
+    /// structures.
+    pub fn free(&mut self, source: AllocationSource) {
+        match source {
+            AllocationSource::Slab { slab, slot, .. } => {
+                self.slabs[slab].release(slot);
+            }
+            AllocationSource::Chunk { resource_type, lifetime, block, chunk, .. } => {
+                let pool = self.pools
+                    .get_mut(&(resource_type, lifetime))
+                    .expect("Freeing from a pool that doesn't exist.");
+
+                pool.blocks[block].release(chunk, &mut pool.free, block);
+            }
+        }
+    }
+
+    /// Give a debug name to the allocation at `offset` in
+    /// `memory`, if it belongs to this region.
+    pub fn set_name(&mut self, memory: vk::DeviceMemory, offset: u64, name: &str) -> bool {
+        match self.pools
+            .values_mut()
+            .flat_map(|pool| pool.blocks.iter_mut())
+            .find(|b| b.memory == memory)
+        {
+            Some(block) => {
+                block.set_name(offset, name.to_string());
+                true
+            }
+            None => false,
         }
     }
+
+    /// Free the device memory of every block of the region.
+    pub fn destroy(&mut self, device: &Device) {
+        self.slabs.clear();
+        self.pools
+            .drain()
+            .flat_map(|(_, pool)| pool.blocks)
+            .for_each(|block| {
+                track_destroyed(block.memory);
+                unsafe { device.free_memory(block.memory, None) };
+            });
+    }
+
+    /// Layout of every block of the region, by kind of
+    /// resources and lifetime class.
+    pub fn layout(&self) -> Vec<BlockLayout> {
+        let mut pools = self.pools.iter().collect::<Vec<_>>();
+        pools.sort_by_key(|(&(resource_type, lifetime), _)| (resource_type as u8, lifetime as u8));
+
+        pools
+            .into_iter()
+            .flat_map(|(&(resource_type, lifetime), pool)| {
+                pool.blocks.iter().map(move |block| (resource_type, lifetime, block))
+            })
+            .map(|(resource_type, lifetime, block)| BlockLayout {
+                memory_type: self.memory_type,
+                properties: self.properties,
+                resource_type,
+                lifetime,
+                size: block.size,
+                allocated: block.allocated,
+                chunks: block.layout(),
+            })
+            .collect()
+    }
+}
+
+/// Size of the chunk to request for an allocation. The offset
+/// handed out has to be aligned, which may waste up to
+/// `alignment - 1` bytes at the start of the chunk, so that
+/// much more is requested. The TLSF bins also start at 16
+/// bytes, so nothing smaller can be handed out.
+fn padded_size(size: u64, alignment: u64) -> u64 {
+    (size + alignment - 1).max(16)
 }
 
-fn align_down(value: u64, alignment: u64) -> u64 {
+pub(crate) fn align_down(value: u64, alignment: u64) -> u64 {
     // Align a value down to another value (the alignment): let
     // us take for example V = 0x3F and an alignment A = 0x20.
     // We have:
@@ -219,8 +728,95 @@ fn align_down(value: u64, alignment: u64) -> u64 {
     value & !(alignment - 1)
 }
 
-fn align_up(value: u64, alignment: u64) -> u64 {
+pub(crate) fn align_up(value: u64, alignment: u64) -> u64 {
     // Aligning up is aligning down the value shifted by one
     // page (that is, value + alignment - 1).
     align_down(value + alignment - 1, alignment)
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Empty block with its chunk listed in `tlsf`, as in
+    /// `MemoryRegion::allocate_chunk`.
+    fn block(size: u64, tlsf: &mut Tlsf) -> MemoryBlock {
+        tlsf.insert_chunk(size, 0, 0);
+        MemoryBlock::with_memory(vk::DeviceMemory::null(), size, None)
+    }
+
+    /// Allocate from the block like `MemoryRegion::allocate_chunk`
+    /// does, and return the chunk claimed and the offset handed
+    /// out.
+    fn allocate(block: &mut MemoryBlock, tlsf: &mut Tlsf, size: u64, alignment: u64) -> (ChunkId, u64) {
+        let chunk = tlsf.get_free_chunk(padded_size(size, alignment)).unwrap();
+        block.claim(chunk.offset, chunk.size, ChunkState::Linear);
+
+        let offset = align_up(chunk.offset, alignment);
+        assert!(offset + size <= chunk.offset + chunk.size, "Allocation overflows its chunk.");
+        (chunk.offset, offset)
+    }
+
+    fn check_layout(block: &MemoryBlock) -> Vec<ChunkLayout> {
+        let layout = block.layout();
+        assert_eq!(layout.iter().map(|c| c.size).sum::<u64>(), block.size);
+
+        let allocated = layout.iter().filter(|c| c.state != ChunkState::Free).map(|c| c.size).sum::<u64>();
+        assert_eq!(allocated, block.allocated);
+        layout
+    }
+
+    #[test]
+    fn offsets_are_aligned() {
+        let mut tlsf = Tlsf::new();
+        let mut block = block(MIN_BLOCK_SIZE, &mut tlsf);
+
+        // The first allocation leaves the next free chunk at an
+        // unaligned offset.
+        allocate(&mut block, &mut tlsf, 24, 1);
+        for (size, alignment) in [(1000, 256), (4, 4), (3000, 4096), (1, 64), (512, 512)] {
+            let (_, offset) = allocate(&mut block, &mut tlsf, size, alignment);
+            assert_eq!(offset % alignment, 0);
+        }
+        check_layout(&block);
+    }
+
+    #[test]
+    fn fresh_block_remainder_is_not_handed_out_twice() {
+        let mut tlsf = Tlsf::new();
+        let mut block = block(MIN_BLOCK_SIZE, &mut tlsf);
+
+        let (first, _) = allocate(&mut block, &mut tlsf, 1000, 1);
+        let (second, _) = allocate(&mut block, &mut tlsf, 1000, 1);
+        assert_eq!(first, 0);
+        assert_ne!(second, first);
+
+        // The freed chunk is reused, without touching the
+        // second allocation.
+        block.release(first, &mut tlsf, 0);
+        let (third, _) = allocate(&mut block, &mut tlsf, 1000, 1);
+        assert_ne!(third, second);
+
+        let layout = check_layout(&block);
+        assert_eq!(layout.iter().filter(|c| c.state != ChunkState::Free).count(), 2);
+    }
+
+    #[test]
+    fn layout_covers_the_block() {
+        let mut tlsf = Tlsf::new();
+        let mut block = block(MIN_BLOCK_SIZE, &mut tlsf);
+        assert_eq!(check_layout(&block).len(), 1);
+
+        let chunks = [100, 5000, 64, 70000]
+            .map(|size| allocate(&mut block, &mut tlsf, size, 16).0);
+        check_layout(&block);
+
+        // Freeing everything merges the chunks back into one.
+        for chunk in [chunks[1], chunks[0], chunks[3], chunks[2]] {
+            block.release(chunk, &mut tlsf, 0);
+            check_layout(&block);
+        }
+        let layout = check_layout(&block);
+        assert_eq!(layout.len(), 1);
+        assert_eq!(layout[0].state, ChunkState::Free);
+    }
+}
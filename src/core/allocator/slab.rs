@@ -0,0 +1,146 @@
+use super::{Allocation, Lifetime};
+
+/// Slot sizes of the small-allocation bins. Requests up to the
+/// largest one are served from slabs instead of the TLSF
+/// structures.
+pub const SMALL_BINS: [u64; 4] = [64, 256, 1024, 4096];
+
+/// Size of the page carved out of a block for each slab.
+pub const SLAB_PAGE_SIZE: u64 = 64 * 1024;
+
+/// Bin a small allocation goes to, if any. Pages are aligned to
+/// the largest bin, so every slot is aligned to its own size,
+/// which covers any alignment up to it.
+pub fn small_bin(size: u64, alignment: u64) -> Option<u64> {
+    let needed = size.max(alignment);
+    SMALL_BINS.iter().copied().find(|&bin| bin >= needed)
+}
+
+/// Page of memory divided into slots of a single size, for
+/// allocations too small to be worth a TLSF chunk of their
+/// own (per-object uniform blocks, tiny index buffers, etc).
+pub struct Slab {
+    /// Size of each slot.
+    pub bin_size: u64,
+    /// Lifetime class of the allocations in the slab.
+    pub lifetime: Lifetime,
+    /// Chunk backing the slab.
+    pub page: Allocation,
+    /// Bitmap of the free slots, one bit per slot.
+    free: Vec<u64>,
+    /// Number of free slots.
+    free_count: u32,
+}
+
+impl Slab {
+    pub fn new(bin_size: u64, lifetime: Lifetime, page: Allocation) -> Self {
+        // Every slot starts free, except for the bits past the
+        // last slot in the last word of the bitmap.
+        let slots = (SLAB_PAGE_SIZE / bin_size) as u32;
+        let mut free = vec![!0u64; slots.div_ceil(64) as usize];
+        if slots % 64 != 0 {
+            *free.last_mut().unwrap() = (1 << (slots % 64)) - 1;
+        }
+
+        Self {
+            bin_size,
+            lifetime,
+            page,
+            free,
+            free_count: slots,
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.free_count == 0
+    }
+
+    /// Take the first free slot, if any.
+    pub fn take(&mut self) -> Option<u32> {
+        let (word, bits) = self.free
+            .iter_mut()
+            .enumerate()
+            .find(|(_, bits)| **bits != 0)?;
+
+        let bit = bits.trailing_zeros();
+        *bits &= !(1 << bit);
+        self.free_count -= 1;
+
+        Some(word as u32 * 64 + bit)
+    }
+
+    /// Give a slot back to the slab.
+    pub fn release(&mut self, slot: u32) {
+        let (word, bit) = ((slot / 64) as usize, slot % 64);
+        debug_assert!(self.free[word] & (1 << bit) == 0, "Slot {} freed twice.", slot);
+
+        self.free[word] |= 1 << bit;
+        self.free_count += 1;
+    }
+
+    /// Offset of a slot within the page's memory object.
+    pub fn slot_offset(&self, slot: u32) -> u64 {
+        self.page.offset + slot as u64 * self.bin_size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::AllocationSource;
+    use vulkanalia::prelude::v1_0::*;
+
+    fn slab(bin_size: u64) -> Slab {
+        let page = Allocation {
+            memory: vk::DeviceMemory::null(),
+            offset: 4096,
+            size: SLAB_PAGE_SIZE,
+            mapped: None,
+            coherent: true,
+            source: AllocationSource::Slab { memory_type: 0, slab: 0, slot: 0 },
+        };
+
+        Slab::new(bin_size, Lifetime::Persistent, page)
+    }
+
+    #[test]
+    fn small_requests_pick_the_smallest_bin() {
+        assert_eq!(small_bin(10, 4), Some(64));
+        assert_eq!(small_bin(64, 64), Some(64));
+        assert_eq!(small_bin(100, 256), Some(256));
+        assert_eq!(small_bin(4096, 1), Some(4096));
+        assert_eq!(small_bin(4097, 1), None);
+        assert_eq!(small_bin(16, 8192), None);
+    }
+
+    #[test]
+    fn slots_round_trip() {
+        // 64 slots of 1 KiB: exactly one word of the bitmap.
+        let mut slab = slab(1024);
+        let slots = (0..64).map(|_| slab.take().unwrap()).collect::<Vec<_>>();
+        assert_eq!(slots, (0..64).collect::<Vec<_>>());
+        assert!(slab.is_full());
+        assert_eq!(slab.take(), None);
+
+        slab.release(5);
+        assert!(!slab.is_full());
+        assert_eq!(slab.take(), Some(5));
+        assert_eq!(slab.slot_offset(5), 4096 + 5 * 1024);
+    }
+
+    #[test]
+    fn partial_bitmap_words_hold_no_extra_slots() {
+        // 16 slots of 4 KiB, in the first bits of the word.
+        let mut slab = slab(4096);
+        for slot in 0..16 {
+            assert_eq!(slab.take(), Some(slot));
+        }
+        assert_eq!(slab.take(), None);
+
+        slab.release(15);
+        slab.release(0);
+        assert_eq!(slab.take(), Some(0));
+        assert_eq!(slab.take(), Some(15));
+        assert!(slab.is_full());
+    }
+}
@@ -1,7 +1,7 @@
 /// Chunk metadata used by the TLSF allocator.
 pub struct ChunkInfo {
     /// Size of the chunk in bytes.
-    size: u64,
+    pub size: u64,
     /// Offset of the chunk within the memory block.
     pub offset: u64,
     /// Index of the block the chunk is part of.
@@ -12,9 +12,9 @@ pub struct ChunkInfo {
 type FreeList = Vec<ChunkInfo>;
 
 /// Number of first level bins. The first level super-blocks go
-/// from 2^4 (16 b) to 2^27 (128 Mb), so there are 27 - 4 = 23
-/// bins.
-const FL_BIN_COUNT: usize = 23;
+/// from 2^4 (16 b) to 2^28 (256 Mb, a whole block) included,
+/// so there are 28 - 4 + 1 = 25 bins.
+const FL_BIN_COUNT: usize = 25;
 
 /// Number of second level bins. We use a single byte for the
 /// bitmap, so there are 8 bins, each corresponding to a range
@@ -60,6 +60,20 @@ impl Tlsf {
         );
     }
 
+    /// Take a specific free chunk out of the structure, when it
+    /// is merged with a neighbour being freed.
+    pub fn remove_chunk(&mut self, size: u64, offset: u64, block: usize) {
+        let (fl, sl) = self.get_indices(size);
+        let list = &mut self.free_lists[fl][sl];
+        let index = list
+            .iter()
+            .position(|c| c.offset == offset && c.block == block)
+            .expect("Free chunk is not listed.");
+        list.swap_remove(index);
+
+        self.clear_empty_bin(fl, sl);
+    }
+
     pub fn get_free_chunk(
         &mut self,
         size: u64,
@@ -70,7 +84,9 @@ impl Tlsf {
         // that this is still O(1), since the bitmaps are fixed
         // size.
         let (fl, sl) = self.find_available(size)?;
-        let chunk = self.free_lists[fl][sl].pop()?;
+        let mut chunk = self.free_lists[fl][sl].pop()?;
+
+        self.clear_empty_bin(fl, sl);
 
         // The minimum size of this free chunk is the size of
         // the allocation rounded up to the next second level
@@ -79,16 +95,31 @@ impl Tlsf {
         let minimum_size = self.next_block_size(size);
         
         // Then, the remaining free space is re-inserted back
-        // into the TLSF structure, if it is large enough.
+        // into the TLSF structure, if it is large enough;
+        // otherwise it stays part of the returned chunk, whose
+        // size is what the allocation actually consumes.
         let remainder = chunk.size - minimum_size;
         if remainder > 16 {
             let offset = chunk.offset + minimum_size;
             self.insert_chunk(remainder, offset, chunk.block);
+            chunk.size = minimum_size;
         }
 
         Some(chunk)
     }
 
+    fn clear_empty_bin(&mut self, fl: usize, sl: usize) {
+        // If that was the last chunk of its bin, the bitmaps
+        // have to say so, or later searches would land on an
+        // empty list.
+        if self.free_lists[fl][sl].is_empty() {
+            self.second_level[fl] &= !(1 << sl);
+            if self.second_level[fl] == 0 {
+                self.first_level &= !(1 << fl);
+            }
+        }
+    }
+
     fn find_available(
         &self,
         size: u64,
@@ -102,7 +133,7 @@ impl Tlsf {
         // one after that of the current size (chunks of the
         // same block might be smaller than the requested
         // size).
-        let sl = self.second_level[start_fl] & (!0 << (start_sl+1));
+        let sl = self.second_level[start_fl] & (!0u8).checked_shl(start_sl as u32 + 1).unwrap_or(0);
         
         if sl == 0 {
             // If no second level blocks in the current superblock
@@ -157,4 +188,59 @@ impl Tlsf {
         // where (fl,sl) are first and second level indices.
         ((1 << fl) as f32 * (1.0 + (sl+1) as f32/8.0)) as u64
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn emptied_bins_clear_their_bits() {
+        let mut tlsf = Tlsf::new();
+
+        // A 64-byte chunk is alone in bin (2, 0); a 16-byte
+        // request takes it, and the rest goes to a lower bin.
+        tlsf.insert_chunk(64, 0, 0);
+        assert_eq!(tlsf.get_indices(64), (2, 0));
+        let chunk = tlsf.get_free_chunk(16).unwrap();
+        assert_eq!(chunk.offset, 0);
+        assert_eq!(tlsf.second_level[2], 0);
+        assert_eq!(tlsf.first_level & (1 << 2), 0);
+
+        // Removing the remainder leaves nothing listed.
+        let (fl, sl) = tlsf.get_indices(64 - chunk.size);
+        tlsf.remove_chunk(64 - chunk.size, chunk.size, 0);
+        assert_eq!(tlsf.second_level[fl] & (1 << sl), 0);
+        assert_eq!(tlsf.first_level, 0);
+        assert!(tlsf.get_free_chunk(16).is_none());
+    }
+
+    #[test]
+    fn block_sized_chunks_land_in_the_last_bin() {
+        // The largest memory block, 256 MiB.
+        let size = 1 << 28;
+        let mut tlsf = Tlsf::new();
+        assert_eq!(tlsf.get_indices(size), (FL_BIN_COUNT - 1, 0));
+        tlsf.insert_chunk(size, 0, 3);
+        assert_eq!(tlsf.first_level, 1 << (FL_BIN_COUNT - 1));
+
+        let chunk = tlsf.get_free_chunk(size / 2).unwrap();
+        assert_eq!((chunk.offset, chunk.block), (0, 3));
+        assert!(chunk.size >= size / 2);
+    }
+
+    #[test]
+    fn chunks_fit_the_request() {
+        let mut tlsf = Tlsf::new();
+        tlsf.insert_chunk(1 << 20, 0, 0);
+
+        // Each chunk is at least as large as requested, and
+        // they follow each other without overlapping.
+        let mut end = 0;
+        for size in [16, 17, 100, 1000, 4096, 5000] {
+            let chunk = tlsf.get_free_chunk(size).unwrap();
+            assert!(chunk.size >= size);
+            assert_eq!(chunk.offset, end);
+            end = chunk.offset + chunk.size;
+        }
+    }
+}
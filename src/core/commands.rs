@@ -1,6 +1,6 @@
 use crate::{
     renderer::RenderData,
-    core::queues::*, 
+    core::{queues::*, tracker::*},
 };
 
 use vulkanalia::prelude::v1_0::*;
@@ -37,6 +37,7 @@ pub fn create_command_pools(
 
     for frame in &mut data.frames {
         let command_pool = unsafe { device.create_command_pool(&info, None) }?;
+        track_created(command_pool);
         frame.command_pool = command_pool;
     }
     
@@ -1,20 +1,23 @@
-use std::collections::HashSet;
+use std::{collections::HashSet, fmt};
 
 use crate::{
     renderer::{
         RenderData, 
-        PORTABILITY_MACOS_VERSION, 
-        VALIDATION_ENABLED, 
         VALIDATION_LAYER
     },
     core::{
-        queues::*, 
+        queues::*,
         swapchain::get_swapchain_support,
+        allocator::align_up,
     }
 };
 
 use thiserror::Error;
-use vulkanalia::prelude::v1_0::*;
+use vulkanalia::{
+    prelude::v1_0::*,
+    vk::InstanceV1_1,
+    vk::KhrSurfaceExtension,
+};
 use anyhow::{anyhow, Result};
 use::log::*;
 
@@ -32,15 +35,214 @@ pub const REQUIRED_EXTENSIONS: &[vk::ExtensionName] = &[
     vk::KHR_SYNCHRONIZATION2_EXTENSION.name,
 ];
 
+/// Optional device features requested at renderer creation.
+/// Everything here is off by default, which is what a shipping
+/// configuration wants.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DeviceRequirements {
+    /// "Robust development mode": enables `robust_buffer_access`
+    /// and, when `VK_EXT_robustness2` is available, null
+    /// descriptors and robust image access, so that incomplete
+    /// materials read zeros instead of crashing the GPU. This
+    /// has a performance cost and is meant for development
+    /// only.
+    pub robust_development: bool,
+}
+
+/// Optional features that were actually enabled on the logical
+/// device.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EnabledFeatures {
+    /// Out-of-bounds buffer accesses are bounds-checked.
+    pub robust_buffer_access: bool,
+    /// Descriptors can be written with null handles (for
+    /// example `vk::ImageView::null()`), and reading them
+    /// returns zeros.
+    pub null_descriptor: bool,
+    /// Out-of-bounds image accesses are bounds-checked.
+    pub robust_image_access: bool,
+    /// Samplers can use anisotropic filtering. Software
+    /// implementations like lavapipe or SwiftShader don't
+    /// support it, in which case samplers have to be created
+    /// with `anisotropy_enable` set to false.
+    pub sampler_anisotropy: bool,
+    /// Presents can be tagged with an ID (`VK_KHR_present_id`)
+    /// and waited on (`VK_KHR_present_wait`).
+    pub present_wait: bool,
+    /// Pipeline statistics queries can be used.
+    pub pipeline_statistics_query: bool,
+}
+
+/// Kind of descriptor a buffer sub-allocation is bound as when
+/// using dynamic offsets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DynamicBinding {
+    /// `UNIFORM_BUFFER_DYNAMIC` descriptors, aligned to
+    /// `min_uniform_buffer_offset_alignment`.
+    Uniform,
+    /// `STORAGE_BUFFER_DYNAMIC` descriptors, aligned to
+    /// `min_storage_buffer_offset_alignment`.
+    Storage,
+}
+
+/// Physical device limits the renderer relies on, cached once
+/// when the device is picked.
+#[derive(Clone, Copy, Debug)]
+pub struct DeviceLimits {
+    /// Required alignment of dynamic offsets into uniform
+    /// buffers (commonly 64 or 256 bytes).
+    pub min_uniform_buffer_offset_alignment: u64,
+    /// Required alignment of dynamic offsets into storage
+    /// buffers.
+    pub min_storage_buffer_offset_alignment: u64,
+    /// Granularity of flushes and invalidations of
+    /// non-coherent host-visible memory.
+    pub non_coherent_atom_size: u64,
+    /// Row pitch alignment of buffer-image copies the device
+    /// performs best with.
+    pub optimal_buffer_copy_row_pitch_alignment: u64,
+    /// Highest anisotropy level a sampler can use, when
+    /// anisotropic filtering is enabled at all.
+    pub max_sampler_anisotropy: f32,
+    /// Whether graphics and compute queues support timestamp
+    /// queries, and the number of nanoseconds per timestamp
+    /// tick.
+    pub timestamps: bool,
+    pub timestamp_period: f32,
+}
+
+impl Default for DeviceLimits {
+    fn default() -> Self {
+        // An alignment of 1 means "no constraint", which is
+        // what we want until the actual limits are known.
+        Self {
+            min_uniform_buffer_offset_alignment: 1,
+            min_storage_buffer_offset_alignment: 1,
+            non_coherent_atom_size: 1,
+            optimal_buffer_copy_row_pitch_alignment: 1,
+            max_sampler_anisotropy: 1.0,
+            timestamps: false,
+            timestamp_period: 1.0,
+        }
+    }
+}
+
+impl DeviceLimits {
+    pub fn new(
+        instance: &Instance,
+        physical_device: vk::PhysicalDevice,
+    ) -> Self {
+        let properties = unsafe { instance.get_physical_device_properties(physical_device) };
+        Self::from_limits(&properties.limits)
+    }
+
+    pub fn from_limits(limits: &vk::PhysicalDeviceLimits) -> Self {
+        // The spec guarantees these are powers of two, but
+        // some drivers report 0 for "no requirement", which
+        // would break the alignment math.
+        Self {
+            min_uniform_buffer_offset_alignment: limits.min_uniform_buffer_offset_alignment.max(1),
+            min_storage_buffer_offset_alignment: limits.min_storage_buffer_offset_alignment.max(1),
+            non_coherent_atom_size: limits.non_coherent_atom_size.max(1),
+            optimal_buffer_copy_row_pitch_alignment: limits.optimal_buffer_copy_row_pitch_alignment.max(1),
+            max_sampler_anisotropy: limits.max_sampler_anisotropy.max(1.0),
+            timestamps: limits.timestamp_compute_and_graphics == vk::TRUE,
+            timestamp_period: limits.timestamp_period,
+        }
+    }
+
+    /// Alignment every dynamic offset bound as `binding` must
+    /// respect.
+    pub fn dynamic_offset_alignment(&self, binding: DynamicBinding) -> u64 {
+        match binding {
+            DynamicBinding::Uniform => self.min_uniform_buffer_offset_alignment,
+            DynamicBinding::Storage => self.min_storage_buffer_offset_alignment,
+        }
+    }
+
+    /// Round `offset` up to the next valid dynamic offset.
+    pub fn align_dynamic_offset(&self, offset: u64, binding: DynamicBinding) -> u64 {
+        align_up(offset, self.dynamic_offset_alignment(binding))
+    }
+
+    /// Distance between two consecutive elements of `size`
+    /// bytes packed in the same buffer and addressed with
+    /// dynamic offsets (per-object uniform blocks, for
+    /// example).
+    pub fn dynamic_stride(&self, size: u64, binding: DynamicBinding) -> u64 {
+        self.align_dynamic_offset(size, binding)
+    }
+}
+
 // The macro will create an error type with a Display impl that
 // prints the given string.
 #[derive(Error, Debug)]
 #[error("{0}")]
 pub struct SuitabilityError(pub &'static str);
 
+/// Something a physical device lacks to be used by the
+/// renderer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeviceDeficiency {
+    /// No queue family supports graphics operations.
+    NoGraphicsQueue,
+    /// No queue family can present to the window surface.
+    NoPresentQueue,
+    /// A required device extension is not supported.
+    MissingExtension(String),
+    /// A required device feature is not supported.
+    MissingFeature(&'static str),
+    /// The surface has no format the swapchain could use.
+    NoSurfaceFormats,
+    /// The surface has no presentation mode.
+    NoPresentModes,
+}
+
+impl fmt::Display for DeviceDeficiency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DeviceDeficiency::NoGraphicsQueue => write!(f, "no graphics queue family"),
+            DeviceDeficiency::NoPresentQueue => write!(f, "no queue family can present to the window"),
+            DeviceDeficiency::MissingExtension(name) => write!(f, "missing extension {}", name),
+            DeviceDeficiency::MissingFeature(name) => write!(f, "missing feature {}", name),
+            DeviceDeficiency::NoSurfaceFormats => write!(f, "no surface format"),
+            DeviceDeficiency::NoPresentModes => write!(f, "no present mode"),
+        }
+    }
+}
+
+/// Error returned when no physical device can be used, with
+/// what each of them lacked, so that it can be reported as a
+/// whole.
+#[derive(Error, Debug)]
+pub struct NoSuitableDevice {
+    /// Name of each device, with its deficiencies.
+    pub devices: Vec<(String, Vec<DeviceDeficiency>)>,
+}
+
+impl fmt::Display for NoSuitableDevice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Failed to find suitable physical device.")?;
+        if self.devices.is_empty() {
+            return write!(f, " No Vulkan device was found.");
+        }
+
+        for (name, deficiencies) in &self.devices {
+            let deficiencies = deficiencies
+                .iter()
+                .map(|d| d.to_string())
+                .collect::<Vec<_>>();
+            write!(f, "\n  - {}: {}", name, deficiencies.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
 fn check_physical_device_extensions(
     instance: &Instance,
     physical_device: vk::PhysicalDevice,
+    deficiencies: &mut Vec<DeviceDeficiency>,
 ) -> Result<()> {
     // Get the list of supported device extensions on the device
     let extensions = unsafe {
@@ -52,45 +254,160 @@ fn check_physical_device_extensions(
     };
 
     // Check if all required extensions are supported
-    if REQUIRED_EXTENSIONS.iter().all(|e| extensions.contains(e)) {
-        Ok(())
-    } else {
-        Err(anyhow!(SuitabilityError("Missing required device extensions.")))
+    let missing = REQUIRED_EXTENSIONS
+        .iter()
+        .filter(|e| !extensions.contains(e))
+        .map(|e| DeviceDeficiency::MissingExtension(e.to_string()))
+        .collect::<Vec<_>>();
+
+    // The features of the extensions can only be queried if
+    // the extensions are there.
+    if !missing.is_empty() {
+        deficiencies.extend(missing);
+        return Ok(());
     }
+
+    let mut dynamic_rendering = vk::PhysicalDeviceDynamicRenderingFeatures::builder();
+    let mut synchronization2 = vk::PhysicalDeviceSynchronization2Features::builder();
+    let mut features2 = vk::PhysicalDeviceFeatures2::builder()
+        .push_next(&mut dynamic_rendering)
+        .push_next(&mut synchronization2);
+
+    unsafe { instance.get_physical_device_features2(physical_device, &mut features2) };
+
+    if dynamic_rendering.dynamic_rendering != vk::TRUE {
+        deficiencies.push(DeviceDeficiency::MissingFeature("dynamicRendering"));
+    }
+    if synchronization2.synchronization2 != vk::TRUE {
+        deficiencies.push(DeviceDeficiency::MissingFeature("synchronization2"));
+    }
+
+    Ok(())
 }
 
 fn check_physical_device(
     instance: &Instance,
     data: &mut RenderData,
     physical_device: vk::PhysicalDevice,
-) -> Result<()> {
+) -> Result<Vec<DeviceDeficiency>> {
+    // Every check is run, rather than stopping at the first
+    // failure, so that the report says everything the device
+    // lacks at once.
+    let mut deficiencies = Vec::new();
+
     // Each device has a number of associated queue families
     // that represent the supported functionalities (graphics,
     // compute shaders, transfer operations, etc.). We want the
-    // graphics queue, which is used for drawing commands.
-    data.graphics_queue_family = get_graphics_family_index(instance, physical_device)?;
-    
+    // graphics queue, which is used for drawing commands...
+    match get_graphics_family_index(instance, physical_device) {
+        Ok(index) => data.graphics_queue_family = index,
+        Err(_) => deficiencies.push(DeviceDeficiency::NoGraphicsQueue),
+    }
+
     // Then we can check if the device supports all the
-    // required extensions.
-    check_physical_device_extensions(instance, physical_device)?;
+    // required extensions, and their features.
+    check_physical_device_extensions(instance, physical_device, &mut deficiencies)?;
 
-    // Likewise, we can check if the device supports the
-    // included optional features.
-    let features = unsafe { instance.get_physical_device_features(physical_device) };
-    if features.sampler_anisotropy != vk::TRUE {
-        return Err(anyhow!(SuitabilityError("Device does not support anisotropic filtering.")));
+    // Without a surface (in the self-test), there is nothing
+    // to present to, and the checks end here.
+    if data.surface.is_null() {
+        return Ok(deficiencies);
     }
-    
+
+    // Otherwise, we need a queue able to present to our window
+    // surface, which may be in another family.
+    match get_present_family_index(instance, data.surface, physical_device) {
+        Ok(index) => data.present_queue_family = index,
+        Err(_) => deficiencies.push(DeviceDeficiency::NoPresentQueue),
+    }
+
+    // Optional features, like anisotropic filtering, are not
+    // checked here: they are enabled when creating the logical
+    // device if they are available, and the renderer adapts to
+    // their absence, so that software implementations (which
+    // is what CI machines usually have) are not rejected.
+
     // Finally, we can check if the device's swapchain support
     // is sufficient. We want to at least have one supported
     // image format and presentation mode for our window
     // surface.
     let support = get_swapchain_support(instance, data, physical_device)?;
-    if support.formats.is_empty() || support.present_modes.is_empty() {
-        return Err(anyhow!(SuitabilityError("Insufficient swapchain support.")));
+    if support.formats.is_empty() {
+        deficiencies.push(DeviceDeficiency::NoSurfaceFormats);
+    }
+    if support.present_modes.is_empty() {
+        deficiencies.push(DeviceDeficiency::NoPresentModes);
     }
 
-    Ok(())
+    Ok(deficiencies)
+}
+
+/// Names of the software implementations of Vulkan, some of
+/// which report themselves as integrated or virtual GPUs.
+const SOFTWARE_DEVICE_NAMES: [&str; 4] = ["llvmpipe", "lavapipe", "swiftshader", "softpipe"];
+
+/// Information about the selected physical device.
+#[derive(Clone, Debug, Default)]
+pub struct DeviceInfo {
+    pub name: String,
+    pub device_type: vk::PhysicalDeviceType,
+    pub vendor_id: u32,
+    pub driver_version: u32,
+    pub api_version: u32,
+    /// Whether the device is a software implementation running
+    /// on the CPU, which is orders of magnitude slower than any
+    /// GPU.
+    pub is_software: bool,
+    /// What the device supports of the features Vulkan
+    /// normally guarantees, if it only implements the
+    /// portability subset (MoltenVK, on Apple platforms).
+    pub portability_subset: Option<PortabilitySubset>,
+}
+
+/// Features and limits of a device implementing only the
+/// portability subset of Vulkan (`VK_KHR_portability_subset`).
+/// Anything that is false here is not supported by the device,
+/// although it is normally part of core Vulkan.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PortabilitySubset {
+    pub constant_alpha_color_blend_factors: bool,
+    pub events: bool,
+    pub image_view_format_reinterpretation: bool,
+    /// Image views can use other component swizzles than
+    /// IDENTITY.
+    pub image_view_format_swizzle: bool,
+    pub image_view_2d_on_3d_image: bool,
+    pub multisample_array_image: bool,
+    pub mutable_comparison_samplers: bool,
+    pub point_polygons: bool,
+    pub sampler_mip_lod_bias: bool,
+    pub separate_stencil_mask_ref: bool,
+    pub shader_sample_rate_interpolation_functions: bool,
+    pub tessellation_isolines: bool,
+    pub tessellation_point_mode: bool,
+    /// The TRIANGLE_FAN topology can be used.
+    pub triangle_fans: bool,
+    pub vertex_attribute_access_beyond_stride: bool,
+    /// Alignment of the stride of vertex input bindings.
+    pub min_vertex_input_binding_stride_alignment: u32,
+}
+
+impl DeviceInfo {
+    fn new(properties: &vk::PhysicalDeviceProperties) -> Self {
+        let name = properties.device_name.to_string();
+        let lowercase = name.to_lowercase();
+
+        Self {
+            is_software: properties.device_type == vk::PhysicalDeviceType::CPU
+                || SOFTWARE_DEVICE_NAMES.iter().any(|s| lowercase.contains(s)),
+            name,
+            device_type: properties.device_type,
+            vendor_id: properties.vendor_id,
+            driver_version: properties.driver_version,
+            api_version: properties.api_version,
+            portability_subset: None,
+        }
+    }
 }
 
 pub fn pick_physical_device(
@@ -102,25 +419,106 @@ pub fn pick_physical_device(
     // same time, for example), and in fact a Vulkan instance
     // can set up and use any number of them simultaneously,
     // but we will stick here to listing the available physical
-    // devices and picking the first graphics-capable one.
+    // devices and picking the first graphics-capable one,
+    // unless the configuration names a preferred device.
+    let preferred = data.config.preferred_device
+        .as_ref()
+        .map(|name| name.to_lowercase());
+
+    // Software implementations (llvmpipe, SwiftShader) are
+    // suitable, but only picked if there is no hardware device,
+    // or if they are explicitly preferred (to run on CI
+    // machines, for example).
+    //
+    // What the other devices lack is collected, to be reported
+    // in full if none of them is suitable.
+    let mut selected: Option<(vk::PhysicalDevice, vk::PhysicalDeviceProperties, DeviceInfo)> = None;
+    let mut rejected = Vec::new();
     for device in unsafe { instance.enumerate_physical_devices()? } {
         let properties = unsafe { instance.get_physical_device_properties(device) };
+        let info = DeviceInfo::new(&properties);
 
-        if let Err(error) = check_physical_device(instance, data, device) {
-            warn!("Skipping physical device ({}): {}", properties.device_name, error);
-        } else {
-            // If there is a suitable device for graphics,
-            // return it and print its properties.
-            info!("Selected physical device: {}", properties.device_name);
-            return Ok(device);
+        let deficiencies = match check_physical_device(instance, data, device) {
+            Ok(deficiencies) => deficiencies,
+            Err(error) => {
+                warn!("Skipping physical device ({}): {}", info.name, error);
+                continue;
+            }
+        };
+
+        if !deficiencies.is_empty() {
+            let list = deficiencies.iter().map(|d| d.to_string()).collect::<Vec<_>>();
+            warn!("Skipping physical device ({}): {}", info.name, list.join(", "));
+            rejected.push((info.name, deficiencies));
+            continue;
+        }
+
+        let is_preferred = preferred
+            .as_ref()
+            .is_some_and(|name| info.name.to_lowercase().contains(name));
+        let is_better = match &selected {
+            None => true,
+            Some((_, _, current)) => current.is_software && !info.is_software,
+        };
+
+        if is_better || is_preferred {
+            selected = Some((device, properties, info));
+        }
+
+        let is_hardware = selected.as_ref().is_some_and(|(_, _, current)| !current.is_software);
+        if is_preferred || (preferred.is_none() && is_hardware) {
+            break;
         }
     }
 
-    Err(anyhow!(SuitabilityError("Failed to find suitable physical device.")))
+    // If there is a suitable device for graphics, return it
+    // and print its properties. The checks above have set the
+    // queue family of the last device checked, so it is
+    // queried again for the selected one; the limits we care
+    // about are cached right away, so that every sub-allocator
+    // can consult them later.
+    match selected {
+        Some((device, properties, device_info)) => {
+            info!("Selected physical device: {}", properties.device_name);
+
+            if device_info.is_software {
+                let forced = preferred
+                    .as_ref()
+                    .is_some_and(|name| device_info.name.to_lowercase().contains(name));
+                if !data.config.allow_software_adapter && !forced {
+                    return Err(anyhow!(SuitabilityError(
+                        "Only a software Vulkan implementation is available, and software adapters are not allowed."
+                    )));
+                }
+
+                warn!("**********************************************************************");
+                warn!("{} is a software implementation of Vulkan, running on the CPU.", device_info.name);
+                warn!("Rendering will be very slow; check that the GPU drivers are installed.");
+                warn!("**********************************************************************");
+            }
+
+            data.graphics_queue_family = get_graphics_family_index(instance, device)?;
+            data.present_queue_family = if data.surface.is_null() {
+                data.graphics_queue_family
+            } else {
+                get_present_family_index(instance, data.surface, device)?
+            };
+            if data.present_queue_family != data.graphics_queue_family {
+                info!(
+                    "Presenting from queue family {}, rendering on family {}.",
+                    data.present_queue_family,
+                    data.graphics_queue_family,
+                );
+            }
+            data.limits = DeviceLimits::from_limits(&properties.limits);
+            data.device_info = device_info;
+            Ok(device)
+        },
+        None => Err(anyhow!(NoSuitableDevice { devices: rejected })),
+    }
 }
 
 pub fn create_logical_device(
-    entry: &Entry, 
     instance: &Instance, 
     data: &mut RenderData,
 ) -> Result<Device> {
@@ -135,13 +533,16 @@ pub fn create_logical_device(
     // number of queues per family, but that is not a problem
     // since the command buffers can be created on multiple
     // threads and submitted all at once with minimal overhead.
-    // To build the queue family info, we first need to get the
-    // index of the graphics queue family, to support graphics
-    // operations. Technically, we should also check for
-    // present capabilities, but it can be safely assumed on
-    // all common devices that a graphics queue will also
-    // support presentation.
-    let index = get_graphics_family_index(instance, data.physical_device)?;
+    // To build the queue family info, we need the indices of
+    // the graphics queue family, to support graphics
+    // operations, and of the family that can present to the
+    // surface; they are usually the same, but not always (on
+    // some hybrid setups, for example), in which case we need
+    // a queue in each.
+    let mut families = vec![data.graphics_queue_family];
+    if data.present_queue_family != data.graphics_queue_family {
+        families.push(data.present_queue_family);
+    }
 
     // We can then build the queue families info struct. For
     // each supported queue family in our device, we are
@@ -152,12 +553,15 @@ pub fn create_logical_device(
     // want one queue per family, but are still required to
     // provide the priorities, we simply input the array [1.0].
     let priorities = &[1.0];
-    let graphics_queues = &[
-        vk::DeviceQueueCreateInfo::builder()
-            .queue_family_index(index)
-            .queue_priorities(priorities)
-            .build()
-    ];
+    let queue_infos = families
+        .iter()
+        .map(|&index| {
+            vk::DeviceQueueCreateInfo::builder()
+                .queue_family_index(index)
+                .queue_priorities(priorities)
+                .build()
+        })
+        .collect::<Vec<_>>();
     
     // The next piece of information for the logical devices
     // are layers and extensions. Previous implementations of
@@ -165,7 +569,7 @@ pub fn create_logical_device(
     // specific validation layers, but this is no longer the
     // case. However, it is still a good idea to set them
     // anyway to be compatible with older implementations.
-    let layers = if VALIDATION_ENABLED {
+    let layers = if data.config.validation {
         vec![VALIDATION_LAYER.as_ptr()]
     } else {
         vec![]
@@ -177,17 +581,64 @@ pub fn create_logical_device(
         .map(|e| e.as_ptr())
         .collect::<Vec<_>>();
 
-    // Some implementations are not fully conformant, so
-    // certain Vulkan extensions need to be enabled to ensure
-    // portability.
-    if cfg!(target_os = "macos") && entry.version()? >= PORTABILITY_MACOS_VERSION {
-        extensions.push(vk::KHR_PORTABILITY_ENUMERATION_EXTENSION.name.as_ptr());
+    // Some implementations are not fully conformant (MoltenVK,
+    // which implements Vulkan on top of Metal), and advertise
+    // VK_KHR_portability_subset, which the specification then
+    // requires us to enable. It comes with a features struct
+    // telling which of the normally guaranteed features are
+    // missing, which is recorded with the device information
+    // for the renderer to adapt to it.
+    let portability_subset = get_portability_subset(instance, data.physical_device)?;
+    if let Some(subset) = &portability_subset {
+        extensions.push(vk::KHR_PORTABILITY_SUBSET_EXTENSION.name.as_ptr());
+        warn!("The device only implements the Vulkan portability subset: {:?}", subset);
+    }
+
+    // The robust development mode, when requested, turns on
+    // bounds-checking of buffer accesses (a core feature) and,
+    // if the device supports VK_EXT_robustness2, null
+    // descriptors and robust image accesses. None of this is
+    // required, so we only enable what is actually available
+    // and record it for the rest of the renderer.
+    let mut enabled = EnabledFeatures::default();
+    if data.config.requirements.robust_development {
+        enabled = get_robustness_support(instance, data.physical_device)?;
+        if enabled.null_descriptor || enabled.robust_image_access {
+            extensions.push(vk::EXT_ROBUSTNESS2_EXTENSION.name.as_ptr());
+        }
+
+        info!("Robust development mode: {:?}", enabled);
+    }
+
+    // Anisotropic filtering is supported by virtually every
+    // hardware device, but not by software ones, so it is only
+    // enabled if available.
+    let supported = unsafe { instance.get_physical_device_features(data.physical_device) };
+    enabled.sampler_anisotropy = supported.sampler_anisotropy == vk::TRUE;
+    if !enabled.sampler_anisotropy {
+        warn!("Anisotropic filtering is not supported, samplers will not use it.");
+    }
+
+    // Pipeline statistics are only used for profiling, so
+    // they are enabled when supported, and skipped otherwise.
+    enabled.pipeline_statistics_query = supported.pipeline_statistics_query == vk::TRUE;
+
+    // Waiting for presents to actually happen, to pace frames
+    // on the display rather than on the GPU, takes two
+    // extensions that are far from universal, so they are
+    // also only enabled when available.
+    enabled.present_wait = get_present_wait_support(instance, data.physical_device)?;
+    if enabled.present_wait {
+        extensions.push(vk::KHR_PRESENT_ID_EXTENSION.name.as_ptr());
+        extensions.push(vk::KHR_PRESENT_WAIT_EXTENSION.name.as_ptr());
     }
 
     // We can then specify the set of optional device features
     // we want to have, such as anisotropic filtering. 
     let features = vk::PhysicalDeviceFeatures::builder()
-        .sampler_anisotropy(true);
+        .sampler_anisotropy(enabled.sampler_anisotropy)
+        .pipeline_statistics_query(enabled.pipeline_statistics_query)
+        .robust_buffer_access(enabled.robust_buffer_access);
 
     // Furthermore, we want some features available in Vulkan
     // 1.3: synchronization2, to simplify synchronization
@@ -197,20 +648,243 @@ pub fn create_logical_device(
         .synchronization2(true)
         .dynamic_rendering(true);
 
+    let mut robustness2 = vk::PhysicalDeviceRobustness2FeaturesEXT::builder()
+        .null_descriptor(enabled.null_descriptor)
+        .robust_image_access2(enabled.robust_image_access);
+
     // Then, the actual device info struct combines all the
     // information in one place.
-    let info = vk::DeviceCreateInfo::builder()
-        .queue_create_infos(graphics_queues)
+    let mut info = vk::DeviceCreateInfo::builder()
+        .queue_create_infos(&queue_infos)
         .enabled_layer_names(&layers)
         .enabled_extension_names(&extensions)
         .enabled_features(&features)
         .push_next(&mut features13);
 
+    let mut present_id = vk::PhysicalDevicePresentIdFeaturesKHR::builder()
+        .present_id(true);
+    let mut present_wait = vk::PhysicalDevicePresentWaitFeaturesKHR::builder()
+        .present_wait(true);
+
+    if enabled.null_descriptor || enabled.robust_image_access {
+        info = info.push_next(&mut robustness2);
+    }
+
+    if enabled.present_wait {
+        info = info
+            .push_next(&mut present_id)
+            .push_next(&mut present_wait);
+    }
+
+    // The portability features that are supported are enabled,
+    // by chaining the struct with their support.
+    let mut portability_features = portability_subset
+        .map(|subset| subset.features())
+        .unwrap_or_default();
+    if portability_subset.is_some() {
+        info = info.push_next(&mut portability_features);
+    }
+
     // Finally, we can create the device, and set our app
-    // handle for the graphics queue.
+    // handles for the graphics and present queues (the same
+    // queue if they are in the same family).
     let device = unsafe { instance.create_device(data.physical_device, &info, None)? };
     data.graphics_queue = unsafe { device.get_device_queue(data.graphics_queue_family, 0) };
+    data.present_queue = unsafe { device.get_device_queue(data.present_queue_family, 0) };
+    data.features = enabled;
+    data.device_info.portability_subset = portability_subset;
 
     info!("Logical device created.");
     Ok(device)
-}
\ No newline at end of file
+}
+
+fn get_robustness_support(
+    instance: &Instance,
+    physical_device: vk::PhysicalDevice,
+) -> Result<EnabledFeatures> {
+    // Robust buffer access is part of the core features...
+    let features = unsafe { instance.get_physical_device_features(physical_device) };
+    let mut support = EnabledFeatures {
+        robust_buffer_access: features.robust_buffer_access == vk::TRUE,
+        ..Default::default()
+    };
+
+    // ...but null descriptors and robust image accesses come
+    // from VK_EXT_robustness2, so the extension has to be
+    // there before we can query its feature struct.
+    let has_robustness2 = unsafe {
+        instance
+            .enumerate_device_extension_properties(physical_device, None)?
+            .iter()
+            .any(|e| e.extension_name == vk::EXT_ROBUSTNESS2_EXTENSION.name)
+    };
+
+    if has_robustness2 {
+        // Extension features are queried by chaining their
+        // struct to the generic features2 query.
+        let mut robustness2 = vk::PhysicalDeviceRobustness2FeaturesEXT::builder();
+        let mut features2 = vk::PhysicalDeviceFeatures2::builder()
+            .push_next(&mut robustness2);
+
+        unsafe { instance.get_physical_device_features2(physical_device, &mut features2) };
+
+        support.null_descriptor = robustness2.null_descriptor == vk::TRUE;
+        support.robust_image_access = robustness2.robust_image_access2 == vk::TRUE;
+    }
+
+    Ok(support)
+}
+
+fn get_portability_subset(
+    instance: &Instance,
+    physical_device: vk::PhysicalDevice,
+) -> Result<Option<PortabilitySubset>> {
+    let has_extension = unsafe {
+        instance
+            .enumerate_device_extension_properties(physical_device, None)?
+            .iter()
+            .any(|e| e.extension_name == vk::KHR_PORTABILITY_SUBSET_EXTENSION.name)
+    };
+
+    if !has_extension {
+        return Ok(None);
+    }
+
+    let mut features = vk::PhysicalDevicePortabilitySubsetFeaturesKHR::builder();
+    let mut features2 = vk::PhysicalDeviceFeatures2::builder()
+        .push_next(&mut features);
+    unsafe { instance.get_physical_device_features2(physical_device, &mut features2) };
+
+    let mut properties = vk::PhysicalDevicePortabilitySubsetPropertiesKHR::builder();
+    let mut properties2 = vk::PhysicalDeviceProperties2::builder()
+        .push_next(&mut properties);
+    unsafe { instance.get_physical_device_properties2(physical_device, &mut properties2) };
+
+    Ok(Some(PortabilitySubset {
+        constant_alpha_color_blend_factors: features.constant_alpha_color_blend_factors == vk::TRUE,
+        events: features.events == vk::TRUE,
+        image_view_format_reinterpretation: features.image_view_format_reinterpretation == vk::TRUE,
+        image_view_format_swizzle: features.image_view_format_swizzle == vk::TRUE,
+        image_view_2d_on_3d_image: features.image_view_2d_on_3d_image == vk::TRUE,
+        multisample_array_image: features.multisample_array_image == vk::TRUE,
+        mutable_comparison_samplers: features.mutable_comparison_samplers == vk::TRUE,
+        point_polygons: features.point_polygons == vk::TRUE,
+        sampler_mip_lod_bias: features.sampler_mip_lod_bias == vk::TRUE,
+        separate_stencil_mask_ref: features.separate_stencil_mask_ref == vk::TRUE,
+        shader_sample_rate_interpolation_functions: features.shader_sample_rate_interpolation_functions == vk::TRUE,
+        tessellation_isolines: features.tessellation_isolines == vk::TRUE,
+        tessellation_point_mode: features.tessellation_point_mode == vk::TRUE,
+        triangle_fans: features.triangle_fans == vk::TRUE,
+        vertex_attribute_access_beyond_stride: features.vertex_attribute_access_beyond_stride == vk::TRUE,
+        min_vertex_input_binding_stride_alignment: properties.min_vertex_input_binding_stride_alignment,
+    }))
+}
+
+impl PortabilitySubset {
+    /// Features struct enabling everything that is supported.
+    fn features(self) -> vk::PhysicalDevicePortabilitySubsetFeaturesKHR {
+        vk::PhysicalDevicePortabilitySubsetFeaturesKHR::builder()
+            .constant_alpha_color_blend_factors(self.constant_alpha_color_blend_factors)
+            .events(self.events)
+            .image_view_format_reinterpretation(self.image_view_format_reinterpretation)
+            .image_view_format_swizzle(self.image_view_format_swizzle)
+            .image_view_2d_on_3d_image(self.image_view_2d_on_3d_image)
+            .multisample_array_image(self.multisample_array_image)
+            .mutable_comparison_samplers(self.mutable_comparison_samplers)
+            .point_polygons(self.point_polygons)
+            .sampler_mip_lod_bias(self.sampler_mip_lod_bias)
+            .separate_stencil_mask_ref(self.separate_stencil_mask_ref)
+            .shader_sample_rate_interpolation_functions(self.shader_sample_rate_interpolation_functions)
+            .tessellation_isolines(self.tessellation_isolines)
+            .tessellation_point_mode(self.tessellation_point_mode)
+            .triangle_fans(self.triangle_fans)
+            .vertex_attribute_access_beyond_stride(self.vertex_attribute_access_beyond_stride)
+            .build()
+    }
+}
+
+fn get_present_wait_support(
+    instance: &Instance,
+    physical_device: vk::PhysicalDevice,
+) -> Result<bool> {
+    // Both extensions have to be there, and both of their
+    // features supported (present wait is useless without
+    // present IDs to wait on).
+    let extensions = unsafe {
+        instance
+            .enumerate_device_extension_properties(physical_device, None)?
+            .iter()
+            .map(|e| e.extension_name)
+            .collect::<HashSet<_>>()
+    };
+
+    if !extensions.contains(&vk::KHR_PRESENT_ID_EXTENSION.name)
+        || !extensions.contains(&vk::KHR_PRESENT_WAIT_EXTENSION.name) {
+        return Ok(false);
+    }
+
+    let mut present_id = vk::PhysicalDevicePresentIdFeaturesKHR::builder();
+    let mut present_wait = vk::PhysicalDevicePresentWaitFeaturesKHR::builder();
+    let mut features2 = vk::PhysicalDeviceFeatures2::builder()
+        .push_next(&mut present_id)
+        .push_next(&mut present_wait);
+
+    unsafe { instance.get_physical_device_features2(physical_device, &mut features2) };
+
+    Ok(present_id.present_id == vk::TRUE && present_wait.present_wait == vk::TRUE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Limits of a device with 256-byte uniform offset
+    /// alignment, as on many discrete GPUs.
+    fn limits() -> DeviceLimits {
+        let limits = vk::PhysicalDeviceLimits {
+            min_uniform_buffer_offset_alignment: 256,
+            min_storage_buffer_offset_alignment: 16,
+            non_coherent_atom_size: 64,
+            ..Default::default()
+        };
+        DeviceLimits::from_limits(&limits)
+    }
+
+    #[test]
+    fn from_limits_replaces_missing_alignments() {
+        let limits = DeviceLimits::from_limits(&vk::PhysicalDeviceLimits::default());
+        assert_eq!(limits.min_uniform_buffer_offset_alignment, 1);
+        assert_eq!(limits.min_storage_buffer_offset_alignment, 1);
+        assert_eq!(limits.non_coherent_atom_size, 1);
+        assert_eq!(limits.optimal_buffer_copy_row_pitch_alignment, 1);
+        assert_eq!(limits.max_sampler_anisotropy, 1.0);
+        assert!(!limits.timestamps);
+
+        let limits = limits();
+        assert_eq!(limits.min_uniform_buffer_offset_alignment, 256);
+        assert_eq!(limits.min_storage_buffer_offset_alignment, 16);
+        assert_eq!(limits.non_coherent_atom_size, 64);
+    }
+
+    #[test]
+    fn dynamic_offsets_are_aligned() {
+        let limits = limits();
+        assert_eq!(limits.align_dynamic_offset(0, DynamicBinding::Uniform), 0);
+        assert_eq!(limits.align_dynamic_offset(1, DynamicBinding::Uniform), 256);
+        assert_eq!(limits.align_dynamic_offset(256, DynamicBinding::Uniform), 256);
+        assert_eq!(limits.align_dynamic_offset(257, DynamicBinding::Uniform), 512);
+        assert_eq!(limits.align_dynamic_offset(17, DynamicBinding::Storage), 32);
+    }
+
+    #[test]
+    fn dynamic_stride_rounds_element_size() {
+        let limits = limits();
+        // A 64-byte matrix takes a whole 256-byte slot, and so
+        // does a 192-byte block; anything larger takes two.
+        assert_eq!(limits.dynamic_stride(64, DynamicBinding::Uniform), 256);
+        assert_eq!(limits.dynamic_stride(3 * 64, DynamicBinding::Uniform), 256);
+        assert_eq!(limits.dynamic_stride(300, DynamicBinding::Uniform), 512);
+        assert_eq!(limits.dynamic_stride(64, DynamicBinding::Storage), 64);
+        assert_eq!(DeviceLimits::default().dynamic_stride(64, DynamicBinding::Uniform), 64);
+    }
+}
@@ -1,12 +1,11 @@
 use vulkanalia::prelude::v1_0::*;
+use anyhow::Result;
 
 // Data relative to a single render frame:
 //  - Command pool: pool where main buffer is allocated
 //  - Main buffer: handle frame commands
 //  - Swapchain semaphore: wait from the CPU on a swapchain
 //    image request
-//  - Render semaphore: wait from the CPU for drawing to finish
-//    to present image
 //  - In-flight fence: wait on the GPU for the draw commands to
 //    complete
 
@@ -20,10 +19,229 @@ pub struct FrameData {
     /// Semaphore to signal to the host that the image has been
     /// acquired and is ready for rendering.
     pub image_available_semaphore: vk::Semaphore,
-    /// Semaphore to signal to the host that rendering has
-    /// finished and presentation can happen.
-    pub render_finished_semaphore: vk::Semaphore,
     /// Fence to wait for the draw commands on the device to
     /// complete.
     pub in_flight_fence: vk::Fence,
-}
\ No newline at end of file
+    /// Callbacks to run once the frame's fence has been waited
+    /// on, that is when the device is done with everything the
+    /// frame submitted last.
+    pub on_complete: Vec<Box<dyn FnOnce()>>,
+    /// Callbacks registered while the frame is being prepared.
+    /// They are attached to the frame's submission once it is
+    /// made, since until then the fence only tracks the
+    /// previous one, which doesn't use what is being prepared.
+    pub pending_complete: Vec<Box<dyn FnOnce()>>,
+    /// Pool of the pipeline statistics query wrapping the
+    /// frame's rendering, if supported.
+    pub statistics_pool: vk::QueryPool,
+    /// Whether the query was recorded in the last submission
+    /// of the frame, so that it has results to read.
+    pub statistics_recorded: bool,
+    /// Pool of the timestamps written around each view of the
+    /// frame, if supported.
+    pub timestamp_pool: vk::QueryPool,
+    /// Number of views timed in the last submission of the
+    /// frame.
+    pub timed_views: u32,
+}
+
+impl FrameData {
+    /// Attach the callbacks registered while preparing the
+    /// frame to its submission, which was just made.
+    pub fn submitted(&mut self) {
+        let pending = std::mem::take(&mut self.pending_complete);
+        self.on_complete.extend(pending);
+    }
+}
+
+/// Identifies the frame in flight being prepared, for systems
+/// outside the renderer that keep one copy of their resources
+/// per frame in flight.
+#[derive(Clone, Copy, Debug)]
+pub struct FrameToken {
+    /// Index of the frame in flight, in
+    /// `0..Renderer::frames_in_flight()`.
+    pub index: usize,
+    /// Fence signaled when the device has finished executing
+    /// the frame. Resources used by the frame can be reused or
+    /// destroyed once it has been waited on; see also
+    /// `Renderer::on_frame_complete`.
+    pub fence: vk::Fence,
+}
+
+// Once a swapchain image is acquired, the frame has to reach
+// the presentation engine whatever happens, or the image is
+// never handed back and the frame's fence is never signaled
+// again, deadlocking the next wait on it. The steps taking the
+// frame from recording to submission are abstracted below, so
+// that the recovery protocol is written once, and can be
+// exercised with a failure injected at any step.
+
+/// Device operations carrying a frame from recording to
+/// submission, and back to the swapchain if that fails.
+pub(crate) trait FrameSteps {
+    /// Record the frame's command buffer.
+    fn record(&mut self) -> Result<()>;
+    /// Whether the frame's fence is signaled.
+    fn fence_signaled(&mut self) -> Result<bool>;
+    /// Reset the frame's fence to the unsignaled state.
+    fn reset_fence(&mut self) -> Result<()>;
+    /// Submit the frame's command buffer, signaling the fence.
+    fn submit(&mut self) -> Result<()>;
+    /// Record the bare minimum to hand the image back,
+    /// replacing whatever was recorded.
+    fn record_recovery(&mut self) -> Result<()>;
+    /// Present the image of a skipped frame.
+    fn present_recovery(&mut self) -> Result<()>;
+}
+
+/// How a frame left `submit_or_recover`.
+#[derive(Debug)]
+pub(crate) enum FrameOutcome {
+    /// The frame was submitted, and is to be presented.
+    Submitted,
+    /// The frame failed with the given error, and its image
+    /// was presented untouched instead.
+    Skipped(anyhow::Error),
+}
+
+/// Record and submit a frame, or, if that fails, submit and
+/// present an empty frame in its place, which consumes the
+/// "image available" semaphore, signals the fence and hands
+/// the image back to the swapchain. Fails only if the recovery
+/// itself fails.
+pub(crate) fn submit_or_recover(steps: &mut impl FrameSteps) -> Result<FrameOutcome> {
+    // The fence is only restored to the unsignaled state right
+    // before the submission that will signal it again: if
+    // anything before this point fails, the fence stays
+    // signaled and the next wait on it returns immediately.
+    let result = steps.record()
+        .and_then(|_| steps.reset_fence())
+        .and_then(|_| steps.submit());
+    let Err(error) = result else {
+        return Ok(FrameOutcome::Submitted);
+    };
+
+    steps.record_recovery()?;
+
+    // If the failure happened before the fence was reset, it
+    // is still signaled and has to be reset like for a normal
+    // frame; if it happened after, it is already unsignaled
+    // and this submission will signal it again.
+    if steps.fence_signaled()? {
+        steps.reset_fence()?;
+    }
+    steps.submit()?;
+    steps.present_recovery()?;
+
+    Ok(FrameOutcome::Skipped(error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    enum Step {
+        Record,
+        ResetFence,
+        Submit,
+        RecordRecovery,
+    }
+
+    /// Device executing submissions instantly, that fails
+    /// once at each of the given steps.
+    struct FakeDevice {
+        failures: Vec<Step>,
+        fence_signaled: bool,
+        submissions: u32,
+        presented: bool,
+    }
+
+    impl FakeDevice {
+        fn new(failures: &[Step]) -> Self {
+            // The fence of a frame starts signaled, either at
+            // creation or by the frame's previous submission.
+            Self { failures: failures.to_vec(), fence_signaled: true, submissions: 0, presented: false }
+        }
+
+        fn step(&mut self, step: Step) -> Result<()> {
+            if let Some(index) = self.failures.iter().position(|&failure| failure == step) {
+                self.failures.remove(index);
+                return Err(anyhow!("injected failure at {:?}", step));
+            }
+            Ok(())
+        }
+    }
+
+    impl FrameSteps for FakeDevice {
+        fn record(&mut self) -> Result<()> {
+            self.step(Step::Record)
+        }
+
+        fn fence_signaled(&mut self) -> Result<bool> {
+            Ok(self.fence_signaled)
+        }
+
+        fn reset_fence(&mut self) -> Result<()> {
+            self.step(Step::ResetFence)?;
+            self.fence_signaled = false;
+            Ok(())
+        }
+
+        fn submit(&mut self) -> Result<()> {
+            self.step(Step::Submit)?;
+            assert!(!self.fence_signaled, "submitted with a signaled fence");
+            self.fence_signaled = true;
+            self.submissions += 1;
+            Ok(())
+        }
+
+        fn record_recovery(&mut self) -> Result<()> {
+            self.step(Step::RecordRecovery)
+        }
+
+        fn present_recovery(&mut self) -> Result<()> {
+            self.presented = true;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn frame_without_failure_is_submitted() {
+        let mut device = FakeDevice::new(&[]);
+        let outcome = submit_or_recover(&mut device).unwrap();
+        assert!(matches!(outcome, FrameOutcome::Submitted));
+        assert_eq!(device.submissions, 1);
+        assert!(device.fence_signaled);
+        assert!(!device.presented);
+    }
+
+    #[test]
+    fn failed_frame_signals_its_fence_once() {
+        for step in [Step::Record, Step::ResetFence, Step::Submit] {
+            let mut device = FakeDevice::new(&[step]);
+            let outcome = submit_or_recover(&mut device).unwrap();
+            assert!(matches!(outcome, FrameOutcome::Skipped(_)), "{:?}", step);
+
+            // The next wait on the fence returns, the image was
+            // handed back, and only the recovery was submitted.
+            assert!(device.fence_signaled, "{:?}", step);
+            assert!(device.presented, "{:?}", step);
+            assert_eq!(device.submissions, 1, "{:?}", step);
+
+            // And the frame after that goes through normally.
+            let outcome = submit_or_recover(&mut device).unwrap();
+            assert!(matches!(outcome, FrameOutcome::Submitted), "{:?}", step);
+            assert_eq!(device.submissions, 2, "{:?}", step);
+        }
+    }
+
+    #[test]
+    fn failed_recovery_is_an_error() {
+        let mut device = FakeDevice::new(&[Step::Record, Step::RecordRecovery]);
+        assert!(submit_or_recover(&mut device).is_err());
+        assert_eq!(device.submissions, 0);
+    }
+}
@@ -1,14 +1,21 @@
+use crate::core::{
+    devices::{DeviceLimits, EnabledFeatures},
+    tracker::*,
+};
+
 use vulkanalia::{
     prelude::v1_0::*,
     vk::DeviceV1_3,
 };
 use anyhow::Result;
 
+#[track_caller]
 pub fn create_image_view(
     device: &Device,
     image: vk::Image,
     format: vk::Format,
     aspects: vk::ImageAspectFlags,
+    base_mip_level: u32,
     mip_levels: u32,
 ) -> Result<vk::ImageView> {
     // Images in Vulkan are not accessed as such, but through
@@ -17,7 +24,10 @@ pub fn create_image_view(
     // accessed. The first element of the view to define is how
     // the image colors are mapped to the image view colors. We
     // don't want to swizzle (map to a different value) the
-    // color components here, so we just go for the identity.
+    // color components here, so we just go for the identity,
+    // which is also the only mapping devices implementing the
+    // portability subset are guaranteed to support (see
+    // `PortabilitySubset::image_view_format_swizzle`).
     let component_mapping = vk::ComponentMapping::builder()
         .r(vk::ComponentSwizzle::IDENTITY)
         .g(vk::ComponentSwizzle::IDENTITY)
@@ -37,9 +47,14 @@ pub fn create_image_view(
     //   (simultaneous views of the same image, for example for
     //   stereoscopic rendering);
     // - layer_count: the number of accessible array layers.
+    //
+    // Views usually cover the whole mip chain, but a view of a
+    // single level (starting at `base_mip_level`) is what lets
+    // a pass render to, or sample from, one level of a chain
+    // (for downsampling passes, for example).
     let subresource_range = vk::ImageSubresourceRange::builder()
         .aspect_mask(aspects)
-        .base_mip_level(0)
+        .base_mip_level(base_mip_level)
         .level_count(mip_levels)
         .base_array_layer(0)
         .layer_count(1)
@@ -57,7 +72,83 @@ pub fn create_image_view(
         .components(component_mapping)
         .subresource_range(subresource_range);
 
-    Ok(unsafe { device.create_image_view(&info, None)? })
+    let view = unsafe { device.create_image_view(&info, None)? };
+    track_created(view);
+    Ok(view)
+}
+
+/// Quality of texture filtering, as a global setting: the
+/// anisotropy level of samplers, clamped to what the device
+/// supports.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TextureFiltering {
+    /// Trilinear filtering only.
+    Off,
+    X2,
+    X4,
+    X8,
+    #[default]
+    X16,
+}
+
+impl TextureFiltering {
+    /// Maximum anisotropy of samplers at this quality.
+    pub fn anisotropy(self) -> f32 {
+        match self {
+            TextureFiltering::Off => 1.0,
+            TextureFiltering::X2 => 2.0,
+            TextureFiltering::X4 => 4.0,
+            TextureFiltering::X8 => 8.0,
+            TextureFiltering::X16 => 16.0,
+        }
+    }
+}
+
+#[track_caller]
+pub fn create_sampler(
+    device: &Device,
+    features: &EnabledFeatures,
+    limits: &DeviceLimits,
+    filtering: TextureFiltering,
+    mip_levels: u32,
+) -> Result<vk::Sampler> {
+    // Textures are not read directly by shaders, but through
+    // a sampler, which applies filtering and transformations
+    // to compute the final color. The mag and min filters
+    // specify how to interpolate texels that are magnified
+    // (oversampling) or minified (undersampling); LINEAR gives
+    // a smooth bilinear interpolation. The address modes then
+    // specify what happens when reading outside of the image,
+    // REPEAT being the usual choice for tiled textures.
+    //
+    // Anisotropic filtering improves the look of textures
+    // seen at a grazing angle, but it is an optional device
+    // feature: when it was not enabled (on software
+    // implementations, typically), it has to be left off, and
+    // the max anisotropy is then ignored. Otherwise, the
+    // level is the one of the filtering quality setting, within
+    // the device limits.
+    let anisotropy = filtering.anisotropy().min(limits.max_sampler_anisotropy);
+    let info = vk::SamplerCreateInfo::builder()
+        .mag_filter(vk::Filter::LINEAR)
+        .min_filter(vk::Filter::LINEAR)
+        .address_mode_u(vk::SamplerAddressMode::REPEAT)
+        .address_mode_v(vk::SamplerAddressMode::REPEAT)
+        .address_mode_w(vk::SamplerAddressMode::REPEAT)
+        .anisotropy_enable(features.sampler_anisotropy && anisotropy > 1.0)
+        .max_anisotropy(anisotropy)
+        .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
+        .unnormalized_coordinates(false)
+        .compare_enable(false)
+        .compare_op(vk::CompareOp::ALWAYS)
+        .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+        .min_lod(0.0)
+        .max_lod(mip_levels as f32)
+        .mip_lod_bias(0.0);
+
+    let sampler = unsafe { device.create_sampler(&info, None)? };
+    track_created(sampler);
+    Ok(sampler)
 }
 
 pub fn transition_image_layout(
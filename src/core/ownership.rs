@@ -0,0 +1,242 @@
+use vulkanalia::{
+    prelude::v1_0::*,
+    vk::DeviceV1_3,
+};
+
+use crate::core::image::subresource_range;
+
+/// Queue family ownership of a resource created with EXCLUSIVE
+/// sharing, and used from more than one queue family.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ownership {
+    /// The resource is owned by, and can be used on, this
+    /// queue family.
+    Owned(u32),
+    /// The resource was released by a queue family, and has to
+    /// be acquired by another before being used there.
+    Released { from: u32, to: u32 },
+}
+
+impl Ownership {
+    /// Start moving the resource to the `to` family, if it is
+    /// not already owned by it. The returned families are
+    /// passed to the release barrier, and then to the acquire
+    /// one.
+    pub fn release(&mut self, to: u32) -> Option<(u32, u32)> {
+        match *self {
+            Ownership::Owned(from) if from != to => {
+                *self = Ownership::Released { from, to };
+                Some((from, to))
+            }
+            _ => None,
+        }
+    }
+
+    /// Complete a release, if there is one pending: the
+    /// returned families are passed to the acquire barrier.
+    pub fn acquire(&mut self) -> Option<(u32, u32)> {
+        match *self {
+            Ownership::Released { from, to } => {
+                *self = Ownership::Owned(to);
+                Some((from, to))
+            }
+            Ownership::Owned(_) => None,
+        }
+    }
+}
+
+/// Access to an image on both sides of an ownership transfer.
+#[derive(Clone, Copy, Debug)]
+pub struct ImageTransfer {
+    pub image: vk::Image,
+    pub aspects: vk::ImageAspectFlags,
+    /// Layout of the image on the releasing queue, and layout
+    /// it has on the acquiring queue; the transition happens
+    /// during the transfer, and both barriers must agree on it.
+    pub old_layout: vk::ImageLayout,
+    pub new_layout: vk::ImageLayout,
+    /// Stages and accesses of the last use on the releasing
+    /// queue.
+    pub src_stage: vk::PipelineStageFlags2,
+    pub src_access: vk::AccessFlags2,
+    /// Stages and accesses of the first use on the acquiring
+    /// queue.
+    pub dst_stage: vk::PipelineStageFlags2,
+    pub dst_access: vk::AccessFlags2,
+}
+
+/// Access to a buffer on both sides of an ownership transfer.
+#[derive(Clone, Copy, Debug)]
+pub struct BufferTransfer {
+    pub buffer: vk::Buffer,
+    pub src_stage: vk::PipelineStageFlags2,
+    pub src_access: vk::AccessFlags2,
+    pub dst_stage: vk::PipelineStageFlags2,
+    pub dst_access: vk::AccessFlags2,
+}
+
+// Resources created with EXCLUSIVE sharing belong to one queue
+// family at a time. To use them on another family (uploading
+// on the transfer queue and sampling on the graphics one, for
+// example), ownership is transferred with a pair of barriers:
+// a release barrier recorded on the source queue, and an
+// acquire barrier recorded on the destination queue, both with
+// the same source and destination families (and, for images,
+// the same layouts). The release only needs its source stages
+// and accesses, and the acquire its destination ones; the
+// other half of each is ignored. The acquire must also happen
+// after the release on the device, usually through a semaphore
+// between the two submissions.
+//
+// When both families are the same, there is no ownership to
+// transfer: the release records nothing, and the acquire a
+// plain barrier with both halves, which the semaphore (or
+// submission order) completes.
+
+/// Record the release half of an image ownership transfer from
+/// `src_family` to `dst_family`.
+pub fn release_image(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    transfer: &ImageTransfer,
+    src_family: u32,
+    dst_family: u32,
+) {
+    if src_family == dst_family {
+        return;
+    }
+
+    let barrier = image_barrier(transfer, src_family, dst_family)
+        .dst_stage_mask(vk::PipelineStageFlags2::NONE)
+        .dst_access_mask(vk::AccessFlags2::NONE)
+        .build();
+
+    pipeline_barrier(device, command_buffer, &[barrier], &[]);
+}
+
+/// Record the acquire half of an image ownership transfer from
+/// `src_family` to `dst_family`.
+pub fn acquire_image(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    transfer: &ImageTransfer,
+    src_family: u32,
+    dst_family: u32,
+) {
+    let barrier = image_barrier(transfer, src_family, dst_family);
+    let barrier = if src_family == dst_family {
+        barrier.build()
+    } else {
+        barrier
+            .src_stage_mask(vk::PipelineStageFlags2::NONE)
+            .src_access_mask(vk::AccessFlags2::NONE)
+            .build()
+    };
+
+    pipeline_barrier(device, command_buffer, &[barrier], &[]);
+}
+
+/// Record the release half of a buffer ownership transfer from
+/// `src_family` to `dst_family`.
+pub fn release_buffer(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    transfer: &BufferTransfer,
+    src_family: u32,
+    dst_family: u32,
+) {
+    if src_family == dst_family {
+        return;
+    }
+
+    let barrier = buffer_barrier(transfer, src_family, dst_family)
+        .dst_stage_mask(vk::PipelineStageFlags2::NONE)
+        .dst_access_mask(vk::AccessFlags2::NONE)
+        .build();
+
+    pipeline_barrier(device, command_buffer, &[], &[barrier]);
+}
+
+/// Record the acquire half of a buffer ownership transfer from
+/// `src_family` to `dst_family`.
+pub fn acquire_buffer(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    transfer: &BufferTransfer,
+    src_family: u32,
+    dst_family: u32,
+) {
+    let barrier = buffer_barrier(transfer, src_family, dst_family);
+    let barrier = if src_family == dst_family {
+        barrier.build()
+    } else {
+        barrier
+            .src_stage_mask(vk::PipelineStageFlags2::NONE)
+            .src_access_mask(vk::AccessFlags2::NONE)
+            .build()
+    };
+
+    pipeline_barrier(device, command_buffer, &[], &[barrier]);
+}
+
+fn image_barrier(
+    transfer: &ImageTransfer,
+    src_family: u32,
+    dst_family: u32,
+) -> vk::ImageMemoryBarrier2Builder<'static> {
+    // The same family on both sides means no transfer at all,
+    // which is written with the IGNORED family index.
+    let (src_family, dst_family) = if src_family == dst_family {
+        (vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED)
+    } else {
+        (src_family, dst_family)
+    };
+
+    vk::ImageMemoryBarrier2::builder()
+        .src_stage_mask(transfer.src_stage)
+        .src_access_mask(transfer.src_access)
+        .dst_stage_mask(transfer.dst_stage)
+        .dst_access_mask(transfer.dst_access)
+        .old_layout(transfer.old_layout)
+        .new_layout(transfer.new_layout)
+        .src_queue_family_index(src_family)
+        .dst_queue_family_index(dst_family)
+        .image(transfer.image)
+        .subresource_range(subresource_range(transfer.aspects))
+}
+
+fn buffer_barrier(
+    transfer: &BufferTransfer,
+    src_family: u32,
+    dst_family: u32,
+) -> vk::BufferMemoryBarrier2Builder<'static> {
+    let (src_family, dst_family) = if src_family == dst_family {
+        (vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED)
+    } else {
+        (src_family, dst_family)
+    };
+
+    vk::BufferMemoryBarrier2::builder()
+        .src_stage_mask(transfer.src_stage)
+        .src_access_mask(transfer.src_access)
+        .dst_stage_mask(transfer.dst_stage)
+        .dst_access_mask(transfer.dst_access)
+        .src_queue_family_index(src_family)
+        .dst_queue_family_index(dst_family)
+        .buffer(transfer.buffer)
+        .offset(0)
+        .size(vk::WHOLE_SIZE)
+}
+
+fn pipeline_barrier(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    image_barriers: &[vk::ImageMemoryBarrier2],
+    buffer_barriers: &[vk::BufferMemoryBarrier2],
+) {
+    let dependency = vk::DependencyInfo::builder()
+        .image_memory_barriers(image_barriers)
+        .buffer_memory_barriers(buffer_barriers);
+
+    unsafe { device.cmd_pipeline_barrier2(command_buffer, &dependency) };
+}
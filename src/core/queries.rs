@@ -0,0 +1,201 @@
+use std::{
+    mem::{size_of, size_of_val},
+    time::Duration,
+};
+
+use anyhow::Result;
+use vulkanalia::prelude::v1_0::*;
+use log::*;
+
+use crate::{core::tracker::*, renderer::RenderData, view::MAX_VIEWS};
+
+/// Counters collected by the pipeline statistics query. The
+/// results are written in the order of the bits, which is the
+/// order of the fields of [`PipelineStatistics`].
+fn statistics_flags() -> vk::QueryPipelineStatisticFlags {
+    vk::QueryPipelineStatisticFlags::INPUT_ASSEMBLY_VERTICES
+        | vk::QueryPipelineStatisticFlags::INPUT_ASSEMBLY_PRIMITIVES
+        | vk::QueryPipelineStatisticFlags::VERTEX_SHADER_INVOCATIONS
+        | vk::QueryPipelineStatisticFlags::CLIPPING_INVOCATIONS
+        | vk::QueryPipelineStatisticFlags::CLIPPING_PRIMITIVES
+        | vk::QueryPipelineStatisticFlags::FRAGMENT_SHADER_INVOCATIONS
+}
+
+/// Pipeline statistics of a frame, as counted by the device.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PipelineStatistics {
+    /// Vertices read by the input assembler.
+    pub input_vertices: u64,
+    /// Primitives assembled by the input assembler.
+    pub input_primitives: u64,
+    /// Vertex shader invocations.
+    pub vertex_invocations: u64,
+    /// Primitives reaching the clipping stage.
+    pub clipping_invocations: u64,
+    /// Primitives output by the clipping stage.
+    pub clipping_primitives: u64,
+    /// Fragment shader invocations.
+    pub fragment_invocations: u64,
+}
+
+pub fn create_statistics_pools(
+    device: &Device,
+    data: &mut RenderData,
+) -> Result<()> {
+    // Pipeline statistics queries count what went through the
+    // pipeline between a begin and an end command (vertices
+    // read, shader invocations, etc). They are an optional
+    // device feature, so the pools are only created when it
+    // is enabled; rendering then skips the queries for frames
+    // without a pool. Each frame in flight has its own pool,
+    // so that the results of a frame can be read back once its
+    // fence is signaled, without waiting on the device.
+    if !data.features.pipeline_statistics_query {
+        info!("Pipeline statistics queries are not supported.");
+        return Ok(());
+    }
+
+    let info = vk::QueryPoolCreateInfo::builder()
+        .query_type(vk::QueryType::PIPELINE_STATISTICS)
+        .query_count(1)
+        .pipeline_statistics(statistics_flags());
+
+    for frame in &mut data.frames {
+        frame.statistics_pool = unsafe { device.create_query_pool(&info, None) }?;
+        track_created(frame.statistics_pool);
+    }
+
+    Ok(())
+}
+
+pub fn destroy_statistics_pools(
+    device: &Device,
+    data: &mut RenderData,
+) {
+    for frame in &mut data.frames {
+        if !frame.statistics_pool.is_null() {
+            track_destroyed(frame.statistics_pool);
+            unsafe { device.destroy_query_pool(frame.statistics_pool, None) };
+            frame.statistics_pool = vk::QueryPool::null();
+        }
+    }
+}
+
+/// Read the statistics of a completed query. This doesn't wait
+/// for the results, and returns `None` if they are not
+/// available.
+pub fn read_statistics(device: &Device, pool: vk::QueryPool) -> Option<PipelineStatistics> {
+    let mut results = [0u64; 6];
+    let bytes = unsafe {
+        std::slice::from_raw_parts_mut(results.as_mut_ptr().cast::<u8>(), size_of_val(&results))
+    };
+
+    let result = unsafe {
+        device.get_query_pool_results(
+            pool,
+            0,
+            1,
+            bytes,
+            size_of_val(&results) as u64,
+            vk::QueryResultFlags::_64,
+        )
+    };
+
+    match result {
+        Ok(vk::SuccessCode::SUCCESS) => Some(PipelineStatistics {
+            input_vertices: results[0],
+            input_primitives: results[1],
+            vertex_invocations: results[2],
+            clipping_invocations: results[3],
+            clipping_primitives: results[4],
+            fragment_invocations: results[5],
+        }),
+        Ok(_) => None,
+        Err(e) => {
+            warn!("Failed to read pipeline statistics: {:?}", e);
+            None
+        }
+    }
+}
+
+pub fn create_timestamp_pools(
+    device: &Device,
+    data: &mut RenderData,
+) -> Result<()> {
+    // Each view of a frame is timed on the device with a pair
+    // of timestamps, written before and after its passes. As
+    // for the statistics, each frame in flight has its own
+    // pool, read once its fence is signaled.
+    if !data.limits.timestamps {
+        info!("Timestamp queries are not supported, views won't be timed.");
+        return Ok(());
+    }
+
+    let info = vk::QueryPoolCreateInfo::builder()
+        .query_type(vk::QueryType::TIMESTAMP)
+        .query_count(MAX_VIEWS as u32 * 2);
+
+    for frame in &mut data.frames {
+        frame.timestamp_pool = unsafe { device.create_query_pool(&info, None) }?;
+        track_created(frame.timestamp_pool);
+    }
+
+    Ok(())
+}
+
+pub fn destroy_timestamp_pools(
+    device: &Device,
+    data: &mut RenderData,
+) {
+    for frame in &mut data.frames {
+        if !frame.timestamp_pool.is_null() {
+            track_destroyed(frame.timestamp_pool);
+            unsafe { device.destroy_query_pool(frame.timestamp_pool, None) };
+            frame.timestamp_pool = vk::QueryPool::null();
+        }
+    }
+}
+
+/// Read the device time spent on each of the first `views`
+/// views of a completed frame. Like [`read_statistics`], this
+/// doesn't wait, and returns `None` if the results are not
+/// available.
+pub fn read_view_timings(
+    device: &Device,
+    pool: vk::QueryPool,
+    views: u32,
+    timestamp_period: f32,
+) -> Option<Vec<Duration>> {
+    let mut results = vec![0u64; views as usize * 2];
+    let bytes = unsafe {
+        std::slice::from_raw_parts_mut(results.as_mut_ptr().cast::<u8>(), size_of_val(results.as_slice()))
+    };
+
+    let result = unsafe {
+        device.get_query_pool_results(
+            pool,
+            0,
+            views * 2,
+            bytes,
+            size_of::<u64>() as u64,
+            vk::QueryResultFlags::_64,
+        )
+    };
+
+    match result {
+        Ok(vk::SuccessCode::SUCCESS) => Some(
+            results
+                .chunks_exact(2)
+                .map(|pair| {
+                    let ticks = pair[1].saturating_sub(pair[0]);
+                    Duration::from_nanos((ticks as f64 * timestamp_period as f64) as u64)
+                })
+                .collect(),
+        ),
+        Ok(_) => None,
+        Err(e) => {
+            warn!("Failed to read view timings: {:?}", e);
+            None
+        }
+    }
+}
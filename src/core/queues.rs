@@ -1,7 +1,7 @@
 
 use super::devices::SuitabilityError;
 
-use vulkanalia::prelude::v1_0::*;
+use vulkanalia::{prelude::v1_0::*, vk::KhrSurfaceExtension};
 use anyhow::{anyhow, Result};
 
 pub fn get_graphics_family_index(
@@ -30,4 +30,40 @@ pub fn get_graphics_family_index(
         Some(index) => Ok(index),
         None => Err(anyhow!(SuitabilityError("Missing required queue families."))),
     }
-}
\ No newline at end of file
+}
+
+pub fn get_present_family_index(
+    instance: &Instance,
+    surface: vk::SurfaceKHR,
+    physical_device: vk::PhysicalDevice,
+) -> Result<u32> {
+    // Presenting to a surface is a queue capability too, but
+    // one that depends on the surface: on hybrid setups, the
+    // graphics family of a device may not be able to present
+    // to a window shown by another device. The graphics family
+    // is preferred when it can present, since one queue for
+    // both avoids sharing the swapchain images between
+    // families; otherwise, any family that can present will
+    // do.
+    let families = unsafe {
+        instance.get_physical_device_queue_family_properties(physical_device)
+    };
+
+    let supports = |index: u32| unsafe {
+        instance.get_physical_device_surface_support_khr(physical_device, index, surface)
+    };
+
+    if let Ok(graphics) = get_graphics_family_index(instance, physical_device) {
+        if supports(graphics)? {
+            return Ok(graphics);
+        }
+    }
+
+    for index in 0..families.len() as u32 {
+        if supports(index)? {
+            return Ok(index);
+        }
+    }
+
+    Err(anyhow!(SuitabilityError("No queue family can present to the surface.")))
+}
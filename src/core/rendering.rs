@@ -0,0 +1,110 @@
+use vulkanalia::{
+    prelude::v1_0::*,
+    vk::DeviceV1_3,
+};
+
+/// How the swapchain color attachment is treated at the start
+/// and at the end of the main pass.
+#[derive(Clone, Copy, Debug)]
+pub struct ColorAttachment {
+    /// What happens to the previous contents of the image:
+    /// CLEAR to `clear_color`, LOAD to keep them (to redraw
+    /// only part of the image, or accumulate over frames), or
+    /// DONT_CARE when every pixel is overwritten anyway.
+    pub load_op: vk::AttachmentLoadOp,
+    /// Whether what was rendered is kept (STORE) or may be
+    /// discarded (DONT_CARE).
+    pub store_op: vk::AttachmentStoreOp,
+    /// Color the attachment is cleared to with CLEAR.
+    pub clear_color: [f32; 4],
+}
+
+impl Default for ColorAttachment {
+    fn default() -> Self {
+        Self {
+            load_op: vk::AttachmentLoadOp::CLEAR,
+            store_op: vk::AttachmentStoreOp::STORE,
+            clear_color: [0.1, 0.1, 0.1, 1.0],
+        }
+    }
+}
+
+pub fn color_attachment_info(
+    view: vk::ImageView,
+    attachment: &ColorAttachment,
+    contents_defined: bool,
+) -> vk::RenderingAttachmentInfo {
+    // With dynamic rendering, attachments are described when
+    // the rendering scope begins, rather than in a render pass
+    // object: the view and the layout it is in during
+    // rendering, what to do with its contents on load and
+    // store, and the clear value used with CLEAR.
+    //
+    // Loading only makes sense if the image holds something:
+    // swapchain images start out undefined, so the first
+    // frame rendered to each of them after the swapchain is
+    // (re)created has to clear it, whatever the configured
+    // load operation.
+    let load_op = match attachment.load_op {
+        vk::AttachmentLoadOp::LOAD if !contents_defined => vk::AttachmentLoadOp::CLEAR,
+        load_op => load_op,
+    };
+
+    let clear_value = vk::ClearValue {
+        color: vk::ClearColorValue { float32: attachment.clear_color },
+    };
+
+    vk::RenderingAttachmentInfo::builder()
+        .image_view(view)
+        .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+        .load_op(load_op)
+        .store_op(attachment.store_op)
+        .clear_value(clear_value)
+        .build()
+}
+
+/// Clear `rect` of the first color attachment to `color`. This
+/// must be recorded inside a rendering scope, and `rect` must
+/// lie within its render area.
+pub fn clear_region(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    rect: vk::Rect2D,
+    color: [f32; 4],
+) {
+    // Unlike vkCmdClearColorImage, which clears whole
+    // subresources outside of rendering, clear_attachments
+    // works on the attachments of the current rendering scope,
+    // and can be limited to a rectangle.
+    let attachment = vk::ClearAttachment::builder()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .color_attachment(0)
+        .clear_value(vk::ClearValue {
+            color: vk::ClearColorValue { float32: color },
+        });
+
+    let rect = vk::ClearRect::builder()
+        .rect(rect)
+        .base_array_layer(0)
+        .layer_count(1);
+
+    unsafe { device.cmd_clear_attachments(command_buffer, &[attachment], &[rect]) };
+}
+
+/// Intersection of `rect` with the area of an image of the
+/// given extent, or `None` if they don't overlap.
+pub fn clip_rect(rect: vk::Rect2D, extent: vk::Extent2D) -> Option<vk::Rect2D> {
+    let x0 = rect.offset.x.max(0) as i64;
+    let y0 = rect.offset.y.max(0) as i64;
+    let x1 = (rect.offset.x as i64 + rect.extent.width as i64).min(extent.width as i64);
+    let y1 = (rect.offset.y as i64 + rect.extent.height as i64).min(extent.height as i64);
+
+    if x1 <= x0 || y1 <= y0 {
+        return None;
+    }
+
+    Some(vk::Rect2D {
+        offset: vk::Offset2D { x: x0 as i32, y: y0 as i32 },
+        extent: vk::Extent2D { width: (x1 - x0) as u32, height: (y1 - y0) as u32 },
+    })
+}
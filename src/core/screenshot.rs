@@ -0,0 +1,259 @@
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use vulkanalia::{
+    prelude::v1_0::*,
+    vk::DeviceV1_3,
+};
+
+use crate::{
+    core::{image::transition_image_layout, tracker::*},
+    renderer::RenderData,
+};
+
+// Screenshots are taken from the swapchain image of a frame,
+// right after it is rendered to and before it is handed to the
+// presentation engine: once presented, an image can't be used
+// again until it is acquired anew, which may be several frames
+// later. The frame's command buffer copies the image into a
+// host-visible buffer on its way to the presentation layout,
+// and once the frame has completed the buffer is read back and
+// encoded as a PNG.
+//
+// The renderer has no allocator of its own, and a screenshot
+// is a one-off, so the buffer gets a memory object of its own,
+// freed as soon as the PNG is written. Both are freed when the
+// readback is dropped, so that they don't leak if recording
+// the frame unwinds, or if the frame never completes (the
+// readback is then dropped once the device is done with it).
+
+/// Buffer a swapchain image is copied into, with what is needed
+/// to turn its contents into a PNG. The buffer and its memory
+/// are destroyed when it is dropped.
+pub struct Readback {
+    /// Device the buffer was created from, to destroy it.
+    device: Device,
+    pub buffer: vk::Buffer,
+    pub memory: vk::DeviceMemory,
+    /// Size and format of the copied image.
+    pub extent: vk::Extent2D,
+    pub format: vk::Format,
+    /// Bytes between the starts of two rows in the buffer.
+    pub row_pitch: u64,
+    /// Where the PNG is written.
+    pub path: PathBuf,
+}
+
+/// Byte order of a swapchain format that can be saved, as
+/// whether the red and blue channels are swapped relative to
+/// RGBA.
+fn is_bgra(format: vk::Format) -> Result<bool> {
+    match format {
+        vk::Format::B8G8R8A8_UNORM | vk::Format::B8G8R8A8_SRGB => Ok(true),
+        vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_SRGB => Ok(false),
+        _ => Err(anyhow!("Screenshots of {:?} swapchains are not supported.", format)),
+    }
+}
+
+/// Check that screenshots can be taken from the current
+/// swapchain.
+pub fn check_screenshot_support(data: &RenderData) -> Result<()> {
+    if !data.swapchain_usage.contains(vk::ImageUsageFlags::TRANSFER_SRC) {
+        return Err(anyhow!("The surface doesn't support copying from swapchain images."));
+    }
+
+    is_bgra(data.swapchain_format).map(|_| ())
+}
+
+/// Create a buffer to copy the current swapchain images into.
+pub unsafe fn create_readback(
+    instance: &Instance,
+    device: &Device,
+    data: &RenderData,
+    path: &Path,
+) -> Result<Readback> {
+    check_screenshot_support(data)?;
+
+    // Rows are copied with the pitch the device copies fastest
+    // with; the PNG encoder is then fed one row at a time,
+    // skipping the padding at the end of each.
+    let extent = data.swapchain_extent;
+    let alignment = data.limits.optimal_buffer_copy_row_pitch_alignment.max(4);
+    let row_pitch = (extent.width as u64 * 4).next_multiple_of(alignment);
+    let size = row_pitch * extent.height as u64;
+
+    let info = vk::BufferCreateInfo::builder()
+        .size(size)
+        .usage(vk::BufferUsageFlags::TRANSFER_DST)
+        .sharing_mode(vk::SharingMode::EXCLUSIVE);
+    let buffer = device.create_buffer(&info, None)?;
+    track_created(buffer);
+
+    // The buffer is only read by the host, so cached memory is
+    // preferred when there is some: reading uncached memory is
+    // very slow. Coherent memory spares the invalidation.
+    let requirements = device.get_buffer_memory_requirements(buffer);
+    let properties = instance.get_physical_device_memory_properties(data.physical_device);
+    let host_visible = vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
+    let memory_type = [host_visible | vk::MemoryPropertyFlags::HOST_CACHED, host_visible]
+        .iter()
+        .find_map(|&flags| {
+            (0..properties.memory_type_count).find(|&i| {
+                requirements.memory_type_bits & (1 << i) != 0
+                    && properties.memory_types[i as usize].property_flags.contains(flags)
+            })
+        });
+
+    let Some(memory_type) = memory_type else {
+        track_destroyed(buffer);
+        device.destroy_buffer(buffer, None);
+        return Err(anyhow!("No host-visible memory to read the screenshot back into."));
+    };
+
+    let memory_info = vk::MemoryAllocateInfo::builder()
+        .allocation_size(requirements.size)
+        .memory_type_index(memory_type);
+    let memory = match device.allocate_memory(&memory_info, None) {
+        Ok(memory) => memory,
+        Err(error) => {
+            track_destroyed(buffer);
+            device.destroy_buffer(buffer, None);
+            return Err(error.into());
+        }
+    };
+    track_created(memory);
+
+    let readback = Readback {
+        device: device.clone(),
+        buffer,
+        memory,
+        extent,
+        format: data.swapchain_format,
+        row_pitch,
+        path: path.to_path_buf(),
+    };
+
+    device.bind_buffer_memory(buffer, memory, 0)?;
+
+    Ok(readback)
+}
+
+/// Record the copy of a swapchain image, rendered to and in the
+/// COLOR_ATTACHMENT_OPTIMAL layout, into the readback buffer,
+/// leaving the image in the PRESENT_SRC_KHR layout.
+pub fn record_readback(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    image: vk::Image,
+    readback: &Readback,
+) -> Result<()> {
+    transition_image_layout(
+        device,
+        command_buffer,
+        image,
+        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+    )?;
+
+    let subresource = vk::ImageSubresourceLayers::builder()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .mip_level(0)
+        .base_array_layer(0)
+        .layer_count(1);
+    let region = vk::BufferImageCopy::builder()
+        .buffer_offset(0)
+        .buffer_row_length((readback.row_pitch / 4) as u32)
+        .buffer_image_height(0)
+        .image_subresource(subresource)
+        .image_offset(vk::Offset3D { x: 0, y: 0, z: 0 })
+        .image_extent(vk::Extent3D {
+            width: readback.extent.width,
+            height: readback.extent.height,
+            depth: 1,
+        });
+
+    unsafe {
+        device.cmd_copy_image_to_buffer(
+            command_buffer,
+            image,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            readback.buffer,
+            &[region],
+        );
+    }
+
+    // Waiting on the frame's fence doesn't make the copy
+    // visible to the host by itself: the barrier does.
+    let barrier = vk::BufferMemoryBarrier2::builder()
+        .src_stage_mask(vk::PipelineStageFlags2::COPY)
+        .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+        .dst_stage_mask(vk::PipelineStageFlags2::HOST)
+        .dst_access_mask(vk::AccessFlags2::HOST_READ)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .buffer(readback.buffer)
+        .offset(0)
+        .size(vk::WHOLE_SIZE);
+    let barriers = &[barrier];
+    let dependency = vk::DependencyInfo::builder()
+        .buffer_memory_barriers(barriers);
+    unsafe { device.cmd_pipeline_barrier2(command_buffer, &dependency) };
+
+    transition_image_layout(
+        device,
+        command_buffer,
+        image,
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        vk::ImageLayout::PRESENT_SRC_KHR,
+    )
+}
+
+/// Encode the contents of the readback buffer as a PNG. The
+/// copy must have completed.
+pub unsafe fn write_readback(device: &Device, readback: &Readback) -> Result<()> {
+    let bgra = is_bgra(readback.format)?;
+    let vk::Extent2D { width, height } = readback.extent;
+    let size = readback.row_pitch * height as u64;
+
+    // Rows are packed, swizzled to RGBA, and made opaque: the
+    // swapchain is presented with opaque composition, so
+    // whatever is in the alpha channel was never shown.
+    let ptr = device.map_memory(readback.memory, 0, size, vk::MemoryMapFlags::empty())?;
+    let mapped = std::slice::from_raw_parts(ptr.cast::<u8>(), size as usize);
+    let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+    for row in mapped.chunks_exact(readback.row_pitch as usize) {
+        for texel in row[..width as usize * 4].chunks_exact(4) {
+            let (r, b) = if bgra { (texel[2], texel[0]) } else { (texel[0], texel[2]) };
+            pixels.extend_from_slice(&[r, texel[1], b, 255]);
+        }
+    }
+    device.unmap_memory(readback.memory);
+
+    // UNORM and SRGB swapchains hold the same bytes, encoded
+    // for display either way (the SRGB format only does the
+    // encoding on write), which is what PNG expects.
+    let file = File::create(&readback.path)
+        .with_context(|| format!("Failed to create {}", readback.path.display()))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_srgb(png::SrgbRenderingIntent::Perceptual);
+
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&pixels))
+        .with_context(|| format!("Failed to write {}", readback.path.display()))
+}
+
+impl Drop for Readback {
+    fn drop(&mut self) {
+        track_destroyed(self.buffer);
+        unsafe { self.device.destroy_buffer(self.buffer, None) };
+        track_destroyed(self.memory);
+        unsafe { self.device.free_memory(self.memory, None) };
+    }
+}
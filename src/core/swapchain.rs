@@ -1,6 +1,6 @@
 use crate::{
     renderer::RenderData,
-    core::{queues::*, image::*},
+    core::{image::*, tracker::*},
 };
 
 use vk::KhrSwapchainExtension;
@@ -57,33 +57,172 @@ pub fn get_swapchain_support(
     })
 }
 
+/// 8-bit sRGB, supported on virtually every surface.
+pub const SURFACE_FORMAT_SRGB: vk::SurfaceFormatKHR = vk::SurfaceFormatKHR {
+    format: vk::Format::B8G8R8A8_SRGB,
+    color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+};
+
+/// 10-bit HDR10, with the PQ (ST 2084) transfer function and
+/// BT.2020 primaries; the application writes PQ-encoded values.
+pub const SURFACE_FORMAT_HDR10: vk::SurfaceFormatKHR = vk::SurfaceFormatKHR {
+    format: vk::Format::A2B10G10R10_UNORM_PACK32,
+    color_space: vk::ColorSpaceKHR::HDR10_ST2084_EXT,
+};
+
+/// 10-bit Display P3, with the sRGB transfer function.
+pub const SURFACE_FORMAT_DISPLAY_P3: vk::SurfaceFormatKHR = vk::SurfaceFormatKHR {
+    format: vk::Format::A2B10G10R10_UNORM_PACK32,
+    color_space: vk::ColorSpaceKHR::DISPLAY_P3_NONLINEAR_EXT,
+};
+
 fn get_swapchain_surface_format(
     formats: &[vk::SurfaceFormatKHR],
+    preferences: &[vk::SurfaceFormatKHR],
 ) -> vk::SurfaceFormatKHR {
     // The first setting to determine is the surface format,
     // which itself consists of two fields: 'format', which
     // specifies the color channels and types, and
     // 'color_space' which indicates the supported color space.
-    // In our case, we will want a B8G8R8A8_SRGB format (B, G,
+    // By default, we will want a B8G8R8A8_SRGB format (B, G,
     // R and alpha channels of 8 bits each in sRGB color space,
     // which makes for 32 bits of color per pixel, the most
     // common bit depth) and a sRGB color space (standard
     // non-linear RGB space, made to match more closely the way
-    // the human eye perceives color). If this surface format
-    // is not available, we will just default on the first one
-    // available.
-    formats
+    // the human eye perceives color). The configuration can
+    // list other formats to try first, like HDR or wide-gamut
+    // ones, which only some displays support. If none of the
+    // preferred formats is available, we will just default on
+    // the first one available.
+    let format = preferences
         .iter()
-        .find(|f| {
-            f.format == vk::Format::B8G8R8A8_SRGB
-            && f.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
-        })
-        .cloned()
-        .unwrap_or(formats[0])
+        .find(|p| formats.iter().any(|f| f.format == p.format && f.color_space == p.color_space))
+        .cloned();
+
+    match format {
+        Some(format) => {
+            info!("Surface format: {:?}, {:?}.", format.format, format.color_space);
+            format
+        },
+        None => {
+            warn!(
+                "None of the preferred surface formats is supported, using {:?}, {:?}.",
+                formats[0].format,
+                formats[0].color_space,
+            );
+            formats[0]
+        },
+    }
+}
+
+/// How images are presented to the display, as a trade-off
+/// between latency, tearing and power use. Each preference
+/// resolves to the first present mode the surface supports in
+/// its list.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PresentPreference {
+    /// Wait for the vertical blank, capping the frame rate to
+    /// the refresh rate of the display (FIFO).
+    Vsync,
+    /// Like vsync, but present late frames right away, tearing
+    /// instead of waiting for the next blank (FIFO_RELAXED).
+    Relaxed,
+    /// Render as fast as possible, the display showing the
+    /// latest complete frame at each blank, without tearing
+    /// (MAILBOX).
+    #[default]
+    LowLatency,
+    /// Present right away, with tearing (IMMEDIATE).
+    Immediate,
+}
+
+impl PresentPreference {
+    /// Present modes satisfying the preference, best first.
+    /// FIFO is always last, since it is the only mode that is
+    /// guaranteed to be supported.
+    pub fn modes(self) -> &'static [vk::PresentModeKHR] {
+        match self {
+            PresentPreference::Vsync => &[vk::PresentModeKHR::FIFO],
+            PresentPreference::Relaxed => &[
+                vk::PresentModeKHR::FIFO_RELAXED,
+                vk::PresentModeKHR::FIFO,
+            ],
+            PresentPreference::LowLatency => &[
+                vk::PresentModeKHR::MAILBOX,
+                vk::PresentModeKHR::FIFO,
+            ],
+            PresentPreference::Immediate => &[
+                vk::PresentModeKHR::IMMEDIATE,
+                vk::PresentModeKHR::MAILBOX,
+                vk::PresentModeKHR::FIFO,
+            ],
+        }
+    }
+}
+
+/// Rotation the application applies to its rendering, for the
+/// swapchain images to show upright once the display applies
+/// the surface transform.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SurfaceRotation {
+    #[default]
+    None,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+}
+
+impl SurfaceRotation {
+    /// Rotation of a surface transform, clockwise. Mirrored
+    /// transforms keep their rotation only: no compositor we
+    /// know of asks for them.
+    pub fn from_transform(transform: vk::SurfaceTransformFlagsKHR) -> Self {
+        type T = vk::SurfaceTransformFlagsKHR;
+        if transform.intersects(T::ROTATE_90 | T::HORIZONTAL_MIRROR_ROTATE_90) {
+            SurfaceRotation::Rotate90
+        } else if transform.intersects(T::ROTATE_180 | T::HORIZONTAL_MIRROR_ROTATE_180) {
+            SurfaceRotation::Rotate180
+        } else if transform.intersects(T::ROTATE_270 | T::HORIZONTAL_MIRROR_ROTATE_270) {
+            SurfaceRotation::Rotate270
+        } else {
+            SurfaceRotation::None
+        }
+    }
+
+    pub fn degrees(self) -> u32 {
+        match self {
+            SurfaceRotation::None => 0,
+            SurfaceRotation::Rotate90 => 90,
+            SurfaceRotation::Rotate180 => 180,
+            SurfaceRotation::Rotate270 => 270,
+        }
+    }
+
+    /// Whether the width and height of the swapchain extent are
+    /// swapped relative to what is shown: the projection then
+    /// uses the aspect ratio of the swapped extent.
+    pub fn swaps_axes(self) -> bool {
+        matches!(self, SurfaceRotation::Rotate90 | SurfaceRotation::Rotate270)
+    }
+}
+
+/// Transform applied to the swapchain images at presentation.
+/// The identity is used whenever the surface supports it, the
+/// compositor taking care of any rotation of the display
+/// (possibly at the cost of an extra blit); otherwise, the
+/// application has to render for the current transform of
+/// the surface, as on some Android devices.
+fn get_swapchain_pre_transform(capabilities: &vk::SurfaceCapabilitiesKHR) -> vk::SurfaceTransformFlagsKHR {
+    if capabilities.supported_transforms.contains(vk::SurfaceTransformFlagsKHR::IDENTITY) {
+        vk::SurfaceTransformFlagsKHR::IDENTITY
+    } else {
+        capabilities.current_transform
+    }
 }
 
 fn get_swapchain_present_mode(
     present_modes: &[vk::PresentModeKHR],
+    preference: PresentPreference,
 ) -> vk::PresentModeKHR {
     // The second property of the swapchain to determine is the
     // presentation mode, which is the way images are sent from
@@ -106,10 +245,17 @@ fn get_swapchain_present_mode(
     //   what is commonly known as "triple buffering", which
     //   results in fewer latency with no tearing, but also a
     //   higher CPU and GPU usage.
-    present_modes
+    //
+    // Which of them to use is a preference of the renderer
+    // configuration, each preference listing the modes that
+    // fit it in order; FIFO is the only mode that is
+    // guaranteed to be supported, so we fall back on it when
+    // none of them is.
+    preference
+        .modes()
         .iter()
         .cloned()
-        .find(|&m| m == vk::PresentModeKHR::MAILBOX)
+        .find(|m| present_modes.contains(m))
         .unwrap_or(vk::PresentModeKHR::FIFO)
 }
 
@@ -154,20 +300,41 @@ instance: &Instance,
     data: &mut RenderData,
 ) -> Result<()> {
     // To create the swapchain, we will first query the
-    // graphics queue family index and support info for the
-    // device...
-    let index = get_graphics_family_index(instance, data.physical_device)?;
+    // support info for the device...
     let support = get_swapchain_support(instance, data, data.physical_device)?;
     
     // ...as well as the image format, presentation and extent.
-    let surface_format = get_swapchain_surface_format(&support.formats);
-    let present_mode = get_swapchain_present_mode(&support.present_modes);
+    let surface_format = get_swapchain_surface_format(&support.formats, &data.config.surface_formats);
+    let present_mode = get_swapchain_present_mode(&support.present_modes, data.config.present_mode);
+    let pre_transform = get_swapchain_pre_transform(&support.capabilities);
     let extent = get_swapchain_extent(window, support.capabilities);
 
+    // A swapchain can't have a zero-sized extent, which is
+    // what we get when the window is minimised, or transiently
+    // from some window managers during interactive resizing.
+    // In that case, creation is deferred until a non-zero size
+    // arrives: the swapchain is left null, and the renderer
+    // doesn't present anything in the meantime.
+    if extent.width == 0 || extent.height == 0 {
+        destroy_retired_swapchain(device, data);
+        data.swapchain_images.clear();
+        data.swapchain_image_count = 0;
+        data.swapchain_image_defined.clear();
+        data.swapchain_extent = extent;
+
+        info!("Zero-sized surface, swapchain creation deferred.");
+        return Ok(());
+    }
+
     // We then have to decide the number of images that our
     // swapchain will contain; it is recommended to have at
-    // least one more than the minimum.
-    let mut image_count = support.capabilities.min_image_count + 1;
+    // least one more than the minimum, unless the
+    // configuration asks for a specific count. Either way, it
+    // has to stay within the supported range (a maximum of 0
+    // meaning that there is no maximum).
+    let mut image_count = data.config.desired_image_count
+        .unwrap_or(support.capabilities.min_image_count + 1)
+        .max(support.capabilities.min_image_count);
     if support.capabilities.max_image_count != 0 
         && image_count > support.capabilities.max_image_count {
         image_count = support.capabilities.max_image_count;
@@ -184,9 +351,17 @@ instance: &Instance,
     // - CONCURRENT: images can be used across multiple queue
     //   families.
     //
-    // Since we only have one queue, we can use EXCLUSIVE.
-    let queue_family_indices = &[index];
-    let image_sharing_mode = vk::SharingMode::EXCLUSIVE;
+    // With a single queue for rendering and presenting, we can
+    // use EXCLUSIVE. With a separate present queue, the images
+    // are shared with CONCURRENT, which spares transferring
+    // their ownership to the present queue every frame, for a
+    // cost that doesn't matter for images only written once.
+    let (queue_family_indices, image_sharing_mode) =
+        if data.graphics_queue_family == data.present_queue_family {
+            (vec![data.graphics_queue_family], vk::SharingMode::EXCLUSIVE)
+        } else {
+            (vec![data.graphics_queue_family, data.present_queue_family], vk::SharingMode::CONCURRENT)
+        };
 
     // We can finally fill in the (large) swapchain info
     // struct. After the swpachain image count and format, we
@@ -201,11 +376,14 @@ instance: &Instance,
     //   It is also possible that images will be rendered
     //   separately first to perform operations like
     //   post-processing, in which case they would be used as
-    //   TRANSFER_DST (transfer destination flag).
+    //   TRANSFER_DST (transfer destination flag). They are
+    //   also TRANSFER_SRC, when the surface allows it, so that
+    //   screenshots can be copied out of them.
     // - pre_transform: a transform that should be applied to
     //   the images before presentation, like a clockwise
     //   rotation or horizontal flip. We don't want any special
-    //   transform, so we specify the identity.
+    //   transform, so we specify the identity, unless the
+    //   surface doesn't support it (see above).
     // - composite_alpha: specifies if the alpha channel should
     //   be used for blending with other windows in the window
     //   system. We don't want that, so we set it to OPAQUE.
@@ -215,7 +393,13 @@ instance: &Instance,
     // - old_swapchain: a pointer to the prior swapchain if it
     //   is being recreated because it has become invalid or
     //   unoptimized while the application is running, for
-    //   example because the window was resized.
+    //   example because the window was resized. Passing it
+    //   lets the driver reuse its resources, instead of
+    //   tearing everything down and showing a black frame in
+    //   between.
+    let image_usage = vk::ImageUsageFlags::COLOR_ATTACHMENT
+        | vk::ImageUsageFlags::TRANSFER_DST
+        | (support.capabilities.supported_usage_flags & vk::ImageUsageFlags::TRANSFER_SRC);
     let info = vk::SwapchainCreateInfoKHR::builder()
         .surface(data.surface)
         .min_image_count(image_count)
@@ -223,23 +407,47 @@ instance: &Instance,
         .image_color_space(surface_format.color_space)
         .image_extent(extent)
         .image_array_layers(1)
-        .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT
-            | vk::ImageUsageFlags::TRANSFER_DST)
+        .image_usage(image_usage)
         .image_sharing_mode(image_sharing_mode)
-        .queue_family_indices(queue_family_indices)
-        .pre_transform(vk::SurfaceTransformFlagsKHR::IDENTITY)
+        .queue_family_indices(&queue_family_indices)
+        .pre_transform(pre_transform)
         .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
         .present_mode(present_mode)
         .clipped(true)
-        .old_swapchain(vk::SwapchainKHR::null());
+        .old_swapchain(data.swapchain);
 
-    // And actually create the swapchain.
-    data.swapchain = unsafe { device.create_swapchain_khr(&info, None)? };
+    // And actually create the swapchain. The old swapchain is
+    // retired by the creation, even if it fails: its images
+    // that were already acquired can still be presented, but
+    // no new ones can be acquired. Since the renderer waits
+    // for the device to be idle before recreating, nothing
+    // uses it anymore, and it can be destroyed right away.
+    let result = unsafe { device.create_swapchain_khr(&info, None) };
+    destroy_retired_swapchain(device, data);
+    data.swapchain = result?;
+    track_created(data.swapchain);
     data.swapchain_images = unsafe { device.get_swapchain_images_khr(data.swapchain)? };
+
+    // The image count is only a minimum, and the driver may
+    // create more images than requested; everything allocated
+    // per swapchain image follows the actual count.
+    data.swapchain_image_count = data.swapchain_images.len() as u32;
+    if data.swapchain_image_count != image_count {
+        info!("Requested {} swapchain images, got {}.", image_count, data.swapchain_image_count);
+    }
+
+    data.swapchain_image_defined = vec![false; data.swapchain_image_count as usize];
     data.swapchain_format = surface_format.format;
+    data.swapchain_color_space = surface_format.color_space;
+    data.swapchain_usage = image_usage;
+    data.swapchain_transform = pre_transform;
+    data.surface_transform = support.capabilities.current_transform;
+    if pre_transform != vk::SurfaceTransformFlagsKHR::IDENTITY {
+        info!("Swapchain images are presented with the {:?} transform.", pre_transform);
+    }
     data.swapchain_extent = extent;
 
-    info!("Swapchain created.");
+    info!("Swapchain created, presenting with {:?} ({:?} preferred).", present_mode, data.config.present_mode);
     Ok(())
 }
 
@@ -261,6 +469,7 @@ pub fn create_swapchain_image_views(
             i, 
             data.swapchain_format, 
             vk::ImageAspectFlags::COLOR,
+            0,
             1,
         ))
         .collect::<Result<Vec<_>, _>>()?;
@@ -269,17 +478,49 @@ pub fn create_swapchain_image_views(
     Ok(())
 }
 
+/// Whether a swapchain currently exists to present to; this is
+/// false while its creation is deferred because of a zero-sized
+/// surface.
+pub fn swapchain_ready(data: &RenderData) -> bool {
+    data.swapchain != vk::SwapchainKHR::null()
+}
+
 pub fn destroy_swapchain(
     device: &Device,
-    data: &RenderData,
+    data: &mut RenderData,
 ) {
-    // Swapchain
-    unsafe { device.destroy_swapchain_khr(data.swapchain, None) };
+    destroy_swapchain_image_views(device, data);
+    destroy_retired_swapchain(device, data);
 
-    // Image views
+    info!("Destroyed the swapchain and related objects.");
+}
+
+/// Destroy the views of the swapchain images, keeping the
+/// swapchain itself, to be passed as the old swapchain when
+/// recreating it.
+pub fn destroy_swapchain_image_views(
+    device: &Device,
+    data: &mut RenderData,
+) {
     data.swapchain_image_views
-        .iter()
-        .for_each(|&v| unsafe { device.destroy_image_view(v, None) });
+        .drain(..)
+        .for_each(|v| {
+            track_destroyed(v);
+            unsafe { device.destroy_image_view(v, None) };
+        });
+}
 
-    info!("Destroyed the swapchain and related objects.");
+/// Destroy the swapchain handle, once it has been replaced or
+/// isn't needed anymore. The handle is reset, so that a failed
+/// recreation doesn't leave a dangling one to be destroyed
+/// twice.
+fn destroy_retired_swapchain(
+    device: &Device,
+    data: &mut RenderData,
+) {
+    if !data.swapchain.is_null() {
+        track_destroyed(data.swapchain);
+        unsafe { device.destroy_swapchain_khr(data.swapchain, None) };
+        data.swapchain = vk::SwapchainKHR::null();
+    }
 }
\ No newline at end of file
@@ -1,4 +1,4 @@
-use crate::renderer::RenderData;
+use crate::{core::tracker::*, renderer::RenderData};
 
 use vulkanalia::prelude::v1_0::*;
 use anyhow::Result;
@@ -34,11 +34,11 @@ pub fn create_sync_objects(
     for frame in &mut data.frames {
         // In our case, we will need one semaphore to signal
         // that an image has been acquired and is ready for
-        // rendering, and one to signal that rendering has
-        // finished and presentation can happen.
+        // rendering (the one to signal that rendering has
+        // finished is per swapchain image, see below).
         frame.image_available_semaphore = unsafe { device.create_semaphore(&semaphore_info, None) }?;
-        frame.render_finished_semaphore = unsafe { device.create_semaphore(&semaphore_info, None) }?;
-        
+        track_created(frame.image_available_semaphore);
+
         // Furthermore, we need to create a fence for each
         // frame to sync the CPU with the GPU: if the CPU is
         // submitting work faster than the GPU can process it,
@@ -49,24 +49,69 @@ pub fn create_sync_objects(
         // multiple frames "in-flight" (worked on
         // asynchronously).
         frame.in_flight_fence = unsafe { device.create_fence(&fence_info, None) }?;
+        track_created(frame.in_flight_fence);
     }
+
+    create_render_finished_semaphores(device, data)?;
    
     info!("Sync objects created.");
     Ok(())
 }
 
+pub fn create_render_finished_semaphores(
+    device: &Device,
+    data: &mut RenderData,
+) -> Result<()> {
+    // The semaphore signaling that rendering has finished is
+    // waited on by the presentation engine, and we have no way
+    // to know when it is done with it: the fence of the frame
+    // only tells us that rendering completed, not that the
+    // image was presented. With more swapchain images than
+    // frames in flight, a per-frame semaphore could then be
+    // signaled again while a previous present still waits on
+    // it. Instead, there is one semaphore per swapchain image:
+    // an image can only be acquired again once its previous
+    // present is done, and with it the wait on its semaphore.
+    // This means the semaphores are recreated along with the
+    // swapchain, whose image count may change.
+    let semaphore_info = vk::SemaphoreCreateInfo::builder();
+
+    for _ in 0..data.swapchain_image_count {
+        let semaphore = unsafe { device.create_semaphore(&semaphore_info, None) }?;
+        track_created(semaphore);
+        data.render_finished_semaphores.push(semaphore);
+    }
+
+    Ok(())
+}
+
+pub fn destroy_render_finished_semaphores(
+    device: &Device,
+    data: &mut RenderData,
+) {
+    data.render_finished_semaphores
+        .drain(..)
+        .for_each(|s| {
+            track_destroyed(s);
+            unsafe { device.destroy_semaphore(s, None) };
+        });
+}
+
 pub fn destroy_sync_objects(
     device: &Device,
     data: &mut RenderData,
 ) {
     for frame in &mut data.frames {
+        track_destroyed(frame.image_available_semaphore);
+        track_destroyed(frame.in_flight_fence);
         unsafe {
             device.destroy_semaphore(frame.image_available_semaphore, None);
-            device.destroy_semaphore(frame.render_finished_semaphore, None);
             device.destroy_fence(frame.in_flight_fence, None);
         }
     }
 
+    destroy_render_finished_semaphores(device, data);
+
     info!("Sync objects destroyed.");
 }
 
@@ -0,0 +1,132 @@
+use std::{
+    collections::HashMap,
+    panic::Location,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, MutexGuard,
+    },
+};
+
+use vulkanalia::prelude::v1_0::*;
+use log::*;
+
+// Vulkan objects have to be destroyed in order: children of
+// the device before the device, and children of the instance
+// (the surface, the debug messenger) before the instance, the
+// driver being free to crash otherwise, possibly long after
+// the faulty call. With destruction spread over the renderer
+// and the modules that create each object, the order is easy
+// to get wrong as features are added; so, when validation is
+// on, every object the renderer creates is recorded with the
+// place it was created at, and forgotten when it is destroyed.
+// Whatever is left when the device or the instance is about to
+// be destroyed was leaked, or is going to be destroyed too
+// late, and is reported with its creation site. With
+// validation off, recording is a single atomic load.
+
+/// Whether objects are being tracked.
+static TRACKING: AtomicBool = AtomicBool::new(false);
+
+/// Live objects, by type and raw handle, with their creation
+/// site.
+type Registry = HashMap<(vk::ObjectType, u64), &'static Location<'static>>;
+
+static LIVE: Mutex<Option<Registry>> = Mutex::new(None);
+
+fn live() -> MutexGuard<'static, Option<Registry>> {
+    LIVE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Turn tracking on or off. Turning it off forgets the objects
+/// tracked so far.
+pub fn set_tracking(enabled: bool) {
+    TRACKING.store(enabled, Ordering::Relaxed);
+    *live() = enabled.then(HashMap::new);
+}
+
+/// Record the creation of `handle`, at the location of the
+/// caller.
+#[track_caller]
+pub fn track_created<H: vk::Handle<Repr = u64>>(handle: H) {
+    if !TRACKING.load(Ordering::Relaxed) || handle.is_null() {
+        return;
+    }
+
+    let location = Location::caller();
+    if let Some(live) = live().as_mut() {
+        live.insert((H::TYPE, handle.as_raw()), location);
+    }
+}
+
+/// Record the destruction of `handle`. Destroying an object
+/// that is not tracked (destroyed twice, typically) is
+/// reported.
+#[track_caller]
+pub fn track_destroyed<H: vk::Handle<Repr = u64>>(handle: H) {
+    if !TRACKING.load(Ordering::Relaxed) || handle.is_null() {
+        return;
+    }
+
+    let removed = live()
+        .as_mut()
+        .and_then(|live| live.remove(&(H::TYPE, handle.as_raw())));
+    if removed.is_none() {
+        error!(
+            "Destroying {:?} {:#x} at {}, which is not alive.",
+            H::TYPE,
+            handle.as_raw(),
+            Location::caller(),
+        );
+    }
+}
+
+/// Objects that are children of the instance rather than of
+/// the device.
+fn is_instance_child(object_type: vk::ObjectType) -> bool {
+    matches!(
+        object_type,
+        vk::ObjectType::SURFACE_KHR | vk::ObjectType::DEBUG_UTILS_MESSENGER_EXT
+    )
+}
+
+/// Check that every child of the device was destroyed, before
+/// destroying the device.
+pub fn check_device_children() {
+    check_children("device", |object_type| !is_instance_child(object_type));
+}
+
+/// Check that every child of the instance was destroyed, before
+/// destroying the instance.
+pub fn check_instance_children() {
+    check_children("instance", |_| true);
+}
+
+fn check_children(parent: &str, is_child: impl Fn(vk::ObjectType) -> bool) {
+    if !TRACKING.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let mut leaked = live()
+        .iter()
+        .flatten()
+        .filter(|((object_type, _), _)| is_child(*object_type))
+        .map(|(&(object_type, handle), location)| (object_type, handle, *location))
+        .collect::<Vec<_>>();
+
+    if leaked.is_empty() {
+        return;
+    }
+
+    leaked.sort_by_key(|&(_, _, location)| (location.file(), location.line()));
+    error!("{} objects are still alive when destroying the {}:", leaked.len(), parent);
+    for (object_type, handle, location) in &leaked {
+        error!("  {:?} {:#x}, created at {}", object_type, handle, location);
+    }
+
+    // While a panic unwinds, the leaks are most likely a
+    // consequence of it, and panicking again would abort.
+    debug_assert!(
+        leaked.is_empty() || std::thread::panicking(),
+        "Vulkan objects outlive the {}", parent,
+    );
+}
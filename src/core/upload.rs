@@ -0,0 +1,425 @@
+use std::time::Duration;
+
+use crate::{
+    core::allocator::{Allocation, Allocator},
+    renderer::RenderError,
+    texture::{block_size, level_size, texel_size},
+};
+
+use vulkanalia::{
+    prelude::v1_0::*,
+    vk::DeviceV1_3,
+};
+use anyhow::{anyhow, Result};
+
+// Resources are uploaded through a host-visible staging buffer,
+// from which the device copies them into device-local memory.
+// A staging buffer as large as the biggest resource would
+// waste (or run out of) host-visible memory, which is usually
+// much scarcer than device-local memory; so the staging buffer
+// has a fixed size, the staging budget, and larger resources
+// are uploaded in chunks that fit in it. Each chunk is copied
+// into the staging buffer, copied to the resource on the
+// device, and waited on before the staging buffer is reused
+// for the next chunk: the host memory used never exceeds the
+// budget, however large the resource.
+//
+// Buffers are split at any byte. Images are split between
+// regions, and large regions between rows of texel blocks
+// (rows of texels for uncompressed formats, rows of 4x4 blocks
+// for compressed ones), since a copy can't start in the middle
+// of a block.
+
+/// Everything an upload needs: the staging buffer, and a
+/// command buffer and fence to record and wait on the copies.
+pub struct UploadContext<'a> {
+    pub device: &'a Device,
+    /// Allocator of the staging memory, to flush it when it is
+    /// not coherent.
+    pub allocator: &'a Allocator,
+    /// Queue the copies are submitted to; any queue supports
+    /// transfer operations.
+    pub queue: vk::Queue,
+    /// Command buffer to record the copies in. It is reset
+    /// before each chunk, so its pool must allow it.
+    pub command_buffer: vk::CommandBuffer,
+    /// Fence signaled when a chunk is copied, unsignaled when
+    /// the upload starts.
+    pub fence: vk::Fence,
+    /// Staging buffer, bound to `staging_memory`.
+    pub staging: vk::Buffer,
+    /// Size of the staging buffer, which is the staging budget.
+    /// The memory bound to it may be larger, rounded up to the
+    /// buffer's requirements, but copies must not go past the
+    /// end of the buffer.
+    pub staging_size: u64,
+    /// Host-visible memory of the staging buffer.
+    pub staging_memory: &'a Allocation,
+    /// Time to wait for each chunk to be copied before giving
+    /// up, typically the renderer's frame timeout
+    /// ([`Renderer::frame_timeout`](crate::renderer::Renderer::frame_timeout)).
+    pub timeout: Duration,
+}
+
+/// Progress of an upload, reported after each chunk.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct UploadProgress {
+    /// Number of bytes copied to the resource so far.
+    pub done: u64,
+    /// Size of the resource.
+    pub total: u64,
+}
+
+impl UploadProgress {
+    /// Fraction of the resource uploaded, between 0 and 1.
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 {
+            1.0
+        } else {
+            self.done as f32 / self.total as f32
+        }
+    }
+}
+
+/// Part of an image upload that fits in the staging budget.
+#[derive(Clone, Debug)]
+pub struct ImageChunk {
+    /// Range of the source data copied into the staging buffer
+    /// for this chunk.
+    pub data_offset: u64,
+    pub data_size: u64,
+    /// Copy regions, with offsets in the staging buffer.
+    pub regions: Vec<vk::BufferImageCopy>,
+}
+
+/// Split an upload of `size` bytes into chunks of at most
+/// `budget` bytes, as (offset, size) pairs.
+pub fn buffer_chunks(size: u64, budget: u64) -> impl Iterator<Item = (u64, u64)> {
+    let budget = budget.max(1);
+    (0..size.div_ceil(budget)).map(move |i| {
+        let offset = i * budget;
+        (offset, budget.min(size - offset))
+    })
+}
+
+/// Size in bytes, and height in texels, of a row of texel
+/// blocks of the format.
+fn block_row(format: vk::Format, width: u32) -> (u64, u32) {
+    match block_size(format) {
+        Some(block) => (width.div_ceil(4).max(1) as u64 * block, 4),
+        None => (width as u64 * texel_size(format), 1),
+    }
+}
+
+/// Split the upload of an image, given its source data regions
+/// (tightly packed, as returned by the texture loaders), into
+/// chunks of at most `budget` bytes. Regions that don't fit in
+/// the budget are split between rows of texel blocks, which
+/// fails if a single row doesn't fit.
+pub fn image_chunks(
+    format: vk::Format,
+    regions: &[vk::BufferImageCopy],
+    budget: u64,
+) -> Result<Vec<ImageChunk>> {
+    // Offsets in the staging buffer must be multiples of 4,
+    // and of the texel block size; all of them are powers of
+    // two, so the largest is a multiple of the others.
+    let alignment = block_size(format).unwrap_or_else(|| texel_size(format)).max(4);
+
+    let mut chunks: Vec<ImageChunk> = Vec::new();
+    let mut current: Option<ImageChunk> = None;
+
+    for region in regions {
+        if region.buffer_row_length != 0 || region.buffer_image_height != 0 {
+            return Err(anyhow!("Only tightly packed image regions can be uploaded in chunks."));
+        }
+
+        let extent = region.image_extent;
+        let layers = region.image_subresource.layer_count as u64 * extent.depth as u64;
+        let (row_size, row_height) = block_row(format, extent.width);
+        let rows = extent.height.div_ceil(row_height) as u64;
+        let size = level_size(format, extent.width, extent.height) * layers;
+
+        // Whole regions are added to the current chunk as long
+        // as they fit, to keep the number of submissions down.
+        let pieces = if size <= budget {
+            vec![(0, rows, *region)]
+        } else if layers == 1 {
+            let rows_per_piece = budget / row_size;
+            if rows_per_piece == 0 {
+                return Err(anyhow!(
+                    "A row of the image ({} bytes) doesn't fit in the staging budget ({} bytes).",
+                    row_size,
+                    budget,
+                ));
+            }
+
+            (0..rows)
+                .step_by(rows_per_piece as usize)
+                .map(|first| {
+                    let count = rows_per_piece.min(rows - first);
+                    let y = first as u32 * row_height;
+                    let height = (count as u32 * row_height).min(extent.height - y);
+
+                    let mut piece = *region;
+                    piece.image_offset.y += y as i32;
+                    piece.image_extent.height = height;
+                    (first, count, piece)
+                })
+                .collect()
+        } else {
+            return Err(anyhow!(
+                "An image region with {} layers doesn't fit in the staging budget; split it by layer.",
+                layers,
+            ));
+        };
+
+        for (first_row, row_count, mut piece) in pieces {
+            let piece_size = if row_count == rows { size } else { row_count * row_size };
+            let data_offset = region.buffer_offset + first_row * row_size;
+
+            // A piece goes into the current chunk if it fits
+            // after it, at an aligned offset, and if its data
+            // directly follows the chunk's in the source, so
+            // that the chunk is one contiguous copy into the
+            // staging buffer.
+            let fits = current.as_ref().is_some_and(|chunk| {
+                chunk.data_offset + chunk.data_size == data_offset
+                    && chunk.data_size % alignment == 0
+                    && chunk.data_size + piece_size <= budget
+            });
+
+            if !fits {
+                chunks.extend(current.take());
+                current = Some(ImageChunk { data_offset, data_size: 0, regions: Vec::new() });
+            }
+
+            let chunk = current.as_mut().unwrap();
+            piece.buffer_offset = chunk.data_size;
+            chunk.data_size += piece_size;
+            chunk.regions.push(piece);
+        }
+    }
+
+    chunks.extend(current);
+    Ok(chunks)
+}
+
+impl UploadContext<'_> {
+    /// Staging budget: the size of the staging buffer.
+    pub fn budget(&self) -> u64 {
+        self.staging_size
+    }
+
+    /// Upload `data` to `buffer`, starting at `offset` in it.
+    /// The buffer must have the TRANSFER_DST usage.
+    ///
+    /// # Safety
+    ///
+    /// The device must not be using the staging buffer, nor
+    /// the written range of `buffer`.
+    pub unsafe fn upload_buffer(
+        &self,
+        buffer: vk::Buffer,
+        offset: u64,
+        data: &[u8],
+        mut on_progress: impl FnMut(UploadProgress),
+    ) -> Result<()> {
+        let total = data.len() as u64;
+
+        for (chunk_offset, size) in buffer_chunks(total, self.budget()) {
+            let bytes = &data[chunk_offset as usize..(chunk_offset + size) as usize];
+            let region = vk::BufferCopy::builder()
+                .src_offset(0)
+                .dst_offset(offset + chunk_offset)
+                .size(size);
+
+            self.submit(bytes, |command_buffer| {
+                self.device.cmd_copy_buffer(command_buffer, self.staging, buffer, &[region]);
+            })?;
+
+            on_progress(UploadProgress { done: chunk_offset + size, total });
+        }
+
+        Ok(())
+    }
+
+    /// Upload the pixel data of an image, with copy regions
+    /// relative to `data` (as returned by the texture loaders).
+    /// The image must be in the TRANSFER_DST_OPTIMAL layout,
+    /// and is left in it.
+    ///
+    /// # Safety
+    ///
+    /// The device must not be using the staging buffer, nor
+    /// the image.
+    pub unsafe fn upload_image(
+        &self,
+        image: vk::Image,
+        format: vk::Format,
+        data: &[u8],
+        regions: &[vk::BufferImageCopy],
+        mut on_progress: impl FnMut(UploadProgress),
+    ) -> Result<()> {
+        let chunks = image_chunks(format, regions, self.budget())?;
+        let total = chunks.iter().map(|chunk| chunk.data_size).sum();
+        let mut done = 0;
+
+        for chunk in chunks {
+            let start = chunk.data_offset as usize;
+            let end = start + chunk.data_size as usize;
+            let bytes = data.get(start..end).ok_or_else(|| anyhow!("Image regions out of the data's bounds."))?;
+
+            self.submit(bytes, |command_buffer| {
+                self.device.cmd_copy_buffer_to_image(
+                    command_buffer,
+                    self.staging,
+                    image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    chunk.regions.as_slice(),
+                );
+            })?;
+
+            done += chunk.data_size;
+            on_progress(UploadProgress { done, total });
+        }
+
+        Ok(())
+    }
+
+    /// Copy `bytes` to the staging buffer, then record the
+    /// copies out of it with `record`, submit them, and wait
+    /// for them to complete, so that the staging buffer can be
+    /// reused.
+    ///
+    /// If the copies don't complete within the timeout,
+    /// [`RenderError::Timeout`] is returned; the device may
+    /// still be using the staging buffer and the command
+    /// buffer then, so the context can't be used again until
+    /// the device is idle.
+    unsafe fn submit(&self, bytes: &[u8], record: impl FnOnce(vk::CommandBuffer)) -> Result<()> {
+        self.staging_memory.write(0, bytes);
+        self.allocator.flush(self.device, self.staging_memory, 0, bytes.len() as u64)?;
+
+        self.device.reset_command_buffer(self.command_buffer, vk::CommandBufferResetFlags::empty())?;
+        let info = vk::CommandBufferBeginInfo::builder()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        self.device.begin_command_buffer(self.command_buffer, &info)?;
+        record(self.command_buffer);
+        self.device.end_command_buffer(self.command_buffer)?;
+
+        let cmd_info = &[vk::CommandBufferSubmitInfo::builder()
+            .command_buffer(self.command_buffer)];
+        let submit_info = vk::SubmitInfo2::builder()
+            .command_buffer_infos(cmd_info);
+
+        self.device.queue_submit2(self.queue, &[submit_info], self.fence)?;
+        let timeout = self.timeout.as_nanos().min(u64::MAX as u128) as u64;
+        if self.device.wait_for_fences(&[self.fence], true, timeout)? == vk::SuccessCode::TIMEOUT {
+            return Err(RenderError::Timeout("a staging copy to complete").into());
+        }
+        self.device.reset_fences(&[self.fence])?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tightly packed copy region of a single-layer image, at
+    /// `offset` in the source data.
+    fn region(offset: u64, width: u32, height: u32) -> vk::BufferImageCopy {
+        let subresource = vk::ImageSubresourceLayers::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .mip_level(0)
+            .base_array_layer(0)
+            .layer_count(1);
+
+        vk::BufferImageCopy::builder()
+            .buffer_offset(offset)
+            .image_subresource(subresource)
+            .image_extent(vk::Extent3D { width, height, depth: 1 })
+            .build()
+    }
+
+    #[test]
+    fn buffer_chunks_cover_the_buffer() {
+        assert_eq!(buffer_chunks(10, 4).collect::<Vec<_>>(), [(0, 4), (4, 4), (8, 2)]);
+        assert_eq!(buffer_chunks(8, 4).collect::<Vec<_>>(), [(0, 4), (4, 4)]);
+        assert_eq!(buffer_chunks(3, 16).collect::<Vec<_>>(), [(0, 3)]);
+        assert_eq!(buffer_chunks(0, 4).count(), 0);
+        // A zero budget still makes progress, a byte at a time.
+        assert_eq!(buffer_chunks(2, 0).collect::<Vec<_>>(), [(0, 1), (1, 1)]);
+    }
+
+    #[test]
+    fn compressed_regions_split_between_block_rows() {
+        // 16x10 BC1: 4 blocks of 8 bytes per row, and 3 rows
+        // of blocks, the last one only 2 texels high.
+        let format = vk::Format::BC1_RGBA_UNORM_BLOCK;
+        let chunks = image_chunks(format, &[region(0, 16, 10)], 64).unwrap();
+
+        assert_eq!(chunks.len(), 2);
+        assert_eq!((chunks[0].data_offset, chunks[0].data_size), (0, 64));
+        assert_eq!((chunks[1].data_offset, chunks[1].data_size), (64, 32));
+
+        let pieces = chunks.iter().flat_map(|chunk| &chunk.regions).collect::<Vec<_>>();
+        assert_eq!(pieces.len(), 2);
+        assert_eq!((pieces[0].image_offset.y, pieces[0].image_extent.height), (0, 8));
+        assert_eq!((pieces[1].image_offset.y, pieces[1].image_extent.height), (8, 2));
+        assert!(pieces.iter().all(|piece| piece.buffer_offset == 0));
+        assert!(pieces.iter().all(|piece| piece.image_extent.width == 16));
+    }
+
+    #[test]
+    fn rows_larger_than_the_budget_fail() {
+        // A row of 16x16 BC1 blocks is 32 bytes.
+        let format = vk::Format::BC1_RGBA_UNORM_BLOCK;
+        assert!(image_chunks(format, &[region(0, 16, 16)], 16).is_err());
+    }
+
+    #[test]
+    fn regions_are_batched_while_they_fit() {
+        // Mip chain of an 8x8 BC1 image: 32 bytes, then a
+        // single block for every smaller level.
+        let format = vk::Format::BC1_RGBA_UNORM_BLOCK;
+        let regions = [region(0, 8, 8), region(32, 4, 4), region(40, 2, 2), region(48, 1, 1)];
+
+        let chunks = image_chunks(format, &regions, 1024).unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!((chunks[0].data_offset, chunks[0].data_size), (0, 56));
+        let offsets = chunks[0].regions.iter().map(|r| r.buffer_offset).collect::<Vec<_>>();
+        assert_eq!(offsets, [0, 32, 40, 48]);
+
+        // With room for two levels only, the chain is split
+        // where the budget runs out.
+        let chunks = image_chunks(format, &regions, 40).unwrap();
+        let sizes = chunks.iter().map(|c| (c.data_offset, c.data_size)).collect::<Vec<_>>();
+        assert_eq!(sizes, [(0, 40), (40, 16)]);
+        let offsets = chunks[1].regions.iter().map(|r| r.buffer_offset).collect::<Vec<_>>();
+        assert_eq!(offsets, [0, 8]);
+    }
+
+    #[test]
+    fn batched_regions_start_at_aligned_offsets() {
+        // 3x1 texels of R16_SFLOAT are 6 bytes, so the next
+        // level can't follow them in the same chunk: offsets
+        // in the staging buffer must be multiples of 4.
+        let format = vk::Format::R16_SFLOAT;
+        let regions = [region(0, 3, 1), region(6, 1, 1)];
+
+        let chunks = image_chunks(format, &regions, 1024).unwrap();
+        let sizes = chunks.iter().map(|c| (c.data_offset, c.data_size)).collect::<Vec<_>>();
+        assert_eq!(sizes, [(0, 6), (6, 2)]);
+        assert!(chunks.iter().flat_map(|c| &c.regions).all(|r| r.buffer_offset % 4 == 0));
+    }
+
+    #[test]
+    fn padded_regions_are_rejected() {
+        let mut padded = region(0, 4, 4);
+        padded.buffer_row_length = 8;
+        assert!(image_chunks(vk::Format::R8G8B8A8_UNORM, &[padded], 1024).is_err());
+    }
+}
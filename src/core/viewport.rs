@@ -0,0 +1,172 @@
+use glam::{vec3, Mat4, Vec2};
+use vulkanalia::prelude::v1_0::*;
+
+/// Length of the jitter sequence, after which it repeats.
+pub const JITTER_SAMPLES: u64 = 8;
+
+pub fn flipped_viewport(extent: vk::Extent2D) -> vk::Viewport {
+    // Vulkan's clip space has its Y axis pointing down, the
+    // opposite of OpenGL and of most content and projection
+    // conventions. Rather than negating Y in every projection
+    // matrix (and flipping the front face winding to make up
+    // for it), we flip the viewport itself: a viewport with a
+    // negative height, starting at the bottom of the
+    // framebuffer, maps clip space Y up to the top of the
+    // image. This is core since Vulkan 1.1 (it used to require
+    // VK_KHR_maintenance1), and it keeps the standard
+    // COUNTER_CLOCKWISE front faces valid.
+    vk::Viewport::builder()
+        .x(0.0)
+        .y(extent.height as f32)
+        .width(extent.width as f32)
+        .height(-(extent.height as f32))
+        .min_depth(0.0)
+        .max_depth(1.0)
+        .build()
+}
+
+pub fn full_scissor(extent: vk::Extent2D) -> vk::Rect2D {
+    // The scissor rectangle is not affected by the flip: it is
+    // always expressed in framebuffer coordinates, here
+    // covering the whole image.
+    vk::Rect2D::builder()
+        .offset(vk::Offset2D { x: 0, y: 0 })
+        .extent(extent)
+        .build()
+}
+
+/// Set a Y-flipped viewport and a full scissor covering
+/// `extent`, for pipelines with dynamic viewport and scissor
+/// state. Every draw path should go through this, so that
+/// geometry is wound the same way everywhere.
+pub fn set_viewport(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    extent: vk::Extent2D,
+) {
+    unsafe {
+        device.cmd_set_viewport(command_buffer, 0, &[flipped_viewport(extent)]);
+        device.cmd_set_scissor(command_buffer, 0, &[full_scissor(extent)]);
+    }
+}
+
+/// Y-flipped viewport covering `rect` of the framebuffer, for
+/// rendering to part of it (a view of a split screen, for
+/// example), with the same flip as [`flipped_viewport`].
+pub fn flipped_viewport_rect(rect: vk::Rect2D) -> vk::Viewport {
+    vk::Viewport::builder()
+        .x(rect.offset.x as f32)
+        .y((rect.offset.y + rect.extent.height as i32) as f32)
+        .width(rect.extent.width as f32)
+        .height(-(rect.extent.height as f32))
+        .min_depth(0.0)
+        .max_depth(1.0)
+        .build()
+}
+
+/// Set a Y-flipped viewport and a scissor covering `rect`, so
+/// that drawing stays inside it.
+pub fn set_viewport_rect(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    rect: vk::Rect2D,
+) {
+    unsafe {
+        device.cmd_set_viewport(command_buffer, 0, &[flipped_viewport_rect(rect)]);
+        device.cmd_set_scissor(command_buffer, 0, &[rect]);
+    }
+}
+
+/// Element `index` of the Halton low-discrepancy sequence in the
+/// given `base`, in [0, 1).
+pub fn halton(mut index: u64, base: u64) -> f32 {
+    let mut fraction = 1.0;
+    let mut result = 0.0;
+
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+
+    result
+}
+
+/// Sub-pixel offset of frame `frame` for temporal techniques
+/// (TAA, upscalers), in NDC units for a render target of the
+/// given `extent`.
+pub fn jitter_offset(frame: u64, extent: vk::Extent2D) -> Vec2 {
+    // Temporal techniques accumulate samples over several
+    // frames, so each frame has to sample a slightly different
+    // position inside its pixels. The Halton(2, 3) sequence
+    // covers the pixel evenly in a few samples, without the
+    // regular patterns of a grid. Index 0 is skipped, since it
+    // is (0, 0) in every base. The offset is centered on the
+    // pixel (in [-0.5, 0.5) pixels), then converted to NDC,
+    // where a pixel is 2 / extent wide.
+    let index = frame % JITTER_SAMPLES + 1;
+    let pixel = Vec2::new(halton(index, 2), halton(index, 3)) - 0.5;
+
+    pixel * 2.0 / Vec2::new(extent.width.max(1) as f32, extent.height.max(1) as f32)
+}
+
+/// Apply an NDC `jitter` offset to a projection matrix.
+pub fn jittered_projection(projection: Mat4, jitter: Vec2) -> Mat4 {
+    // Translating in clip space moves x and y by the jitter
+    // times w, which becomes a constant NDC offset after the
+    // perspective divide; this works for both perspective and
+    // orthographic projections.
+    Mat4::from_translation(vec3(jitter.x, jitter.y, 0.0)) * projection
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::vec4;
+
+    fn assert_near(actual: Vec2, expected: Vec2) {
+        assert!((actual - expected).abs().max_element() < 1e-6, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn halton_known_values() {
+        let base_2 = (0..8).map(|i| halton(i, 2)).collect::<Vec<_>>();
+        assert_eq!(base_2, [0.0, 0.5, 0.25, 0.75, 0.125, 0.625, 0.375, 0.875]);
+
+        let base_3 = (0..7).map(|i| halton(i, 3)).collect::<Vec<_>>();
+        let expected = [0.0, 3.0, 6.0, 1.0, 4.0, 7.0, 2.0].map(|ninths| ninths / 9.0);
+        for (value, expected) in base_3.iter().zip(expected) {
+            assert!((value - expected).abs() < 1e-6, "{} != {}", value, expected);
+        }
+    }
+
+    #[test]
+    fn jitter_is_scaled_to_pixels() {
+        let extent = vk::Extent2D { width: 100, height: 50 };
+
+        // Halton(2, 3) at index 1 is (1/2, 1/3), a sixth of a
+        // pixel up from the center; a pixel is 2/50 NDC high.
+        assert_near(jitter_offset(0, extent), Vec2::new(0.0, -1.0 / 6.0 * 2.0 / 50.0));
+        // At index 2, (1/4, 2/3).
+        assert_near(jitter_offset(1, extent), Vec2::new(-0.25 * 2.0 / 100.0, 1.0 / 6.0 * 2.0 / 50.0));
+
+        // The sequence repeats, and stays within half a pixel.
+        assert_eq!(jitter_offset(JITTER_SAMPLES, extent), jitter_offset(0, extent));
+        for frame in 0..JITTER_SAMPLES {
+            let pixels = jitter_offset(frame, extent) * Vec2::new(50.0, 25.0);
+            assert!(pixels.abs().max_element() <= 0.5, "{}", pixels);
+        }
+    }
+
+    #[test]
+    fn jittered_projection_offsets_ndc() {
+        let projection = Mat4::perspective_rh(1.0, 2.0, 0.1, 100.0);
+        let jitter = Vec2::new(0.01, -0.02);
+        let point = vec4(0.3, -0.2, -5.0, 1.0);
+
+        let clip = projection * point;
+        let jittered = jittered_projection(projection, jitter) * point;
+        let ndc = clip.truncate().truncate() / clip.w;
+        assert_near(jittered.truncate().truncate() / jittered.w, ndc + jitter);
+    }
+}
@@ -1,4 +1,20 @@
-pub mod core;
+pub(crate) mod core;
+pub(crate) mod limiter;
+pub(crate) mod oplog;
+pub(crate) mod stats;
+pub(crate) mod throttle;
+mod window;
+
 pub mod app;
+pub mod assets;
+pub mod config;
+pub mod light;
+pub mod mesh;
+pub mod prelude;
 pub mod renderer;
-pub mod window;
\ No newline at end of file
+pub mod selftest;
+pub mod shaders;
+pub mod texture;
+pub mod view;
+
+pub use app::{run, AppConfig, AppHooks};
\ No newline at end of file
@@ -0,0 +1,207 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use glam::{Vec3, Vec4};
+use log::*;
+
+/// Number of lights in the uniform light block. Beyond that,
+/// the least important lights are dropped.
+pub const MAX_LIGHTS: usize = 16;
+
+/// Whether dropping lights was already reported, since lights
+/// are packed every frame.
+static DROP_WARNED: AtomicBool = AtomicBool::new(false);
+
+/// A dynamic light.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Light {
+    /// Light infinitely far away, with parallel rays (the sun).
+    Directional {
+        /// Direction the light travels in, in world space.
+        direction: Vec3,
+        color: Vec3,
+        intensity: f32,
+    },
+    /// Light emitted in every direction from a point, fading
+    /// out to nothing at `radius`.
+    Point {
+        position: Vec3,
+        color: Vec3,
+        intensity: f32,
+        radius: f32,
+    },
+}
+
+impl Light {
+    /// How much the light contributes around `viewer`, to
+    /// decide which lights to keep. Directional lights light
+    /// everything, so they always come before point lights.
+    fn importance(&self, viewer: Vec3) -> (bool, f32) {
+        match *self {
+            Light::Directional { intensity, .. } => (true, intensity),
+            Light::Point { position, intensity, .. } => {
+                (false, intensity / (1.0 + position.distance_squared(viewer)))
+            }
+        }
+    }
+
+    fn to_gpu(self) -> GpuLight {
+        match self {
+            Light::Directional { direction, color, intensity } => GpuLight {
+                position: direction.normalize_or_zero().extend(0.0),
+                color: color.extend(intensity),
+                params: Vec4::ZERO,
+            },
+            Light::Point { position, color, intensity, radius } => GpuLight {
+                position: position.extend(1.0),
+                color: color.extend(intensity),
+                params: Vec4::new(radius, 0.0, 0.0, 0.0),
+            },
+        }
+    }
+}
+
+/// A light as laid out in uniform buffers (std140: every
+/// member is a vec4).
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GpuLight {
+    /// Direction (w = 0) for a directional light, or position
+    /// (w = 1) for a point light.
+    pub position: Vec4,
+    /// Color, with the intensity in w.
+    pub color: Vec4,
+    /// Radius of a point light in x; the rest is unused.
+    pub params: Vec4,
+}
+
+/// Uniform block holding the lights of a frame.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LightBlock {
+    pub lights: [GpuLight; MAX_LIGHTS],
+    /// Number of valid entries in `lights`.
+    pub count: u32,
+    _padding: [u32; 3],
+}
+
+impl Default for LightBlock {
+    fn default() -> Self {
+        Self {
+            lights: [GpuLight::default(); MAX_LIGHTS],
+            count: 0,
+            _padding: [0; 3],
+        }
+    }
+}
+
+/// Pack `lights` into a uniform block, for a frame seen from
+/// `viewer`.
+#[profiling::function]
+pub fn pack_lights(lights: &[Light], viewer: Vec3) -> LightBlock {
+    let mut block = LightBlock::default();
+
+    // The first directional light is the one the shadows are
+    // cast from, so it is pinned at index 0 whatever the other
+    // lights are. The rest follow, directional lights first.
+    let shadow_caster = lights.iter().position(|light| matches!(light, Light::Directional { .. }));
+    let mut others = lights
+        .iter()
+        .enumerate()
+        .filter(|&(index, _)| Some(index) != shadow_caster)
+        .map(|(_, light)| light)
+        .collect::<Vec<_>>();
+
+    // If there are too many lights, the ones that contribute
+    // the least are dropped. The sort is stable, so that lights
+    // of equal importance are kept in the order they were
+    // given, and the same lights are dropped from one frame to
+    // the next instead of flickering.
+    if lights.len() > MAX_LIGHTS {
+        if !DROP_WARNED.swap(true, Ordering::Relaxed) {
+            warn!(
+                "{} lights exceed the limit of {}; the least important ones are dropped.",
+                lights.len(),
+                MAX_LIGHTS,
+            );
+        }
+
+        others.sort_by(|a, b| {
+            let (a, b) = (a.importance(viewer), b.importance(viewer));
+            b.0.cmp(&a.0).then(b.1.total_cmp(&a.1))
+        });
+    } else {
+        others.sort_by_key(|light| !matches!(light, Light::Directional { .. }));
+    }
+
+    let sorted = shadow_caster.map(|index| &lights[index]).into_iter().chain(others);
+    for (slot, light) in block.lights.iter_mut().zip(sorted) {
+        *slot = light.to_gpu();
+        block.count += 1;
+    }
+
+    block
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sun(intensity: f32) -> Light {
+        Light::Directional { direction: Vec3::NEG_Y, color: Vec3::ONE, intensity }
+    }
+
+    fn point(x: f32, intensity: f32) -> Light {
+        Light::Point { position: Vec3::new(x, 0.0, 0.0), color: Vec3::ONE, intensity, radius: 10.0 }
+    }
+
+    fn packed(block: &LightBlock) -> &[GpuLight] {
+        &block.lights[..block.count as usize]
+    }
+
+    #[test]
+    fn directional_lights_come_first() {
+        let lights = [point(1.0, 1.0), sun(0.5), point(2.0, 1.0), sun(2.0)];
+        let block = pack_lights(&lights, Vec3::ZERO);
+
+        let expected = [sun(0.5), sun(2.0), point(1.0, 1.0), point(2.0, 1.0)].map(Light::to_gpu);
+        assert_eq!(packed(&block), expected);
+    }
+
+    #[test]
+    fn overflow_keeps_the_shadow_caster_and_the_nearest_lights() {
+        // A dim sun given first still casts the shadows, even
+        // with a brighter one and more lights than fit.
+        let mut lights = vec![sun(0.1)];
+        lights.extend((0..10).map(|i| point(20.0 - i as f32, 1.0)));
+        lights.push(sun(10.0));
+        lights.extend((0..10).map(|i| point(10.0 - i as f32, 1.0)));
+        let block = pack_lights(&lights, Vec3::ZERO);
+
+        assert_eq!(block.count as usize, MAX_LIGHTS);
+        let packed = packed(&block);
+        assert_eq!(packed[0], sun(0.1).to_gpu());
+        assert_eq!(packed[1], sun(10.0).to_gpu());
+
+        // The points kept are the 14 closest to the viewer, at
+        // x = 1 to 14, nearest first.
+        let kept = packed[2..].iter().map(|light| light.position.x).collect::<Vec<_>>();
+        assert_eq!(kept, (1..=14).map(|x| x as f32).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn overflow_keeps_equal_lights_in_order() {
+        // Lights of equal importance, told apart by their color.
+        let lights = (0..MAX_LIGHTS + 4)
+            .map(|i| Light::Point {
+                position: Vec3::X,
+                color: Vec3::splat(i as f32),
+                intensity: 1.0,
+                radius: 10.0,
+            })
+            .collect::<Vec<_>>();
+        let block = pack_lights(&lights, Vec3::ZERO);
+
+        let kept = packed(&block).iter().map(|light| light.color.x).collect::<Vec<_>>();
+        assert_eq!(kept, (0..MAX_LIGHTS).map(|i| i as f32).collect::<Vec<_>>());
+    }
+}
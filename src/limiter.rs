@@ -0,0 +1,189 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+// Without vsync (IMMEDIATE or MAILBOX presentation), nothing
+// blocks the frame loop, which then renders as many frames as
+// the CPU and GPU allow, most of which are never displayed. The
+// frame limiter caps the frame rate by waiting at the end of
+// each frame until the frame's time slot is over.
+//
+// A plain `thread::sleep` is not precise enough for this: the
+// OS only wakes the thread up on its scheduler ticks, which
+// are as coarse as 1 to 15.6 ms on Windows, and a few tenths
+// of a millisecond elsewhere, so the thread would regularly
+// oversleep by a good part of a frame. The limiter therefore
+// sleeps until shortly before the deadline, and spins (yielding
+// to other threads) for the rest. Deadlines follow each other
+// at the target interval rather than being counted from the
+// end of the previous wait, so that the errors don't add up,
+// unless the frame loop falls behind by more than a frame, in
+// which case they restart from the current time instead of
+// rendering a burst of frames to catch up.
+
+/// Time before a deadline at which the limiter stops sleeping
+/// and starts spinning.
+const SPIN_MARGIN: Duration = if cfg!(windows) {
+    Duration::from_micros(2000)
+} else {
+    Duration::from_micros(500)
+};
+
+/// Frame rate limiter, waiting at the end of each frame so that
+/// frames don't come faster than the target rate.
+#[derive(Debug, Default)]
+pub struct FrameLimiter {
+    /// Minimum time between two frames, if limited.
+    interval: Option<Duration>,
+    /// Time the current frame's slot ends at.
+    deadline: Option<Instant>,
+}
+
+impl FrameLimiter {
+    pub fn new(target_fps: Option<u32>) -> Self {
+        let mut limiter = Self::default();
+        limiter.set_target_fps(target_fps);
+        limiter
+    }
+
+    /// Set the maximum frame rate, or remove the limit with
+    /// `None`. A target of 0 also removes it.
+    pub fn set_target_fps(&mut self, target_fps: Option<u32>) {
+        self.interval = target_fps
+            .filter(|&fps| fps > 0)
+            .map(|fps| Duration::from_secs(1) / fps);
+        self.deadline = None;
+    }
+
+    /// Maximum frame rate, if limited.
+    pub fn target_fps(&self) -> Option<u32> {
+        self.interval.map(|interval| (1.0 / interval.as_secs_f64()).round() as u32)
+    }
+
+    /// Wait for the end of the current frame's slot, and return
+    /// the time spent waiting: the headroom left by the frame.
+    pub fn wait(&mut self) -> Duration {
+        let start = Instant::now();
+        let Some(deadline) = self.deadline(start) else {
+            return Duration::ZERO;
+        };
+
+        sleep_until(deadline);
+
+        let end = Instant::now();
+        self.advance(deadline, end);
+        end - start
+    }
+
+    /// Time to wait until, for a frame that ended at `now`, if
+    /// limited. The first frame only starts the schedule.
+    fn deadline(&mut self, now: Instant) -> Option<Instant> {
+        let interval = self.interval?;
+        match self.deadline {
+            Some(deadline) => Some(deadline),
+            None => {
+                self.deadline = Some(now + interval);
+                None
+            }
+        }
+    }
+
+    /// Move on to the next frame's slot, once the wait for
+    /// `deadline` ended at `now`.
+    fn advance(&mut self, deadline: Instant, now: Instant) {
+        let Some(interval) = self.interval else {
+            return;
+        };
+
+        self.deadline = Some(if now > deadline + interval {
+            now + interval
+        } else {
+            deadline + interval
+        });
+    }
+}
+
+/// Sleep until `deadline`, precisely.
+fn sleep_until(deadline: Instant) {
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            return;
+        }
+
+        let remaining = deadline - now;
+        if remaining > SPIN_MARGIN {
+            thread::sleep(remaining - SPIN_MARGIN);
+        } else {
+            thread::yield_now();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Interval of a 100 fps target.
+    const INTERVAL: Duration = Duration::from_millis(10);
+
+    #[test]
+    fn unlimited_frames_never_wait() {
+        let now = Instant::now();
+        for target in [None, Some(0)] {
+            let mut limiter = FrameLimiter::new(target);
+            assert_eq!(limiter.target_fps(), None);
+            assert_eq!(limiter.deadline(now), None);
+            assert_eq!(limiter.deadline(now + INTERVAL), None);
+        }
+    }
+
+    #[test]
+    fn deadlines_follow_the_target_interval() {
+        let start = Instant::now();
+        let mut limiter = FrameLimiter::new(Some(100));
+        assert_eq!(limiter.target_fps(), Some(100));
+
+        // The first frame starts the schedule without waiting.
+        assert_eq!(limiter.deadline(start), None);
+        let deadline = limiter.deadline(start + Duration::from_millis(3));
+        assert_eq!(deadline, Some(start + INTERVAL));
+
+        // Oversleeping a little doesn't push the next deadline
+        // back, so that the errors don't add up.
+        limiter.advance(start + INTERVAL, start + INTERVAL + Duration::from_millis(1));
+        assert_eq!(limiter.deadline(start + INTERVAL * 2), Some(start + INTERVAL * 2));
+    }
+
+    #[test]
+    fn late_frames_restart_the_schedule() {
+        let start = Instant::now();
+        let mut limiter = FrameLimiter::new(Some(100));
+        limiter.deadline(start);
+
+        // A frame more than an interval late doesn't leave a
+        // burst of frames to catch up: the schedule restarts
+        // from the end of the frame.
+        let late = start + INTERVAL * 3 + Duration::from_millis(4);
+        limiter.advance(start + INTERVAL, late);
+        assert_eq!(limiter.deadline(late), Some(late + INTERVAL));
+    }
+
+    #[test]
+    fn changing_the_target_restarts_the_schedule() {
+        let start = Instant::now();
+        let mut limiter = FrameLimiter::new(Some(100));
+        limiter.deadline(start);
+
+        limiter.set_target_fps(Some(50));
+        assert_eq!(limiter.target_fps(), Some(50));
+        let later = start + Duration::from_millis(5);
+        assert_eq!(limiter.deadline(later), None);
+        assert_eq!(limiter.deadline(later), Some(later + Duration::from_millis(20)));
+
+        limiter.set_target_fps(None);
+        assert_eq!(limiter.target_fps(), None);
+        assert_eq!(limiter.deadline(later), None);
+    }
+}
@@ -1,16 +0,0 @@
-use winit::event_loop::{EventLoop, ControlFlow};
-use caliban::app::App;
-use anyhow::Result;
-
-fn main() -> Result<()> {
-    std::env::set_var("RUST_LOG", "info");
-    pretty_env_logger::init();
-
-    let event_loop = EventLoop::new().unwrap();
-    event_loop.set_control_flow(ControlFlow::Wait);
-
-    let mut app = App::new();
-    event_loop.run_app(&mut app)?;
-
-    Ok(())
-}
\ No newline at end of file
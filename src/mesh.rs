@@ -0,0 +1,153 @@
+pub mod lod;
+pub mod packed;
+pub mod shapes;
+
+use std::{
+    collections::HashMap,
+    hash::{Hash, Hasher},
+    mem::size_of,
+};
+
+use glam::{Vec2, Vec3};
+use vulkanalia::prelude::v1_0::*;
+
+/// Layout of a vertex type in vertex buffers, as given to the
+/// vertex input state of pipelines. Every layout feeds the same
+/// semantic inputs to shaders (position at location 0, color
+/// at 1, normal at 2, texture coordinates at 3), so a shader
+/// works with any of them.
+pub trait VertexLayout: Copy {
+    fn binding_description() -> vk::VertexInputBindingDescription;
+    fn attribute_descriptions() -> [vk::VertexInputAttributeDescription; 4];
+}
+
+/// Vertex data, as it is laid out in vertex buffers.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Vertex {
+    /// Position of the vertex in model space.
+    pub pos: Vec3,
+    /// Color of the vertex.
+    pub color: Vec3,
+    /// Normal of the vertex.
+    pub normal: Vec3,
+    /// Texture coordinates of the vertex.
+    pub tex_coord: Vec2,
+}
+
+impl Vertex {
+    pub const fn new(pos: Vec3, color: Vec3, normal: Vec3, tex_coord: Vec2) -> Self {
+        Self { pos, color, normal, tex_coord }
+    }
+
+    fn components(&self) -> [f32; 11] {
+        [
+            self.pos.x, self.pos.y, self.pos.z,
+            self.color.x, self.color.y, self.color.z,
+            self.normal.x, self.normal.y, self.normal.z,
+            self.tex_coord.x, self.tex_coord.y,
+        ]
+    }
+}
+
+impl VertexLayout for Vertex {
+    fn binding_description() -> vk::VertexInputBindingDescription {
+        // The binding description tells Vulkan how to pass the
+        // vertex data to the vertex shader: the index of the
+        // binding in the array of bindings, the number of bytes
+        // from one entry to the next (the stride), and whether
+        // to move to the next entry after each vertex or after
+        // each instance.
+        vk::VertexInputBindingDescription::builder()
+            .binding(0)
+            .stride(size_of::<Vertex>() as u32)
+            .input_rate(vk::VertexInputRate::VERTEX)
+            .build()
+    }
+
+    fn attribute_descriptions() -> [vk::VertexInputAttributeDescription; 4] {
+        // Each attribute description describes how to extract
+        // one vertex attribute from the chunk of vertex data
+        // given by the binding: the binding it comes from, the
+        // location it is read from in the shader, its format
+        // (with the same enumeration as color formats, so a
+        // vec3 is R32G32B32_SFLOAT) and its offset within the
+        // vertex.
+        let pos = vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(0)
+            .format(vk::Format::R32G32B32_SFLOAT)
+            .offset(0)
+            .build();
+
+        let color = vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(1)
+            .format(vk::Format::R32G32B32_SFLOAT)
+            .offset(size_of::<Vec3>() as u32)
+            .build();
+
+        let normal = vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(2)
+            .format(vk::Format::R32G32B32_SFLOAT)
+            .offset((2 * size_of::<Vec3>()) as u32)
+            .build();
+
+        let tex_coord = vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(3)
+            .format(vk::Format::R32G32_SFLOAT)
+            .offset((3 * size_of::<Vec3>()) as u32)
+            .build();
+
+        [pos, color, normal, tex_coord]
+    }
+}
+
+// Floats are neither Eq nor Hash (because of NaN), but vertices
+// need both to be deduplicated through a hash map. Comparing
+// the bit patterns of the components is exact, which is what we
+// want for welding vertices that were literally duplicated.
+impl PartialEq for Vertex {
+    fn eq(&self, other: &Self) -> bool {
+        self.components()
+            .iter()
+            .zip(other.components().iter())
+            .all(|(a, b)| a.to_bits() == b.to_bits())
+    }
+}
+
+impl Eq for Vertex {}
+
+impl Hash for Vertex {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.components()
+            .iter()
+            .for_each(|c| c.to_bits().hash(state));
+    }
+}
+
+/// Index an unindexed triangle list ("triangle soup"): every
+/// unique vertex is kept once, and the returned indices
+/// reference it in the order of the original triangles.
+#[profiling::function]
+pub fn index_soup(vertices: &[Vertex]) -> (Vec<Vertex>, Vec<u32>) {
+    let mut unique = HashMap::new();
+    let mut out_vertices = Vec::new();
+    let mut indices = Vec::with_capacity(vertices.len());
+
+    for vertex in vertices {
+        // If the vertex has already been seen, we just reuse
+        // its index; otherwise it is appended to the vertex
+        // list and registered with the next index.
+        let index = *unique.entry(*vertex).or_insert_with(|| {
+            out_vertices.push(*vertex);
+            out_vertices.len() as u32 - 1
+        });
+
+        indices.push(index);
+    }
+
+    (out_vertices, indices)
+}
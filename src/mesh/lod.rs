@@ -0,0 +1,304 @@
+use std::collections::{HashMap, HashSet};
+
+use glam::Vec3;
+use super::Vertex;
+
+/// Levels of detail of a mesh. All levels share the vertex
+/// buffer of the mesh, and only differ by their index buffer;
+/// level 0 is the full resolution mesh.
+pub struct MeshLods {
+    pub levels: Vec<Vec<u32>>,
+}
+
+impl MeshLods {
+    /// Generate `count` levels of detail (including the full
+    /// resolution one) for the given mesh. Each level halves
+    /// the resolution of the clustering grid of the previous
+    /// one, starting from `base_resolution` cells along the
+    /// largest side of the bounding box.
+    #[profiling::function]
+    pub fn generate(
+        vertices: &[Vertex],
+        indices: &[u32],
+        count: usize,
+        base_resolution: u32,
+    ) -> Self {
+        let mut levels = vec![indices.to_vec()];
+        let mut resolution = base_resolution.max(1);
+
+        for _ in 1..count {
+            let simplified = simplify(vertices, indices, resolution);
+
+            // Once the grid is too coarse, every triangle
+            // collapses; there is no point in keeping empty
+            // levels around.
+            if simplified.is_empty() {
+                break;
+            }
+
+            levels.push(simplified);
+            resolution = (resolution / 2).max(1);
+        }
+
+        Self { levels }
+    }
+
+    /// Number of triangles drawn at the given level.
+    pub fn triangle_count(&self, level: usize) -> usize {
+        self.levels[level.min(self.levels.len() - 1)].len() / 3
+    }
+}
+
+/// Simplify a mesh by vertex clustering: the bounding box of
+/// the mesh is divided into a grid with `resolution` cells
+/// along its largest side, all vertices falling in the same
+/// cell are collapsed into the first of them, and the triangles
+/// that became degenerate are dropped. This is crude compared
+/// to quadric error metrics, but fast, robust, and it never
+/// creates new vertices, so the result can reuse the original
+/// vertex buffer.
+#[profiling::function]
+pub fn simplify(vertices: &[Vertex], indices: &[u32], resolution: u32) -> Vec<u32> {
+    if vertices.is_empty() {
+        return Vec::new();
+    }
+
+    let (min, max) = vertices.iter().fold(
+        (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+        |(min, max), v| (min.min(v.pos), max.max(v.pos)),
+    );
+
+    let cell_size = (max - min).max_element() / resolution.max(1) as f32;
+    if cell_size <= 0.0 {
+        return indices.to_vec();
+    }
+
+    // Map each vertex to its cell, and each cell to the first
+    // vertex that fell into it.
+    let mut representatives = HashMap::new();
+    let remap = vertices
+        .iter()
+        .enumerate()
+        .map(|(index, v)| {
+            let cell = ((v.pos - min) / cell_size).floor().as_ivec3();
+            *representatives.entry(cell).or_insert(index as u32)
+        })
+        .collect::<Vec<_>>();
+
+    // Then, remap the triangles, dropping the ones that have
+    // collapsed to a line or a point, and the ones that are
+    // now duplicated.
+    let mut seen = HashSet::new();
+    let mut simplified = Vec::new();
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| remap[triangle[i] as usize]);
+        if a == b || b == c || a == c {
+            continue;
+        }
+
+        // The same triangle can come back with its vertices
+        // rotated, so it is keyed by its rotation starting
+        // with the smallest index (which keeps the winding).
+        let key = if a < b && a < c {
+            (a, b, c)
+        } else if b < c {
+            (b, c, a)
+        } else {
+            (c, a, b)
+        };
+
+        if seen.insert(key) {
+            simplified.extend_from_slice(&[a, b, c]);
+        }
+    }
+
+    simplified
+}
+
+/// Radius, in pixels, of the projection on screen of a
+/// bounding sphere of radius `radius` at distance `distance`
+/// from a perspective camera with vertical field of view
+/// `fov_y` (in radians) and a viewport `viewport_height`
+/// pixels high.
+pub fn projected_radius(radius: f32, distance: f32, fov_y: f32, viewport_height: f32) -> f32 {
+    if distance <= radius {
+        return f32::MAX;
+    }
+
+    radius / (distance * (fov_y / 2.0).tan()) * viewport_height / 2.0
+}
+
+/// Distance-based level of detail selection.
+pub struct LodSelector {
+    /// Screen-space radii (in pixels) below which each level
+    /// is left for the next one, in decreasing order: level 0
+    /// is used for radii above `thresholds[0]`, level 1
+    /// between `thresholds[1]` and `thresholds[0]`, and so on.
+    pub thresholds: Vec<f32>,
+    /// Relative margin around the thresholds before switching
+    /// level, so that objects right at a threshold don't pop
+    /// back and forth every frame.
+    pub hysteresis: f32,
+}
+
+impl Default for LodSelector {
+    fn default() -> Self {
+        Self {
+            thresholds: vec![200.0, 100.0, 50.0, 25.0],
+            hysteresis: 0.1,
+        }
+    }
+}
+
+impl LodSelector {
+    /// Select the level for an object currently drawn at level
+    /// `current`, whose bounding sphere covers `screen_radius`
+    /// pixels, among `level_count` available levels.
+    pub fn select(&self, current: usize, screen_radius: f32, level_count: usize) -> usize {
+        // With more levels than thresholds, the levels past
+        // the last threshold are never selected; an object
+        // drawn at one of them (or at a level the mesh no
+        // longer has) is handled as if at the coarsest one
+        // that can be.
+        let last = level_count.saturating_sub(1).min(self.thresholds.len());
+        let target = self.thresholds
+            .iter()
+            .position(|&t| screen_radius >= t)
+            .unwrap_or(self.thresholds.len())
+            .min(last);
+
+        let current = current.min(last);
+        if target > current {
+            // Getting coarser: the object has to be clearly
+            // below the lower bound of its current level.
+            let bound = self.thresholds[current];
+            if screen_radius >= bound * (1.0 - self.hysteresis) {
+                return current;
+            }
+        } else if target < current {
+            // Getting finer: the object has to be clearly
+            // above the upper bound of its current level.
+            let bound = self.thresholds[current - 1];
+            if screen_radius < bound * (1.0 + self.hysteresis) {
+                return current;
+            }
+        }
+
+        target
+    }
+}
+
+/// Triangles drawn per level of detail, to make the savings
+/// visible.
+#[derive(Default, Debug, Clone)]
+pub struct LodStats {
+    pub triangles: Vec<u64>,
+}
+
+impl LodStats {
+    pub fn record(&mut self, level: usize, triangles: usize) {
+        if self.triangles.len() <= level {
+            self.triangles.resize(level + 1, 0);
+        }
+
+        self.triangles[level] += triangles as u64;
+    }
+
+    /// Reset the counters, at the start of each frame.
+    pub fn reset(&mut self) {
+        self.triangles.iter_mut().for_each(|t| *t = 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::{index_soup, shapes};
+
+    /// Check that the indices form whole triangles of `vertices`,
+    /// wound counter-clockwise when seen from the side their
+    /// normals point to.
+    fn check_triangles(vertices: &[Vertex], indices: &[u32]) {
+        assert_eq!(indices.len() % 3, 0);
+        assert!(indices.iter().all(|&index| (index as usize) < vertices.len()));
+
+        for triangle in indices.chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| vertices[triangle[i] as usize]);
+            let face = (b.pos - a.pos).cross(c.pos - a.pos);
+            assert!(face.dot(a.normal + b.normal + c.normal) > 0.0, "Triangle {:?} is wound clockwise.", triangle);
+        }
+    }
+
+    #[test]
+    fn select_applies_hysteresis() {
+        let selector = LodSelector::default();
+        assert_eq!(selector.select(0, 300.0, 5), 0);
+        assert_eq!(selector.select(0, 150.0, 5), 1);
+        // Just below the threshold: level 0 stays, and just
+        // above it, level 1 stays.
+        assert_eq!(selector.select(0, 195.0, 5), 0);
+        assert_eq!(selector.select(1, 205.0, 5), 1);
+        assert_eq!(selector.select(4, 10.0, 5), 4);
+    }
+
+    #[test]
+    fn select_handles_more_levels_than_thresholds() {
+        let selector = LodSelector::default();
+        assert_eq!(selector.select(7, 10.0, 8), 4);
+        assert_eq!(selector.select(7, 300.0, 8), 0);
+        assert_eq!(selector.select(2, 10.0, 8), 4);
+        // Fewer levels than thresholds.
+        assert_eq!(selector.select(5, 10.0, 2), 1);
+        assert_eq!(selector.select(0, 10.0, 0), 0);
+    }
+
+    #[test]
+    fn cube_has_four_vertices_per_face() {
+        let (vertices, indices) = shapes::cube(2.0);
+        assert_eq!((vertices.len(), indices.len()), (24, 36));
+        check_triangles(&vertices, &indices);
+        assert!(vertices.iter().all(|v| v.pos.abs().max_element() == 1.0));
+    }
+
+    #[test]
+    fn uv_sphere_skips_pole_triangles() {
+        // 9 rings of 13 vertices; 12 quads per band, the bands
+        // at the poles only having one triangle each.
+        let (vertices, indices) = shapes::uv_sphere(1.5, 8, 12);
+        assert_eq!(vertices.len(), 9 * 13);
+        assert_eq!(indices.len(), 3 * 12 * (2 * 8 - 2));
+        check_triangles(&vertices, &indices);
+        assert!(vertices.iter().all(|v| (v.pos.length() - 1.5).abs() < 1e-5));
+
+        // Too few subdivisions are raised to the minimum.
+        let (vertices, indices) = shapes::uv_sphere(1.0, 0, 0);
+        assert_eq!((vertices.len(), indices.len()), (3 * 4, 3 * 3 * 2));
+        check_triangles(&vertices, &indices);
+    }
+
+    #[test]
+    fn plane_has_two_triangles_per_cell() {
+        let (vertices, indices) = shapes::plane(2.0, 4);
+        assert_eq!((vertices.len(), indices.len()), (25, 4 * 4 * 6));
+        check_triangles(&vertices, &indices);
+
+        let (vertices, indices) = shapes::plane(1.0, 0);
+        assert_eq!((vertices.len(), indices.len()), (4, 6));
+        check_triangles(&vertices, &indices);
+    }
+
+    #[test]
+    fn index_soup_welds_duplicates() {
+        // Expand the cube into a triangle soup, and index it
+        // back: the shared corners of each face are welded.
+        let (vertices, indices) = shapes::cube(1.0);
+        let soup = indices.iter().map(|&i| vertices[i as usize]).collect::<Vec<_>>();
+
+        let (welded, soup_indices) = index_soup(&soup);
+        assert_eq!((welded.len(), soup_indices.len()), (24, 36));
+        assert!(soup_indices.iter().all(|&index| (index as usize) < welded.len()));
+        assert!(soup_indices.iter().map(|&i| welded[i as usize]).eq(soup.iter().copied()));
+
+        assert_eq!(index_soup(&[]), (Vec::new(), Vec::new()));
+    }
+}
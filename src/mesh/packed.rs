@@ -0,0 +1,282 @@
+use std::mem::size_of;
+
+use glam::Vec3;
+use vulkanalia::prelude::v1_0::*;
+
+use super::{Vertex, VertexLayout};
+
+/// Compact vertex, for large static meshes where vertex
+/// bandwidth matters: 24 bytes instead of the 44 of [`Vertex`].
+/// Positions keep their full precision, but the other
+/// attributes are quantized.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PackedVertex {
+    /// Position of the vertex in model space, as 3 floats.
+    pub pos: [f32; 3],
+    /// Normal of the vertex, as 10-10-10-2 signed normalized
+    /// integers (the 2 bits of W are unused).
+    pub normal: u32,
+    /// Texture coordinates of the vertex, as half floats.
+    pub tex_coord: [u16; 2],
+    /// Color of the vertex, as 8-bit normalized integers (alpha
+    /// is always opaque).
+    pub color: [u8; 4],
+}
+
+impl From<&Vertex> for PackedVertex {
+    fn from(vertex: &Vertex) -> Self {
+        Self {
+            pos: vertex.pos.to_array(),
+            normal: pack_snorm_10_10_10_2(vertex.normal),
+            tex_coord: vertex.tex_coord.to_array().map(f32_to_f16),
+            color: [
+                unorm8(vertex.color.x),
+                unorm8(vertex.color.y),
+                unorm8(vertex.color.z),
+                u8::MAX,
+            ],
+        }
+    }
+}
+
+impl VertexLayout for PackedVertex {
+    fn binding_description() -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription::builder()
+            .binding(0)
+            .stride(size_of::<PackedVertex>() as u32)
+            .input_rate(vk::VertexInputRate::VERTEX)
+            .build()
+    }
+
+    fn attribute_descriptions() -> [vk::VertexInputAttributeDescription; 4] {
+        // The locations are the same as for the full vertex;
+        // the formats tell the input assembler how to expand
+        // each attribute back to floats, so shaders still read
+        // a vec3 normal or a vec2 for the texture coordinates.
+        // The packed normal format stores X in the low bits,
+        // hence the reversed component order in its name.
+        let pos = vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(0)
+            .format(vk::Format::R32G32B32_SFLOAT)
+            .offset(0)
+            .build();
+
+        let color = vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(1)
+            .format(vk::Format::R8G8B8A8_UNORM)
+            .offset(20)
+            .build();
+
+        let normal = vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(2)
+            .format(vk::Format::A2B10G10R10_SNORM_PACK32)
+            .offset(12)
+            .build();
+
+        let tex_coord = vk::VertexInputAttributeDescription::builder()
+            .binding(0)
+            .location(3)
+            .format(vk::Format::R16G16_SFLOAT)
+            .offset(16)
+            .build();
+
+        [pos, color, normal, tex_coord]
+    }
+}
+
+/// Vertex format used when uploading a mesh.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VertexPacking {
+    /// Full-precision [`Vertex`].
+    #[default]
+    Full,
+    /// Quantized [`PackedVertex`].
+    Packed,
+}
+
+impl VertexPacking {
+    /// Size of a vertex in the buffer.
+    pub fn stride(self) -> usize {
+        match self {
+            VertexPacking::Full => size_of::<Vertex>(),
+            VertexPacking::Packed => size_of::<PackedVertex>(),
+        }
+    }
+
+    pub fn binding_description(self) -> vk::VertexInputBindingDescription {
+        match self {
+            VertexPacking::Full => Vertex::binding_description(),
+            VertexPacking::Packed => PackedVertex::binding_description(),
+        }
+    }
+
+    pub fn attribute_descriptions(self) -> [vk::VertexInputAttributeDescription; 4] {
+        match self {
+            VertexPacking::Full => Vertex::attribute_descriptions(),
+            VertexPacking::Packed => PackedVertex::attribute_descriptions(),
+        }
+    }
+
+    /// Bytes of the vertex buffer for `vertices` in this
+    /// format, ready to be copied to a staging buffer.
+    #[profiling::function]
+    pub fn pack(self, vertices: &[Vertex]) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(vertices.len() * self.stride());
+
+        match self {
+            VertexPacking::Full => {
+                for vertex in vertices {
+                    vertex.pos.to_array()
+                        .iter()
+                        .chain(&vertex.color.to_array())
+                        .chain(&vertex.normal.to_array())
+                        .chain(&vertex.tex_coord.to_array())
+                        .for_each(|c| bytes.extend_from_slice(&c.to_ne_bytes()));
+                }
+            }
+            VertexPacking::Packed => {
+                for vertex in vertices.iter().map(PackedVertex::from) {
+                    vertex.pos.iter().for_each(|c| bytes.extend_from_slice(&c.to_ne_bytes()));
+                    bytes.extend_from_slice(&vertex.normal.to_ne_bytes());
+                    vertex.tex_coord.iter().for_each(|c| bytes.extend_from_slice(&c.to_ne_bytes()));
+                    bytes.extend_from_slice(&vertex.color);
+                }
+            }
+        }
+
+        bytes
+    }
+}
+
+/// Pack a unit vector into the X, Y and Z components of a
+/// 10-10-10-2 signed normalized integer, X in the low bits.
+pub fn pack_snorm_10_10_10_2(v: Vec3) -> u32 {
+    // A 10-bit signed normalized integer maps [-1, 1] to
+    // [-511, 511] (-512 also maps to -1), stored in two's
+    // complement in its 10 bits.
+    let snorm = |c: f32| ((c.clamp(-1.0, 1.0) * 511.0).round() as i32 as u32) & 0x3ff;
+    snorm(v.x) | (snorm(v.y) << 10) | (snorm(v.z) << 20)
+}
+
+fn unorm8(c: f32) -> u8 {
+    (c.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+/// Convert a float to a half float (IEEE 754 binary16), rounded
+/// to the nearest value. Out of range values become infinite.
+pub fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    // Infinities stay infinite, and NaNs stay NaNs (with a
+    // mantissa bit set to not become infinite).
+    if exponent == 0xff {
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+
+    // Half floats have a 5-bit exponent biased by 15, against
+    // 8 bits biased by 127 for floats, and 10 mantissa bits
+    // instead of 23.
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+
+    // Values too small for a normal half float become
+    // subnormals, with the implicit leading bit made explicit
+    // and shifted into the mantissa; values too small even for
+    // those are flushed to zero.
+    if exponent <= 0 {
+        if exponent < -10 {
+            return sign;
+        }
+
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - exponent) as u32;
+        let round = (mantissa >> (shift - 1)) & 1;
+        return sign | ((mantissa >> shift) + round) as u16;
+    }
+
+    // A carry from rounding goes into the exponent, which is
+    // the right result (up to infinity).
+    let half = ((exponent as u32) << 10) | (mantissa >> 13);
+    let round = (mantissa >> 12) & 1;
+    sign | (half + round) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn f16_normal_values() {
+        assert_eq!(f32_to_f16(0.0), 0x0000);
+        assert_eq!(f32_to_f16(-0.0), 0x8000);
+        assert_eq!(f32_to_f16(1.0), 0x3c00);
+        assert_eq!(f32_to_f16(0.5), 0x3800);
+        assert_eq!(f32_to_f16(-2.0), 0xc000);
+        assert_eq!(f32_to_f16(65504.0), 0x7bff);
+        // The smallest normal half float.
+        assert_eq!(f32_to_f16(2f32.powi(-14)), 0x0400);
+    }
+
+    #[test]
+    fn f16_rounds_to_nearest() {
+        // 0.1 is 0x2e66 plus a bit less than half an ulp.
+        assert_eq!(f32_to_f16(0.1), 0x2e66);
+        // A quarter and three quarters of an ulp above 1.
+        assert_eq!(f32_to_f16(1.0 + 2f32.powi(-12)), 0x3c00);
+        assert_eq!(f32_to_f16(1.0 + 3.0 * 2f32.powi(-12)), 0x3c01);
+    }
+
+    #[test]
+    fn f16_subnormals() {
+        assert_eq!(f32_to_f16(2f32.powi(-15)), 0x0200);
+        assert_eq!(f32_to_f16(2f32.powi(-24)), 0x0001);
+        assert_eq!(f32_to_f16(-2f32.powi(-24)), 0x8001);
+        // Three quarters of the smallest subnormal round up to
+        // it, a quarter rounds down to zero.
+        assert_eq!(f32_to_f16(3.0 * 2f32.powi(-26)), 0x0001);
+        assert_eq!(f32_to_f16(2f32.powi(-26)), 0x0000);
+        assert_eq!(f32_to_f16(-1e-10), 0x8000);
+    }
+
+    #[test]
+    fn f16_overflow_and_special_values() {
+        // Past the largest half float, and rounding up to it.
+        assert_eq!(f32_to_f16(65520.0), 0x7c00);
+        assert_eq!(f32_to_f16(1e6), 0x7c00);
+        assert_eq!(f32_to_f16(-1e6), 0xfc00);
+        assert_eq!(f32_to_f16(f32::INFINITY), 0x7c00);
+        assert_eq!(f32_to_f16(f32::NEG_INFINITY), 0xfc00);
+
+        let nan = f32_to_f16(f32::NAN);
+        assert_eq!(nan & 0x7c00, 0x7c00);
+        assert_ne!(nan & 0x3ff, 0);
+    }
+
+    #[test]
+    fn snorm_10_10_10_2_components() {
+        assert_eq!(pack_snorm_10_10_10_2(Vec3::ZERO), 0);
+        assert_eq!(pack_snorm_10_10_10_2(Vec3::X), 0x1ff);
+        assert_eq!(pack_snorm_10_10_10_2(-Vec3::X), 0x201);
+        assert_eq!(pack_snorm_10_10_10_2(Vec3::Y), 0x1ff << 10);
+        assert_eq!(pack_snorm_10_10_10_2(-Vec3::Z), 0x201 << 20);
+        assert_eq!(pack_snorm_10_10_10_2(Vec3::new(0.5, 0.0, 0.0)), 0x100);
+        // Out of range components are clamped.
+        assert_eq!(pack_snorm_10_10_10_2(Vec3::new(2.0, -2.0, 0.0)), 0x1ff | (0x201 << 10));
+    }
+
+    #[test]
+    fn snorm_10_10_10_2_leaves_w_clear() {
+        for v in [-Vec3::ONE, Vec3::ONE, Vec3::new(-0.3, 0.8, -1.0), Vec3::NEG_Z] {
+            assert_eq!(pack_snorm_10_10_10_2(v) >> 30, 0, "{}", v);
+        }
+    }
+}
@@ -0,0 +1,140 @@
+// Procedural shapes, to get geometry without any asset file.
+// All shapes are white, have counter-clockwise front faces
+// when seen from outside, and come with normals and texture
+// coordinates.
+
+use std::f32::consts::PI;
+
+use glam::{vec2, Vec3};
+use super::Vertex;
+
+/// Axis-aligned cube of side `size`, centered on the origin.
+/// Each face has its own 4 vertices, so that normals and
+/// texture coordinates are not shared across edges.
+pub fn cube(size: f32) -> (Vec<Vertex>, Vec<u32>) {
+    // Each face is described by its normal n and two axes
+    // (u, v) spanning it, chosen so that u × v = n: walking
+    // the corners (-u-v, +u-v, +u+v, -u+v) is then
+    // counter-clockwise when looking at the face from outside.
+    let faces = [
+        (Vec3::X, Vec3::NEG_Z, Vec3::Y),
+        (Vec3::NEG_X, Vec3::Z, Vec3::Y),
+        (Vec3::Y, Vec3::X, Vec3::NEG_Z),
+        (Vec3::NEG_Y, Vec3::X, Vec3::Z),
+        (Vec3::Z, Vec3::X, Vec3::Y),
+        (Vec3::NEG_Z, Vec3::NEG_X, Vec3::Y),
+    ];
+
+    // Texture coordinates have their v axis going down, like
+    // image rows.
+    let corners = [
+        (-1.0, -1.0, vec2(0.0, 1.0)),
+        (1.0, -1.0, vec2(1.0, 1.0)),
+        (1.0, 1.0, vec2(1.0, 0.0)),
+        (-1.0, 1.0, vec2(0.0, 0.0)),
+    ];
+
+    let half = size / 2.0;
+    let mut vertices = Vec::with_capacity(24);
+    let mut indices = Vec::with_capacity(36);
+
+    for (normal, u, v) in faces {
+        let base = vertices.len() as u32;
+
+        for (su, sv, tex_coord) in corners {
+            let pos = (normal + su * u + sv * v) * half;
+            vertices.push(Vertex::new(pos, Vec3::ONE, normal, tex_coord));
+        }
+
+        indices.extend_from_slice(&[base, base + 1, base + 2, base + 2, base + 3, base]);
+    }
+
+    (vertices, indices)
+}
+
+/// UV sphere of the given `radius`, centered on the origin,
+/// with `rings` subdivisions from pole to pole and `sectors`
+/// subdivisions around the Y axis.
+pub fn uv_sphere(radius: f32, rings: u32, sectors: u32) -> (Vec<Vertex>, Vec<u32>) {
+    let rings = rings.max(2);
+    let sectors = sectors.max(3);
+
+    // Vertices are laid out ring by ring, from the north pole
+    // (phi = 0) to the south pole (phi = pi). Each ring has
+    // one more vertex than sectors, duplicating the first one
+    // with u = 1 so that the texture wraps around seamlessly.
+    let mut vertices = Vec::with_capacity(((rings + 1) * (sectors + 1)) as usize);
+    for r in 0..=rings {
+        let phi = PI * r as f32 / rings as f32;
+
+        for s in 0..=sectors {
+            let theta = 2.0 * PI * s as f32 / sectors as f32;
+            let normal = Vec3::new(
+                phi.sin() * theta.cos(),
+                phi.cos(),
+                phi.sin() * theta.sin(),
+            );
+
+            let tex_coord = vec2(s as f32 / sectors as f32, r as f32 / rings as f32);
+            vertices.push(Vertex::new(normal * radius, Vec3::ONE, normal, tex_coord));
+        }
+    }
+
+    // Each quad between two rings is split into two triangles.
+    // The triangles touching the poles would be degenerate
+    // (two of their vertices are the pole itself), so they
+    // are skipped.
+    let mut indices = Vec::new();
+    let stride = sectors + 1;
+    for r in 0..rings {
+        for s in 0..sectors {
+            let a = r * stride + s;
+            let b = a + stride;
+
+            if r != 0 {
+                indices.extend_from_slice(&[a, a + 1, b]);
+            }
+            if r != rings - 1 {
+                indices.extend_from_slice(&[a + 1, b + 1, b]);
+            }
+        }
+    }
+
+    (vertices, indices)
+}
+
+/// Square plane of side `size` in the XZ plane, centered on
+/// the origin and facing +Y, split into `subdivisions` cells
+/// along each side.
+pub fn plane(size: f32, subdivisions: u32) -> (Vec<Vertex>, Vec<u32>) {
+    let cells = subdivisions.max(1);
+    let half = size / 2.0;
+
+    let mut vertices = Vec::with_capacity(((cells + 1) * (cells + 1)) as usize);
+    for j in 0..=cells {
+        for i in 0..=cells {
+            let u = i as f32 / cells as f32;
+            let v = j as f32 / cells as f32;
+            let pos = Vec3::new(-half + size * u, 0.0, -half + size * v);
+
+            vertices.push(Vertex::new(pos, Vec3::ONE, Vec3::Y, vec2(u, v)));
+        }
+    }
+
+    // Going +X then +Z is clockwise when seen from above, so
+    // the triangles of each cell are wound the other way.
+    let mut indices = Vec::with_capacity((cells * cells * 6) as usize);
+    let stride = cells + 1;
+    for j in 0..cells {
+        for i in 0..cells {
+            let a = j * stride + i;
+            let b = a + 1;
+            let c = a + stride;
+            let d = c + 1;
+
+            indices.extend_from_slice(&[a, c, b, b, c, d]);
+        }
+    }
+
+    (vertices, indices)
+}
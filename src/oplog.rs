@@ -0,0 +1,190 @@
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::Path,
+    sync::{Mutex, MutexGuard},
+    time::Instant,
+};
+
+/// High-level renderer operation, as recorded by the
+/// [`OpLog`]. Records are plain data, so that recording one
+/// never formats or allocates.
+#[derive(Clone, Copy, Debug)]
+pub enum Op {
+    /// The renderer was created.
+    Created { frames_in_flight: usize },
+    /// A frame was submitted and presented.
+    FrameSubmitted { frame: usize, image: usize },
+    /// Recording or submitting a frame failed, and it was
+    /// skipped.
+    FrameSkipped { frame: usize },
+    /// Waiting for a fence or a swapchain image timed out.
+    Timeout { frame: usize },
+    /// The swapchain was out of date.
+    OutOfDate { frame: usize },
+    /// The surface was lost.
+    SurfaceLost { frame: usize },
+    /// The surface was recreated after being lost.
+    SurfaceCreated,
+    /// The swapchain was (re)created with the given extent.
+    SwapchainCreated { width: u32, height: u32, images: usize },
+    /// A fatal error was returned from `render`.
+    Fatal { frame: usize },
+    /// The renderer was destroyed.
+    Destroyed,
+}
+
+/// A recorded operation, with the time it happened at.
+#[derive(Clone, Copy, Debug)]
+pub struct OpRecord {
+    pub time: Instant,
+    pub op: Op,
+}
+
+/// Ring buffer of the last renderer operations, dumped to a file
+/// when something goes wrong, to see what led up to it. It is
+/// disabled (zero capacity) by default; when enabled, the ring
+/// is allocated once and recording only takes an uncontended
+/// lock and copies a few words. The lock lets a panic hook dump
+/// the log from whichever thread panicked.
+#[derive(Debug)]
+pub struct OpLog {
+    /// The ring, if enabled.
+    ring: Option<Mutex<Ring>>,
+    start: Instant,
+}
+
+#[derive(Debug)]
+struct Ring {
+    records: Vec<OpRecord>,
+    capacity: usize,
+    /// Index of the slot the next record goes to, once the ring
+    /// is full.
+    next: usize,
+}
+
+impl OpLog {
+    pub fn new(capacity: usize) -> Self {
+        let ring = (capacity > 0).then(|| Mutex::new(Ring {
+            records: Vec::with_capacity(capacity),
+            capacity,
+            next: 0,
+        }));
+
+        Self { ring, start: Instant::now() }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.ring.is_some()
+    }
+
+    fn lock(&self) -> Option<MutexGuard<'_, Ring>> {
+        // Records are plain data, so a ring poisoned by a panic
+        // elsewhere is still consistent.
+        self.ring.as_ref().map(|ring| ring.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Record an operation, overwriting the oldest record if the
+    /// ring is full.
+    #[inline]
+    pub fn record(&self, op: Op) {
+        let Some(mut ring) = self.lock() else {
+            return;
+        };
+
+        let record = OpRecord { time: Instant::now(), op };
+        if ring.records.len() < ring.capacity {
+            ring.records.push(record);
+        } else {
+            let next = ring.next;
+            ring.records[next] = record;
+            ring.next = (next + 1) % ring.capacity;
+        }
+    }
+
+    /// Records from the oldest to the most recent.
+    pub fn records(&self) -> Vec<OpRecord> {
+        let Some(ring) = self.lock() else {
+            return Vec::new();
+        };
+
+        let (newer, older) = ring.records.split_at(ring.next);
+        older.iter().chain(newer).copied().collect()
+    }
+
+    /// Write the records to `path`, after a free-form `header`
+    /// (device information, the error that triggered the dump,
+    /// etc). Times are relative to the creation of the log.
+    pub fn dump(&self, path: impl AsRef<Path>, header: &str) -> io::Result<()> {
+        let records = self.records();
+        let mut file = io::BufWriter::new(File::create(path)?);
+        writeln!(file, "{}", header)?;
+
+        for record in records {
+            let time = record.time.duration_since(self.start);
+            writeln!(file, "[{:>12.6}] {:?}", time.as_secs_f64(), record.op)?;
+        }
+
+        file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames(records: &[OpRecord]) -> Vec<usize> {
+        records
+            .iter()
+            .map(|record| match record.op {
+                Op::FrameSubmitted { frame, .. } => frame,
+                op => panic!("Unexpected operation {:?}.", op),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn disabled_log_records_nothing() {
+        let log = OpLog::new(0);
+        assert!(!log.enabled());
+        log.record(Op::Destroyed);
+        assert!(log.records().is_empty());
+    }
+
+    #[test]
+    fn full_ring_wraps_around() {
+        let log = OpLog::new(3);
+        for frame in 0..2 {
+            log.record(Op::FrameSubmitted { frame, image: 0 });
+        }
+        assert_eq!(frames(&log.records()), [0, 1]);
+
+        // Past the capacity, the oldest records are overwritten,
+        // but still come out oldest first.
+        for frame in 2..8 {
+            log.record(Op::FrameSubmitted { frame, image: 0 });
+        }
+        let records = log.records();
+        assert_eq!(frames(&records), [5, 6, 7]);
+        assert!(records.windows(2).all(|pair| pair[0].time <= pair[1].time));
+    }
+
+    #[test]
+    fn dump_writes_oldest_first() {
+        let log = OpLog::new(2);
+        for frame in 0..5 {
+            log.record(Op::FrameSubmitted { frame, image: frame % 2 });
+        }
+
+        let path = std::env::temp_dir().join(format!("caliban-oplog-test-{}.txt", std::process::id()));
+        log.dump(&path, "Reason: test").unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let lines = text.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "Reason: test");
+        assert!(lines[1].ends_with("FrameSubmitted { frame: 3, image: 1 }"));
+        assert!(lines[2].ends_with("FrameSubmitted { frame: 4, image: 0 }"));
+    }
+}
@@ -0,0 +1,79 @@
+// Supported public API of the crate. Everything an application
+// needs is re-exported here, so that `use caliban::prelude::*`
+// is enough, without reaching into internal modules.
+
+pub use crate::app::{run, App, AppConfig, AppHooks, Context, FrameContext, LoadingProgress, StartupTasks, WindowMode};
+pub use crate::assets::{AssetError, Assets};
+pub use crate::config::RendererConfig;
+pub use crate::light::{pack_lights, GpuLight, Light, LightBlock, MAX_LIGHTS};
+pub use crate::renderer::{take_validation_errors, InstanceError, Renderer, RenderError, SwapchainError, ValidationMessage};
+pub use crate::limiter::FrameLimiter;
+pub use crate::throttle::{PresentationState, PresentationThrottle};
+pub use crate::oplog::{Op, OpLog, OpRecord};
+pub use crate::view::{split_screen, view_constants_offset, View, ViewConstants, MAX_VIEWS};
+pub use crate::stats::{FrameStats, FrameTime, FrameWaits, LatencyMode, FRAME_TIME_SAMPLES, MAX_FRAME_DT};
+
+pub use crate::core::frame::FrameToken;
+pub use crate::core::queries::PipelineStatistics;
+pub use crate::core::image::TextureFiltering;
+pub use crate::core::swapchain::{
+    PresentPreference,
+    SurfaceRotation,
+    SURFACE_FORMAT_DISPLAY_P3,
+    SURFACE_FORMAT_HDR10,
+    SURFACE_FORMAT_SRGB,
+};
+pub use crate::core::rendering::ColorAttachment;
+pub use crate::core::ownership::{
+    acquire_buffer,
+    acquire_image,
+    release_buffer,
+    release_image,
+    BufferTransfer,
+    ImageTransfer,
+    Ownership,
+};
+pub use crate::core::upload::{buffer_chunks, image_chunks, ImageChunk, UploadContext, UploadProgress};
+pub use crate::core::viewport::{flipped_viewport_rect, halton, jitter_offset, jittered_projection, set_viewport_rect};
+pub use crate::core::devices::{
+    DeviceDeficiency,
+    DeviceInfo,
+    DeviceLimits,
+    DeviceRequirements,
+    DynamicBinding,
+    EnabledFeatures,
+    NoSuitableDevice,
+    PortabilitySubset,
+};
+pub use crate::core::allocator::{
+    Allocation,
+    AllocationError,
+    AllocationInfo,
+    Allocator,
+    AllocatorStats,
+    BlockLayout,
+    ChunkLayout,
+    ChunkState,
+    Lifetime,
+    LifetimeStats,
+    MemoryUse,
+    ResourceCategory,
+    ResourceType,
+    UsageStats,
+};
+
+pub use crate::mesh::{
+    index_soup,
+    lod::{LodSelector, LodStats, MeshLods},
+    packed::{PackedVertex, VertexPacking},
+    shapes,
+    Vertex,
+    VertexLayout,
+};
+pub use crate::texture::{is_srgb, load_dds, DdsError, DdsTexture};
+
+// Low-level helpers, for standalone Vulkan code that only
+// borrows parts of the renderer (like the examples).
+pub use crate::core::queues::get_graphics_family_index;
+pub use crate::core::viewport::{flipped_viewport, full_scissor, set_viewport};
+pub use crate::renderer::VALIDATION_LAYER;
@@ -1,14 +1,42 @@
-use crate::core::{
-    commands::*, 
-    devices::*, 
-    frame::*, 
-    image::*, 
-    swapchain::*,
-    sync::*,
+use crate::{
+    config::RendererConfig,
+    limiter::FrameLimiter,
+    stats::{FrameStats, FrameTime, FrameTimer, FrameWaits, LatencyMode},
+    texture::is_srgb,
+    oplog::{Op, OpLog},
+    core::{
+        commands::*, 
+        devices::*, 
+        frame::*, 
+        image::*, 
+        queries::*,
+        rendering::*,
+        screenshot::*,
+        swapchain::*,
+        sync::*,
+        tracker::*,
+        viewport::{jitter_offset, set_viewport_rect},
+    },
+    view::{View, MAX_VIEWS},
 };
 
-use std::collections::HashSet;
+use std::{
+    cell::Cell,
+    collections::HashSet,
+    rc::Rc,
+    ffi::{c_char, c_void, CStr, CString},
+    fmt,
+    ops::RangeInclusive,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, Once, Weak,
+    },
+    time::{Duration, Instant},
+};
 
+use glam::Vec2;
 use winit::window::Window;
 use vulkanalia::{
     prelude::v1_0::*,
@@ -16,46 +44,307 @@ use vulkanalia::{
     window as vk_window,
     loader::{LibloadingLoader, LIBRARY},
     Version,
+    VkResult,
     vk::ExtDebugUtilsExtension,
     vk::KhrSurfaceExtension,
     vk::KhrSwapchainExtension,
+    vk::KhrPresentWaitExtension,
 };
 use anyhow::{anyhow, Result};
+use thiserror::Error;
 use log::*;
 
+/// Default for [`RendererConfig::validation`].
 pub const VALIDATION_ENABLED: bool = cfg!(debug_assertions);
 pub const VALIDATION_LAYER: vk::ExtensionName = vk::ExtensionName::from_bytes(b"VK_LAYER_KHRONOS_validation");
 pub const PORTABILITY_MACOS_VERSION: Version = Version::new(1, 3, 216);
+/// Default for [`RendererConfig::frames_in_flight`].
 pub const MAX_FRAMES_IN_FLIGHT: usize = 2;
+/// Supported numbers of frames in flight: a single frame
+/// serializes the CPU and the GPU, for the lowest latency, and
+/// more than three only adds latency without keeping the GPU
+/// any busier.
+pub const FRAMES_IN_FLIGHT_RANGE: RangeInclusive<usize> = 1..=3;
+/// Default time to wait for a frame fence or a swapchain image
+/// before giving up on the frame.
+pub const DEFAULT_FRAME_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Validation errors reported since they were last taken, up to
+/// [`MAX_VALIDATION_ERRORS`].
+static VALIDATION_ERRORS: Mutex<Vec<ValidationMessage>> = Mutex::new(Vec::new());
+
+/// Operation logs of the live renderers, dumped when the
+/// process panics, with the file to write each one to and the
+/// header to write first. Logs of destroyed renderers can't be
+/// upgraded anymore, and are pruned as new ones are added.
+static PANIC_OP_LOGS: Mutex<Vec<(Weak<OpLog>, PathBuf, String)>> = Mutex::new(Vec::new());
+
+/// Installs the panic hook dumping [`PANIC_OP_LOGS`], once for
+/// the whole process.
+static PANIC_HOOK: Once = Once::new();
+
+/// Number of validation errors kept until they are taken; the
+/// first ones are usually the cause of the next ones.
+const MAX_VALIDATION_ERRORS: usize = 16;
+
+/// A message from the validation layers, with the context the
+/// layers give along with it.
+#[derive(Clone, Debug, Default)]
+pub struct ValidationMessage {
+    /// Name of the check that failed (the VUID, for errors).
+    pub id_name: String,
+    pub id_number: i32,
+    pub message: String,
+    /// Objects involved, with their debug names if they have
+    /// one.
+    pub objects: Vec<String>,
+    /// Debug labels active on the queue.
+    pub queue_labels: Vec<String>,
+    /// Debug labels active in the command buffer.
+    pub command_buffer_labels: Vec<String>,
+}
+
+impl ValidationMessage {
+    /// Copy the message out of the callback data.
+    ///
+    /// # Safety
+    ///
+    /// `data` must be valid callback data, as given to the
+    /// debug callback by the layers.
+    pub unsafe fn from_callback_data(data: &vk::DebugUtilsMessengerCallbackDataEXT) -> Self {
+        // Every string and array of the callback data is
+        // optional, and only valid for the duration of the
+        // callback, hence the copies.
+        let string = |ptr: *const c_char| {
+            if ptr.is_null() {
+                String::new()
+            } else {
+                CStr::from_ptr(ptr).to_string_lossy().into_owned()
+            }
+        };
+        let objects = raw_slice(data.objects, data.object_count)
+            .iter()
+            .map(|object| {
+                let name = string(object.object_name);
+                if name.is_empty() {
+                    format!("{:?} {:#x}", object.object_type, object.object_handle)
+                } else {
+                    format!("{:?} {:#x} \"{}\"", object.object_type, object.object_handle, name)
+                }
+            })
+            .collect();
+
+        let labels = |labels: &[vk::DebugUtilsLabelEXT]| -> Vec<String> {
+            labels.iter().map(|label| string(label.label_name)).collect()
+        };
+
+        Self {
+            id_name: string(data.message_id_name),
+            id_number: data.message_id_number,
+            message: string(data.message),
+            objects,
+            queue_labels: labels(raw_slice(data.queue_labels, data.queue_label_count)),
+            command_buffer_labels: labels(raw_slice(data.cmd_buf_labels, data.cmd_buf_label_count)),
+        }
+    }
+}
+
+/// Slice from an optional array of the callback data.
+unsafe fn raw_slice<'a, T>(ptr: *const T, count: u32) -> &'a [T] {
+    if ptr.is_null() || count == 0 {
+        &[]
+    } else {
+        std::slice::from_raw_parts(ptr, count as usize)
+    }
+}
+
+impl fmt::Display for ValidationMessage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{} ({:#x})] {}", self.id_name, self.id_number, self.message)?;
+
+        if !self.objects.is_empty() {
+            write!(f, "\n  objects: {}", self.objects.join(", "))?;
+        }
+        if !self.queue_labels.is_empty() {
+            write!(f, "\n  queue labels: {}", self.queue_labels.join(" > "))?;
+        }
+        if !self.command_buffer_labels.is_empty() {
+            write!(f, "\n  command buffer labels: {}", self.command_buffer_labels.join(" > "))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Errors returned by [`Renderer::render`].
+#[derive(Error, Debug)]
+pub enum RenderError {
+    /// The swapchain no longer matches the surface and has to
+    /// be recreated before rendering again.
+    #[error("Swapchain out of date.")]
+    OutOfDate,
+    /// The surface was lost (the window moved to a display
+    /// that went away, for example). The surface and the
+    /// swapchain have to be recreated, from the window, with
+    /// [`Renderer::recreate_surface`], before rendering again.
+    #[error("Surface lost.")]
+    SurfaceLost,
+    /// Recording or submitting the frame failed. The frame was
+    /// skipped, but its synchronization objects were restored,
+    /// so rendering can simply be retried.
+    #[error("Frame skipped: {0:#}")]
+    FrameSkipped(anyhow::Error),
+    /// Waiting for the GPU or the presentation engine took
+    /// longer than the frame timeout. Nothing was submitted,
+    /// so rendering can be retried, but repeated timeouts
+    /// usually mean a hung device or compositor.
+    #[error("Timed out waiting for {0}.")]
+    Timeout(&'static str),
+    /// Any other error, after which the renderer can't be
+    /// expected to work anymore (device lost, for example).
+    #[error(transparent)]
+    Fatal(#[from] anyhow::Error),
+}
+
+/// Error returned to the application when the swapchain could
+/// not be recreated.
+#[derive(Error, Debug)]
+pub enum SwapchainError {
+    /// Recreation failed with an error that retrying won't fix
+    /// (device or surface lost, for example).
+    #[error("Failed to recreate the swapchain: {0:#}")]
+    Fatal(anyhow::Error),
+    /// Recreation kept failing with retryable errors; the
+    /// errors of the last attempts are attached, oldest first.
+    #[error("Failed to recreate the swapchain after {attempts} attempts: {}", errors.join("; "))]
+    RetriesExhausted { attempts: u32, errors: Vec<String> },
+}
+
+/// Whether an error comes from the surface being lost, in
+/// which case the surface has to be recreated along with the
+/// swapchain.
+pub fn is_surface_lost(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .any(|e| e.downcast_ref::<vk::ErrorCode>() == Some(&vk::ErrorCode::SURFACE_LOST_KHR))
+}
+
+/// Whether a swapchain recreation error may go away by
+/// retrying later. Some compositors transiently fail the
+/// creation right after a mode change (with
+/// INITIALIZATION_FAILED or NATIVE_WINDOW_IN_USE, for example),
+/// while a lost device or surface never comes back on its own.
+pub fn is_retryable(error: &anyhow::Error) -> bool {
+    match error.chain().find_map(|e| e.downcast_ref::<vk::ErrorCode>()) {
+        Some(&code) => !matches!(
+            code,
+            vk::ErrorCode::DEVICE_LOST | vk::ErrorCode::SURFACE_LOST_KHR
+        ),
+        None => false,
+    }
+}
+
+/// Errors returned by [`Renderer::create`] when Vulkan itself
+/// can't be set up, with an explanation users can act on.
+#[derive(Error, Debug)]
+pub enum InstanceError {
+    /// The Vulkan loader library could not be loaded.
+    #[error("Could not load the Vulkan library ({0}). Make sure a Vulkan loader is installed (it usually comes with the graphics driver, or the Vulkan SDK).")]
+    MissingLoader(String),
+    /// The loader found no driver supporting the requested
+    /// Vulkan version.
+    #[error("No Vulkan 1.3 driver was found. Make sure the graphics driver is installed and up to date, and that the GPU supports Vulkan 1.3.")]
+    NoDriver,
+    /// An instance extension required by the window system (or
+    /// the debug utilities) is not supported.
+    #[error("The Vulkan instance extension {0} is not available; it is required by the window system or the renderer. Check that the graphics driver supports this platform.")]
+    MissingExtension(String),
+    /// The validation layer was required but is not installed.
+    #[error("The Vulkan validation layer is not available. Install the Vulkan SDK, or disable validation (CALIBAN_VALIDATION=0).")]
+    MissingValidationLayer,
+    /// Any other error from instance creation.
+    #[error("Failed to create the Vulkan instance: {0}")]
+    Vulkan(vk::ErrorCode),
+}
+
+impl From<vk::ErrorCode> for RenderError {
+    fn from(error: vk::ErrorCode) -> Self {
+        RenderError::Fatal(anyhow!(error))
+    }
+}
+
+/// Commands recorded by the application in the main pass.
+pub type CustomPass = Box<dyn FnOnce(vk::CommandBuffer, &Device)>;
+
+/// Commands recorded by the application for each view of the
+/// frame, with the index of the view.
+pub type ViewPass = Box<dyn Fn(vk::CommandBuffer, &Device, usize, &View)>;
 
 /// Application data for rendering.
 #[derive(Default)]
-pub struct RenderData {
+pub(crate) struct RenderData {
     /// The surface to render to.
     pub surface: vk::SurfaceKHR,
     /// Debug messenger for the validation layers.
     pub debug_messenger: vk::DebugUtilsMessengerEXT,
+    /// Set by the messenger's callback, which gets it as its
+    /// user data, when the validation layer reports an error,
+    /// so that the renderer can dump its operation log. Each
+    /// renderer has its own, to only react to its own errors.
+    pub validation_error: Arc<AtomicBool>,
     /// The physical device (GPU) used for rendering.
     pub physical_device: vk::PhysicalDevice,
+    /// Limits of the physical device, cached at creation.
+    pub limits: DeviceLimits,
+    /// Properties of the physical device.
+    pub device_info: DeviceInfo,
+    /// Configuration the renderer was created with.
+    pub config: RendererConfig,
+    /// Optional device features actually enabled.
+    pub features: EnabledFeatures,
     /// Queue for graphics operations.
     pub graphics_queue: vk::Queue,
     /// Queue family index for graphics operations.
     pub graphics_queue_family: u32,
+    /// Queue to present swapchain images on, and its family;
+    /// it is the graphics queue when that one can present.
+    pub present_queue: vk::Queue,
+    pub present_queue_family: u32,
     /// Swapchain object to present rendering results (an array
     /// of presentable images) to a surface.
     pub swapchain: vk::SwapchainKHR,
     /// Format of the swapchain images.
     pub swapchain_format: vk::Format,
+    /// Color space the swapchain images are presented in.
+    pub swapchain_color_space: vk::ColorSpaceKHR,
+    /// Usage the swapchain images were created with.
+    pub swapchain_usage: vk::ImageUsageFlags,
+    /// Transform the swapchain images are presented with, and
+    /// current transform of the surface when the swapchain was
+    /// created, to notice rotations of the display.
+    pub swapchain_transform: vk::SurfaceTransformFlagsKHR,
+    pub surface_transform: vk::SurfaceTransformFlagsKHR,
     /// Array of presentable images associated with the
     /// swapchain.
     pub swapchain_images: Vec<vk::Image>,
+    /// Number of swapchain images actually created, 0 when
+    /// there is no swapchain.
+    pub swapchain_image_count: u32,
     /// Views to the swapchain images.
     pub swapchain_image_views: Vec<vk::ImageView>,
     /// Extent of the swapchain images.
     pub swapchain_extent: vk::Extent2D,
+    /// Semaphores signaled when rendering to each swapchain
+    /// image has finished and presentation can happen.
+    pub render_finished_semaphores: Vec<vk::Semaphore>,
+    /// Whether each swapchain image holds a previous frame, as
+    /// opposed to undefined contents (right after creation).
+    pub swapchain_image_defined: Vec<bool>,
+    /// Load and store operations of the swapchain image.
+    pub color_attachment: ColorAttachment,
     /// Frame data for each frame in flight (in presentation or
     /// being rendered to).
-    pub frames: [FrameData; MAX_FRAMES_IN_FLIGHT],
+    pub frames: Vec<FrameData>,
 }
 
 /// Main renderer struct.
@@ -72,20 +361,111 @@ pub struct Renderer {
     pub device: Device,
     /// Current frame in the swapchain.
     frame: usize,
+    /// Time to wait for a fence or a swapchain image before
+    /// returning a timeout error, in nanoseconds.
+    frame_timeout: u64,
+    /// Last operations of the renderer, for post-mortem
+    /// debugging. It is shared with the panic hook that dumps
+    /// it.
+    op_log: Arc<OpLog>,
+    /// Partial clears to record in the next frame.
+    pending_clears: Vec<(vk::Rect2D, [f32; 4])>,
+    /// User passes to record in the next frame, with their
+    /// name.
+    pending_passes: Vec<(String, CustomPass)>,
+    /// Views of the frame, and the user passes to record in
+    /// each of them in the next frame.
+    views: Vec<View>,
+    pending_view_passes: Vec<(String, ViewPass)>,
+    /// Device time spent on each view of the last completed
+    /// frame, when timestamps are supported.
+    view_timings: Vec<Duration>,
+    /// ID of the last present, when present IDs are supported.
+    present_id: u64,
+    /// ID of the first present on the current swapchain; IDs
+    /// below it belong to a retired swapchain and can't be
+    /// waited on.
+    swapchain_first_present_id: u64,
+    /// Time the last present wait returned at.
+    last_present_wait: Option<Instant>,
+    /// Interval between the last two present waits, that is
+    /// the actual time between two frames on the display.
+    present_interval: Option<Duration>,
+    /// Pipeline statistics of the last completed frame.
+    pipeline_statistics: Option<PipelineStatistics>,
+    /// Shared samplers, by mip level count, following the
+    /// texture filtering setting.
+    samplers: Vec<(u32, vk::Sampler)>,
+    /// Whether a setting of the swapchain changed, so that it
+    /// has to be recreated.
+    swapchain_outdated: bool,
+    /// Timing of the last frames.
+    frame_timer: FrameTimer,
+    /// Time blocked so far in the frame being rendered.
+    waits: FrameWaits,
+    /// Wait at the end of each frame keeping the frame rate
+    /// under the target, if any.
+    limiter: FrameLimiter,
+    /// When the application read the input for the frame being
+    /// rendered, and when the frame was queued for
+    /// presentation, for the input latency estimate.
+    input_time: Option<Instant>,
+    presented_at: Option<Instant>,
+    /// Time of the frame being rendered, as given to `render`.
+    time: FrameTime,
+    /// Clear color as a function of the frame number, replacing
+    /// the one of the color attachment when set.
+    clear_animation: Option<Box<dyn FnMut(u64) -> [f32; 4]>>,
+    /// Where to save a screenshot of the next frame, when one
+    /// was requested.
+    pending_screenshot: Option<PathBuf>,
+    /// Whether the renderer was destroyed, explicitly or when
+    /// dropped.
+    destroyed: bool,
 }
 
 impl Renderer {
-    pub unsafe fn create(window: &Window) -> Result<Self> {
+    /// Create the renderer for the given window. The
+    /// configuration is completed with the overrides found in
+    /// the environment (see
+    /// [`RendererConfig::with_env_overrides`]).
+    pub unsafe fn create(window: &Window, config: RendererConfig) -> Result<Self> {
         // To create a Vulkan instance, we first need a special
         // function loader to load the initial commands from
         // the Vulkan DLL. Next we create an entry point using
         // this loader, and finally use the entry point, window
         // handle and application data to create the Vulkan
         // instance.
-        let loader = LibloadingLoader::new(LIBRARY)?;
-        let entry = Entry::new(loader).map_err(|b| anyhow!("{}", b))?;
-        let mut data = RenderData::default();
-        let instance = create_instance(window, &entry, &mut data)?;
+        let loader = LibloadingLoader::new(LIBRARY)
+            .map_err(|e| InstanceError::MissingLoader(e.to_string()))?;
+        let entry = Entry::new(loader)
+            .map_err(|e| InstanceError::MissingLoader(e.to_string()))?;
+        let config = config.with_env_overrides();
+        if !FRAMES_IN_FLIGHT_RANGE.contains(&config.frames_in_flight) {
+            return Err(anyhow!(
+                "Invalid number of frames in flight: {} (expected {} to {}).",
+                config.frames_in_flight,
+                FRAMES_IN_FLIGHT_RANGE.start(),
+                FRAMES_IN_FLIGHT_RANGE.end(),
+            ));
+        }
+
+        let frame_timeout = config.frame_timeout.as_nanos().min(u64::MAX as u128) as u64;
+        let limiter = FrameLimiter::new(config.target_fps);
+        let mut data = RenderData {
+            frames: (0..config.frames_in_flight).map(|_| FrameData::default()).collect(),
+            config,
+            ..Default::default()
+        };
+        // Objects are tracked, to check the order they are
+        // destroyed in, when validation is on (see the tracker
+        // module). The validation layers may turn out to be
+        // missing, in which case tracking is turned off again.
+        set_tracking(data.config.validation);
+        let instance = create_instance(Some(window), &entry, &mut data)?;
+        if !data.config.validation {
+            set_tracking(false);
+        }
         
         // Since Vulkan is a platform agnostic API, it does not
         // interface directly with the window system on its
@@ -99,6 +479,7 @@ impl Renderer {
         // function to handle the platform differences for us
         // and return a proper Vulkan surface.
         data.surface = vk_window::create_surface(&instance, window, window)?;
+        track_created(data.surface);
         info!("Surface created.");
 
         // The next step involves choosing a physical device to
@@ -106,7 +487,7 @@ impl Renderer {
         // and then creating a logical device to interface with
         // the application.
         data.physical_device = pick_physical_device(&instance, &mut data)?;
-        let device = create_logical_device(&entry, &instance, &mut data)?;
+        let device = create_logical_device(&instance, &mut data)?;
 
         // We then have to create the swapchain, which is the
         // structure presenting rendered images to the surface,
@@ -127,6 +508,20 @@ impl Renderer {
         // ensure that the CPU and GPU are in sync when
         // rendering.
         create_sync_objects(&device, &mut data)?;
+        create_statistics_pools(&device, &mut data)?;
+        create_timestamp_pools(&device, &mut data)?;
+
+        let op_log = Arc::new(OpLog::new(data.config.op_log_capacity));
+        if op_log.enabled() {
+            let device = describe_device(&instance, data.physical_device);
+            dump_op_log_on_panic(Arc::downgrade(&op_log), data.config.op_log_path.clone(), device);
+        }
+        op_log.record(Op::Created { frames_in_flight: data.frames.len() });
+        op_log.record(Op::SwapchainCreated {
+            width: data.swapchain_extent.width,
+            height: data.swapchain_extent.height,
+            images: data.swapchain_image_count as usize,
+        });
 
         Ok(Self { 
             entry, 
@@ -134,10 +529,89 @@ impl Renderer {
             data, 
             device, 
             frame: 0,
+            frame_timeout,
+            op_log,
+            pending_clears: Vec::new(),
+            pending_passes: Vec::new(),
+            views: Vec::new(),
+            pending_view_passes: Vec::new(),
+            view_timings: Vec::new(),
+            present_id: 0,
+            swapchain_first_present_id: 1,
+            last_present_wait: None,
+            present_interval: None,
+            pipeline_statistics: None,
+            samplers: Vec::new(),
+            swapchain_outdated: false,
+            frame_timer: FrameTimer::default(),
+            waits: FrameWaits::default(),
+            limiter,
+            input_time: None,
+            presented_at: None,
+            time: FrameTime::default(),
+            clear_animation: None,
+            pending_screenshot: None,
+            destroyed: false,
         })
     }
 
-    pub unsafe fn render(&mut self) -> Result<()> {
+    #[profiling::function]
+    pub unsafe fn render(&mut self, time: FrameTime) -> Result<(), RenderError> {
+        let frame = self.frame;
+        self.time = time;
+        let result = self.render_frame();
+
+        // The frame limiter waits out the rest of the frame's
+        // slot, after the present rather than before the next
+        // frame, so that the wait doesn't delay the frame
+        // between the application reading its input and the
+        // image being presented. Frames that failed before
+        // doing any work don't wait: the caller is about to
+        // retry (recreating the swapchain, for example).
+        if matches!(result, Ok(()) | Err(RenderError::FrameSkipped(_))) {
+            self.waits.idle = self.limiter.wait();
+        }
+
+        // The waits are recorded for every frame that went
+        // through, presented or skipped.
+        let waits = std::mem::take(&mut self.waits);
+        let input_time = self.input_time.take();
+        let presented_at = self.presented_at.take();
+        if presented_at.is_some() || matches!(result, Err(RenderError::FrameSkipped(_))) {
+            let latency = input_time.zip(presented_at).map(|(input, presented)| presented - input);
+            self.frame_timer.record_waits(waits, latency);
+        }
+
+        // The outcome of the frame goes to the operation log,
+        // which is dumped if the renderer is now unusable, or
+        // if the validation layer reported an error.
+        match &result {
+            Ok(_) => {}
+            Err(RenderError::OutOfDate) => self.op_log.record(Op::OutOfDate { frame }),
+            Err(RenderError::SurfaceLost) => self.op_log.record(Op::SurfaceLost { frame }),
+            Err(RenderError::FrameSkipped(_)) => self.op_log.record(Op::FrameSkipped { frame }),
+            Err(RenderError::Timeout(_)) => self.op_log.record(Op::Timeout { frame }),
+            Err(RenderError::Fatal(error)) => {
+                self.op_log.record(Op::Fatal { frame });
+                self.dump_op_log(&format!("{:#}", error));
+            }
+        }
+
+        // The errors are only read for the dump: they stay
+        // there for the application to take.
+        if self.data.validation_error.swap(false, Ordering::Relaxed) && self.op_log.enabled() {
+            let errors = VALIDATION_ERRORS.lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .iter()
+                .map(|error| error.to_string())
+                .collect::<Vec<_>>();
+            self.dump_op_log(&format!("validation error\n{}", errors.join("\n")));
+        }
+
+        result
+    }
+
+    unsafe fn render_frame(&mut self) -> Result<(), RenderError> {
         // The first step is to acquire an image on the
         // swapchain. Before that, however, we need to wait for
         // the previous frame to finish rendering, which is
@@ -145,18 +619,49 @@ impl Renderer {
         // frame. The wait_for_fences function also takes a
         // boolean value to wait either for all or any of the
         // fences to be signaled, and a timeout value to wait
-        // for.
+        // for. The timeout is finite, so that a hung driver
+        // shows up as an error instead of freezing the
+        // application.
+        //
+        // If there is no swapchain to present to (the surface
+        // is zero-sized), there is nothing to render either.
+        if !self.can_present() {
+            return Ok(());
+        }
+
+        let frame = &self.data.frames[self.frame];
+        let wait_start = Instant::now();
+        let wait_result = {
+            profiling::scope!("wait_for_fences");
+            self.device.wait_for_fences(
+                &[frame.in_flight_fence],
+                true, 
+                self.frame_timeout
+            )?
+        };
+        self.waits.fence += wait_start.elapsed();
+
+        if wait_result == vk::SuccessCode::TIMEOUT {
+            warn!("Timed out waiting for the fence of frame {}.", self.frame);
+            return Err(RenderError::Timeout("the previous frame to complete"));
+        }
+
+        // The device is now done with everything this frame
+        // submitted last time around, so deferred work tied to
+        // it can run.
         let frame = &mut self.data.frames[self.frame];
-        self.device.wait_for_fences(
-            &[frame.in_flight_fence],
-            true, 
-            u64::MAX
-        )?;
+        frame.on_complete.drain(..).for_each(|callback| callback());
+        if frame.statistics_recorded {
+            self.pipeline_statistics = read_statistics(&self.device, frame.statistics_pool);
+        }
+        if frame.timed_views > 0 {
+            let period = self.data.limits.timestamp_period;
+            if let Some(timings) = read_view_timings(&self.device, frame.timestamp_pool, frame.timed_views, period) {
+                self.view_timings = timings;
+            }
+        }
+        let frame = &self.data.frames[self.frame];
 
-        // After completing, the fence is restored to the
-        // unsignaled state for the coming frame.
-        self.device.reset_fences(&[frame.in_flight_fence])?;
-        
         // The "acquire next image" method takes in the
         // swapchain from which to acquire the image, a timeout
         // value specifying how long the function is to wait if
@@ -164,13 +669,17 @@ impl Renderer {
         // and/or a fence to signal when the image is acquired,
         // and returns a result on the index of the next
         // available presentable image in the swapchain.
-        let index_result = self.device
-            .acquire_next_image_khr(
+        let acquire_start = Instant::now();
+        let index_result = {
+            profiling::scope!("acquire_next_image");
+            self.device.acquire_next_image_khr(
                 self.data.swapchain,
-                u64::MAX,
+                self.frame_timeout,
                 frame.image_available_semaphore,
                 vk::Fence::null()
-            );
+            )
+        };
+        self.waits.acquire = acquire_start.elapsed();
         
         // The result contains the index of the acquired image
         // in the swapchain, but if the swapchain is no longer
@@ -181,15 +690,134 @@ impl Renderer {
         // used for rendering) or a SUBOPTIMAL error (the
         // swapchain can still be used, but the surface
         // properties are no longer matched exactly). In the
-        // first case, we have to recreate the swapchain.
+        // first case, we have to recreate the swapchain. The
+        // call may also succeed without an image when it
+        // times out (TIMEOUT, or NOT_READY for a zero
+        // timeout), in which case the semaphore won't be
+        // signaled and there is nothing to clean up.
         let image_index = match index_result {
+            Ok((_, vk::SuccessCode::TIMEOUT | vk::SuccessCode::NOT_READY)) => {
+                warn!("Timed out acquiring a swapchain image for frame {}.", self.frame);
+                return Err(RenderError::Timeout("a swapchain image"));
+            },
             Ok((index, _)) => index as usize,
             Err(vk::ErrorCode::OUT_OF_DATE_KHR) => {
-                return Err(anyhow!("Swapchain out of date."));
+                return Err(RenderError::OutOfDate);
             },
-            Err(e) => return Err(anyhow!("Failed to acquire next image: {:?}", e)),
+            Err(vk::ErrorCode::SURFACE_LOST_KHR) => {
+                return Err(RenderError::SurfaceLost);
+            },
+            Err(e) => return Err(anyhow!("Failed to acquire next image: {:?}", e).into()),
+        };
+
+        // From now on, the image is ours and the "image
+        // available" semaphore will be signaled, so whatever
+        // happens the frame has to be carried to presentation:
+        // if recording or submitting fails, we present the
+        // image untouched instead (see `submit_or_recover`).
+
+        // An animated clear color is evaluated for the frame
+        // about to be recorded, numbered like its present.
+        if let Some(animation) = &mut self.clear_animation {
+            self.data.color_attachment.clear_color = animation(self.present_id + 1);
+        }
+
+        // A requested screenshot is copied out of the image in
+        // the frame's command buffer, before presentation.
+        let readback = self.pending_screenshot.take().and_then(|path| {
+            create_readback(&self.instance, &self.device, &self.data, &path)
+                .inspect_err(|error| error!("Failed to capture a screenshot: {:#}", error))
+                .ok()
+        });
+
+        let passes = std::mem::take(&mut self.pending_passes);
+        let view_passes = std::mem::take(&mut self.pending_view_passes);
+        let outcome = submit_or_recover(&mut RendererSteps {
+            renderer: self,
+            image_index,
+            passes: Some((passes, view_passes)),
+            readback: readback.as_ref(),
+        });
+        self.pending_clears.clear();
+        let outcome = outcome?;
+
+        // Either way, something was submitted.
+        let submitted = matches!(outcome, FrameOutcome::Submitted);
+        let frame = &mut self.data.frames[self.frame];
+        frame.submitted();
+        frame.statistics_recorded = submitted && !frame.statistics_pool.is_null();
+        frame.timed_views = if submitted && !frame.timestamp_pool.is_null() {
+            self.views.len() as u32
+        } else {
+            0
         };
 
+        if let FrameOutcome::Skipped(error) = outcome {
+            warn!("Skipped frame {}: {:#}", self.frame, error);
+            self.data.swapchain_image_defined[image_index] = false;
+            self.frame = (self.frame + 1) % self.data.frames.len();
+
+            // The screenshot is retried on the next frame.
+            if let Some(readback) = readback {
+                self.pending_screenshot = Some(readback.path.clone());
+            }
+            self.frame_timer.tick(Instant::now());
+
+            return Err(RenderError::FrameSkipped(error));
+        }
+
+        // The present operation is then executed on the queue,
+        // and the frame counter is incremented.
+        //
+        // Presenting from a separate thread, while this one
+        // records the next frame, would hide compositors that
+        // block in the present. It is not done for now: the
+        // present queue is only a queue of its own when the
+        // graphics family can't present, and on most devices it
+        // is the graphics queue itself, which the frame and
+        // the uploads (see `UploadContext::queue`) also submit
+        // to, so every submission would need a lock shared with
+        // that thread. The present's result also drives swapchain
+        // recreation, with its retries and backoff, in the
+        // application's event loop, which would have to agree
+        // with the present thread on when to rebuild.
+        self.op_log.record(Op::FrameSubmitted { frame: self.frame, image: image_index });
+        self.data.swapchain_image_defined[image_index] = true;
+        self.present_id += 1;
+        let present_start = Instant::now();
+        let present_result = self.present_frame(image_index, Some(self.present_id));
+        let presented_at = Instant::now();
+        self.waits.present = presented_at - present_start;
+        self.presented_at = Some(presented_at);
+        if let Some(readback) = readback {
+            self.save_screenshot(readback);
+        }
+        self.frame += 1;
+        self.frame %= self.data.frames.len();
+        self.frame_timer.tick(Instant::now());
+
+        match present_result {
+            Ok(vk::SuccessCode::SUBOPTIMAL_KHR) => {
+                self.check_surface_transform();
+                Ok(())
+            },
+            Ok(_) => Ok(()),
+            Err(vk::ErrorCode::OUT_OF_DATE_KHR) => Err(RenderError::OutOfDate),
+            Err(vk::ErrorCode::SURFACE_LOST_KHR) => Err(RenderError::SurfaceLost),
+            Err(e) => Err(anyhow!("Failed to present image: {:?}", e).into()),
+        }
+    }
+
+    #[profiling::function]
+    unsafe fn record_frame(
+        &self,
+        image_index: usize,
+        passes: Vec<(String, CustomPass)>,
+        view_passes: Vec<(String, ViewPass)>,
+        readback: Option<&Readback>,
+    ) -> Result<()> {
+        let frame = &self.data.frames[self.frame];
+
         // Command buffers are allocated from pools and
         // recorded with commands to send to the GPU. Changing
         // commands dynamically requires changing the buffers,
@@ -236,47 +864,168 @@ impl Renderer {
 
         self.device.begin_command_buffer(frame.main_buffer, &info)?;
 
+        // If supported, the rendering is wrapped in a pipeline
+        // statistics query. Queries have to be reset before
+        // being reused, outside of any rendering scope, and
+        // the query begins and ends outside of it as well.
+        let statistics_pool = frame.statistics_pool;
+        if !statistics_pool.is_null() {
+            self.device.cmd_reset_query_pool(frame.main_buffer, statistics_pool, 0, 1);
+            self.device.cmd_begin_query(frame.main_buffer, statistics_pool, 0, vk::QueryControlFlags::empty());
+        }
+
+        let timestamp_pool = frame.timestamp_pool;
+        if !timestamp_pool.is_null() {
+            self.device.cmd_reset_query_pool(frame.main_buffer, timestamp_pool, 0, MAX_VIEWS as u32 * 2);
+        }
+
         // Then, we can start by transitioning the swapchain
-        // image into a drawable layout, to clear the color.
+        // image into a layout we can render to. If its
+        // previous contents are to be loaded, they have to be
+        // preserved, so the transition starts from the layout
+        // the image was presented in; otherwise, starting from
+        // UNDEFINED lets the driver discard them.
         let image = self.data.swapchain_images[image_index];
+        let defined = self.data.swapchain_image_defined[image_index];
+        let attachment = &self.data.color_attachment;
+        let old_layout = if defined && attachment.load_op == vk::AttachmentLoadOp::LOAD {
+            vk::ImageLayout::PRESENT_SRC_KHR
+        } else {
+            vk::ImageLayout::UNDEFINED
+        };
+
         transition_image_layout(
             &self.device, 
             frame.main_buffer, 
             image,
-            vk::ImageLayout::UNDEFINED, 
-            vk::ImageLayout::GENERAL
+            old_layout, 
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
         )?;
 
-        // We will clear this image with a 120-frame flashing
-        // blue color; the subresource range affected is the
-        // color bit.
-        let clear_color = vk::ClearColorValue {
-            float32: [0.0, 0.0, 1.0, 1.0],
-        };
+        // The rendering scope covers the whole image, with the
+        // swapchain image as its only color attachment, loaded
+        // and stored as configured.
+        let color_attachments = &[color_attachment_info(
+            self.data.swapchain_image_views[image_index],
+            attachment,
+            defined,
+        )];
 
-        let ranges = &[subresource_range(vk::ImageAspectFlags::COLOR)];
-        self.device.cmd_clear_color_image(
-            frame.main_buffer, 
-            image, 
-            vk::ImageLayout::GENERAL,
-            &clear_color, 
-            ranges
-        );
+        let render_area = vk::Rect2D::builder()
+            .offset(vk::Offset2D { x: 0, y: 0 })
+            .extent(self.data.swapchain_extent);
+
+        let rendering_info = vk::RenderingInfo::builder()
+            .render_area(render_area)
+            .layer_count(1)
+            .color_attachments(color_attachments);
+
+        self.device.cmd_begin_rendering(frame.main_buffer, &rendering_info);
+
+        // Partial clears requested for this frame are applied
+        // on top of the loaded (or cleared) contents.
+        for &(rect, color) in &self.pending_clears {
+            if let Some(rect) = clip_rect(rect, self.data.swapchain_extent) {
+                clear_region(&self.device, frame.main_buffer, rect, color);
+            }
+        }
+
+        // Then come the views, one after the other, each with
+        // the viewport and scissor set to its rectangle, in
+        // which the view passes are recorded in the order they
+        // were added. Each view is timed with a pair of
+        // timestamps; the timestamp at the end of a view also
+        // waits for the work of the previous views, which is
+        // small compared to a whole view.
+        for (index, view) in self.views.iter().enumerate() {
+            profiling::scope!("view");
+            if !timestamp_pool.is_null() {
+                self.device.cmd_write_timestamp2(
+                    frame.main_buffer,
+                    vk::PipelineStageFlags2::TOP_OF_PIPE,
+                    timestamp_pool,
+                    index as u32 * 2,
+                );
+            }
+
+            if let Some(rect) = clip_rect(view.rect, self.data.swapchain_extent) {
+                let label_name = CString::new(format!("view {}", index)).unwrap_or_default();
+                let label = vk::DebugUtilsLabelEXT::builder().label_name(label_name.as_bytes_with_nul());
+                if self.data.config.validation {
+                    self.instance.cmd_begin_debug_utils_label_ext(frame.main_buffer, &label);
+                }
+
+                for (name, pass) in &view_passes {
+                    profiling::scope!("view pass", name);
+                    set_viewport_rect(&self.device, frame.main_buffer, rect);
+                    pass(frame.main_buffer, &self.device, index, view);
+                }
+
+                if self.data.config.validation {
+                    self.instance.cmd_end_debug_utils_label_ext(frame.main_buffer);
+                }
+            }
+
+            if !timestamp_pool.is_null() {
+                self.device.cmd_write_timestamp2(
+                    frame.main_buffer,
+                    vk::PipelineStageFlags2::BOTTOM_OF_PIPE,
+                    timestamp_pool,
+                    index as u32 * 2 + 1,
+                );
+            }
+        }
+
+        // User passes come last, in the order they were added,
+        // each in a debug label when the debug utils are there
+        // to show it in captures and validation messages.
+        for (name, pass) in passes {
+            profiling::scope!("custom pass", &name);
+            let label_name = CString::new(name.as_str()).unwrap_or_default();
+            let label = vk::DebugUtilsLabelEXT::builder().label_name(label_name.as_bytes_with_nul());
+
+            if self.data.config.validation {
+                self.instance.cmd_begin_debug_utils_label_ext(frame.main_buffer, &label);
+            }
+
+            pass(frame.main_buffer, &self.device);
+
+            if self.data.config.validation {
+                self.instance.cmd_end_debug_utils_label_ext(frame.main_buffer);
+            }
+        }
+
+        self.device.cmd_end_rendering(frame.main_buffer);
+
+        if !statistics_pool.is_null() {
+            self.device.cmd_end_query(frame.main_buffer, statistics_pool, 0);
+        }
 
         // Now, the image can be transitioned again for
-        // presentation to the surface.
-        transition_image_layout(
-            &self.device, 
-            frame.main_buffer,
-            image, 
-            vk::ImageLayout::GENERAL,
-            vk::ImageLayout::PRESENT_SRC_KHR 
-        )?;
+        // presentation to the surface, through a copy to the
+        // screenshot buffer if one was requested.
+        match readback {
+            Some(readback) => record_readback(&self.device, frame.main_buffer, image, readback)?,
+            None => transition_image_layout(
+                &self.device, 
+                frame.main_buffer,
+                image, 
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                vk::ImageLayout::PRESENT_SRC_KHR 
+            )?,
+        }
 
         // All commands have been recorded, so the command
         // buffer can be ended.
         self.device.end_command_buffer(frame.main_buffer)?;
 
+        Ok(())
+    }
+
+    #[profiling::function]
+    unsafe fn submit_frame(&self, image_index: usize) -> Result<()> {
+        let frame = &self.data.frames[self.frame];
+
         // The next step is to prepare the submission for the
         // queue. There are two semaphores to signal, the
         // "image available" semaphore, which waits for
@@ -287,12 +1036,12 @@ impl Renderer {
             frame.image_available_semaphore
         )];
 
-        // ...and the "render finished" semaphore, which
-        // signals the end of the execution of all graphics
-        // pipeline stages.
+        // ...and the "render finished" semaphore of the image,
+        // which signals the end of the execution of all
+        // graphics pipeline stages.
         let signal_info = &[semaphore_submit(
             vk::PipelineStageFlags2::ALL_GRAPHICS,
-            frame.render_finished_semaphore
+            self.data.render_finished_semaphores[image_index]
         )];
 
         // Furthermore, we have submit info on the command
@@ -318,55 +1067,978 @@ impl Renderer {
             frame.in_flight_fence
         )?;
 
+        Ok(())
+    }
+
+    #[profiling::function]
+    unsafe fn present_frame(&self, image_index: usize, present_id: Option<u64>) -> VkResult<vk::SuccessCode> {
         // The final step is to present the image to the
         // surface. The present info struct takes the
         // semaphores to wait on and signal, the swapchain to
         // present to, and the index of the image to present.
-        let wait_semaphores = &[frame.render_finished_semaphore];
+        let wait_semaphores = &[self.data.render_finished_semaphores[image_index]];
         let swapchains = &[self.data.swapchain];
         let image_indices = &[image_index as u32];
-        let present_info = vk::PresentInfoKHR::builder()
+        let mut present_info = vk::PresentInfoKHR::builder()
             .wait_semaphores(wait_semaphores)
             .swapchains(swapchains)
             .image_indices(image_indices);
 
-        // The present operation is then executed on the queue,
-        // and the frame counter is incremented.
-        self.device.queue_present_khr(self.data.graphics_queue, &present_info)?;
-        
-        self.frame += 1;
-        self.frame %= MAX_FRAMES_IN_FLIGHT;
+        // When supported, the present is also tagged with an
+        // increasing ID, that can later be waited on to know
+        // when the image actually reached the display.
+        let present_ids = &[present_id.unwrap_or(0)];
+        let mut present_id_info = vk::PresentIdKHR::builder()
+            .present_ids(present_ids);
+        if self.data.features.present_wait && present_id.is_some() {
+            present_info = present_info.push_next(&mut present_id_info);
+        }
+
+        self.device.queue_present_khr(self.data.present_queue, &present_info)
+    }
+
+    /// Wait for the current frame to complete, and write the
+    /// screenshot copied in it.
+    unsafe fn save_screenshot(&mut self, readback: Readback) {
+        // If the frame doesn't complete, the device may still
+        // write to the buffer, so it is only destroyed once the
+        // frame's fence is waited on again (or the device is
+        // idle, when the renderer is destroyed).
+        let frame = &mut self.data.frames[self.frame];
+        let result = match self.device.wait_for_fences(&[frame.in_flight_fence], true, self.frame_timeout) {
+            Ok(vk::SuccessCode::TIMEOUT) | Err(_) => {
+                error!("Frame {} didn't complete, the screenshot is lost.", self.frame);
+                frame.on_complete.push(Box::new(move || drop(readback)));
+                return;
+            }
+            Ok(_) => write_readback(&self.device, &readback),
+        };
+
+        match result {
+            Ok(()) => info!("Saved a screenshot to {}.", readback.path.display()),
+            Err(error) => error!("Failed to save a screenshot to {}: {:#}", readback.path.display(), error),
+        }
+    }
+
+    /// Recreate the swapchain, after the window was resized or
+    /// the swapchain became out of date. If the window is
+    /// zero-sized, the swapchain is only destroyed, and
+    /// creation is deferred to a later call.
+    #[profiling::function]
+    pub unsafe fn recreate_swapchain(&mut self, window: &Window) -> Result<()> {
+        let result = self.rebuild_swapchain(window);
+        self.frame_timer.record_swapchain_recreation(result.is_ok());
+        result
+    }
+
+    unsafe fn rebuild_swapchain(&mut self, window: &Window) -> Result<()> {
+        // The swapchain images may still be in use by frames
+        // in flight, so we have to wait for them to complete
+        // before destroying anything. The swapchain itself is
+        // kept until the new one is created from it.
+        self.device.device_wait_idle()?;
+        destroy_render_finished_semaphores(&self.device, &mut self.data);
+        destroy_swapchain_image_views(&self.device, &mut self.data);
+
+        create_swapchain(window, &self.instance, &self.device, &mut self.data)?;
+        create_swapchain_image_views(&self.device, &mut self.data)?;
+        create_render_finished_semaphores(&self.device, &mut self.data)?;
+        self.swapchain_outdated = false;
+
+        // Present IDs are per swapchain, so the ones given to
+        // the old swapchain can't be waited on anymore.
+        self.swapchain_first_present_id = self.present_id + 1;
+        self.last_present_wait = None;
+
+        self.op_log.record(Op::SwapchainCreated {
+            width: self.data.swapchain_extent.width,
+            height: self.data.swapchain_extent.height,
+            images: self.data.swapchain_image_count as usize,
+        });
+
+        Ok(())
+    }
+
+    /// After a SUBOPTIMAL present, check whether the display was
+    /// rotated, in which case the swapchain is recreated to
+    /// pick up the new transform. A surface presented with the
+    /// identity while it is rotated reports SUBOPTIMAL for every
+    /// frame, so only a change of the transform counts.
+    fn check_surface_transform(&mut self) {
+        let capabilities = unsafe {
+            self.instance.get_physical_device_surface_capabilities_khr(self.data.physical_device, self.data.surface)
+        };
+
+        if let Ok(capabilities) = capabilities {
+            if capabilities.current_transform != self.data.surface_transform {
+                info!("The surface transform changed to {:?}.", capabilities.current_transform);
+                self.swapchain_outdated = true;
+            }
+        }
+    }
+
+    /// Recreate the surface and the swapchain, after the
+    /// surface was lost. Everything presenting to the old
+    /// surface goes with it: the swapchain can't even be passed
+    /// as the old swapchain, since it belongs to another
+    /// surface.
+    #[profiling::function]
+    pub unsafe fn recreate_surface(&mut self, window: &Window) -> Result<()> {
+        warn!("The surface was lost, recreating it.");
+        self.device.device_wait_idle()?;
+        destroy_render_finished_semaphores(&self.device, &mut self.data);
+        destroy_swapchain(&self.device, &mut self.data);
+
+        track_destroyed(self.data.surface);
+        self.instance.destroy_surface_khr(self.data.surface, None);
+
+        // The handle is reset first, so that a failed creation
+        // doesn't leave the old one to be destroyed twice.
+        self.data.surface = vk::SurfaceKHR::null();
+        self.data.surface = vk_window::create_surface(&self.instance, window, window)?;
+        track_created(self.data.surface);
+        self.op_log.record(Op::SurfaceCreated);
+
+        // The new surface may be on another display, driven
+        // by another device, in which case there is nothing
+        // left to present with; the present queue was created
+        // with the device, so it has to be able to present to
+        // the new surface. The formats and present modes
+        // supported may have changed too, but those are
+        // queried again when creating the swapchain.
+        let supported = self.instance.get_physical_device_surface_support_khr(
+            self.data.physical_device,
+            self.data.present_queue_family,
+            self.data.surface,
+        )?;
+        if !supported {
+            return Err(anyhow!("The present queue can't present to the new surface."));
+        }
+
+        self.recreate_swapchain(window)
+    }
+
+    /// Write the operation log to the configured file, with the
+    /// device information and `reason` as a header. This does
+    /// nothing if the log is disabled.
+    pub fn dump_op_log(&self, reason: &str) {
+        if !self.op_log.enabled() {
+            return;
+        }
+
+        let device = describe_device(&self.instance, self.data.physical_device);
+        let header = format!("Reason: {}\n{}", reason, device);
+
+        let path = &self.data.config.op_log_path;
+        match self.op_log.dump(path, &header) {
+            Ok(_) => error!("Operation log written to {}.", path.display()),
+            Err(e) => warn!("Failed to write the operation log: {}", e),
+        }
+    }
+
+    /// Validation errors reported since the last call, oldest
+    /// first (at most 16 are kept).
+    pub fn take_validation_errors(&self) -> Vec<ValidationMessage> {
+        take_validation_errors()
+    }
+
+    /// Set how the swapchain image is loaded and stored in the
+    /// main pass, from the next frame on.
+    pub fn set_color_attachment(&mut self, attachment: ColorAttachment) {
+        self.data.color_attachment = attachment;
+    }
+
+    /// How the swapchain image is loaded and stored in the main
+    /// pass.
+    pub fn color_attachment(&self) -> &ColorAttachment {
+        &self.data.color_attachment
+    }
+
+    /// How images are presented to the display.
+    pub fn present_preference(&self) -> PresentPreference {
+        self.data.config.present_mode
+    }
+
+    /// Change how images are presented to the display. This
+    /// marks the swapchain for recreation, which the
+    /// application does before the next frame (see
+    /// [`Renderer::swapchain_outdated`]).
+    pub fn set_present_preference(&mut self, preference: PresentPreference) {
+        if preference != self.data.config.present_mode {
+            self.data.config.present_mode = preference;
+            self.swapchain_outdated = true;
+        }
+    }
+
+    /// Whether the swapchain has to be recreated for a change
+    /// of settings to take effect.
+    pub fn swapchain_outdated(&self) -> bool {
+        self.swapchain_outdated
+    }
+
+    /// Number of images in the swapchain, as created by the
+    /// driver from the configured
+    /// [`RendererConfig::desired_image_count`]; 0 when there is
+    /// no swapchain (the window is minimised).
+    pub fn swapchain_image_count(&self) -> u32 {
+        self.data.swapchain_image_count
+    }
+
+    /// Extent of the swapchain images, in pixels.
+    pub fn extent(&self) -> vk::Extent2D {
+        self.data.swapchain_extent
+    }
+
+    /// Format of the swapchain images, as negotiated with the
+    /// surface. It can change when the swapchain is recreated.
+    pub fn swapchain_format(&self) -> vk::Format {
+        self.data.swapchain_format
+    }
+
+    /// Format and color space of the swapchain, picked from
+    /// [`RendererConfig::surface_formats`]. The application
+    /// adapts its tone mapping and output encoding to it (PQ
+    /// encoding for HDR10, for example).
+    pub fn surface_format(&self) -> vk::SurfaceFormatKHR {
+        vk::SurfaceFormatKHR {
+            format: self.data.swapchain_format,
+            color_space: self.data.swapchain_color_space,
+        }
+    }
+
+    /// Rotation the rendering has to apply for the image to be
+    /// shown upright, when the surface can't present without
+    /// transform (on some Android devices). With a 90 or 270
+    /// degree rotation, the projection and viewport use the
+    /// swapchain extent with its width and height swapped.
+    pub fn surface_rotation(&self) -> SurfaceRotation {
+        SurfaceRotation::from_transform(self.data.swapchain_transform)
+    }
+
+    /// Whether colors written to the swapchain images are
+    /// encoded to sRGB by the hardware. When they are not,
+    /// shaders writing linear colors have to encode them
+    /// themselves for the output to look right.
+    pub fn output_is_srgb(&self) -> bool {
+        is_srgb(self.data.swapchain_format)
+    }
+
+    /// Set the color the swapchain image is cleared to at the
+    /// start of each frame (when the load operation is CLEAR),
+    /// stopping any clear animation.
+    pub fn set_clear_color(&mut self, color: [f32; 4]) {
+        self.clear_animation = None;
+        self.data.color_attachment.clear_color = color;
+    }
 
+    /// Animate the clear color: `animation` is called before
+    /// each frame with the frame number (counting from 1), and
+    /// returns the color to clear to. This lasts until the next
+    /// call to [`Renderer::set_clear_color`].
+    pub fn set_animated_clear(&mut self, animation: impl FnMut(u64) -> [f32; 4] + 'static) {
+        self.clear_animation = Some(Box::new(animation));
+    }
+
+    /// Record `pass` in the next frame. See
+    /// [`FrameContext::custom_pass`](crate::app::FrameContext::custom_pass).
+    pub fn add_custom_pass(
+        &mut self,
+        name: impl Into<String>,
+        pass: impl FnOnce(vk::CommandBuffer, &Device) + 'static,
+    ) {
+        self.pending_passes.push((name.into(), Box::new(pass)));
+    }
+
+    /// Set the views the frame is split into, in swapchain
+    /// coordinates (see [`split_screen`](crate::view::split_screen)).
+    /// The rectangles are not updated when the swapchain is
+    /// resized, so they are typically set again every frame,
+    /// from the current [`Renderer::extent`].
+    pub fn set_views(&mut self, views: Vec<View>) -> Result<()> {
+        if views.len() > MAX_VIEWS {
+            return Err(anyhow!("{} views requested, at most {} are supported.", views.len(), MAX_VIEWS));
+        }
+
+        self.views = views;
         Ok(())
     }
 
-    /// Wait for the logical device to finish operations.
+    /// Views the frame is split into.
+    pub fn views(&self) -> &[View] {
+        &self.views
+    }
+
+    /// Record `pass` in each view of the next frame. See
+    /// [`FrameContext::view_pass`](crate::app::FrameContext::view_pass).
+    pub fn add_view_pass(
+        &mut self,
+        name: impl Into<String>,
+        pass: impl Fn(vk::CommandBuffer, &Device, usize, &View) + 'static,
+    ) {
+        self.pending_view_passes.push((name.into(), Box::new(pass)));
+    }
+
+    /// Device time spent on each view of the last completed
+    /// frame, in the order of [`Renderer::views`]. This is
+    /// empty if the device doesn't support timestamps.
+    pub fn view_timings(&self) -> &[Duration] {
+        &self.view_timings
+    }
+
+    /// Clear `rect` of the swapchain image to `color` in the
+    /// next frame, on top of what the load operation left in
+    /// it. The rectangle is clipped to the image.
+    pub fn clear_region(&mut self, rect: vk::Rect2D, color: [f32; 4]) {
+        self.pending_clears.push((rect, color));
+    }
+
+    /// Wait until the present with the given ID (as counted by
+    /// the renderer, one per presented frame) has reached the
+    /// display, or `timeout` elapsed. Returns whether the
+    /// present happened. This fails if the device doesn't
+    /// support `VK_KHR_present_wait`.
+    pub fn wait_for_present(&self, present_id: u64, timeout: Duration) -> Result<bool> {
+        if !self.data.features.present_wait {
+            return Err(anyhow!("VK_KHR_present_wait is not supported by the device."));
+        }
+
+        let timeout = timeout.as_nanos().min(u64::MAX as u128) as u64;
+        match unsafe { self.device.wait_for_present_khr(self.data.swapchain, present_id, timeout) } {
+            Ok(vk::SuccessCode::TIMEOUT) => Ok(false),
+            Ok(_) => Ok(true),
+            Err(e) => Err(anyhow!("Failed to wait for present {}: {:?}", present_id, e)),
+        }
+    }
+
+    /// In low-latency mode (see [`RendererConfig::low_latency`]),
+    /// wait for the previous frame to be on the display before
+    /// starting the next one. Waiting on the fences only limits
+    /// the number of frames queued on the GPU; the presentation
+    /// engine may still hold several images, each adding a
+    /// frame of latency between input and display. This is
+    /// called before updating the application, so that the
+    /// input is read as late as possible.
+    #[profiling::function]
+    pub fn pace_frame(&mut self) {
+        if !self.data.config.low_latency
+            || !self.data.features.present_wait
+            || !self.can_present()
+        {
+            return;
+        }
+
+        // The previous frame is the one before the last
+        // present, so that one image is always queued and the
+        // GPU never starves.
+        let target = self.present_id.saturating_sub(1);
+        if target < self.swapchain_first_present_id {
+            return;
+        }
+
+        let result = unsafe {
+            self.device.wait_for_present_khr(self.data.swapchain, target, self.frame_timeout)
+        };
+
+        // An out of date swapchain is handled when rendering the
+        // frame, and a timeout only means the frame is paced
+        // like without low-latency mode.
+        match result {
+            Ok(vk::SuccessCode::TIMEOUT) => warn!("Timed out waiting for present {}.", target),
+            Ok(_) => {
+                let now = Instant::now();
+                if let Some(last) = self.last_present_wait {
+                    self.present_interval = Some(now - last);
+                }
+                self.last_present_wait = Some(now);
+            },
+            Err(vk::ErrorCode::OUT_OF_DATE_KHR) => {},
+            Err(e) => warn!("Failed to wait for present {}: {:?}", target, e),
+        }
+    }
+
+    /// Time between the last two frames reaching the display,
+    /// as measured by [`Renderer::pace_frame`]. This is only
+    /// available in low-latency mode.
+    pub fn present_interval(&self) -> Option<Duration> {
+        self.present_interval
+    }
+
+    /// Sub-pixel offset to apply to projections this frame, in
+    /// NDC units (see
+    /// [`jittered_projection`](crate::prelude::jittered_projection)),
+    /// along with the offset of the previous frame, to remove
+    /// it from motion vectors. Both are zero unless
+    /// [`RendererConfig::temporal_jitter`] is set.
+    pub fn jitter(&self) -> (Vec2, Vec2) {
+        if !self.data.config.temporal_jitter {
+            return (Vec2::ZERO, Vec2::ZERO);
+        }
+
+        let extent = self.data.swapchain_extent;
+        (
+            jitter_offset(self.present_id, extent),
+            jitter_offset(self.present_id.wrapping_sub(1), extent),
+        )
+    }
+
+    /// Time of the last frame rendered, as given to
+    /// [`Renderer::render`], for time-dependent rendering
+    /// (animated uniforms, for example).
+    pub fn time(&self) -> FrameTime {
+        self.time
+    }
+
+    /// Frame rate and frame time, averaged over the last
+    /// frames, and number of frames rendered and of swapchain
+    /// recreations so far.
+    pub fn stats(&self) -> FrameStats {
+        self.frame_timer.stats()
+    }
+
+    /// Maximum frame rate, if limited.
+    pub fn target_fps(&self) -> Option<u32> {
+        self.limiter.target_fps()
+    }
+
+    /// Limit the frame rate to `target_fps` frames per second,
+    /// or not at all with `None`; this takes effect from the
+    /// next frame. `render` then returns no sooner than the
+    /// end of the frame's slot, which also throttles event
+    /// loops running with `ControlFlow::Poll`. The limit is
+    /// best kept at or under the refresh rate with vsync,
+    /// which otherwise limits the frame rate first.
+    pub fn set_target_fps(&mut self, target_fps: Option<u32>) {
+        self.data.config.target_fps = target_fps;
+        self.limiter.set_target_fps(target_fps);
+    }
+
+    pub fn latency_mode(&self) -> LatencyMode {
+        self.data.config.latency_mode
+    }
+
+    /// Change where the frame loop waits for the frame in
+    /// flight; this takes effect from the next frame.
+    pub fn set_latency_mode(&mut self, mode: LatencyMode) {
+        self.data.config.latency_mode = mode;
+    }
+
+    /// Wait for the frame about to be rendered to be free,
+    /// that is, for the GPU to complete its previous use, as
+    /// `render` does otherwise. This is called before updating
+    /// the application in [`LatencyMode::WaitBeforeInput`].
+    /// Failures and timeouts are left for `render` to run into
+    /// and report.
+    #[profiling::function]
+    pub fn wait_for_frame(&mut self) {
+        if !self.can_present() {
+            return;
+        }
+
+        let start = Instant::now();
+        let fence = self.data.frames[self.frame].in_flight_fence;
+        let _ = unsafe { self.device.wait_for_fences(&[fence], true, self.frame_timeout) };
+        self.waits.fence += start.elapsed();
+    }
+
+    /// Mark the time the application reads its input for the
+    /// next frame, right before it updates, for the input
+    /// latency estimate of [`FrameStats`].
+    pub fn input_sampled(&mut self) {
+        self.input_time = Some(Instant::now());
+    }
+
+    /// Pipeline statistics of the last completed frame, or
+    /// `None` if pipeline statistics queries are not supported
+    /// (see [`EnabledFeatures::pipeline_statistics_query`]).
+    pub fn pipeline_statistics(&self) -> Option<PipelineStatistics> {
+        self.pipeline_statistics
+    }
+
+    /// ID of the last present, to be given to
+    /// [`Renderer::wait_for_present`].
+    pub fn last_present_id(&self) -> u64 {
+        self.present_id
+    }
+
+    /// Whether the renderer can currently present to the
+    /// surface; frames rendered while this is false are no-ops.
+    pub fn can_present(&self) -> bool {
+        swapchain_ready(&self.data)
+    }
+
+    /// Save a screenshot of the next frame rendered to `path`,
+    /// as a PNG. Presented images can't be read back, so the
+    /// frame is copied while it is rendered, and the file is
+    /// written by the [`Renderer::render`] call that renders
+    /// it, which waits for the frame to complete to do so.
+    /// Failing to write the file is logged, but doesn't fail
+    /// rendering. This returns an error if screenshots can't be
+    /// taken from the current swapchain (because of its
+    /// format, or if the surface doesn't allow copies from its
+    /// images).
+    pub fn capture_screenshot(&mut self, path: &Path) -> Result<()> {
+        if !self.can_present() {
+            return Err(anyhow!("No swapchain to take a screenshot from."));
+        }
+
+        check_screenshot_support(&self.data)?;
+        self.pending_screenshot = Some(path.to_path_buf());
+        Ok(())
+    }
+
+    /// Time to wait for the previous frame or for a swapchain
+    /// image before `render` returns [`RenderError::Timeout`].
+    pub fn frame_timeout(&self) -> Duration {
+        Duration::from_nanos(self.frame_timeout)
+    }
+
+    /// Set the time to wait for the previous frame or for a
+    /// swapchain image before `render` returns
+    /// [`RenderError::Timeout`].
+    pub fn set_frame_timeout(&mut self, timeout: Duration) {
+        self.frame_timeout = timeout.as_nanos().min(u64::MAX as u128) as u64;
+    }
+
+    /// Index of the frame in flight that the next call to
+    /// [`Renderer::render`] will use, between 0 and
+    /// [`Renderer::frames_in_flight`].
+    pub fn frame_index(&self) -> usize {
+        self.frame
+    }
+
+    /// Number of frames that can be in flight at the same time;
+    /// systems that write to resources read by the GPU need one
+    /// copy per frame in flight.
+    pub fn frames_in_flight(&self) -> usize {
+        self.data.frames.len()
+    }
+
+    /// Token of the frame in flight that the next call to
+    /// [`Renderer::render`] will use.
+    pub fn frame_token(&self) -> FrameToken {
+        FrameToken {
+            index: self.frame,
+            fence: self.data.frames[self.frame].in_flight_fence,
+        }
+    }
+
+    /// Run `callback` once the device is done with the next
+    /// submission of frame `frame_index`, or with its last one
+    /// if it is not the frame being prepared (or when the
+    /// renderer is destroyed). This is where resources used by
+    /// the frame can safely be destroyed.
+    ///
+    /// Callbacks for the frame being prepared (the one of
+    /// [`Renderer::frame_token`]) wait for the frame to be
+    /// submitted, and then for its fence to be waited on, the
+    /// next time the frame comes around: the fence waited on
+    /// at the start of `render` is that of the previous
+    /// submission, which the resources of this frame aren't
+    /// part of.
+    pub fn on_frame_complete(&mut self, frame_index: usize, callback: impl FnOnce() + 'static) {
+        let frame = &mut self.data.frames[frame_index];
+        if frame_index == self.frame {
+            frame.pending_complete.push(Box::new(callback));
+        } else {
+            frame.on_complete.push(Box::new(callback));
+        }
+    }
+
+    /// Information about the physical device in use.
+    pub fn device_info(&self) -> &DeviceInfo {
+        &self.data.device_info
+    }
+
+    /// Optional device features enabled on the logical device.
+    /// When `null_descriptor` is set, texture slots of
+    /// incomplete materials can be left as
+    /// `vk::ImageView::null()`.
+    pub fn features(&self) -> &EnabledFeatures {
+        &self.data.features
+    }
+
+    /// Whether textures of the given format can be sampled on
+    /// this device. Block-compressed formats, in particular,
+    /// are not universally supported (BC formats are mostly
+    /// missing on mobile GPUs), so loaded textures should be
+    /// checked before being uploaded.
+    pub fn supports_texture_format(&self, format: vk::Format) -> bool {
+        let properties = unsafe {
+            self.instance.get_physical_device_format_properties(self.data.physical_device, format)
+        };
+
+        properties.optimal_tiling_features.contains(vk::FormatFeatureFlags::SAMPLED_IMAGE)
+    }
+
+    /// Whether textures of the given format can be sampled with
+    /// linear filtering on this device. Float formats, 32-bit
+    /// ones in particular, are not always filterable; samplers
+    /// for them have to use NEAREST otherwise.
+    pub fn supports_linear_filtering(&self, format: vk::Format) -> bool {
+        let properties = unsafe {
+            self.instance.get_physical_device_format_properties(self.data.physical_device, format)
+        };
+
+        properties.optimal_tiling_features.contains(vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR)
+    }
+
+    /// Create a linear, repeating sampler for a texture with
+    /// `mip_levels` levels, with the current texture filtering
+    /// quality. Anisotropic filtering is only used when the
+    /// device supports it. The caller owns the sampler and
+    /// destroys it before the renderer; later changes of the
+    /// filtering quality don't affect it.
+    pub fn create_sampler(&self, mip_levels: u32) -> Result<vk::Sampler> {
+        create_sampler(
+            &self.device,
+            &self.data.features,
+            &self.data.limits,
+            self.data.config.texture_filtering,
+            mip_levels,
+        )
+    }
+
+    /// Shared linear, repeating sampler for textures with
+    /// `mip_levels` levels, owned by the renderer. Unlike
+    /// samplers from [`Renderer::create_sampler`], it follows
+    /// the texture filtering quality: the handle changes when
+    /// the quality does, so it should be fetched again when
+    /// writing descriptors rather than kept around.
+    pub fn sampler(&mut self, mip_levels: u32) -> Result<vk::Sampler> {
+        if let Some(&(_, sampler)) = self.samplers.iter().find(|(levels, _)| *levels == mip_levels) {
+            return Ok(sampler);
+        }
+
+        let sampler = self.create_sampler(mip_levels)?;
+        self.samplers.push((mip_levels, sampler));
+
+        Ok(sampler)
+    }
+
+    /// Current texture filtering quality.
+    pub fn texture_filtering(&self) -> TextureFiltering {
+        self.data.config.texture_filtering
+    }
+
+    /// Change the texture filtering quality. The shared samplers
+    /// (see [`Renderer::sampler`]) are recreated right away,
+    /// and the old ones destroyed once the frames in flight
+    /// that may use them are complete.
+    pub fn set_texture_filtering(&mut self, filtering: TextureFiltering) -> Result<()> {
+        if filtering == self.data.config.texture_filtering {
+            return Ok(());
+        }
+
+        // Every new sampler is created before any is swapped
+        // in, so that a failure leaves the renderer with its
+        // previous filtering and samplers, instead of a mix of
+        // both.
+        let mut samplers = Vec::with_capacity(self.samplers.len());
+        for &(mip_levels, _) in &self.samplers {
+            let sampler = create_sampler(
+                &self.device,
+                &self.data.features,
+                &self.data.limits,
+                filtering,
+                mip_levels,
+            );
+
+            match sampler {
+                Ok(sampler) => samplers.push(sampler),
+                Err(error) => {
+                    samplers.into_iter().for_each(|sampler| {
+                        track_destroyed(sampler);
+                        unsafe { self.device.destroy_sampler(sampler, None) };
+                    });
+                    return Err(error);
+                }
+            }
+        }
+
+        self.data.config.texture_filtering = filtering;
+        info!(
+            "Texture filtering set to {:?} (anisotropy {}).",
+            filtering,
+            filtering.anisotropy().min(self.data.limits.max_sampler_anisotropy),
+        );
+
+        let retired = self.samplers
+            .iter_mut()
+            .zip(samplers)
+            .map(|((_, old), new)| std::mem::replace(old, new))
+            .collect::<Vec<_>>();
+
+        if retired.is_empty() {
+            return Ok(());
+        }
+
+        // Every frame in flight may have been submitted with
+        // the old samplers, so they are only destroyed once
+        // each frame has completed once more.
+        let retired = Rc::new(retired);
+        let pending = Rc::new(Cell::new(self.data.frames.len()));
+        for frame in &mut self.data.frames {
+            let (device, retired, pending) = (self.device.clone(), retired.clone(), pending.clone());
+            frame.on_complete.push(Box::new(move || {
+                pending.set(pending.get() - 1);
+                if pending.get() == 0 {
+                    retired
+                        .iter()
+                        .for_each(|&sampler| {
+                            track_destroyed(sampler);
+                            unsafe { device.destroy_sampler(sampler, None) };
+                        });
+                }
+            }));
+        }
+
+        Ok(())
+    }
+
+    /// Wait for the logical device to finish operations. A
+    /// failure (device lost, typically) is only logged, since
+    /// there is nothing left to wait for then.
     pub fn wait_idle(&self) {
-        unsafe { self.device.device_wait_idle().unwrap() };
+        if let Err(e) = unsafe { self.device.device_wait_idle() } {
+            error!("Failed to wait for the device to be idle: {:?}", e);
+        }
     }
 
+    /// Destroy every Vulkan object of the renderer, after
+    /// waiting for the device to be idle. Destroying twice is a
+    /// no-op, and a renderer dropped without being destroyed is
+    /// destroyed then.
     pub unsafe fn destroy(&mut self) {
-        destroy_swapchain(&self.device, &self.data);
+        // The renderer counts as destroyed from the start, so
+        // that if anything below panics, dropping the renderer
+        // while unwinding doesn't destroy the same objects
+        // again.
+        if self.destroyed {
+            return;
+        }
+        self.destroyed = true;
+
+        // Frames may still be in flight, and destroying their
+        // semaphores, command buffers or swapchain images while
+        // the device uses them is invalid; so we wait for
+        // everything to complete first.
+        self.wait_idle();
+        self.op_log.record(Op::Destroyed);
+
+        // Pending completion callbacks are run before anything
+        // is torn down, since they may release resources
+        // created from the device. They are application code,
+        // and the renderer may be destroyed because the
+        // application panicked already: a panic in one of them
+        // would then abort the process, so it is contained, at
+        // the cost of what the callback had left to release.
+        self.data.frames
+            .iter_mut()
+            .flat_map(|f| f.on_complete.drain(..).chain(f.pending_complete.drain(..)))
+            .for_each(|callback| {
+                if panic::catch_unwind(AssertUnwindSafe(callback)).is_err() {
+                    error!("A frame completion callback panicked while destroying the renderer.");
+                }
+            });
+
+        destroy_swapchain(&self.device, &mut self.data);
+
+        self.samplers
+            .drain(..)
+            .for_each(|(_, sampler)| {
+                track_destroyed(sampler);
+                self.device.destroy_sampler(sampler, None);
+            });
 
         self.data.frames
             .iter()
-            .for_each(|f| self.device.destroy_command_pool(f.command_pool, None));
+            .for_each(|f| {
+                track_destroyed(f.command_pool);
+                self.device.destroy_command_pool(f.command_pool, None);
+            });
 
         destroy_sync_objects(&self.device, &mut self.data);
+        destroy_statistics_pools(&self.device, &mut self.data);
+        destroy_timestamp_pools(&self.device, &mut self.data);
 
-        self.instance.destroy_surface_khr(self.data.surface, None);
+        // Everything created from the device is gone by now;
+        // with validation on, anything left over is reported
+        // here, with where it was created, rather than as a
+        // crash somewhere in the driver.
+        check_device_children();
         self.device.destroy_device(None);
 
-        if VALIDATION_ENABLED {
+        // The surface and the debug messenger are children of
+        // the instance, not of the device. The messenger goes
+        // last, to still report the destruction of the rest.
+        track_destroyed(self.data.surface);
+        self.instance.destroy_surface_khr(self.data.surface, None);
+
+        if self.data.config.validation {
+            track_destroyed(self.data.debug_messenger);
             self.instance.destroy_debug_utils_messenger_ext(self.data.debug_messenger, None);
         }
-        
+
+        check_instance_children();
         self.instance.destroy_instance(None);
         info!("Destroyed the Vulkan instance.");
     }
 }
 
-fn create_instance(window: &Window, entry: &Entry, data: &mut RenderData) -> Result<Instance> {
+/// The frame steps of `submit_or_recover` on the renderer's
+/// device, for the frame being rendered.
+struct RendererSteps<'a> {
+    renderer: &'a Renderer,
+    image_index: usize,
+    /// Passes to record, taken by the first recording.
+    passes: Option<(Vec<(String, CustomPass)>, Vec<(String, ViewPass)>)>,
+    readback: Option<&'a Readback>,
+}
+
+// The steps are only built in `Renderer::render_frame`, which
+// already upholds the safety requirements of the device calls.
+impl FrameSteps for RendererSteps<'_> {
+    fn record(&mut self) -> Result<()> {
+        let (passes, view_passes) = self.passes.take().unwrap_or_default();
+        unsafe { self.renderer.record_frame(self.image_index, passes, view_passes, self.readback) }
+    }
+
+    fn fence_signaled(&mut self) -> Result<bool> {
+        let fence = self.renderer.data.frames[self.renderer.frame].in_flight_fence;
+        let status = unsafe { self.renderer.device.get_fence_status(fence)? };
+        Ok(status == vk::SuccessCode::SUCCESS)
+    }
+
+    fn reset_fence(&mut self) -> Result<()> {
+        let fence = self.renderer.data.frames[self.renderer.frame].in_flight_fence;
+        unsafe { self.renderer.device.reset_fences(&[fence])? };
+        Ok(())
+    }
+
+    fn submit(&mut self) -> Result<()> {
+        unsafe { self.renderer.submit_frame(self.image_index) }
+    }
+
+    fn record_recovery(&mut self) -> Result<()> {
+        let renderer = self.renderer;
+        let buffer = renderer.data.frames[renderer.frame].main_buffer;
+
+        // The command buffer may have been left in the
+        // recording or invalid state; resetting it brings it
+        // back to the initial state, and we record the bare
+        // minimum to present the image: a transition to the
+        // presentation layout.
+        unsafe {
+            renderer.device.reset_command_buffer(buffer, vk::CommandBufferResetFlags::empty())?;
+
+            let info = vk::CommandBufferBeginInfo::builder()
+                .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+
+            renderer.device.begin_command_buffer(buffer, &info)?;
+            transition_image_layout(
+                &renderer.device,
+                buffer,
+                renderer.data.swapchain_images[self.image_index],
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::PRESENT_SRC_KHR
+            )?;
+            renderer.device.end_command_buffer(buffer)?;
+        }
+        Ok(())
+    }
+
+    fn present_recovery(&mut self) -> Result<()> {
+        // An out of date swapchain or a lost surface at this
+        // point is not an error, since the frame is skipped
+        // anyway; the next frame will run into it again.
+        match unsafe { self.renderer.present_frame(self.image_index, None) } {
+            Ok(_) | Err(vk::ErrorCode::OUT_OF_DATE_KHR | vk::ErrorCode::SURFACE_LOST_KHR) => Ok(()),
+            Err(e) => Err(anyhow!("Failed to present skipped frame: {:?}", e)),
+        }
+    }
+}
+
+// Renderers are meant to be destroyed explicitly, once the
+// application is done with them; but when a panic unwinds
+// through the application, nothing gets to call `destroy`.
+// Leaving the objects to the driver's own cleanup at exit makes
+// the validation layer report every one of them as leaked, and
+// hangs some drivers, so the renderer destroys itself when it
+// is dropped, in the same order as `destroy` does. The window
+// must still be alive then, for the surface to be destroyed
+// before it: applications declare the renderer before the
+// window, so that it is dropped first.
+impl Drop for Renderer {
+    fn drop(&mut self) {
+        if !self.destroyed {
+            if !std::thread::panicking() {
+                warn!("The renderer was dropped without being destroyed.");
+            }
+
+            unsafe { self.destroy() };
+        }
+    }
+}
+
+/// Name, type, driver and API versions of a physical device,
+/// for the header of operation log dumps.
+fn describe_device(instance: &Instance, physical_device: vk::PhysicalDevice) -> String {
+    let properties = unsafe { instance.get_physical_device_properties(physical_device) };
+    format!(
+        "Device: {} ({:?}), driver version {:#x}, Vulkan {}\n",
+        properties.device_name,
+        properties.device_type,
+        properties.driver_version,
+        Version::from(properties.api_version),
+    )
+}
+
+/// Dump the operation log when the process panics, as long as
+/// the renderer owning it is alive. The panic hook is only
+/// installed with the first log, and chained to the previous
+/// one (printing the panic message, by default), so that
+/// creating renderers over and over doesn't stack up hooks;
+/// it dumps whichever logs are alive when the panic happens.
+fn dump_op_log_on_panic(op_log: Weak<OpLog>, path: PathBuf, device: String) {
+    let mut logs = PANIC_OP_LOGS.lock().unwrap_or_else(|e| e.into_inner());
+    logs.retain(|(op_log, _, _)| op_log.strong_count() > 0);
+    logs.push((op_log, path, device));
+    drop(logs);
+
+    PANIC_HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            previous(info);
+
+            let logs = PANIC_OP_LOGS.lock().unwrap_or_else(|e| e.into_inner());
+            for (op_log, path, device) in logs.iter() {
+                let Some(op_log) = op_log.upgrade() else {
+                    continue;
+                };
+
+                let header = format!("Reason: panic: {}\n{}", info, device);
+                match op_log.dump(path, &header) {
+                    Ok(_) => error!("Operation log written to {}.", path.display()),
+                    Err(e) => warn!("Failed to write the operation log: {}", e),
+                }
+            }
+        }));
+    });
+}
+
+/// Validation errors reported since the last call, oldest first
+/// (at most 16 are kept). Unlike
+/// [`Renderer::take_validation_errors`], this can be called
+/// once the renderer is gone, to check what its destruction
+/// reported.
+pub fn take_validation_errors() -> Vec<ValidationMessage> {
+    std::mem::take(&mut *VALIDATION_ERRORS.lock().unwrap_or_else(|e| e.into_inner()))
+}
+
+/// Create the Vulkan instance, with the extensions to present
+/// to `window`, or without any for headless use (the
+/// self-test).
+pub(crate) fn create_instance(window: Option<&Window>, entry: &Entry, data: &mut RenderData) -> Result<Instance> {
     // Validation layers: because the Vulkan API is designed
     // around the idea of minimal driver overhead, there is
     // very little default error checking. Instead, Vulkan
@@ -385,14 +2057,21 @@ fn create_instance(window: &Window, entry: &Entry, data: &mut RenderData) -> Res
             .collect::<HashSet<_>>()
     };
 
-    // ...then check if validation layers are available...
-    if VALIDATION_ENABLED && !available_layers.contains(&VALIDATION_LAYER) {
-        return Err(anyhow!("Validation layer not available."));
+    // ...then check if validation layers are available. They
+    // are a development tool, so unless they were explicitly
+    // asked for, their absence is not a reason not to run...
+    if data.config.validation && !available_layers.contains(&VALIDATION_LAYER) {
+        if data.config.validation_required {
+            return Err(InstanceError::MissingValidationLayer.into());
+        }
+
+        warn!("Validation layer not available, continuing without validation.");
+        data.config.validation = false;
     }
     
     // ...and finally put in our layers list, which we will
     // give to Vulkan later.
-    let layers = if VALIDATION_ENABLED {
+    let layers = if data.config.validation {
         vec![VALIDATION_LAYER.as_ptr()]
     } else {
         Vec::new()
@@ -409,17 +2088,21 @@ fn create_instance(window: &Window, entry: &Entry, data: &mut RenderData) -> Res
         .api_version(vk::make_version(1, 3, 0));
 
     // Extensions: enumerate the required extensions for window
-    // integration and convert them to C strings.
-    let mut extensions = vk_window::get_required_instance_extensions(window)
-        .iter()
-        .map(|e| e.as_ptr())
-        .collect::<Vec<_>>();
+    // integration, if there is a window.
+    let mut required_extensions = window
+        .map(|window| {
+            vk_window::get_required_instance_extensions(window)
+                .iter()
+                .map(|&&e| e)
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
 
     // If the validation layers are enabled, we add the debut
     // utils extension to set up a callback for the validation
     // layer messages.
-    if VALIDATION_ENABLED {
-        extensions.push(vk::EXT_DEBUG_UTILS_EXTENSION.name.as_ptr());
+    if data.config.validation {
+        required_extensions.push(vk::EXT_DEBUG_UTILS_EXTENSION.name);
     }
 
     // Some platforms (macOS) have not a fully compliant Vulkan
@@ -432,8 +2115,8 @@ fn create_instance(window: &Window, entry: &Entry, data: &mut RenderData) -> Res
         entry.version()? >= PORTABILITY_MACOS_VERSION
     {
         info!("Enabling extensions for macOS portability.");
-        extensions.push(vk::KHR_GET_PHYSICAL_DEVICE_PROPERTIES2_EXTENSION.name.as_ptr());
-        extensions.push(vk::KHR_PORTABILITY_ENUMERATION_EXTENSION.name.as_ptr());
+        required_extensions.push(vk::KHR_GET_PHYSICAL_DEVICE_PROPERTIES2_EXTENSION.name);
+        required_extensions.push(vk::KHR_PORTABILITY_ENUMERATION_EXTENSION.name);
         
         vk::InstanceCreateFlags::ENUMERATE_PORTABILITY_KHR
     }
@@ -441,6 +2124,42 @@ fn create_instance(window: &Window, entry: &Entry, data: &mut RenderData) -> Res
         vk::InstanceCreateFlags::empty()
     };
 
+    // A missing extension would only make instance creation
+    // fail with a generic EXTENSION_NOT_PRESENT error, so we
+    // check them beforehand to report which one is missing.
+    let available_extensions = unsafe {
+        entry
+            .enumerate_instance_extension_properties(None)?
+            .iter()
+            .map(|e| e.extension_name)
+            .collect::<HashSet<_>>()
+    };
+
+    if let Some(missing) = required_extensions.iter().find(|e| !available_extensions.contains(e)) {
+        return Err(InstanceError::MissingExtension(missing.to_string()).into());
+    }
+
+    // Surfaces only report formats in the extended color
+    // spaces (HDR, wide gamut) if VK_EXT_swapchain_colorspace
+    // is enabled, which is only done if one of them is
+    // preferred, and skipped if unavailable.
+    let extended_color_space = data.config.surface_formats
+        .iter()
+        .any(|f| f.color_space != vk::ColorSpaceKHR::SRGB_NONLINEAR);
+    if extended_color_space {
+        if available_extensions.contains(&vk::EXT_SWAPCHAIN_COLORSPACE_EXTENSION.name) {
+            required_extensions.push(vk::EXT_SWAPCHAIN_COLORSPACE_EXTENSION.name);
+        } else {
+            warn!("Extended color spaces are not supported, HDR and wide-gamut formats are unavailable.");
+        }
+    }
+
+    // Then, the extension names are converted to C strings.
+    let extensions = required_extensions
+        .iter()
+        .map(|e| e.as_ptr())
+        .collect::<Vec<_>>();
+
     // Instance info: combines the application and extensions
     // info, and enables the given layers
     let mut info = vk::InstanceCreateInfo::builder()
@@ -452,12 +2171,17 @@ fn create_instance(window: &Window, entry: &Entry, data: &mut RenderData) -> Res
     // Debug info: set up a debug messenger for the validation
     // layers, that calls our debug callback function to print
     // messages for all severity levels and types of events.
+    // The callback gets the renderer's validation error flag
+    // as its user data; the flag is reference counted, so its
+    // address doesn't change when the render data is moved.
     let mut debug_info = vk::DebugUtilsMessengerCreateInfoEXT::builder()
-        .message_severity(vk::DebugUtilsMessageSeverityFlagsEXT::all())
+        .message_severity(data.config.validation_severity)
         .message_type(vk::DebugUtilsMessageTypeFlagsEXT::all())
-        .user_callback(Some(debug_callback));
+        .user_callback(Some(debug_callback))
+        .build();
+    debug_info.user_data = Arc::as_ptr(&data.validation_error) as *mut c_void;
 
-    if VALIDATION_ENABLED {
+    if data.config.validation {
         // Vulkan structs, like the instance info, have the
         // ability to be extended with other structs, which can
         // in turn be extended with other structs, and so on.
@@ -469,13 +2193,19 @@ fn create_instance(window: &Window, entry: &Entry, data: &mut RenderData) -> Res
     }
 
     // We can give a custom allocator to the instance, but we
-    // set it here to None.
-    let instance = unsafe { entry.create_instance(&info, None)? };
+    // set it here to None. INCOMPATIBLE_DRIVER is what the
+    // loader returns when it finds no (suitable) driver at all.
+    let instance = match unsafe { entry.create_instance(&info, None) } {
+        Ok(instance) => instance,
+        Err(vk::ErrorCode::INCOMPATIBLE_DRIVER) => return Err(InstanceError::NoDriver.into()),
+        Err(e) => return Err(InstanceError::Vulkan(e).into()),
+    };
 
-    if VALIDATION_ENABLED {
+    if data.config.validation {
         // Create the debug messenger in the instance with our
         // debug info and link it to our app data
         data.debug_messenger = unsafe { instance.create_debug_utils_messenger_ext(&debug_info, None)? };
+        track_created(data.debug_messenger);
     }
 
     info!("Vulkan instance created.");
@@ -486,7 +2216,7 @@ extern "system" fn debug_callback(
     severity: vk::DebugUtilsMessageSeverityFlagsEXT,
     type_: vk::DebugUtilsMessageTypeFlagsEXT,
     data: *const vk::DebugUtilsMessengerCallbackDataEXT,
-    _: *mut std::ffi::c_void,
+    user_data: *mut c_void,
 ) -> vk::Bool32 {
     // The debug callback function ensures that we print
     // messages with our own log system instead of the
@@ -504,24 +2234,127 @@ extern "system" fn debug_callback(
     //     PERFORMANCE (non-optimal use of the API)
     //  3) 'pCallbackData': the debug message data
     //  4) 'pUserData': a pointer to user-defined data, here
-    //     unused
-
-    let data = unsafe { *data };
-    let message = unsafe { std::ffi::CStr::from_ptr(data.message) }.to_string_lossy();
-    
-    if severity >= vk::DebugUtilsMessageSeverityFlagsEXT::ERROR {
-        error!("({type_:?}) {message}");
-    } else if severity >= vk::DebugUtilsMessageSeverityFlagsEXT::WARNING {
-        warn!("({type_:?}) {message}");
-    } else if severity >= vk::DebugUtilsMessageSeverityFlagsEXT::INFO {
-        debug!("({type_:?}) {message}");
-    } else {
-        trace!("({type_:?}) {message}");
+    //     the validation error flag of the renderer
+    // The callback is called from whatever thread made the
+    // Vulkan call, driver threads included, and unwinding
+    // across the FFI boundary aborts the process; so anything
+    // that could panic (the logger, for example) is contained
+    // here, and the message is dropped if it does.
+    if data.is_null() {
+        return vk::FALSE;
     }
 
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+        let message = unsafe { ValidationMessage::from_callback_data(&*data) };
+
+        if severity >= vk::DebugUtilsMessageSeverityFlagsEXT::ERROR {
+            error!("({type_:?}) {message}");
+
+            let mut errors = VALIDATION_ERRORS.lock().unwrap_or_else(|e| e.into_inner());
+            if errors.len() < MAX_VALIDATION_ERRORS {
+                errors.push(message);
+            }
+            if !user_data.is_null() {
+                let flag = unsafe { &*(user_data as *const AtomicBool) };
+                flag.store(true, Ordering::Relaxed);
+            }
+        } else if severity >= vk::DebugUtilsMessageSeverityFlagsEXT::WARNING {
+            warn!("({type_:?}) {message}");
+        } else if severity >= vk::DebugUtilsMessageSeverityFlagsEXT::INFO {
+            debug!("({type_:?}) {message}");
+        } else {
+            trace!("({type_:?}) {message}");
+        }
+    }));
+
     // If the callback returns true, the call is aborted with a
     // VALIDATION_FAILED error code; it should therefore only
     // return true when testing the validation layers
     // themselves.
     vk::FALSE
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validation_message_from_callback_data() {
+        let objects = [
+            vk::DebugUtilsObjectNameInfoEXT::builder()
+                .object_type(vk::ObjectType::BUFFER)
+                .object_handle(0x2a)
+                .object_name(b"vertices\0"),
+            vk::DebugUtilsObjectNameInfoEXT::builder()
+                .object_type(vk::ObjectType::IMAGE)
+                .object_handle(0x10),
+        ];
+        let queue_labels = [
+            vk::DebugUtilsLabelEXT::builder().label_name(b"frame 3\0"),
+        ];
+        let command_buffer_labels = [
+            vk::DebugUtilsLabelEXT::builder().label_name(b"main pass\0"),
+            vk::DebugUtilsLabelEXT::builder().label_name(b"shadows\0"),
+        ];
+        let data = vk::DebugUtilsMessengerCallbackDataEXT::builder()
+            .message_id_name(b"VUID-vkCmdDraw-None-02699\0")
+            .message_id_number(0x5c0ec5d6)
+            .message(b"Descriptor set was never updated.\0")
+            .objects(&objects)
+            .queue_labels(&queue_labels)
+            .cmd_buf_labels(&command_buffer_labels);
+
+        let message = unsafe { ValidationMessage::from_callback_data(&data) };
+        assert_eq!(message.id_name, "VUID-vkCmdDraw-None-02699");
+        assert_eq!(message.id_number, 0x5c0ec5d6);
+        assert_eq!(message.message, "Descriptor set was never updated.");
+        assert_eq!(message.objects, ["BUFFER 0x2a \"vertices\"", "IMAGE 0x10"]);
+        assert_eq!(message.queue_labels, ["frame 3"]);
+        assert_eq!(message.command_buffer_labels, ["main pass", "shadows"]);
+
+        assert_eq!(
+            message.to_string(),
+            "[VUID-vkCmdDraw-None-02699 (0x5c0ec5d6)] Descriptor set was never updated.\n  \
+             objects: BUFFER 0x2a \"vertices\", IMAGE 0x10\n  \
+             queue labels: frame 3\n  \
+             command buffer labels: main pass > shadows",
+        );
+    }
+
+    #[test]
+    fn validation_message_from_empty_callback_data() {
+        // Every pointer is null, and every count 0.
+        let data = vk::DebugUtilsMessengerCallbackDataEXT::default();
+
+        let message = unsafe { ValidationMessage::from_callback_data(&data) };
+        assert!(message.id_name.is_empty());
+        assert!(message.message.is_empty());
+        assert!(message.objects.is_empty());
+        assert!(message.queue_labels.is_empty());
+        assert!(message.command_buffer_labels.is_empty());
+        assert_eq!(message.to_string(), "[ (0x0)] ");
+    }
+
+    #[test]
+    fn validation_errors_set_their_messenger_flag() {
+        let data = vk::DebugUtilsMessengerCallbackDataEXT::builder()
+            .message(b"Submitted a signaled fence.\0");
+        let flag = AtomicBool::new(false);
+        let user_data = &flag as *const AtomicBool as *mut c_void;
+
+        // Errors set the flag given to the messenger as its
+        // user data, warnings don't; messengers without a flag
+        // only collect the error.
+        let validation = vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION;
+        debug_callback(vk::DebugUtilsMessageSeverityFlagsEXT::WARNING, validation, &*data, user_data);
+        assert!(!flag.load(Ordering::Relaxed));
+
+        debug_callback(vk::DebugUtilsMessageSeverityFlagsEXT::ERROR, validation, &*data, user_data);
+        assert!(flag.load(Ordering::Relaxed));
+
+        debug_callback(vk::DebugUtilsMessageSeverityFlagsEXT::ERROR, validation, &*data, std::ptr::null_mut());
+
+        let errors = take_validation_errors();
+        assert_eq!(errors.iter().filter(|error| error.message == "Submitted a signaled fence.").count(), 2);
+    }
+}
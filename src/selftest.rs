@@ -0,0 +1,517 @@
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config::RendererConfig,
+    core::{
+        allocator::{Allocation, Allocator, Lifetime, MemoryUse, ResourceCategory, ResourceType},
+        devices::{create_logical_device, pick_physical_device},
+        image::transition_image_layout,
+    },
+    renderer::{create_instance, RenderData},
+    shaders::{FRAGMENT_SHADER, VERTEX_SHADER},
+};
+use vulkanalia::{
+    prelude::v1_0::*,
+    bytecode::Bytecode,
+    loader::{LibloadingLoader, LIBRARY},
+    vk::{DeviceV1_3, ExtDebugUtilsExtension},
+    Version,
+};
+use anyhow::{anyhow, Result};
+
+// The self-test exercises the renderer's stack on its own,
+// without a window (so it also runs on machines without a
+// display, or over SSH): the instance and the device are
+// created by the renderer's own setup code, only without a
+// surface to present to. It prints a report meant to be
+// pasted in bug reports: the devices and their capabilities,
+// then a series of short checks, each timed, with the Vulkan
+// result code of the failing call when one fails. Checks that
+// depend on a failed one are skipped rather than failed, so
+// that the first failure stands out.
+
+/// Size of the image rendered and read back.
+const TEST_IMAGE_SIZE: u32 = 64;
+/// Color the test image is cleared to, and its expected value
+/// once read back as RGBA8.
+const TEST_COLOR: [f32; 4] = [1.0, 0.5, 0.25, 1.0];
+const TEST_COLOR_BYTES: [u8; 4] = [255, 128, 64, 255];
+/// Size of the buffer allocated by the allocation check.
+const TEST_BUFFER_SIZE: u64 = 1 << 20;
+
+enum Outcome {
+    Passed,
+    Failed(String),
+    Skipped(&'static str),
+}
+
+/// State shared by the checks, set up by the first ones.
+#[derive(Default)]
+struct SelfTest {
+    entry: Option<Entry>,
+    instance: Option<Instance>,
+    /// Render data filled by the renderer's setup functions.
+    data: RenderData,
+    device: Option<Device>,
+    command_pool: vk::CommandPool,
+    allocator: Option<Allocator>,
+    failures: usize,
+}
+
+/// Run the self-test, printing the report on the standard
+/// output. Returns whether every check that ran passed.
+pub fn run_self_test() -> bool {
+    println!("caliban {} self-test", env!("CARGO_PKG_VERSION"));
+    println!("OS: {} ({})", std::env::consts::OS, std::env::consts::ARCH);
+    println!();
+
+    let mut test = SelfTest::default();
+    test.check("Vulkan instance", |test| unsafe { test.create_instance() });
+    test.check("Physical device", |test| unsafe { test.pick_device() });
+    test.check("Logical device", |test| unsafe { test.create_device() });
+    test.check("Buffer allocation", |test| unsafe { test.allocate_buffer() });
+    test.check("Image allocation", |test| unsafe { test.allocate_image() });
+    test.check("Render and read back", |test| unsafe { test.render_frame() });
+    test.check("Shader compilation", |test| unsafe { test.create_shader_modules() });
+
+    // These parts of the stack don't exist in this build yet,
+    // and are listed so that reports say so explicitly.
+    test.skip("Pipeline creation", "no pipelines");
+    test.skip("Mip generation", "no mip generation");
+
+    unsafe { test.destroy() };
+
+    println!();
+    if test.failures == 0 {
+        println!("All checks passed.");
+    } else {
+        println!("{} check(s) failed.", test.failures);
+    }
+
+    test.failures == 0
+}
+
+impl SelfTest {
+    /// Run a check, unless one it depends on failed (which
+    /// shows as the state it needs being missing), and print
+    /// its outcome.
+    fn check(&mut self, name: &str, check: impl FnOnce(&mut Self) -> Result<()>) {
+        let start = Instant::now();
+        let outcome = match check(self) {
+            Ok(()) => Outcome::Passed,
+            Err(error) if error.is::<Skip>() => Outcome::Skipped("a previous check failed"),
+            Err(error) => {
+                self.failures += 1;
+                match error.chain().find_map(|e| e.downcast_ref::<vk::ErrorCode>()) {
+                    Some(code) => Outcome::Failed(format!("{:?}: {:#}", code, error)),
+                    None => Outcome::Failed(format!("{:#}", error)),
+                }
+            },
+        };
+
+        print_outcome(name, start.elapsed(), &outcome);
+    }
+
+    fn skip(&mut self, name: &str, reason: &'static str) {
+        print_outcome(name, Duration::ZERO, &Outcome::Skipped(reason));
+    }
+
+    fn device(&self) -> Result<&Device> {
+        self.device.as_ref().ok_or_else(|| Skip.into())
+    }
+
+    unsafe fn create_instance(&mut self) -> Result<()> {
+        let loader = LibloadingLoader::new(LIBRARY).map_err(|e| anyhow!("{}", e))?;
+        let entry = Entry::new(loader).map_err(|e| anyhow!("{}", e))?;
+
+        let version = entry.enumerate_instance_version().unwrap_or(vk::make_version(1, 0, 0));
+        println!("Instance version: {}", Version::from(version));
+
+        // The configuration is the renderer's, environment
+        // overrides included, so that a preferred device or
+        // the validation layer apply here too.
+        self.data.config = RendererConfig::default().with_env_overrides();
+        self.instance = Some(create_instance(None, &entry, &mut self.data)?);
+        self.entry = Some(entry);
+        Ok(())
+    }
+
+    unsafe fn pick_device(&mut self) -> Result<()> {
+        let instance = self.instance.as_ref().ok_or(Skip)?;
+        let devices = instance.enumerate_physical_devices()?;
+
+        for &physical_device in &devices {
+            let properties = instance.get_physical_device_properties(physical_device);
+            println!(
+                "Device: {} ({:?}), Vulkan {}, driver version {:#x}, vendor {:#06x}",
+                properties.device_name,
+                properties.device_type,
+                Version::from(properties.api_version),
+                properties.driver_version,
+                properties.vendor_id,
+            );
+        }
+
+        let physical_device = pick_physical_device(instance, &mut self.data)?;
+        println!("Selected: {}", self.data.device_info.name);
+
+        let memory = instance.get_physical_device_memory_properties(physical_device);
+        for (index, heap) in memory.memory_heaps[..memory.memory_heap_count as usize].iter().enumerate() {
+            println!("  Heap {}: {} MiB, {:?}", index, heap.size >> 20, heap.flags);
+        }
+        for (index, memory_type) in memory.memory_types[..memory.memory_type_count as usize].iter().enumerate() {
+            println!("  Memory type {}: heap {}, {:?}", index, memory_type.heap_index, memory_type.property_flags);
+        }
+
+        let mut extensions = instance
+            .enumerate_device_extension_properties(physical_device, None)?
+            .iter()
+            .map(|e| e.extension_name.to_string())
+            .collect::<Vec<_>>();
+        extensions.sort();
+        println!("  Extensions ({}): {}", extensions.len(), extensions.join(" "));
+        println!();
+
+        self.data.physical_device = physical_device;
+        Ok(())
+    }
+
+    unsafe fn create_device(&mut self) -> Result<()> {
+        let instance = self.instance.as_ref().ok_or(Skip)?;
+        if self.data.physical_device.is_null() {
+            return Err(Skip.into());
+        }
+
+        let device = create_logical_device(instance, &mut self.data)?;
+        println!("  Enabled features: {:?}", self.data.features);
+        let allocator = Allocator::new(instance, self.data.physical_device, self.data.limits);
+
+        let pool_info = vk::CommandPoolCreateInfo::builder()
+            .flags(vk::CommandPoolCreateFlags::TRANSIENT)
+            .queue_family_index(self.data.graphics_queue_family);
+        let command_pool = device.create_command_pool(&pool_info, None);
+
+        self.allocator = Some(allocator);
+        self.device = Some(device);
+        self.command_pool = command_pool?;
+
+        Ok(())
+    }
+
+    /// Create shader modules from the embedded SPIR-V, which
+    /// is where the driver compiles (or at least parses) it.
+    unsafe fn create_shader_modules(&mut self) -> Result<()> {
+        let device = self.device()?;
+
+        for (name, spirv) in [("vertex", VERTEX_SHADER), ("fragment", FRAGMENT_SHADER)] {
+            let bytecode = Bytecode::new(spirv).map_err(|e| anyhow!("Invalid {} shader: {}", name, e))?;
+            let info = vk::ShaderModuleCreateInfo::builder()
+                .code_size(bytecode.code_size())
+                .code(bytecode.code());
+
+            let module = device.create_shader_module(&info, None)?;
+            device.destroy_shader_module(module, None);
+        }
+
+        Ok(())
+    }
+
+    unsafe fn allocate_buffer(&mut self) -> Result<()> {
+        let (buffer, allocation) = self.create_buffer(
+            TEST_BUFFER_SIZE,
+            vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST,
+        )?;
+
+        // Write through the mapping, to check that it is usable.
+        allocation.write(0, &[0xAAu8; 256]);
+        self.destroy_buffer(buffer, allocation);
+        Ok(())
+    }
+
+    unsafe fn allocate_image(&mut self) -> Result<()> {
+        let (image, allocation) = self.create_image()?;
+        self.destroy_image(image, allocation);
+        Ok(())
+    }
+
+    /// Clear an image, copy it to a host-visible buffer, and
+    /// check the pixels that come back: this goes through
+    /// command recording, submission, layout transitions and
+    /// synchronization with the host, like a frame does.
+    unsafe fn render_frame(&mut self) -> Result<()> {
+        let size = (TEST_IMAGE_SIZE * TEST_IMAGE_SIZE * 4) as u64;
+        let (image, image_allocation) = self.create_image()?;
+        let (buffer, buffer_allocation) = match self.create_buffer(size, vk::BufferUsageFlags::TRANSFER_DST) {
+            Ok(buffer) => buffer,
+            Err(error) => {
+                self.destroy_image(image, image_allocation);
+                return Err(error);
+            },
+        };
+
+        let result = self.render_and_read_back(image, buffer, &buffer_allocation, size);
+
+        self.destroy_buffer(buffer, buffer_allocation);
+        self.destroy_image(image, image_allocation);
+        result
+    }
+
+    unsafe fn render_and_read_back(
+        &self,
+        image: vk::Image,
+        buffer: vk::Buffer,
+        buffer_allocation: &Allocation,
+        size: u64,
+    ) -> Result<()> {
+        let device = self.device()?;
+
+        let allocate_info = vk::CommandBufferAllocateInfo::builder()
+            .command_pool(self.command_pool)
+            .level(vk::CommandBufferLevel::PRIMARY)
+            .command_buffer_count(1);
+        let command_buffer = device.allocate_command_buffers(&allocate_info)?[0];
+        let fence = device.create_fence(&vk::FenceCreateInfo::builder(), None)?;
+
+        let result = (|| -> Result<()> {
+            let begin_info = vk::CommandBufferBeginInfo::builder()
+                .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+            device.begin_command_buffer(command_buffer, &begin_info)?;
+
+            transition_image_layout(
+                device,
+                command_buffer,
+                image,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            )?;
+
+            let range = vk::ImageSubresourceRange::builder()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .level_count(1)
+                .layer_count(1);
+            let color = vk::ClearColorValue { float32: TEST_COLOR };
+            device.cmd_clear_color_image(
+                command_buffer,
+                image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &color,
+                &[range],
+            );
+
+            transition_image_layout(
+                device,
+                command_buffer,
+                image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            )?;
+
+            let subresource = vk::ImageSubresourceLayers::builder()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .layer_count(1);
+            let region = vk::BufferImageCopy::builder()
+                .image_subresource(subresource)
+                .image_extent(vk::Extent3D { width: TEST_IMAGE_SIZE, height: TEST_IMAGE_SIZE, depth: 1 });
+            device.cmd_copy_image_to_buffer(
+                command_buffer,
+                image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                buffer,
+                &[region],
+            );
+
+            // Make the copy visible to the host, which reads
+            // the buffer once the fence is signaled.
+            let barrier = vk::BufferMemoryBarrier2::builder()
+                .src_stage_mask(vk::PipelineStageFlags2::COPY)
+                .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+                .dst_stage_mask(vk::PipelineStageFlags2::HOST)
+                .dst_access_mask(vk::AccessFlags2::HOST_READ)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .buffer(buffer)
+                .size(vk::WHOLE_SIZE);
+            let barriers = &[barrier];
+            let dependency = vk::DependencyInfo::builder().buffer_memory_barriers(barriers);
+            device.cmd_pipeline_barrier2(command_buffer, &dependency);
+            device.end_command_buffer(command_buffer)?;
+
+            let command_buffers = &[command_buffer];
+            let submit_info = vk::SubmitInfo::builder().command_buffers(command_buffers);
+            device.queue_submit(self.data.graphics_queue, &[submit_info], fence)?;
+            if device.wait_for_fences(&[fence], true, 5_000_000_000)? == vk::SuccessCode::TIMEOUT {
+                return Err(anyhow!("Timed out waiting for the frame."));
+            }
+
+            if !buffer_allocation.coherent {
+                let range = vk::MappedMemoryRange::builder()
+                    .memory(buffer_allocation.memory)
+                    .offset(0)
+                    .size(vk::WHOLE_SIZE);
+                device.invalidate_mapped_memory_ranges(&[range])?;
+            }
+
+            let mapped = buffer_allocation.mapped.ok_or_else(|| anyhow!("The readback buffer is not mapped."))?;
+            let pixels = std::slice::from_raw_parts(mapped.as_ptr(), size as usize);
+            let wrong = pixels
+                .chunks_exact(4)
+                .filter(|pixel| pixel.iter().zip(TEST_COLOR_BYTES).any(|(&a, b)| a.abs_diff(b) > 1))
+                .count();
+            if wrong > 0 {
+                return Err(anyhow!(
+                    "{} of {} pixels read back with the wrong color (first pixel: {:?}, expected {:?}).",
+                    wrong,
+                    pixels.len() / 4,
+                    &pixels[..4],
+                    TEST_COLOR_BYTES,
+                ));
+            }
+
+            Ok(())
+        })();
+
+        device.device_wait_idle()?;
+        device.destroy_fence(fence, None);
+        device.free_command_buffers(self.command_pool, &[command_buffer]);
+        result
+    }
+
+    unsafe fn create_buffer(&mut self, size: u64, usage: vk::BufferUsageFlags) -> Result<(vk::Buffer, Allocation)> {
+        let (Some(device), Some(allocator)) = (&self.device, &mut self.allocator) else {
+            return Err(Skip.into());
+        };
+
+        let info = vk::BufferCreateInfo::builder()
+            .size(size)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let buffer = device.create_buffer(&info, None)?;
+
+        let requirements = device.get_buffer_memory_requirements(buffer);
+        let allocation = match allocator.allocate(
+            device,
+            requirements,
+            MemoryUse::CpuToGpu,
+            ResourceType::Linear,
+            Lifetime::Transient,
+            ResourceCategory::Staging,
+        ) {
+            Ok(allocation) => allocation,
+            Err(error) => {
+                device.destroy_buffer(buffer, None);
+                return Err(error.into());
+            },
+        };
+
+        if let Err(error) = device.bind_buffer_memory(buffer, allocation.memory, allocation.offset) {
+            device.destroy_buffer(buffer, None);
+            allocator.free(allocation);
+            return Err(error.into());
+        }
+
+        Ok((buffer, allocation))
+    }
+
+    unsafe fn create_image(&mut self) -> Result<(vk::Image, Allocation)> {
+        let (Some(device), Some(allocator)) = (&self.device, &mut self.allocator) else {
+            return Err(Skip.into());
+        };
+
+        let info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::_2D)
+            .format(vk::Format::R8G8B8A8_UNORM)
+            .extent(vk::Extent3D { width: TEST_IMAGE_SIZE, height: TEST_IMAGE_SIZE, depth: 1 })
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+        let image = device.create_image(&info, None)?;
+
+        let requirements = device.get_image_memory_requirements(image);
+        let allocation = match allocator.allocate(
+            device,
+            requirements,
+            MemoryUse::GpuOnly,
+            ResourceType::NonLinear,
+            Lifetime::Persistent,
+            ResourceCategory::RenderTarget,
+        ) {
+            Ok(allocation) => allocation,
+            Err(error) => {
+                device.destroy_image(image, None);
+                return Err(error.into());
+            },
+        };
+
+        if let Err(error) = device.bind_image_memory(image, allocation.memory, allocation.offset) {
+            device.destroy_image(image, None);
+            allocator.free(allocation);
+            return Err(error.into());
+        }
+
+        Ok((image, allocation))
+    }
+
+    unsafe fn destroy_buffer(&mut self, buffer: vk::Buffer, allocation: Allocation) {
+        if let (Some(device), Some(allocator)) = (&self.device, &mut self.allocator) {
+            device.destroy_buffer(buffer, None);
+            allocator.free(allocation);
+        }
+    }
+
+    unsafe fn destroy_image(&mut self, image: vk::Image, allocation: Allocation) {
+        if let (Some(device), Some(allocator)) = (&self.device, &mut self.allocator) {
+            device.destroy_image(image, None);
+            allocator.free(allocation);
+        }
+    }
+
+    unsafe fn destroy(&mut self) {
+        if let Some(device) = self.device.take() {
+            let _ = device.device_wait_idle();
+            if let Some(mut allocator) = self.allocator.take() {
+                allocator.destroy(&device);
+            }
+            device.destroy_command_pool(self.command_pool, None);
+            device.destroy_device(None);
+        }
+
+        if let Some(instance) = self.instance.take() {
+            if !self.data.debug_messenger.is_null() {
+                instance.destroy_debug_utils_messenger_ext(self.data.debug_messenger, None);
+            }
+            instance.destroy_instance(None);
+        }
+
+        // The loader library is only unloaded once everything
+        // created from it is gone.
+        drop(self.entry.take());
+    }
+}
+
+/// Error of a check that can't run because a check it depends
+/// on failed.
+#[derive(Debug)]
+struct Skip;
+
+impl fmt::Display for Skip {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "skipped")
+    }
+}
+
+impl std::error::Error for Skip {}
+
+fn print_outcome(name: &str, time: Duration, outcome: &Outcome) {
+    match outcome {
+        Outcome::Passed => println!("[PASS] {:<24} {:>8.2} ms", name, time.as_secs_f64() * 1000.0),
+        Outcome::Failed(error) => println!("[FAIL] {:<24} {:>8.2} ms  {}", name, time.as_secs_f64() * 1000.0, error),
+        Outcome::Skipped(reason) => println!("[SKIP] {:<24}              {}", name, reason),
+    }
+}
@@ -0,0 +1,13 @@
+// SPIR-V of the shaders in `shaders/`, compiled by the build
+// script and embedded in the binary. Loading the shaders from
+// disk is still preferred, since it lets them be edited and
+// reloaded without rebuilding; the embedded ones are the
+// fallback when the files can't be found (a binary copied
+// without its `shaders/` directory, typically), so that the
+// renderer can always start.
+
+/// SPIR-V of `shaders/shader.vert`.
+pub const VERTEX_SHADER: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/shader.vert.spv"));
+
+/// SPIR-V of `shaders/shader.frag`.
+pub const FRAGMENT_SHADER: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/shader.frag.spv"));
@@ -0,0 +1,169 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// Number of frames the frame rate is averaged over.
+pub const FRAME_TIME_SAMPLES: usize = 120;
+
+/// Longest time step of a frame. Longer pauses (a breakpoint,
+/// dragging the window on some platforms) are counted as this,
+/// so that simulations don't jump ahead.
+pub const MAX_FRAME_DT: Duration = Duration::from_millis(100);
+
+/// Time of a frame, as seen by the application.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FrameTime {
+    /// Time step since the previous frame, at most
+    /// [`MAX_FRAME_DT`]; zero for the first frame.
+    pub dt: Duration,
+    /// Application time: the sum of the time steps so far. It
+    /// only moves forward, and doesn't count pauses beyond the
+    /// maximum time step, nor the loading screen.
+    pub elapsed: Duration,
+}
+
+/// Where the CPU waits for the GPU in the frame loop. With
+/// vsync, the loop is throttled by these waits, and the input
+/// read by the application ages while they block: the later
+/// the input is read relative to them, the lower the latency.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LatencyMode {
+    /// Update the application first, then wait for the frame
+    /// in flight to be free when rendering. The CPU work of a
+    /// frame overlaps the wait, but the input is read before
+    /// it.
+    #[default]
+    SubmitThenWait,
+    /// Wait for the frame in flight to be free before updating
+    /// the application, so that the input is read once the
+    /// wait is over, right before recording.
+    WaitBeforeInput,
+}
+
+/// Time the CPU spent blocked in each call of a frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FrameWaits {
+    /// Waiting for the fence of the frame in flight.
+    pub fence: Duration,
+    /// Acquiring the swapchain image.
+    pub acquire: Duration,
+    /// Queuing the image for presentation.
+    pub present: Duration,
+    /// Sleeping in the frame limiter, after the frame.
+    pub idle: Duration,
+}
+
+/// Timing of the frames rendered, as measured on the CPU
+/// between calls to `Renderer::render`, and how often the
+/// swapchain had to be recreated.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FrameStats {
+    /// Frames per second, averaged over the last
+    /// [`FRAME_TIME_SAMPLES`] frames.
+    pub fps: f64,
+    /// Average time between two frames, in milliseconds.
+    pub frame_time_ms: f64,
+    /// Average time blocked on the fence, the acquire and the
+    /// present of a frame, in milliseconds.
+    pub fence_wait_ms: f64,
+    pub acquire_wait_ms: f64,
+    pub present_wait_ms: f64,
+    /// Average time the frame limiter waited after a frame, in
+    /// milliseconds: the headroom left at the target frame
+    /// rate. It is 0 without a target.
+    pub idle_ms: f64,
+    /// Estimate of the input latency, in milliseconds: the
+    /// average time from the application reading the input to
+    /// the frame being queued for presentation. The image then
+    /// reaches the display after the images queued before it,
+    /// which adds up to a refresh interval each with vsync.
+    pub input_latency_ms: f64,
+    /// Number of frames rendered since the renderer was
+    /// created. Unlike the index of the frame in flight, it
+    /// never wraps around, so it can date resources.
+    pub frame_index: u64,
+    /// Total number of swapchain recreation attempts, and of
+    /// failed ones, to spot flaky environments.
+    pub swapchain_recreations: u64,
+    pub swapchain_recreation_failures: u64,
+}
+
+/// Ring buffer of the last frame times.
+#[derive(Debug, Default)]
+pub struct FrameTimer {
+    times: VecDeque<Duration>,
+    /// Sum of `times`, kept up to date to not sum them again
+    /// every frame.
+    total: Duration,
+    last: Option<Instant>,
+    frame_index: u64,
+    /// Waits of the last frames, and their input latency when
+    /// they were presented.
+    waits: VecDeque<(FrameWaits, Option<Duration>)>,
+    swapchain_recreations: u64,
+    swapchain_recreation_failures: u64,
+}
+
+impl FrameTimer {
+    /// Register a frame rendered at `now`.
+    pub fn tick(&mut self, now: Instant) {
+        if let Some(last) = self.last {
+            let time = now - last;
+            if self.times.len() == FRAME_TIME_SAMPLES {
+                if let Some(oldest) = self.times.pop_front() {
+                    self.total -= oldest;
+                }
+            }
+
+            self.times.push_back(time);
+            self.total += time;
+        }
+
+        self.last = Some(now);
+        self.frame_index += 1;
+    }
+
+    /// Register the waits of a frame, and its input latency if
+    /// it was presented.
+    pub fn record_waits(&mut self, waits: FrameWaits, latency: Option<Duration>) {
+        if self.waits.len() == FRAME_TIME_SAMPLES {
+            self.waits.pop_front();
+        }
+
+        self.waits.push_back((waits, latency));
+    }
+
+    /// Register an attempt at recreating the swapchain.
+    pub fn record_swapchain_recreation(&mut self, succeeded: bool) {
+        self.swapchain_recreations += 1;
+        if !succeeded {
+            self.swapchain_recreation_failures += 1;
+        }
+    }
+
+    pub fn stats(&self) -> FrameStats {
+        let frame_time = match self.times.len() {
+            0 => 0.0,
+            n => self.total.as_secs_f64() / n as f64,
+        };
+
+        let average_ms = |durations: &mut dyn Iterator<Item = Duration>| {
+            let (count, total) = durations.fold((0, Duration::ZERO), |(n, total), d| (n + 1, total + d));
+            if count == 0 { 0.0 } else { total.as_secs_f64() * 1000.0 / count as f64 }
+        };
+
+        FrameStats {
+            fps: if frame_time > 0.0 { 1.0 / frame_time } else { 0.0 },
+            frame_time_ms: frame_time * 1000.0,
+            fence_wait_ms: average_ms(&mut self.waits.iter().map(|(w, _)| w.fence)),
+            acquire_wait_ms: average_ms(&mut self.waits.iter().map(|(w, _)| w.acquire)),
+            present_wait_ms: average_ms(&mut self.waits.iter().map(|(w, _)| w.present)),
+            idle_ms: average_ms(&mut self.waits.iter().map(|(w, _)| w.idle)),
+            input_latency_ms: average_ms(&mut self.waits.iter().filter_map(|(_, latency)| *latency)),
+            frame_index: self.frame_index,
+            swapchain_recreations: self.swapchain_recreations,
+            swapchain_recreation_failures: self.swapchain_recreation_failures,
+        }
+    }
+}
@@ -0,0 +1,77 @@
+pub mod dds;
+
+pub use dds::{load_dds, parse_dds, DdsError, DdsTexture};
+
+use vulkanalia::prelude::v1_0::*;
+
+/// Size in bytes of a 4x4 block for block-compressed formats,
+/// or `None` for uncompressed ones.
+pub fn block_size(format: vk::Format) -> Option<u64> {
+    match format {
+        vk::Format::BC1_RGBA_UNORM_BLOCK
+        | vk::Format::BC1_RGBA_SRGB_BLOCK
+        | vk::Format::BC4_UNORM_BLOCK
+        | vk::Format::BC4_SNORM_BLOCK => Some(8),
+        vk::Format::BC2_UNORM_BLOCK
+        | vk::Format::BC2_SRGB_BLOCK
+        | vk::Format::BC3_UNORM_BLOCK
+        | vk::Format::BC3_SRGB_BLOCK
+        | vk::Format::BC5_UNORM_BLOCK
+        | vk::Format::BC5_SNORM_BLOCK
+        | vk::Format::BC6H_UFLOAT_BLOCK
+        | vk::Format::BC6H_SFLOAT_BLOCK
+        | vk::Format::BC7_UNORM_BLOCK
+        | vk::Format::BC7_SRGB_BLOCK => Some(16),
+        _ => None,
+    }
+}
+
+/// Whether the format is an sRGB one, that is, whether the
+/// hardware encodes colors written to it from linear to sRGB
+/// (and decodes them when sampling). Shaders writing to
+/// non-sRGB targets through storage images, which never get
+/// the automatic encoding, have to apply the gamma curve
+/// themselves.
+pub fn is_srgb(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::R8_SRGB
+            | vk::Format::R8G8_SRGB
+            | vk::Format::R8G8B8_SRGB
+            | vk::Format::B8G8R8_SRGB
+            | vk::Format::R8G8B8A8_SRGB
+            | vk::Format::B8G8R8A8_SRGB
+            | vk::Format::A8B8G8R8_SRGB_PACK32
+            | vk::Format::BC1_RGB_SRGB_BLOCK
+            | vk::Format::BC1_RGBA_SRGB_BLOCK
+            | vk::Format::BC2_SRGB_BLOCK
+            | vk::Format::BC3_SRGB_BLOCK
+            | vk::Format::BC7_SRGB_BLOCK
+    )
+}
+
+/// Size in bytes of a texel of an uncompressed format.
+pub(crate) fn texel_size(format: vk::Format) -> u64 {
+    match format {
+        vk::Format::R16_SFLOAT => 2,
+        _ => 4,
+    }
+}
+
+/// Size in bytes of one mip level of a 2D image of the given
+/// format. Only the formats the loaders produce are handled:
+/// BC formats, 32-bit RGBA/BGRA, and single-channel 16 and 32
+/// bit floats (height maps).
+pub fn level_size(format: vk::Format, width: u32, height: u32) -> u64 {
+    match block_size(format) {
+        // Compressed formats store the image in 4x4 blocks, so
+        // the size is rounded up to whole blocks, even for the
+        // 1x1 and 2x2 levels at the end of the mip chain.
+        Some(block) => {
+            let blocks_x = width.div_ceil(4).max(1) as u64;
+            let blocks_y = height.div_ceil(4).max(1) as u64;
+            blocks_x * blocks_y * block
+        }
+        None => width as u64 * height as u64 * texel_size(format),
+    }
+}
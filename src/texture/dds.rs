@@ -0,0 +1,460 @@
+use std::path::Path;
+
+use vulkanalia::prelude::v1_0::*;
+use thiserror::Error;
+use log::*;
+
+use super::level_size;
+use crate::assets::{AssetError, Assets};
+
+// A DDS file starts with the "DDS " magic number, followed by a
+// 124-byte header describing the image (size, mip count,
+// pixel format, capabilities), and optionally by a 20-byte
+// "DX10" header when the pixel format is given as a DXGI
+// format instead of a legacy FourCC code. The pixel data
+// follows, stored layer by layer (or cube face by cube face),
+// each layer holding its whole mip chain.
+const MAGIC: &[u8; 4] = b"DDS ";
+const HEADER_SIZE: usize = 124;
+const DX10_HEADER_SIZE: usize = 20;
+
+// Pixel format flags.
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+const FOURCC_DX10: u32 = u32::from_le_bytes(*b"DX10");
+
+// Capabilities, second set.
+const DDSCAPS2_CUBEMAP: u32 = 0x200;
+const DDSCAPS2_CUBEMAP_ALL_FACES: u32 = 0xFC00;
+const DDSCAPS2_VOLUME: u32 = 0x20_0000;
+
+// DX10 header misc flags.
+const DDS_RESOURCE_MISC_TEXTURECUBE: u32 = 0x4;
+const DDS_DIMENSION_TEXTURE2D: u32 = 3;
+
+/// Errors returned when loading a DDS file.
+#[derive(Error, Debug)]
+pub enum DdsError {
+    #[error("Not a DDS file.")]
+    InvalidMagic,
+    #[error("Invalid DDS header: {0}")]
+    InvalidHeader(&'static str),
+    #[error("Unsupported DDS pixel format: {0}")]
+    UnsupportedFormat(String),
+    #[error("Unsupported DDS texture: {0}")]
+    UnsupportedTexture(&'static str),
+    #[error("DDS pixel data is truncated: expected {expected} bytes, found {found}.")]
+    Truncated { expected: u64, found: u64 },
+}
+
+/// A DDS texture, ready to be uploaded: the pixel data is kept
+/// as in the file, with one copy region per mip level and array
+/// layer.
+pub struct DdsTexture {
+    /// Format of the pixel data.
+    pub format: vk::Format,
+    /// Extent of the first mip level.
+    pub extent: vk::Extent2D,
+    /// Number of mip levels.
+    pub mip_levels: u32,
+    /// Number of array layers; 6 per cube for cube maps.
+    pub array_layers: u32,
+    /// Whether the layers are the faces of a cube map (or of a
+    /// cube map array), in +X, -X, +Y, -Y, +Z, -Z order.
+    pub cube: bool,
+    /// Pixel data of every layer and mip level.
+    pub data: Vec<u8>,
+    /// Copy regions from `data` (once in a staging buffer) to
+    /// each subresource of the image.
+    pub regions: Vec<vk::BufferImageCopy>,
+}
+
+/// Load a DDS texture from the assets. See [`parse_dds`].
+pub fn load_dds(assets: &Assets, relative: impl AsRef<Path>) -> Result<DdsTexture, AssetError> {
+    let relative = relative.as_ref();
+    let (bytes, mount) = assets.read_from(relative)?;
+    let texture = parse_dds(&bytes).map_err(|source| AssetError::Dds {
+        path: relative.to_path_buf(),
+        mount: mount.to_path_buf(),
+        source,
+    })?;
+
+    info!(
+        "Loaded DDS texture {} from {} ({}x{}, {:?}, {} mips, {} layers).",
+        relative.display(),
+        mount.display(),
+        texture.extent.width,
+        texture.extent.height,
+        texture.format,
+        texture.mip_levels,
+        texture.array_layers,
+    );
+
+    Ok(texture)
+}
+
+/// Parse a DDS file in memory. Only 2D textures, texture arrays
+/// and cube maps are supported, with BC1 to BC7 compressed
+/// payloads or 32-bit RGBA/BGRA pixels; anything else is
+/// rejected with [`DdsError::UnsupportedFormat`].
+pub fn parse_dds(bytes: &[u8]) -> Result<DdsTexture, DdsError> {
+    if bytes.len() < 4 || &bytes[..4] != MAGIC {
+        return Err(DdsError::InvalidMagic);
+    }
+
+    let header = bytes.get(4..4 + HEADER_SIZE).ok_or(DdsError::InvalidHeader("file too short"))?;
+    let field = |offset: usize| u32::from_le_bytes(header[offset..offset + 4].try_into().unwrap());
+
+    if field(0) as usize != HEADER_SIZE {
+        return Err(DdsError::InvalidHeader("wrong header size"));
+    }
+
+    let height = field(8);
+    let width = field(12);
+    let mip_levels = field(24).max(1);
+    let pf_flags = field(76);
+    let four_cc = field(80);
+    let caps2 = field(108);
+
+    if width == 0 || height == 0 {
+        return Err(DdsError::InvalidHeader("zero-sized image"));
+    }
+
+    // A full mip chain goes down to 1x1, which is
+    // floor(log2(size)) + 1 levels.
+    if mip_levels > 32 - width.max(height).leading_zeros() {
+        return Err(DdsError::InvalidHeader("too many mip levels"));
+    }
+
+    if caps2 & DDSCAPS2_VOLUME != 0 {
+        return Err(DdsError::UnsupportedTexture("volume textures"));
+    }
+
+    // The pixel format is either given by a legacy FourCC code,
+    // by a DXGI format in the DX10 header, or, for uncompressed
+    // images, by the channel bit masks.
+    let mut data_offset = 4 + HEADER_SIZE;
+    let mut array_size = 1;
+    let mut cube = caps2 & DDSCAPS2_CUBEMAP != 0;
+    let dx10 = pf_flags & DDPF_FOURCC != 0 && four_cc == FOURCC_DX10;
+
+    let format = if pf_flags & DDPF_FOURCC != 0 {
+        if dx10 {
+            let header = bytes
+                .get(data_offset..data_offset + DX10_HEADER_SIZE)
+                .ok_or(DdsError::InvalidHeader("missing DX10 header"))?;
+            let dx10_field = |offset: usize| u32::from_le_bytes(header[offset..offset + 4].try_into().unwrap());
+            data_offset += DX10_HEADER_SIZE;
+
+            if dx10_field(4) != DDS_DIMENSION_TEXTURE2D {
+                return Err(DdsError::UnsupportedTexture("only 2D textures are supported"));
+            }
+
+            cube = dx10_field(8) & DDS_RESOURCE_MISC_TEXTURECUBE != 0;
+            array_size = dx10_field(12).max(1);
+            dxgi_format(dx10_field(0))?
+        } else {
+            four_cc_format(four_cc)?
+        }
+    } else if pf_flags & DDPF_RGB != 0 {
+        rgb_format(field(84), field(88), field(92), field(96), field(100))?
+    } else {
+        return Err(DdsError::UnsupportedFormat(format!("pixel format flags {:#x}", pf_flags)));
+    };
+
+    // Cube maps store their 6 faces as consecutive layers
+    // (legacy files may declare only some faces, which Vulkan
+    // can't represent, so we require all of them). In a DX10
+    // cube map array, the array size counts cubes, not faces.
+    if cube && !dx10 && caps2 & DDSCAPS2_CUBEMAP_ALL_FACES != DDSCAPS2_CUBEMAP_ALL_FACES {
+        return Err(DdsError::UnsupportedTexture("cube maps with missing faces"));
+    }
+    if cube && width != height {
+        return Err(DdsError::InvalidHeader("non-square cube map"));
+    }
+    let array_layers = if cube { array_size.checked_mul(6) } else { Some(array_size) }
+        .ok_or(DdsError::InvalidHeader("too many array layers"))?;
+
+    // Before going further, the file has to actually contain
+    // every layer's mip chain; checking this first also keeps
+    // a corrupted header from making us build millions of
+    // copy regions.
+    let level_extent = |level: u32| ((width >> level).max(1), (height >> level).max(1));
+    let chain_size = (0..mip_levels)
+        .map(|level| {
+            let (level_width, level_height) = level_extent(level);
+            level_size(format, level_width, level_height)
+        })
+        .sum::<u64>();
+    let size = chain_size.saturating_mul(array_layers as u64);
+
+    let data = &bytes[data_offset..];
+    if (data.len() as u64) < size {
+        return Err(DdsError::Truncated { expected: size, found: data.len() as u64 });
+    }
+
+    // Each layer holds its whole mip chain, so the copy regions
+    // are laid out layer-major; each one copies a single mip
+    // level of a single layer, tightly packed in the buffer
+    // (buffer row length and image height of 0).
+    let aspect = vk::ImageAspectFlags::COLOR;
+    let mut regions = Vec::with_capacity((array_layers * mip_levels) as usize);
+    let mut offset = 0;
+
+    for layer in 0..array_layers {
+        for level in 0..mip_levels {
+            let (level_width, level_height) = level_extent(level);
+
+            let subresource = vk::ImageSubresourceLayers::builder()
+                .aspect_mask(aspect)
+                .mip_level(level)
+                .base_array_layer(layer)
+                .layer_count(1);
+
+            let region = vk::BufferImageCopy::builder()
+                .buffer_offset(offset)
+                .buffer_row_length(0)
+                .buffer_image_height(0)
+                .image_subresource(subresource)
+                .image_offset(vk::Offset3D { x: 0, y: 0, z: 0 })
+                .image_extent(vk::Extent3D { width: level_width, height: level_height, depth: 1 })
+                .build();
+
+            regions.push(region);
+            offset += level_size(format, level_width, level_height);
+        }
+    }
+
+    Ok(DdsTexture {
+        format,
+        extent: vk::Extent2D { width, height },
+        mip_levels,
+        array_layers,
+        cube,
+        data: data[..size as usize].to_vec(),
+        regions,
+    })
+}
+
+fn four_cc_format(four_cc: u32) -> Result<vk::Format, DdsError> {
+    // Legacy FourCC codes carry no color space information;
+    // they are conventionally treated as linear.
+    match &four_cc.to_le_bytes() {
+        b"DXT1" => Ok(vk::Format::BC1_RGBA_UNORM_BLOCK),
+        b"DXT2" | b"DXT3" => Ok(vk::Format::BC2_UNORM_BLOCK),
+        b"DXT4" | b"DXT5" => Ok(vk::Format::BC3_UNORM_BLOCK),
+        b"ATI1" | b"BC4U" => Ok(vk::Format::BC4_UNORM_BLOCK),
+        b"BC4S" => Ok(vk::Format::BC4_SNORM_BLOCK),
+        b"ATI2" | b"BC5U" => Ok(vk::Format::BC5_UNORM_BLOCK),
+        b"BC5S" => Ok(vk::Format::BC5_SNORM_BLOCK),
+        code => Err(DdsError::UnsupportedFormat(format!(
+            "FourCC '{}'",
+            String::from_utf8_lossy(code)
+        ))),
+    }
+}
+
+fn dxgi_format(format: u32) -> Result<vk::Format, DdsError> {
+    match format {
+        // Single-channel floats, for height maps and other
+        // data textures.
+        41 => Ok(vk::Format::R32_SFLOAT),
+        54 => Ok(vk::Format::R16_SFLOAT),
+        28 => Ok(vk::Format::R8G8B8A8_UNORM),
+        29 => Ok(vk::Format::R8G8B8A8_SRGB),
+        71 => Ok(vk::Format::BC1_RGBA_UNORM_BLOCK),
+        72 => Ok(vk::Format::BC1_RGBA_SRGB_BLOCK),
+        74 => Ok(vk::Format::BC2_UNORM_BLOCK),
+        75 => Ok(vk::Format::BC2_SRGB_BLOCK),
+        77 => Ok(vk::Format::BC3_UNORM_BLOCK),
+        78 => Ok(vk::Format::BC3_SRGB_BLOCK),
+        80 => Ok(vk::Format::BC4_UNORM_BLOCK),
+        81 => Ok(vk::Format::BC4_SNORM_BLOCK),
+        83 => Ok(vk::Format::BC5_UNORM_BLOCK),
+        84 => Ok(vk::Format::BC5_SNORM_BLOCK),
+        87 => Ok(vk::Format::B8G8R8A8_UNORM),
+        91 => Ok(vk::Format::B8G8R8A8_SRGB),
+        95 => Ok(vk::Format::BC6H_UFLOAT_BLOCK),
+        96 => Ok(vk::Format::BC6H_SFLOAT_BLOCK),
+        98 => Ok(vk::Format::BC7_UNORM_BLOCK),
+        99 => Ok(vk::Format::BC7_SRGB_BLOCK),
+        _ => Err(DdsError::UnsupportedFormat(format!("DXGI format {}", format))),
+    }
+}
+
+fn rgb_format(bit_count: u32, r: u32, g: u32, b: u32, a: u32) -> Result<vk::Format, DdsError> {
+    match (bit_count, r, g, b, a) {
+        (32, 0xFF, 0xFF00, 0xFF_0000, 0xFF00_0000) => Ok(vk::Format::R8G8B8A8_UNORM),
+        (32, 0xFF_0000, 0xFF00, 0xFF, 0xFF00_0000) => Ok(vk::Format::B8G8R8A8_UNORM),
+        _ => Err(DdsError::UnsupportedFormat(format!(
+            "{}-bit RGB with masks {:#x}/{:#x}/{:#x}/{:#x}",
+            bit_count, r, g, b, a
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Header of a DDS file (with the magic number) for a
+    /// `width`x`height` image of `mip_levels` levels, with the
+    /// given pixel format flags and FourCC code.
+    fn header(width: u32, height: u32, mip_levels: u32, pf_flags: u32, four_cc: &[u8; 4], caps2: u32) -> Vec<u8> {
+        let mut header = [0u32; HEADER_SIZE / 4];
+        header[0] = HEADER_SIZE as u32;
+        header[2] = height;
+        header[3] = width;
+        header[6] = mip_levels;
+        header[18] = 32;
+        header[19] = pf_flags;
+        header[20] = u32::from_le_bytes(*four_cc);
+        header[27] = caps2;
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(header.iter().flat_map(|field| field.to_le_bytes()));
+        bytes
+    }
+
+    /// DX10 header for a 2D texture of `array_size` layers (or
+    /// cubes) in the DXGI format `format`.
+    fn dx10_header(format: u32, misc_flags: u32, array_size: u32) -> Vec<u8> {
+        [format, DDS_DIMENSION_TEXTURE2D, misc_flags, array_size, 0]
+            .iter()
+            .flat_map(|field| field.to_le_bytes())
+            .collect()
+    }
+
+    /// Recognizable pixel data of `size` bytes.
+    fn payload(size: usize) -> Vec<u8> {
+        (0..size).map(|i| i as u8).collect()
+    }
+
+    #[test]
+    fn bc1_mip_chain_round_trips() {
+        // 8x8 BC1 with a full mip chain: 2x2 blocks, then a
+        // single 8-byte block for every smaller level.
+        let pixels = payload(32 + 8 + 8 + 8);
+        let mut bytes = header(8, 8, 4, DDPF_FOURCC, b"DXT1", 0);
+        bytes.extend(&pixels);
+
+        let texture = parse_dds(&bytes).unwrap();
+        assert_eq!(texture.format, vk::Format::BC1_RGBA_UNORM_BLOCK);
+        assert_eq!((texture.extent.width, texture.extent.height), (8, 8));
+        assert_eq!((texture.mip_levels, texture.array_layers, texture.cube), (4, 1, false));
+        assert_eq!(texture.data, pixels);
+
+        let offsets = texture.regions.iter().map(|r| r.buffer_offset).collect::<Vec<_>>();
+        assert_eq!(offsets, [0, 32, 40, 48]);
+        let extents = texture.regions
+            .iter()
+            .map(|r| (r.image_subresource.mip_level, r.image_extent.width, r.image_extent.height))
+            .collect::<Vec<_>>();
+        assert_eq!(extents, [(0, 8, 8), (1, 4, 4), (2, 2, 2), (3, 1, 1)]);
+    }
+
+    #[test]
+    fn bc3_round_trips_without_trailing_bytes() {
+        // A mip count of 0 means a single level; 16x16 BC3 is
+        // 4x4 blocks of 16 bytes. Bytes past the payload are
+        // not part of the texture.
+        let pixels = payload(4 * 4 * 16);
+        let mut bytes = header(16, 16, 0, DDPF_FOURCC, b"DXT5", 0);
+        bytes.extend(&pixels);
+        bytes.extend([0xAA; 7]);
+
+        let texture = parse_dds(&bytes).unwrap();
+        assert_eq!(texture.format, vk::Format::BC3_UNORM_BLOCK);
+        assert_eq!(texture.mip_levels, 1);
+        assert_eq!(texture.data, pixels);
+        assert_eq!(texture.regions.len(), 1);
+    }
+
+    #[test]
+    fn dx10_header_gives_format_and_layers() {
+        // Two layers of a 4x4 BC7 texture, a block each.
+        let mut bytes = header(4, 4, 1, DDPF_FOURCC, b"DX10", 0);
+        bytes.extend(dx10_header(98, 0, 2));
+        bytes.extend(payload(2 * 16));
+
+        let texture = parse_dds(&bytes).unwrap();
+        assert_eq!(texture.format, vk::Format::BC7_UNORM_BLOCK);
+        assert_eq!((texture.array_layers, texture.cube), (2, false));
+        let layers = texture.regions
+            .iter()
+            .map(|r| (r.image_subresource.base_array_layer, r.buffer_offset))
+            .collect::<Vec<_>>();
+        assert_eq!(layers, [(0, 0), (1, 16)]);
+    }
+
+    #[test]
+    fn dx10_cube_map_counts_faces() {
+        let mut bytes = header(4, 4, 1, DDPF_FOURCC, b"DX10", 0);
+        bytes.extend(dx10_header(71, DDS_RESOURCE_MISC_TEXTURECUBE, 1));
+        bytes.extend(payload(6 * 8));
+
+        let texture = parse_dds(&bytes).unwrap();
+        assert_eq!(texture.format, vk::Format::BC1_RGBA_UNORM_BLOCK);
+        assert_eq!((texture.array_layers, texture.cube), (6, true));
+    }
+
+    #[test]
+    fn dx10_float_formats_have_texel_sizes() {
+        // 4x2 single-channel floats with two levels, the second
+        // one 2x1: 4 bytes per texel for R32, 2 for R16.
+        for (dxgi, format, texel) in [(41, vk::Format::R32_SFLOAT, 4), (54, vk::Format::R16_SFLOAT, 2)] {
+            let pixels = payload((8 + 2) * texel);
+            let mut bytes = header(4, 2, 2, DDPF_FOURCC, b"DX10", 0);
+            bytes.extend(dx10_header(dxgi, 0, 1));
+            bytes.extend(&pixels);
+
+            let texture = parse_dds(&bytes).unwrap();
+            assert_eq!(texture.format, format);
+            assert_eq!((texture.extent.width, texture.extent.height), (4, 2));
+            assert_eq!(texture.data, pixels);
+
+            let offsets = texture.regions.iter().map(|r| r.buffer_offset).collect::<Vec<_>>();
+            assert_eq!(offsets, [0, 8 * texel as u64]);
+
+            // One byte short of the chain.
+            assert!(matches!(
+                parse_dds(&bytes[..bytes.len() - 1]),
+                Err(DdsError::Truncated { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn truncated_files_are_rejected() {
+        assert!(matches!(parse_dds(b"DDS"), Err(DdsError::InvalidMagic)));
+        assert!(matches!(parse_dds(b"PNG \0\0\0\0"), Err(DdsError::InvalidMagic)));
+
+        // Header cut short.
+        let bytes = header(8, 8, 1, DDPF_FOURCC, b"DXT1", 0);
+        assert!(matches!(parse_dds(&bytes[..64]), Err(DdsError::InvalidHeader(_))));
+
+        // DX10 header missing.
+        let bytes = header(4, 4, 1, DDPF_FOURCC, b"DX10", 0);
+        assert!(matches!(parse_dds(&bytes), Err(DdsError::InvalidHeader(_))));
+
+        // Payload one byte short of the 2x2 blocks of level 0.
+        let mut bytes = header(8, 8, 1, DDPF_FOURCC, b"DXT1", 0);
+        bytes.extend(payload(31));
+        assert!(matches!(
+            parse_dds(&bytes),
+            Err(DdsError::Truncated { expected: 32, found: 31 })
+        ));
+    }
+
+    #[test]
+    fn unsupported_four_cc_is_rejected() {
+        let mut bytes = header(4, 4, 1, DDPF_FOURCC, b"ETC1", 0);
+        bytes.extend(payload(8));
+
+        match parse_dds(&bytes) {
+            Err(DdsError::UnsupportedFormat(format)) => assert_eq!(format, "FourCC 'ETC1'"),
+            Err(error) => panic!("unexpected error: {}", error),
+            Ok(_) => panic!("ETC1 was accepted"),
+        }
+    }
+}
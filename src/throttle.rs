@@ -0,0 +1,150 @@
+use std::time::{Duration, Instant};
+
+/// Interval between frames while the window is occluded, so
+/// that animations keep roughly advancing without burning
+/// power on frames nobody sees.
+pub const OCCLUDED_FRAME_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Whether, and how often, the application should present.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PresentationState {
+    /// The window is visible: render at full rate.
+    Active,
+    /// The window is occluded (fully covered, or on another
+    /// desktop): render at a low rate.
+    Occluded,
+    /// The window is minimised: don't render at all.
+    Minimised,
+}
+
+/// Single source of truth for "should we render right now",
+/// combining the window states that make rendering pointless.
+#[derive(Debug)]
+pub struct PresentationThrottle {
+    minimised: bool,
+    occluded: bool,
+    /// Time the last frame was rendered at.
+    last_frame: Option<Instant>,
+}
+
+impl Default for PresentationThrottle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PresentationThrottle {
+    pub fn new() -> Self {
+        Self {
+            minimised: false,
+            occluded: false,
+            last_frame: None,
+        }
+    }
+
+    pub fn set_minimised(&mut self, minimised: bool) {
+        self.minimised = minimised;
+    }
+
+    pub fn set_occluded(&mut self, occluded: bool) {
+        self.occluded = occluded;
+    }
+
+    pub fn state(&self) -> PresentationState {
+        // Minimisation wins over occlusion: a minimised window
+        // is occluded too, but there is no surface to present
+        // to at all.
+        if self.minimised {
+            PresentationState::Minimised
+        } else if self.occluded {
+            PresentationState::Occluded
+        } else {
+            PresentationState::Active
+        }
+    }
+
+    /// Whether a frame should be rendered at time `now`.
+    pub fn should_render(&self, now: Instant) -> bool {
+        match self.state() {
+            PresentationState::Active => true,
+            PresentationState::Minimised => false,
+            PresentationState::Occluded => match self.last_frame {
+                Some(last) => now.duration_since(last) >= OCCLUDED_FRAME_INTERVAL,
+                None => true,
+            },
+        }
+    }
+
+    /// Register that a frame was rendered at time `now`.
+    pub fn frame_rendered(&mut self, now: Instant) {
+        self.last_frame = Some(now);
+    }
+
+    /// When the event loop should wake up to render the next
+    /// throttled frame, if rendering is currently throttled.
+    pub fn next_wake(&self, now: Instant) -> Option<Instant> {
+        match self.state() {
+            PresentationState::Occluded => Some(self.last_frame
+                .map_or(now, |last| last + OCCLUDED_FRAME_INTERVAL)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn state_follows_window_events() {
+        let mut throttle = PresentationThrottle::new();
+        assert_eq!(throttle.state(), PresentationState::Active);
+
+        throttle.set_occluded(true);
+        assert_eq!(throttle.state(), PresentationState::Occluded);
+
+        // Minimising also occludes the window on most
+        // platforms; minimisation takes precedence.
+        throttle.set_minimised(true);
+        assert_eq!(throttle.state(), PresentationState::Minimised);
+
+        throttle.set_minimised(false);
+        assert_eq!(throttle.state(), PresentationState::Occluded);
+
+        throttle.set_occluded(false);
+        assert_eq!(throttle.state(), PresentationState::Active);
+    }
+
+    #[test]
+    fn occluded_frames_are_throttled() {
+        let start = Instant::now();
+        let mut throttle = PresentationThrottle::new();
+        throttle.set_occluded(true);
+
+        // Nothing rendered yet: render right away.
+        assert!(throttle.should_render(start));
+        assert_eq!(throttle.next_wake(start), Some(start));
+
+        throttle.frame_rendered(start);
+        let soon = start + OCCLUDED_FRAME_INTERVAL / 2;
+        assert!(!throttle.should_render(soon));
+        assert_eq!(throttle.next_wake(soon), Some(start + OCCLUDED_FRAME_INTERVAL));
+        assert!(throttle.should_render(start + OCCLUDED_FRAME_INTERVAL));
+    }
+
+    #[test]
+    fn only_occlusion_schedules_wakes() {
+        let now = Instant::now();
+        let mut throttle = PresentationThrottle::new();
+        throttle.frame_rendered(now);
+        assert!(throttle.should_render(now));
+        assert_eq!(throttle.next_wake(now), None);
+
+        throttle.set_minimised(true);
+        assert!(!throttle.should_render(now + OCCLUDED_FRAME_INTERVAL));
+        assert_eq!(throttle.next_wake(now), None);
+
+        throttle.set_minimised(false);
+        assert!(throttle.should_render(now));
+    }
+}
@@ -0,0 +1,133 @@
+use std::mem::size_of;
+
+use glam::{Mat4, Vec4};
+use vulkanalia::prelude::v1_0::*;
+
+use crate::core::devices::{DeviceLimits, DynamicBinding};
+
+// A frame can be split into several views, each with its own
+// camera and rectangle of the swapchain image: two players
+// side by side, or the viewports of an editor. All views are
+// rendered within the frame's single rendering scope, one after
+// the other, with the viewport and scissor set to the view's
+// rectangle, so that nothing drawn for a view spills over the
+// others. Each view has its own frame constants (camera
+// matrices, viewport), which applications store one after the
+// other in a uniform buffer and bind with a dynamic offset per
+// view, rather than updating a single block between views.
+
+/// Maximum number of views in a frame.
+pub const MAX_VIEWS: usize = 8;
+
+/// A camera rendering to a rectangle of the swapchain image.
+#[derive(Clone, Copy, Debug)]
+pub struct View {
+    /// Rectangle of the swapchain image the view renders to,
+    /// in pixels.
+    pub rect: vk::Rect2D,
+    /// Transform from world space to the camera's space.
+    pub view: Mat4,
+    /// Vertical field of view, in radians.
+    pub fov_y: f32,
+    /// Distances of the near and far clipping planes.
+    pub near: f32,
+    pub far: f32,
+}
+
+impl View {
+    /// View of `rect` through the camera `view`, with a 60°
+    /// field of view.
+    pub fn new(rect: vk::Rect2D, view: Mat4) -> Self {
+        Self {
+            rect,
+            view,
+            fov_y: 60f32.to_radians(),
+            near: 0.1,
+            far: 100.0,
+        }
+    }
+
+    /// Aspect ratio of the view's rectangle, which the
+    /// projection uses rather than that of the whole window:
+    /// each half of a window split in two is twice as tall, in
+    /// proportion, as the window.
+    pub fn aspect(&self) -> f32 {
+        self.rect.extent.width.max(1) as f32 / self.rect.extent.height.max(1) as f32
+    }
+
+    /// Projection of the view, for Vulkan's [0, 1] depth range
+    /// and the flipped viewport the renderer sets.
+    pub fn projection(&self) -> Mat4 {
+        Mat4::perspective_rh(self.fov_y, self.aspect(), self.near, self.far)
+    }
+
+    /// Frame constants of the view, as laid out in uniform
+    /// buffers.
+    pub fn constants(&self) -> ViewConstants {
+        let projection = self.projection();
+        let rect = self.rect;
+
+        ViewConstants {
+            view: self.view,
+            projection,
+            view_projection: projection * self.view,
+            viewport: Vec4::new(
+                rect.offset.x as f32,
+                rect.offset.y as f32,
+                rect.extent.width as f32,
+                rect.extent.height as f32,
+            ),
+        }
+    }
+}
+
+/// Frame constants of a view, as laid out in uniform buffers
+/// (std140).
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ViewConstants {
+    pub view: Mat4,
+    pub projection: Mat4,
+    pub view_projection: Mat4,
+    /// Rectangle of the view, as (x, y, width, height) in
+    /// pixels.
+    pub viewport: Vec4,
+}
+
+/// Dynamic offset of the constants of view `index`, in a
+/// uniform buffer holding the constants of every view one
+/// after the other.
+pub fn view_constants_offset(limits: &DeviceLimits, index: usize) -> u64 {
+    limits.dynamic_stride(size_of::<ViewConstants>() as u64, DynamicBinding::Uniform) * index as u64
+}
+
+/// Split an image of the given `extent` into `count` views of
+/// the same size (as close as the pixels allow), in a grid as
+/// square as possible, filled row by row: two views side by
+/// side, four in quadrants, etc.
+pub fn split_screen(extent: vk::Extent2D, count: usize) -> Vec<vk::Rect2D> {
+    if count == 0 {
+        return Vec::new();
+    }
+
+    let columns = (count as f32).sqrt().ceil() as u32;
+    let rows = (count as u32).div_ceil(columns);
+
+    // Edges are computed for each cell rather than adding up
+    // cell sizes, so that the rounding never leaves a gap or
+    // an overlap between views.
+    let edge = |size: u32, i: u32, n: u32| (size as u64 * i as u64 / n as u64) as u32;
+
+    (0..count as u32)
+        .map(|i| {
+            let (column, row) = (i % columns, i / columns);
+            let (x0, x1) = (edge(extent.width, column, columns), edge(extent.width, column + 1, columns));
+            let (y0, y1) = (edge(extent.height, row, rows), edge(extent.height, row + 1, rows));
+
+            vk::Rect2D {
+                offset: vk::Offset2D { x: x0 as i32, y: y0 as i32 },
+                extent: vk::Extent2D { width: x1 - x0, height: y1 - y0 },
+            }
+        })
+        .collect()
+}
@@ -1,21 +1,61 @@
-use crate::app::App;
+mod geometry;
+
+use std::time::Instant;
+
+use crate::{
+    app::{App, WindowMode, MAX_TIMEOUT_RETRIES},
+    renderer::RenderError,
+    stats::LatencyMode,
+    throttle::PresentationState,
+};
 use winit::{
     application::ApplicationHandler, 
     dpi::LogicalSize, 
-    event::WindowEvent, 
-    event_loop::ActiveEventLoop,
+    event::{ElementState, WindowEvent},
+    event_loop::{ActiveEventLoop, ControlFlow},
+    keyboard::{KeyCode, PhysicalKey},
     window::Window
 };
+use geometry::{default_size, WindowGeometry};
+use log::*;
 
 impl ApplicationHandler for App {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
         if self.window.is_none() {
-            let window_attr = Window::default_attributes()
-                .with_title("caliban")
-                .with_inner_size(LogicalSize::new(1024, 576));
+            // The window is placed where it was last time if
+            // that was saved, with the configured size, or
+            // sized after the monitor otherwise.
+            let saved = self.config.geometry_file
+                .as_deref()
+                .and_then(WindowGeometry::load)
+                .map(|geometry| geometry.fit(event_loop));
+
+            let mut window_attr = Window::default_attributes()
+                .with_title(self.config.title.clone());
+
+            window_attr = match (saved, self.config.size) {
+                (Some(geometry), _) => {
+                    let attr = window_attr
+                        .with_inner_size(geometry.size)
+                        .with_maximized(geometry.maximized);
+                    match geometry.position {
+                        Some(position) => attr.with_position(position),
+                        None => attr,
+                    }
+                },
+                (None, Some((width, height))) => window_attr.with_inner_size(LogicalSize::new(width, height)),
+                (None, None) => window_attr.with_inner_size(default_size(event_loop)),
+            };
 
             let window = event_loop.create_window(window_attr).unwrap();
-            self.init(window).unwrap();
+            if let Err(error) = self.guard(|app| app.init(window)) {
+                self.fail(event_loop, error.context("Failed to initialize the renderer"));
+                return;
+            }
+
+            if self.config.window_mode != WindowMode::Windowed {
+                self.set_window_mode(self.config.window_mode);
+            }
         }
     }
 
@@ -25,8 +65,24 @@ impl ApplicationHandler for App {
             _: winit::window::WindowId,
             event: WindowEvent,
         ) {
+        // Every call into the application is guarded, so that
+        // if it panics, the renderer is destroyed in order
+        // before the panic carries on unwinding.
+        self.guard(|app| app.hooks.event(&event));
+
         match event {
             WindowEvent::CloseRequested => {
+                // The window placement is saved while the
+                // window still exists, unless it is fullscreen:
+                // the placement is then that of the monitor,
+                // and the last windowed one is kept.
+                let windowed = self.window_mode == WindowMode::Windowed;
+                if let (Some(path), Some(window), true) = (&self.config.geometry_file, &self.window, windowed) {
+                    if let Err(e) = WindowGeometry::of(window).save(path) {
+                        warn!("Failed to save the window geometry to {}: {}", path.display(), e);
+                    }
+                }
+
                 // Render operations are asynchronous, which
                 // means that we may call the destroy function
                 // before drawing and presentation are
@@ -41,21 +97,185 @@ impl ApplicationHandler for App {
                 // Close the window
                 event_loop.exit();
             },
-            WindowEvent::Resized(size) => {
-                if size.width == 0 || size.height == 0 {
-                    // If resizing has put either the width or
-                    // the height to 0, the window has been
-                    // minimized.
-                    self.minimised = true;
-                } else {
-                    self.minimised = false;
-                    self.resized = true;
+            WindowEvent::Resized(_) => {
+                // The swapchain is recreated on the next frame;
+                // whether the new size can be presented to
+                // (it can't if the window was minimised to a
+                // zero size) is decided by the renderer.
+                self.resized = true;
+                self.sync_window_mode();
+                self.window.as_ref().unwrap().request_redraw();
+            },
+            WindowEvent::KeyboardInput { event, .. } => {
+                if event.state == ElementState::Pressed
+                    && !event.repeat
+                    && event.physical_key == PhysicalKey::Code(KeyCode::F11)
+                {
+                    self.set_window_mode(self.window_mode.toggled());
                 }
             },
+            WindowEvent::Occluded(occluded) => {
+                // On some platforms, the window is reported as
+                // occluded when it is completely covered or on
+                // another desktop; rendering is then throttled
+                // until it becomes visible again.
+                self.presentation.set_occluded(occluded);
+                if !occluded {
+                    self.window.as_ref().unwrap().request_redraw();
+                }
+            },
+            WindowEvent::Focused(true) => {
+                // Getting the focus means the window is
+                // visible, even if the platform didn't tell us
+                // it stopped being occluded.
+                self.presentation.set_occluded(false);
+            },
             WindowEvent::RedrawRequested => {
-                unsafe { self.renderer.as_mut().unwrap().render().unwrap() };
+                // Once the application has failed, the event
+                // loop is on its way out, but may still deliver
+                // a few events.
+                if self.renderer.is_none() {
+                    return;
+                }
+
+                // A change of the swapchain settings is applied
+                // like a resize.
+                if self.renderer.as_ref().is_some_and(|r| r.swapchain_outdated()) {
+                    self.resized = true;
+                }
+
+                if self.resized {
+                    if let Err(error) = self.recreate_swapchain() {
+                        self.fail(event_loop, error.into());
+                        return;
+                    }
+
+                    // If recreation failed and is to be retried
+                    // later, there is no swapchain to render to.
+                    if self.resized {
+                        return;
+                    }
+                }
+
+                let now = Instant::now();
+                if !self.presentation.should_render(now) {
+                    return;
+                }
+
+                self.presentation.frame_rendered(now);
+
+                // In low-latency mode, we wait for the previous
+                // frame to be displayed before reading the input
+                // and updating the application for this one;
+                // depending on the latency mode, the wait for
+                // the frame in flight happens here too, rather
+                // than when rendering.
+                let renderer = self.renderer.as_mut().unwrap();
+                renderer.pace_frame();
+                if renderer.latency_mode() == LatencyMode::WaitBeforeInput {
+                    renderer.wait_for_frame();
+                }
+                renderer.input_sampled();
+                if let Err(error) = self.guard(|app| app.update()) {
+                    self.fail(event_loop, error.context("Startup failed"));
+                    return;
+                }
+
+                // A skipped frame leaves the renderer in a
+                // consistent state, so we just ask for another
+                // one; timeouts are retried a bounded number of
+                // times, and anything else is fatal. Rendering
+                // runs the passes of the application, hence the
+                // guard.
+                let result = self.guard(|app| unsafe { app.renderer.as_mut().unwrap().render(app.time) });
+                match result {
+                    Ok(()) => self.timeouts = 0,
+                    Err(RenderError::OutOfDate) => {
+                        self.resized = true;
+                        self.window.as_ref().unwrap().request_redraw();
+                    },
+                    Err(RenderError::SurfaceLost) => {
+                        if let Err(error) = self.recreate_surface() {
+                            self.fail(event_loop, error.into());
+                            return;
+                        }
+                        self.window.as_ref().unwrap().request_redraw();
+                    },
+                    Err(RenderError::FrameSkipped(_)) => {
+                        self.window.as_ref().unwrap().request_redraw();
+                    },
+                    Err(RenderError::Timeout(_)) if self.timeouts < MAX_TIMEOUT_RETRIES => {
+                        self.timeouts += 1;
+                        self.window.as_ref().unwrap().request_redraw();
+                    },
+                    Err(error) => {
+                        self.fail(event_loop, anyhow::Error::from(error).context("Failed to render frame"));
+                        return;
+                    },
+                }
+
+                self.update_title();
+
+                // Whatever happened to the frame, this is where
+                // it ends for the profiler.
+                profiling::finish_frame!();
             },
             _ => (),
         }
     }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        // While rendering is throttled, the OS won't ask for
+        // redraws, so the event loop wakes itself up at the
+        // throttled rate to render the next frame.
+        let now = Instant::now();
+
+        // The loading screen is redrawn continuously, for the
+        // startup tasks to progress, and the swapchain is
+        // recreated as soon as its settings changed.
+        let outdated = self.renderer.as_ref().is_some_and(|r| r.swapchain_outdated());
+        if !self.startup.is_done() || outdated {
+            if let Some(window) = &self.window {
+                window.request_redraw();
+            }
+        }
+
+        // A failed swapchain recreation is retried at a set
+        // time, and nothing is rendered until then.
+        if let Some(retry_at) = self.recreate_retry_at {
+            if retry_at <= now {
+                if let Some(window) = &self.window {
+                    window.request_redraw();
+                }
+            } else {
+                event_loop.set_control_flow(ControlFlow::WaitUntil(retry_at));
+                return;
+            }
+        }
+
+        match self.presentation.next_wake(now) {
+            Some(wake) => {
+                if wake <= now {
+                    if let Some(window) = &self.window {
+                        window.request_redraw();
+                    }
+                }
+
+                event_loop.set_control_flow(ControlFlow::WaitUntil(wake.max(now)));
+            },
+            // In continuous mode, a new frame is requested as
+            // soon as the previous one is done, as long as the
+            // window is visible; event-driven applications wait
+            // for the next event instead.
+            None if self.config.continuous_redraw
+                && self.presentation.state() == PresentationState::Active => {
+                if let Some(window) = &self.window {
+                    window.request_redraw();
+                }
+
+                event_loop.set_control_flow(ControlFlow::Poll);
+            },
+            None => event_loop.set_control_flow(ControlFlow::Wait),
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,204 @@
+use std::{
+    fs,
+    io,
+    path::Path,
+};
+
+use winit::{
+    dpi::{LogicalSize, PhysicalPosition, PhysicalSize, Size},
+    event_loop::ActiveEventLoop,
+    monitor::MonitorHandle,
+    window::Window,
+};
+use log::*;
+
+/// Fraction of the monitor covered by the default window, on
+/// each axis.
+const DEFAULT_MONITOR_FRACTION: f64 = 0.6;
+
+/// Size of the default window when there is no monitor to
+/// size it from.
+const FALLBACK_SIZE: LogicalSize<u32> = LogicalSize::new(1024, 576);
+
+/// Part of a restored window that has to be on a monitor, on
+/// each axis, for its position to be kept.
+const MIN_VISIBLE: i32 = 64;
+
+/// Default size of the window: a fraction of the primary
+/// monitor, or of the first one on platforms that don't have a
+/// notion of primary monitor (Wayland).
+pub fn default_size(event_loop: &ActiveEventLoop) -> Size {
+    let monitor = event_loop
+        .primary_monitor()
+        .or_else(|| event_loop.available_monitors().next());
+
+    match monitor {
+        Some(monitor) => {
+            let size = monitor.size();
+            PhysicalSize::new(
+                (size.width as f64 * DEFAULT_MONITOR_FRACTION) as u32,
+                (size.height as f64 * DEFAULT_MONITOR_FRACTION) as u32,
+            ).into()
+        },
+        None => FALLBACK_SIZE.into(),
+    }
+}
+
+/// Placement of the window, saved on exit and restored at the
+/// next start.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WindowGeometry {
+    /// Position of the window, if the platform lets us know
+    /// and set it (Wayland doesn't).
+    pub position: Option<PhysicalPosition<i32>>,
+    pub size: PhysicalSize<u32>,
+    pub maximized: bool,
+    /// Name of the monitor the window was on.
+    pub monitor: Option<String>,
+    /// Scale factor of that monitor, to keep the same logical
+    /// size if it changed.
+    pub scale_factor: f64,
+}
+
+impl WindowGeometry {
+    pub fn of(window: &Window) -> Self {
+        Self {
+            position: window.outer_position().ok(),
+            size: window.inner_size(),
+            maximized: window.is_maximized(),
+            monitor: window.current_monitor().and_then(|monitor| monitor.name()),
+            scale_factor: window.scale_factor(),
+        }
+    }
+
+    /// Load the geometry saved at `path`, if any. A missing or
+    /// invalid file is not an error, the window just gets its
+    /// default placement.
+    pub fn load(path: &Path) -> Option<Self> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+            Err(e) => {
+                warn!("Failed to read the window geometry from {}: {}", path.display(), e);
+                return None;
+            },
+        };
+
+        let geometry = Self::parse(&text);
+        if geometry.is_none() {
+            warn!("Ignoring invalid window geometry in {}.", path.display());
+        }
+
+        geometry
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut text = String::new();
+        if let Some(position) = self.position {
+            text += &format!("x = {}\ny = {}\n", position.x, position.y);
+        }
+        text += &format!("width = {}\nheight = {}\n", self.size.width, self.size.height);
+        text += &format!("maximized = {}\n", self.maximized);
+        if let Some(monitor) = &self.monitor {
+            text += &format!("monitor = {}\n", monitor);
+        }
+        text += &format!("scale_factor = {}\n", self.scale_factor);
+
+        fs::write(path, text)
+    }
+
+    fn parse(text: &str) -> Option<Self> {
+        // The file is a list of "key = value" lines, so that it
+        // can be edited by hand.
+        let value = |key: &str| {
+            text.lines()
+                .filter_map(|line| line.split_once('='))
+                .find(|(k, _)| k.trim() == key)
+                .map(|(_, v)| v.trim())
+        };
+
+        let position = match (value("x"), value("y")) {
+            (Some(x), Some(y)) => Some(PhysicalPosition::new(x.parse().ok()?, y.parse().ok()?)),
+            _ => None,
+        };
+
+        let size = PhysicalSize::new(value("width")?.parse().ok()?, value("height")?.parse().ok()?);
+        if size.width == 0 || size.height == 0 {
+            return None;
+        }
+
+        Some(Self {
+            position,
+            size,
+            maximized: value("maximized").is_some_and(|v| v == "true"),
+            monitor: value("monitor").map(str::to_string),
+            scale_factor: value("scale_factor").and_then(|v| v.parse().ok()).unwrap_or(1.0),
+        })
+    }
+
+    /// Adapt the geometry to the monitors currently connected:
+    /// the size follows the scale factor of the monitor and
+    /// fits on it, and the position is dropped (leaving the
+    /// placement to the system) if the window would end up
+    /// mostly off-screen.
+    pub fn fit(mut self, event_loop: &ActiveEventLoop) -> Self {
+        let monitors = event_loop.available_monitors().collect::<Vec<_>>();
+
+        let saved_monitor = self.monitor
+            .as_ref()
+            .and_then(|name| monitors.iter().find(|m| m.name().as_ref() == Some(name)));
+        if self.monitor.is_some() && saved_monitor.is_none() {
+            info!("The monitor the window was on is gone, using the default placement.");
+            self.position = None;
+        }
+
+        let Some(monitor) = saved_monitor
+            .cloned()
+            .or_else(|| event_loop.primary_monitor())
+            .or_else(|| monitors.first().cloned())
+        else {
+            return self;
+        };
+
+        // The same logical size on a monitor with a different
+        // scale factor takes a different number of pixels.
+        let scale_factor = monitor.scale_factor();
+        if scale_factor > 0.0 && self.scale_factor > 0.0 && scale_factor != self.scale_factor {
+            let ratio = scale_factor / self.scale_factor;
+            self.size = PhysicalSize::new(
+                (self.size.width as f64 * ratio) as u32,
+                (self.size.height as f64 * ratio) as u32,
+            );
+            self.scale_factor = scale_factor;
+        }
+
+        let bounds = monitor.size();
+        self.size.width = self.size.width.clamp(1, bounds.width.max(1));
+        self.size.height = self.size.height.clamp(1, bounds.height.max(1));
+
+        if let Some(position) = self.position {
+            if !monitors.iter().any(|m| self.overlaps(m, position)) {
+                info!("The saved window position is off-screen, using the default placement.");
+                self.position = None;
+            }
+        }
+
+        self
+    }
+
+    /// Whether the window, at `position`, has enough of itself
+    /// on `monitor` to be grabbed.
+    fn overlaps(&self, monitor: &MonitorHandle, position: PhysicalPosition<i32>) -> bool {
+        let origin = monitor.position();
+        let size = monitor.size();
+
+        let overlap = |start: i32, length: u32, monitor_start: i32, monitor_length: u32| {
+            let end = start.saturating_add(length as i32);
+            let monitor_end = monitor_start.saturating_add(monitor_length as i32);
+            end.min(monitor_end) - start.max(monitor_start)
+        };
+
+        overlap(position.x, self.size.width, origin.x, size.width) >= MIN_VISIBLE
+            && overlap(position.y, self.size.height, origin.y, size.height) >= MIN_VISIBLE
+    }
+}